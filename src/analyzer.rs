@@ -0,0 +1,640 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashSet, VecDeque},
+    fmt::Write,
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    case_insensitive_string::folds, tokenizer::Lexer, CaseInsensitiveStr, CaseInsensitiveString,
+    Language, Stemmer, StemmerKind, TermMap,
+};
+
+/// Turns text into terms. The same analyzer is normally used to build an index and to read its
+/// queries, so that query terms match indexed ones, but a different one can deliberately be used
+/// on the query side (see [`SearchOptions::query_analyzer`](crate::SearchOptions)).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Analyzer {
+    /// Additional terms emitted after a term
+    #[serde(default, skip_serializing_if = "TermMap::is_empty")]
+    synonyms: TermMap<Vec<String>>,
+    /// Words dropped from the text, not even counted as tokens
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    stop_words: BTreeSet<CaseInsensitiveString<'static>>,
+    /// Reduces terms to their stem, synonyms included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stemmer: Option<StemmerKind>,
+    /// Stems terms and drops stop words as usual in this language, see
+    /// [`Analyzer::with_language`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<Language>,
+    /// Smallest and largest number of consecutive words emitted as a single term, see
+    /// [`Analyzer::with_ngram_range`]. Only single words when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ngram_range: Option<(usize, usize)>,
+    /// Keeps terms differing only in case apart, see [`Analyzer::with_case_sensitivity`]
+    #[serde(default, skip_serializing_if = "is_false")]
+    case_sensitive: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+/// Joins the words of n-grams, which never contain whitespace themselves
+pub const NGRAM_SEPARATOR: char = ' ';
+
+/// Precedes the characters changed by case folding in the terms of case-sensitive analyzers
+const CASE_MARK: char = '^';
+
+/// Follows [`CASE_MARK`] when the character is written as its hexadecimal code point, ended by
+/// `;`
+const CODE_POINT: char = '#';
+
+/// Appends the character `c` of a term to `key`, marked if case folding would change it.
+/// Uppercase ASCII letters are only preceded by the mark, which is enough to tell them apart.
+/// Other folded characters, such as `Σ`, `ς` or `ß`, may fold to the same characters as others,
+/// so they are replaced by their code point: `Σ` becomes `^#3a3;`.
+fn push_marked(key: &mut String, c: char) {
+    if c == CASE_MARK || c.is_ascii_uppercase() {
+        key.push(CASE_MARK);
+        key.push(c);
+    } else if folds(c) {
+        write!(key, "{CASE_MARK}{CODE_POINT}{:x};", c as u32).unwrap();
+    } else {
+        key.push(c);
+    }
+}
+
+/// Word of the term `term` emitted by a case-sensitive analyzer, without its case marks
+pub(crate) fn unmark(term: &str) -> Cow<'_, str> {
+    if !term.contains(CASE_MARK) || term.len() == 1 {
+        return Cow::Borrowed(term);
+    }
+    let mut word = String::with_capacity(term.len());
+    let mut chars = term.chars();
+    while let Some(c) = chars.next() {
+        if c != CASE_MARK {
+            word.push(c);
+            continue;
+        }
+        let rest = chars.as_str();
+        let code_point = rest
+            .strip_prefix(CODE_POINT)
+            .and_then(|rest| rest.split_once(';'))
+            .and_then(|(hex, _)| {
+                Some((
+                    hex.len(),
+                    char::from_u32(u32::from_str_radix(hex, 16).ok()?)?,
+                ))
+            });
+        match code_point {
+            Some((len, c)) => {
+                word.push(c);
+                chars = rest[len + 2..].chars();
+            }
+            None => word.extend(chars.next()),
+        }
+    }
+    Cow::Owned(word)
+}
+
+/// Terms of the last words analyzed, to form the n-grams of the next ones, see
+/// [`Analyzer::with_ngram_range`]
+#[derive(Debug, Default)]
+pub(crate) struct NgramWindow {
+    terms: VecDeque<String>,
+}
+
+/// Text of `text` from the start of `first` to the end of `last`, both slices of `text`
+fn span<'a>(text: &'a str, first: &str, last: &str) -> &'a str {
+    let offset = |s: &str| s.as_ptr() as usize - text.as_ptr() as usize;
+    &text[offset(first)..offset(last) + last.len()]
+}
+
+/// Common English words, see [`Analyzer::english_stop_words`]
+const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a",
+    "about",
+    "above",
+    "after",
+    "again",
+    "against",
+    "all",
+    "am",
+    "an",
+    "and",
+    "any",
+    "are",
+    "as",
+    "at",
+    "be",
+    "because",
+    "been",
+    "before",
+    "being",
+    "below",
+    "between",
+    "both",
+    "but",
+    "by",
+    "can",
+    "could",
+    "did",
+    "do",
+    "does",
+    "doing",
+    "down",
+    "during",
+    "each",
+    "few",
+    "for",
+    "from",
+    "further",
+    "had",
+    "has",
+    "have",
+    "having",
+    "he",
+    "her",
+    "here",
+    "hers",
+    "herself",
+    "him",
+    "himself",
+    "his",
+    "how",
+    "i",
+    "if",
+    "in",
+    "into",
+    "is",
+    "it",
+    "its",
+    "itself",
+    "just",
+    "me",
+    "more",
+    "most",
+    "my",
+    "myself",
+    "no",
+    "nor",
+    "not",
+    "now",
+    "of",
+    "off",
+    "on",
+    "once",
+    "only",
+    "or",
+    "other",
+    "our",
+    "ours",
+    "ourselves",
+    "out",
+    "over",
+    "own",
+    "same",
+    "she",
+    "should",
+    "so",
+    "some",
+    "such",
+    "than",
+    "that",
+    "the",
+    "their",
+    "theirs",
+    "them",
+    "themselves",
+    "then",
+    "there",
+    "these",
+    "they",
+    "this",
+    "those",
+    "through",
+    "to",
+    "too",
+    "under",
+    "until",
+    "up",
+    "very",
+    "was",
+    "we",
+    "were",
+    "what",
+    "when",
+    "where",
+    "which",
+    "while",
+    "who",
+    "whom",
+    "why",
+    "will",
+    "with",
+    "would",
+    "you",
+    "your",
+    "yours",
+    "yourself",
+    "yourselves",
+];
+
+/// Common French words, see [`Analyzer::french_stop_words`]
+const FRENCH_STOP_WORDS: &[&str] = &[
+    "à", "au", "aux", "avec", "ce", "ces", "cette", "dans", "de", "des", "du", "elle", "elles",
+    "en", "est", "et", "eux", "il", "ils", "je", "la", "le", "les", "leur", "leurs", "lui", "ma",
+    "mais", "me", "même", "mes", "moi", "mon", "ne", "nos", "notre", "nous", "on", "ou", "où",
+    "par", "pas", "pour", "qu", "que", "qui", "sa", "se", "ses", "son", "sont", "sur", "ta", "te",
+    "tes", "toi", "ton", "tu", "un", "une", "vos", "votre", "vous",
+];
+
+impl Analyzer {
+    /// Analyzer splitting text with the [`Lexer`] and nothing more
+    pub fn standard() -> &'static Self {
+        static STANDARD: OnceLock<Analyzer> = OnceLock::new();
+        STANDARD.get_or_init(Self::default)
+    }
+
+    pub fn is_standard(&self) -> bool {
+        self == Self::standard()
+    }
+
+    /// Also emits `synonyms` each time `term` is encountered
+    pub fn with_synonyms<S: Into<String>>(
+        mut self,
+        term: &str,
+        synonyms: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.synonyms
+            .entry(term.to_owned().into())
+            .or_default()
+            .extend(synonyms.into_iter().map(Into::into));
+        self
+    }
+
+    /// Drops `stop_words` from the text: they are neither indexed nor counted in the length of
+    /// documents, and ignored in queries
+    pub fn with_stop_words(
+        mut self,
+        stop_words: impl IntoIterator<Item = CaseInsensitiveString<'static>>,
+    ) -> Self {
+        self.stop_words.extend(stop_words);
+        self
+    }
+
+    /// A built-in list of common English words, such as "the", "and" or "of", to use with
+    /// [`Analyzer::with_stop_words`]
+    pub fn english_stop_words() -> HashSet<CaseInsensitiveString<'static>> {
+        ENGLISH_STOP_WORDS.iter().map(|&w| w.into()).collect()
+    }
+
+    /// A built-in list of common French words, such as "le", "et" or "de", to use with
+    /// [`Analyzer::with_stop_words`]
+    pub fn french_stop_words() -> HashSet<CaseInsensitiveString<'static>> {
+        FRENCH_STOP_WORDS.iter().map(|&w| w.into()).collect()
+    }
+
+    /// Reduces terms to their stem with `stemmer`. Since queries go through the analyzer of the
+    /// index, `running` then matches documents containing `runs`.
+    pub fn with_stemmer(mut self, stemmer: impl Into<StemmerKind>) -> Self {
+        self.stemmer = Some(stemmer.into());
+        self
+    }
+
+    /// Stems terms with the stemmer of `language`, instead of the one set by
+    /// [`Analyzer::with_stemmer`], and also drops its stop words
+    pub fn with_language(mut self, language: Language) -> Self {
+        self.language = Some(language);
+        self
+    }
+
+    /// Analyzer of the documents detected to be written in `language`, or in an unknown
+    /// language if `None`, which are then not stemmed at all
+    pub(crate) fn for_language(&self, language: Option<Language>) -> Self {
+        Self {
+            stemmer: None,
+            language,
+            ..self.clone()
+        }
+    }
+
+    /// Emits every run of `min` to `max` consecutive words as a single term, the words joined by
+    /// [`NGRAM_SEPARATOR`]: with `(1, 2)`, "machine learning" also gives the term "machine
+    /// learning", which only documents where both words follow each other contain. Stop words
+    /// are skipped beforehand and each word is stemmed, but synonyms are not part of n-grams. As
+    /// queries go through the same analyzer, they are split into the same n-grams.
+    ///
+    /// Each n-gram counts as a token in the length of documents, but not as a position: with
+    /// positions tracked, phrases match the same words as without n-grams. Punctuation is left
+    /// out of n-grams.
+    ///
+    /// # Panics
+    ///
+    /// If `min` is zero or greater than `max`
+    pub fn with_ngram_range(mut self, min: usize, max: usize) -> Self {
+        assert!(0 < min && min <= max, "invalid n-gram range {min}..={max}");
+        self.ngram_range = ((min, max) != (1, 1)).then_some((min, max));
+        self
+    }
+
+    /// Keeps terms differing only in case, such as `Rust` and `rust`, apart. Terms are compared
+    /// ignoring case throughout the crate, so a case-sensitive analyzer marks every character
+    /// case folding would change: uppercase ASCII letters (and `^`) are preceded by `^`, so that
+    /// `Rust` becomes the term `^Rust`, and other letters are written as `^#` followed by their
+    /// hexadecimal code point and `;`, so that `Σ` becomes `^#3a3;`, see [`Analyzer::key`].
+    /// Stemmers lowercase the words they stem.
+    ///
+    /// As the analyzer is stored in indexes, they keep the mode they were built in, and searches
+    /// mixing case-sensitive and case-insensitive analyzers are rejected.
+    pub fn with_case_sensitivity(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    pub fn is_case_sensitive(&self) -> bool {
+        self.case_sensitive
+    }
+
+    /// Term stored in indexes for the word `term`: `term` itself, unless the analyzer is case
+    /// sensitive and `term` has uppercase letters
+    pub fn key<'a>(&self, term: &'a str) -> Cow<'a, str> {
+        self.case_key(Cow::Borrowed(term))
+    }
+
+    /// Word stored as the term `term`, the inverse of [`Analyzer::key`]: `term` itself, unless
+    /// the analyzer is case sensitive
+    pub fn word<'a>(&self, term: &'a str) -> Cow<'a, str> {
+        match self.case_sensitive {
+            true => unmark(term),
+            false => Cow::Borrowed(term),
+        }
+    }
+
+    fn case_key<'a>(&self, term: Cow<'a, str>) -> Cow<'a, str> {
+        if !self.case_sensitive || !term.contains(|c| c == CASE_MARK || folds(c)) {
+            return term;
+        }
+        let mut key = String::with_capacity(term.len() + 4);
+        for c in term.chars() {
+            push_marked(&mut key, c);
+        }
+        Cow::Owned(key)
+    }
+
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        let stem = match (self.language, self.stemmer) {
+            (Some(language), _) => language.stem(word),
+            (None, Some(stemmer)) => stemmer.stem(word),
+            (None, None) => Cow::Borrowed(word),
+        };
+        self.case_key(stem)
+    }
+
+    /// Calls `emit` with every term of `text`, in order
+    pub fn analyze<'a>(&self, text: &'a str, mut emit: impl FnMut(Cow<'a, str>)) {
+        self.analyze_with_forms(text, |term, _| emit(term));
+    }
+
+    /// Like [`Analyzer::analyze`], also passing the word of `text` each term was derived from
+    pub fn analyze_with_forms<'a>(
+        &self,
+        text: &'a str,
+        mut emit: impl FnMut(Cow<'a, str>, &'a str),
+    ) {
+        self.analyze_in_window(text, &mut NgramWindow::default(), |term, form, _| {
+            emit(term, form)
+        });
+    }
+
+    /// Like [`Analyzer::analyze_with_forms`], `text` following the text `window` was last used
+    /// with, so that n-grams span both. The form of an n-gram only covers its words in `text`.
+    ///
+    /// `emit` is also told whether the term takes up a new position: the first term of each
+    /// word does, synonyms and n-grams longer than the shortest one stand at the position of
+    /// the word they end at, so that the words of a phrase keep consecutive positions.
+    pub(crate) fn analyze_in_window<'a>(
+        &self,
+        text: &'a str,
+        window: &mut NgramWindow,
+        mut emit: impl FnMut(Cow<'a, str>, &'a str, bool),
+    ) {
+        let (min, max) = self.ngram_range.unwrap_or((1, 1));
+        // Words of `text` among the last ones of `window`
+        let mut words: VecDeque<&'a str> = VecDeque::new();
+        for token in Lexer::new(text) {
+            if self.stop_words.contains(CaseInsensitiveStr::new(token))
+                || self.language.is_some_and(|l| l.is_stop_word(token))
+            {
+                continue;
+            }
+            let term = self.stem(token);
+            // Punctuation is not part of n-grams
+            let in_ngrams = max > 1 && token.chars().any(char::is_alphanumeric);
+            if in_ngrams {
+                if window.terms.len() == max {
+                    window.terms.pop_front();
+                }
+                if words.len() == max {
+                    words.pop_front();
+                }
+                window.terms.push_back(term.to_string());
+                words.push_back(token);
+            }
+            let mut new_position = true;
+            if min == 1 {
+                emit(term, token, true);
+                new_position = false;
+            }
+            let longest = if in_ngrams { window.terms.len() } else { 0 };
+            for n in min.max(2)..=longest {
+                let start = window.terms.len() - n;
+                let mut ngram = String::new();
+                for term in window.terms.range(start..) {
+                    if !ngram.is_empty() {
+                        ngram.push(NGRAM_SEPARATOR);
+                    }
+                    ngram.push_str(term);
+                }
+                let first = words[words.len().saturating_sub(n)];
+                emit(Cow::Owned(ngram), span(text, first, token), new_position);
+                new_position = false;
+            }
+            if let Some(synonyms) = self.synonyms.get(CaseInsensitiveStr::new(token)) {
+                for synonym in synonyms {
+                    emit(
+                        Cow::Owned(self.stem(synonym).into_owned()),
+                        token,
+                        new_position,
+                    );
+                    new_position = false;
+                }
+            }
+        }
+    }
+
+    /// Terms of `text` taking up consecutive positions, the ones phrases are matched with: its
+    /// words, or its shortest n-grams when single words are not terms
+    pub(crate) fn phrase_terms<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        let mut terms = Vec::new();
+        self.analyze_in_window(
+            text,
+            &mut NgramWindow::default(),
+            |term, _, new_position| {
+                if new_position {
+                    terms.push(term);
+                }
+            },
+        );
+        terms
+    }
+
+    /// The term of the single word `word`, synonyms left out, as looked up in indexes. `None`,
+    /// with a warning, if `word` has no term, as a stop word, or several, as words joined by
+    /// punctuation.
+    pub(crate) fn word_term<'a>(&self, word: &'a str) -> Option<Cow<'a, str>> {
+        let mut terms = self.phrase_terms(word);
+        if terms.len() != 1 {
+            log::warn!("{word:?} is not a single term but {n}", n = terms.len());
+            return None;
+        }
+        terms.pop()
+    }
+
+    /// Returns the terms of `text`, in order
+    pub fn terms<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        let mut terms = Vec::new();
+        self.analyze(text, |t| terms.push(t));
+        terms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FrenchStemmer, PorterStemmer};
+
+    #[test]
+    fn expands_synonyms() {
+        let analyzer = Analyzer::default().with_synonyms("car", ["automobile", "vehicle"]);
+        assert_eq!(
+            analyzer.terms("red Car"),
+            ["red", "Car", "automobile", "vehicle"]
+        );
+        assert_eq!(Analyzer::standard().terms("red Car"), ["red", "Car"]);
+        assert!(!analyzer.is_standard());
+    }
+
+    #[test]
+    fn drops_stop_words() {
+        let analyzer = Analyzer::default()
+            .with_stop_words(Analyzer::english_stop_words())
+            .with_synonyms("car", ["automobile"]);
+        assert_eq!(
+            analyzer.terms("The state OF the Car"),
+            ["state", "Car", "automobile"]
+        );
+    }
+
+    #[test]
+    fn stems_terms() {
+        let analyzer = Analyzer::default()
+            .with_stemmer(PorterStemmer)
+            .with_synonyms("jogging", ["running"]);
+        assert_eq!(
+            analyzer.terms("Jogging cats in Rust"),
+            ["jog", "run", "cat", "in", "Rust"]
+        );
+
+        let saved = serde_json::to_string(&analyzer).unwrap();
+        let loaded: Analyzer = serde_json::from_str(&saved).unwrap();
+        assert_eq!(loaded, analyzer);
+        let unstemmed: Analyzer = serde_json::from_str("{}").unwrap();
+        assert!(unstemmed.is_standard());
+
+        let french = Analyzer::default().with_stemmer(FrenchStemmer);
+        assert_eq!(french.terms("Chevaux"), ["cheval"]);
+        let saved = serde_json::to_string(&french).unwrap();
+        assert_eq!(saved, r#"{"stemmer":"french"}"#);
+        assert_eq!(serde_json::from_str::<Analyzer>(&saved).unwrap(), french);
+    }
+
+    #[test]
+    fn emits_ngrams() {
+        let analyzer = Analyzer::default()
+            .with_stop_words(Analyzer::english_stop_words())
+            .with_stemmer(PorterStemmer)
+            .with_ngram_range(1, 3);
+        assert_eq!(
+            analyzer.terms("Machine learning of models"),
+            [
+                "machin",
+                "learn",
+                "machin learn",
+                "model",
+                "learn model",
+                "machin learn model"
+            ]
+        );
+        let bigrams = Analyzer::default().with_ngram_range(2, 2);
+        assert_eq!(bigrams.terms("a b c"), ["a b", "b c"]);
+        assert!(bigrams.terms("a").is_empty());
+        let mut forms = Vec::new();
+        bigrams.analyze_with_forms("Deep  Learning! Again", |term, form| {
+            forms.push((term, form))
+        });
+        assert_eq!(
+            forms,
+            [
+                (Cow::Borrowed("Deep Learning"), "Deep  Learning"),
+                (Cow::Borrowed("Learning Again"), "Learning! Again")
+            ]
+        );
+        assert_eq!(
+            Analyzer::default().with_ngram_range(1, 1),
+            Analyzer::default()
+        );
+    }
+
+    #[test]
+    fn case_sensitive_terms_are_marked() {
+        let analyzer = Analyzer::default()
+            .with_case_sensitivity(true)
+            .with_synonyms("rust", ["Ferris"]);
+        assert_eq!(
+            analyzer.terms("Rust rust a^b XML"),
+            ["^Rust", "^Ferris", "rust", "^Ferris", "a", "^^", "b", "^X^M^L"]
+        );
+        assert_eq!(analyzer.key("rust"), "rust");
+        assert_eq!(analyzer.key("Rust"), "^Rust");
+        assert_eq!(Analyzer::standard().key("Rust"), "Rust");
+        for term in [
+            "^Rust", "rust", "^X^M^L", "^^", "^", "^#3a3;", "^#", "^#zz;",
+        ] {
+            assert_eq!(unmark(&analyzer.key(&unmark(term))), unmark(term));
+        }
+        assert_eq!(unmark("^X^M^L"), "XML");
+
+        // Words equal ignoring case keep distinct keys
+        let keys: Vec<_> = [
+            "ΟΔΟΣ", "οδος", "ΟΔΟς", "Straße", "STRASSE", "strasse", "Ёлка", "ёлка",
+        ]
+        .into_iter()
+        .map(|word| {
+            let key = analyzer.key(word).into_owned();
+            assert_eq!(unmark(&key), word);
+            CaseInsensitiveString::from(key)
+        })
+        .collect();
+        for (i, a) in keys.iter().enumerate() {
+            for b in &keys[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        assert_eq!(analyzer.key("ς"), "^#3c2;");
+    }
+}
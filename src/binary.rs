@@ -0,0 +1,406 @@
+//! Binary index storage, faster to load than JSON.
+//!
+//! Layout: the `MAGIC` bytes, then a [`bincode`] record holding the index settings as JSON
+//! (analyzer, boosts and options) followed by every document. Bincode is not self-describing, so
+//! fields skipped when empty in JSON cannot be skipped here: documents go through [`DocumentRef`]
+//! and [`DocumentRecord`], which always write every field, while the settings keep the JSON
+//! encoding and its compatibility rules. Files of the first version, tagged `IDXBIN01`, lack the
+//! file size of documents, and those of the second, tagged `IDXBIN02`, their language.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+
+use crate::{term_map, Analyzer, Document, Index, IndexOptions, Language, SourceKind, TermMap};
+
+const MAGIC: &[u8; 8] = b"IDXBIN03";
+/// Tag of the second version, whose documents have no language
+const MAGIC_V2: &[u8; 8] = b"IDXBIN02";
+/// Tag of the first version, whose documents have no size
+const MAGIC_V1: &[u8; 8] = b"IDXBIN01";
+
+#[derive(Serialize, Deserialize)]
+struct Settings {
+    analyzer: Analyzer,
+    #[serde(default)]
+    boosts: HashMap<PathBuf, f64>,
+    #[serde(default)]
+    options: IndexOptions,
+    #[serde(default)]
+    root: Option<PathBuf>,
+}
+
+/// Borrowed [`Document`], written field by field
+#[derive(Serialize)]
+struct DocumentRef<'a> {
+    term_frequency: &'a TermMap<usize>,
+    count: usize,
+    title: &'a Option<String>,
+    positions: &'a Option<TermMap<Vec<usize>>>,
+    forms: &'a Option<TermMap<Vec<String>>>,
+    modified: Option<SystemTime>,
+    size: Option<u64>,
+    kind: Option<SourceKind>,
+    language: Option<Language>,
+}
+
+/// Owned counterpart of [`DocumentRef`]. Terms are read as owned strings, hence the `'static`
+/// keys, as bincode cannot lend strings out of a reader.
+#[derive(Deserialize)]
+struct DocumentRecord {
+    #[serde(deserialize_with = "term_map::deserialize")]
+    term_frequency: TermMap<usize>,
+    count: usize,
+    title: Option<String>,
+    #[serde(deserialize_with = "term_map::deserialize_optional")]
+    positions: Option<TermMap<Vec<usize>>>,
+    #[serde(deserialize_with = "term_map::deserialize_optional")]
+    forms: Option<TermMap<Vec<String>>>,
+    modified: Option<SystemTime>,
+    size: Option<u64>,
+    kind: Option<SourceKind>,
+    language: Option<Language>,
+}
+
+/// [`DocumentRecord`] of the second version of the format
+#[derive(Deserialize)]
+struct DocumentRecordV2 {
+    #[serde(deserialize_with = "term_map::deserialize")]
+    term_frequency: TermMap<usize>,
+    count: usize,
+    title: Option<String>,
+    #[serde(deserialize_with = "term_map::deserialize_optional")]
+    positions: Option<TermMap<Vec<usize>>>,
+    #[serde(deserialize_with = "term_map::deserialize_optional")]
+    forms: Option<TermMap<Vec<String>>>,
+    modified: Option<SystemTime>,
+    size: Option<u64>,
+    kind: Option<SourceKind>,
+}
+
+impl From<DocumentRecordV2> for DocumentRecord {
+    fn from(d: DocumentRecordV2) -> Self {
+        Self {
+            term_frequency: d.term_frequency,
+            count: d.count,
+            title: d.title,
+            positions: d.positions,
+            forms: d.forms,
+            modified: d.modified,
+            size: d.size,
+            kind: d.kind,
+            language: None,
+        }
+    }
+}
+
+/// [`DocumentRecord`] of the first version of the format
+#[derive(Deserialize)]
+struct DocumentRecordV1 {
+    #[serde(deserialize_with = "term_map::deserialize")]
+    term_frequency: TermMap<usize>,
+    count: usize,
+    title: Option<String>,
+    #[serde(deserialize_with = "term_map::deserialize_optional")]
+    positions: Option<TermMap<Vec<usize>>>,
+    #[serde(deserialize_with = "term_map::deserialize_optional")]
+    forms: Option<TermMap<Vec<String>>>,
+    modified: Option<SystemTime>,
+    kind: Option<SourceKind>,
+}
+
+impl From<DocumentRecordV1> for DocumentRecord {
+    fn from(d: DocumentRecordV1) -> Self {
+        Self {
+            term_frequency: d.term_frequency,
+            count: d.count,
+            title: d.title,
+            positions: d.positions,
+            forms: d.forms,
+            modified: d.modified,
+            size: None,
+            kind: d.kind,
+            language: None,
+        }
+    }
+}
+
+fn invalid_data(err: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+fn decode<T: serde::de::DeserializeOwned>(reader: &mut impl Read) -> io::Result<T> {
+    bincode::serde::decode_from_std_read(reader, bincode::config::standard()).map_err(invalid_data)
+}
+
+impl Index {
+    /// Writes the index in a binary format, loaded by [`Index::load_bin`] faster than JSON by
+    /// [`Index::load`]
+    pub fn save_bin<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        let settings = serde_json::to_string(&Settings {
+            analyzer: self.analyzer.clone(),
+            boosts: self.boosts.clone(),
+            options: self.options.clone(),
+            root: self.root.clone(),
+        })
+        .map_err(|err| io::Error::other(err.to_string()))?;
+        let documents: Vec<_> = self
+            .documents
+            .iter()
+            .map(|(path, d)| {
+                let document = DocumentRef {
+                    term_frequency: &d.term_frequency,
+                    count: d.count,
+                    title: &d.title,
+                    positions: &d.positions,
+                    forms: &d.forms,
+                    modified: d.modified,
+                    size: d.size,
+                    kind: d.kind,
+                    language: d.language,
+                };
+                (path.as_path(), document)
+            })
+            .collect();
+        let record: (&str, Vec<(&Path, DocumentRef)>) = (&settings, documents);
+        bincode::serde::encode_into_std_write(record, &mut writer, bincode::config::standard())
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        writer.flush()
+    }
+
+    /// Loads an index written by [`Index::save_bin`]
+    pub fn load_bin<R: Read>(reader: R) -> io::Result<Self> {
+        Self::decode_bin(&mut BufReader::new(reader))
+    }
+
+    /// Like [`Index::load_bin`], reading the file at `path` through a memory map rather than
+    /// buffered reads: it is paged in as it is decoded, without a copy in a read buffer. This is
+    /// not zero-copy: the documents are decoded into owned terms, so the whole index is still
+    /// held in memory once loaded. [`MappedIndex`] searches a file in place.
+    ///
+    /// The file must not be modified while it is loaded: its content would change under the
+    /// decoder, which is undefined behavior. The map is released before returning, so the file
+    /// can be replaced afterwards.
+    ///
+    /// [`MappedIndex`]: crate::MappedIndex
+    pub fn load_mmap(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: see above, the file is not modified while mapped
+        let map = unsafe { Mmap::map(&file)? };
+        Self::decode_bin(&mut &map[..])
+    }
+
+    fn decode_bin(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0u8; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        let (settings, documents): (String, Vec<(PathBuf, DocumentRecord)>) = match &magic {
+            MAGIC => decode(reader)?,
+            MAGIC_V2 => {
+                let (settings, documents): (String, Vec<(PathBuf, DocumentRecordV2)>) =
+                    decode(reader)?;
+                let documents = documents.into_iter().map(|(p, d)| (p, d.into())).collect();
+                (settings, documents)
+            }
+            MAGIC_V1 => {
+                let (settings, documents): (String, Vec<(PathBuf, DocumentRecordV1)>) =
+                    decode(reader)?;
+                let documents = documents.into_iter().map(|(p, d)| (p, d.into())).collect();
+                (settings, documents)
+            }
+            _ => return Err(invalid_data("not a binary index")),
+        };
+        let settings: Settings = serde_json::from_str(&settings).map_err(invalid_data)?;
+        let documents = documents
+            .into_iter()
+            .map(|(path, d)| {
+                let document = Document {
+                    term_frequency: d.term_frequency,
+                    count: d.count,
+                    title: d.title,
+                    positions: d.positions,
+                    forms: d.forms,
+                    modified: d.modified,
+                    size: d.size,
+                    kind: d.kind,
+                    language: d.language,
+                    max_count: Default::default(),
+                };
+                (path, document)
+            })
+            .collect();
+        let index = Self {
+            documents,
+            analyzer: settings.analyzer,
+            boosts: settings.boosts,
+            options: settings.options,
+            root: settings.root,
+            postings: Default::default(),
+        };
+        index.check_boosts()?;
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{testutil::TempDir, SearchOptions};
+
+    #[test]
+    fn round_trip() {
+        let dir = TempDir::new("binary");
+        let a = dir.write("a.txt", "Rust tokenizer rust");
+        dir.write("b.md", "# Notes\nxml parser");
+        dir.write("c.json", r#"{"name": "config"}"#);
+        let d = dir.write(
+            "d.txt",
+            "The horses were galloping across the meadow while the children watched the sunset \
+            from the top of the hill.",
+        );
+        let mut index = Index::new_with_options(
+            dir.path(),
+            IndexOptions {
+                capture_titles: true,
+                track_positions: true,
+                keep_term_forms: true,
+                detect_language: true,
+                analyzer: Analyzer::default().with_synonyms("rust", ["ferris"]),
+                ..Default::default()
+            },
+        );
+        index.set_boost(&a, 2f64).unwrap();
+        assert_eq!(
+            index.document(&d).unwrap().language(),
+            Some(Language::English)
+        );
+
+        let mut saved = Vec::new();
+        index.save_bin(&mut saved).unwrap();
+        let loaded = Index::load_bin(&saved[..]).unwrap();
+        assert_eq!(loaded, index);
+        assert_eq!(
+            loaded.search_with_options("ferris", &SearchOptions::default()),
+            index.search("ferris")
+        );
+
+        let mut json = Vec::new();
+        index.save(&mut json).unwrap();
+        assert!(Index::load_bin(&json[..]).is_err());
+        assert!(Index::load_bin(&saved[..saved.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn loads_memory_mapped() {
+        let dir = TempDir::new("binary-mmap");
+        dir.write("a.txt", "rust tokenizer rust");
+        dir.write("b.txt", "xml parser");
+        dir.write("c.txt", "csv parser");
+        dir.write("d.txt", "gardening notes");
+        dir.write("e.txt", "cooking notes");
+        let index = Index::new(dir.path());
+
+        let store = TempDir::new("binary-mmap-store");
+        let path = store.write("index.bin", "");
+        index.save_bin(File::create(&path).unwrap()).unwrap();
+        let loaded = Index::load_mmap(&path).unwrap();
+        assert_eq!(loaded, index);
+        assert_eq!(loaded.search("rust"), index.search("rust"));
+        assert_eq!(loaded.search("parser").len(), 2);
+
+        let json = store.write("index.json", "");
+        index.save(File::create(&json).unwrap()).unwrap();
+        assert!(Index::load_mmap(&json).is_err());
+        assert!(Index::load_mmap(store.write("empty.bin", "")).is_err());
+        assert!(Index::load_mmap(store.path().join("missing.bin")).is_err());
+    }
+
+    #[test]
+    fn loads_first_version() {
+        let dir = TempDir::new("binary-v1");
+        dir.write("a.txt", "Rust tokenizer rust");
+        dir.write("b.md", "# Notes\nxml parser");
+        let index = Index::new(dir.path());
+
+        // Bincode writes structs as the sequence of their fields
+        let settings = serde_json::to_string(&Settings {
+            analyzer: index.analyzer.clone(),
+            boosts: index.boosts.clone(),
+            options: index.options.clone(),
+            root: index.root.clone(),
+        })
+        .unwrap();
+        let documents: Vec<_> = index
+            .documents
+            .iter()
+            .map(|(path, d)| {
+                let fields = (
+                    &d.term_frequency,
+                    d.count,
+                    &d.title,
+                    &d.positions,
+                    &d.forms,
+                    d.modified,
+                    d.kind,
+                );
+                (path.as_path(), fields)
+            })
+            .collect();
+        let mut saved = MAGIC_V1.to_vec();
+        bincode::serde::encode_into_std_write(
+            (&settings, documents),
+            &mut saved,
+            bincode::config::standard(),
+        )
+        .unwrap();
+
+        let loaded = Index::load_bin(&saved[..]).unwrap();
+        assert_eq!(loaded.documents.len(), 2);
+        assert!(loaded.documents.values().all(|d| d.size.is_none()));
+        assert_eq!(loaded.search("rust"), index.search("rust"));
+    }
+
+    /// Compares load times of large indexes, run with
+    /// `cargo test --release --features binary -- --ignored --nocapture`. Only reports the
+    /// timings.
+    #[test]
+    #[ignore]
+    fn load_times_against_json() {
+        let dir = TempDir::new("binary-bench");
+        for i in 0..2_000 {
+            let words: Vec<_> = (0..200)
+                .map(|j| format!("term{}", (i * 7 + j * 13) % 5_000))
+                .collect();
+            dir.write(format!("doc{i}.txt"), words.join(" "));
+        }
+        let index = Index::new(dir.path());
+        let (mut json, mut bin) = (Vec::new(), Vec::new());
+        index.save(&mut json).unwrap();
+        index.save_bin(&mut bin).unwrap();
+
+        let start = Instant::now();
+        let from_json = Index::load(&json[..]).unwrap();
+        let json_time = start.elapsed();
+        let start = Instant::now();
+        let from_bin = Index::load_bin(&bin[..]).unwrap();
+        let bin_time = start.elapsed();
+
+        assert_eq!(from_json, from_bin);
+        assert!(
+            bin_time < json_time && bin.len() < json.len(),
+            "JSON: {json_len} bytes in {json_time:?}, binary: {bin_len} bytes in {bin_time:?}",
+            json_len = json.len(),
+            bin_len = bin.len()
+        );
+    }
+}
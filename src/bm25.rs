@@ -0,0 +1,96 @@
+//! Okapi BM25 ranking.
+
+use std::path::Path;
+
+use crate::{
+    parse_query, query_matcher, sort_results, CaseInsensitiveStr, IdfScheme, Index, SearchOptions,
+};
+
+impl Index {
+    /// Ranks the documents matching `terms` with Okapi BM25 rather than tf-idf: the weight of a
+    /// term saturates as it repeats, `k1` controlling how fast, and is normalized by the document
+    /// length relative to the average one, `b` controlling how much (`0` to ignore lengths, `1`
+    /// for full normalization). Usual values are `k1 = 1.2` and `b = 0.75`. Document boosts
+    /// and the boosts of query terms, such as `rust^2`, apply, title boosts do not.
+    pub fn search_bm25<'a>(&'a self, terms: &'_ str, k1: f64, b: f64) -> Vec<(&'a Path, f64)> {
+        let options = SearchOptions::default();
+        let (terms, filter) =
+            parse_query(terms, &self.analyzer, &options, &self.vocabulary()).unwrap_or_default();
+        let matches = query_matcher(&terms, filter.as_ref(), &options);
+        let weights: Vec<_> = terms
+            .iter()
+            .map(|(t, boost)| (t, self.idf(t, IdfScheme::Smoothed) * boost))
+            .collect();
+        let total: usize = self.documents.values().map(|d| d.count).sum();
+        let average_length = total as f64 / self.documents.len().max(1) as f64;
+
+        let mut results: Vec<_> = self
+            .documents
+            .iter()
+            .filter(|(_, d)| matches(d))
+            .map(|(path, d)| {
+                let length = d.count as f64 / average_length;
+                let score: f64 = weights
+                    .iter()
+                    .map(|(t, idf)| {
+                        let tf = d
+                            .term_frequency
+                            .get(CaseInsensitiveStr::new(t))
+                            .copied()
+                            .unwrap_or_default() as f64;
+                        idf * tf * (k1 + 1f64) / (tf + k1 * (1f64 - b + b * length))
+                    })
+                    .sum();
+                (path.as_path(), self.boost(path) * score)
+            })
+            // Lengths are undefined when every document is empty
+            .filter(|(_, score)| score.is_finite() && score != &0f64)
+            .collect();
+        sort_results(&mut results);
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{testutil::TempDir, Index};
+
+    #[test]
+    fn bm25_demotes_repeated_terms() {
+        let dir = TempDir::new("bm25");
+        let relevant = dir.write("relevant.txt", "rust borrow checker notes");
+        let spam = dir.write("spam.txt", "rust ".repeat(50).trim_end());
+        for i in 0..6 {
+            dir.write(format!("filler{i}.txt"), "zebra yak");
+        }
+        let index = Index::new(dir.path());
+        let paths = |results: Vec<(&std::path::Path, f64)>| {
+            results
+                .into_iter()
+                .map(|(p, _)| p.to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            paths(index.search("rust borrow")),
+            [spam.clone(), relevant.clone()]
+        );
+        assert_eq!(
+            paths(index.search_bm25("rust borrow", 1.2, 0.75)),
+            [relevant.clone(), spam.clone()]
+        );
+        assert!(index.search_bm25("quux", 1.2, 0.75).is_empty());
+        assert_eq!(paths(index.search_bm25("rust -borrow", 1.2, 0.75)), [spam]);
+        assert_eq!(
+            paths(index.search_bm25("rust AND borrow", 1.2, 0.75)),
+            [relevant]
+        );
+
+        let empty = TempDir::new("bm25-empty");
+        empty.write("a.txt", "");
+        empty.write("b.txt", "");
+        assert!(Index::new(empty.path())
+            .search_bm25("rust", 1.2, 0.75)
+            .is_empty());
+    }
+}
@@ -1,5 +1,6 @@
 use std::{
-    borrow::Cow,
+    borrow::{Borrow, Cow},
+    cmp::Ordering,
     fmt,
     hash::{Hash, Hasher},
     ops::{Deref, DerefMut},
@@ -7,19 +8,115 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
+/// Case folding of `c`: its lowercase form, except for `ß` and `ẞ`, folded to `ss` as in
+/// `STRASSE`, and the final sigma `ς`, folded to `σ` like `Σ`. Locale-specific mappings such as
+/// the Turkish dotless `ı` are not applied.
+pub(crate) fn fold(c: char) -> impl Iterator<Item = char> {
+    let special = match c {
+        'ß' | 'ẞ' => "ss",
+        'ς' => "σ",
+        _ => "",
+    };
+    let lower = special.is_empty().then(|| c.to_lowercase());
+    special.chars().chain(lower.into_iter().flatten())
+}
+
+/// Whether `c` is changed by [`fold`]
+pub(crate) fn folds(c: char) -> bool {
+    let mut folded = fold(c);
+    folded.next() != Some(c) || folded.next().is_some()
+}
+
+/// Characters of `s` case folded, see [`fold`]
+fn folded(s: &str) -> impl Iterator<Item = char> + '_ {
+    s.chars().flat_map(fold)
+}
+
+/// String compared, hashed and ordered ignoring case. Non-ASCII letters are case folded as well,
+/// so `Straße` equals `STRASSE` and `ΟΔΟΣ` equals `οδος`.
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct CaseInsensitiveString<'a>(Cow<'a, str>);
 
-impl Hash for CaseInsensitiveString<'_> {
+/// Borrowed counterpart of [`CaseInsensitiveString`], to look up maps keyed by
+/// `CaseInsensitiveString<'static>` without allocating an owned key
+#[repr(transparent)]
+pub struct CaseInsensitiveStr(str);
+
+impl CaseInsensitiveStr {
+    pub fn new(s: &str) -> &Self {
+        // SAFETY: `CaseInsensitiveStr` is a `repr(transparent)` wrapper around `str`
+        unsafe { &*(s as *const str as *const Self) }
+    }
+
+    /// Whether the string starts with `prefix`, ignoring case
+    pub fn starts_with(&self, prefix: &str) -> bool {
+        let mut chars = folded(&self.0);
+        folded(prefix).all(|c| chars.next() == Some(c))
+    }
+}
+
+// `Hash`, `PartialEq` and `Ord` all work on the characters returned by `folded`, so that equal
+// strings hash alike and compare `Equal`
+
+/// Hashes the UTF-8 encoding of the folded characters
+impl Hash for CaseInsensitiveStr {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        for b in self.as_bytes() {
-            state.write_u8(b.to_ascii_lowercase());
+        let mut buf = [0; 4];
+        for c in folded(&self.0) {
+            state.write(c.encode_utf8(&mut buf).as_bytes());
         }
         state.write_u8(0xff);
     }
 }
 
+impl PartialEq for CaseInsensitiveStr {
+    fn eq(&self, other: &Self) -> bool {
+        folded(&self.0).eq(folded(&other.0))
+    }
+}
+
+impl Eq for CaseInsensitiveStr {}
+
+impl PartialOrd for CaseInsensitiveStr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Orders like the folded strings, consistently with [`Eq`] and [`Hash`]
+impl Ord for CaseInsensitiveStr {
+    fn cmp(&self, other: &Self) -> Ordering {
+        folded(&self.0).cmp(folded(&other.0))
+    }
+}
+
+impl fmt::Debug for CaseInsensitiveStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl Deref for CaseInsensitiveStr {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Borrow<CaseInsensitiveStr> for CaseInsensitiveString<'_> {
+    fn borrow(&self) -> &CaseInsensitiveStr {
+        CaseInsensitiveStr::new(&self.0)
+    }
+}
+
+impl Hash for CaseInsensitiveString<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Borrow::<CaseInsensitiveStr>::borrow(self).hash(state)
+    }
+}
+
 impl fmt::Debug for CaseInsensitiveString<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt::Debug::fmt(self.0.as_ref(), f)
@@ -64,12 +161,24 @@ impl DerefMut for CaseInsensitiveString<'_> {
 
 impl PartialEq for CaseInsensitiveString<'_> {
     fn eq(&self, other: &Self) -> bool {
-        self.0.eq_ignore_ascii_case(other)
+        Borrow::<CaseInsensitiveStr>::borrow(self) == Borrow::<CaseInsensitiveStr>::borrow(other)
     }
 }
 
 impl Eq for CaseInsensitiveString<'_> {}
 
+impl PartialOrd for CaseInsensitiveString<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CaseInsensitiveString<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        Borrow::<CaseInsensitiveStr>::borrow(self).cmp(Borrow::<CaseInsensitiveStr>::borrow(other))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -95,10 +204,140 @@ mod tests {
         assert_eq!(a, b);
     }
 
+    #[test]
+    fn borrowed_lookup() {
+        use std::collections::HashMap;
+
+        let mut map = HashMap::new();
+        map.insert(CaseInsensitiveString::from("Rust".to_owned()), 1);
+        assert_eq!(map.get(CaseInsensitiveStr::new("RUST")), Some(&1));
+        assert_eq!(map.get(CaseInsensitiveStr::new("rusty")), None);
+    }
+
     #[test]
     fn differs() {
         let a: CaseInsensitiveString<'_> = "this1".into();
         let b: CaseInsensitiveString<'_> = "this".into();
         assert_ne!(a, b);
     }
+
+    #[test]
+    fn ordering() {
+        let cis = CaseInsensitiveString::from;
+        assert!(cis("abc") < cis("abd"));
+        assert!(cis("abd") > cis("abc"));
+        assert!(cis("ABC") < cis("abd"));
+        assert!(cis("abc") < cis("ABD"));
+        assert!(cis("abc") < cis("abcd"));
+        assert_eq!(cis("aBc").cmp(&cis("AbC")), Ordering::Equal);
+        assert!(cis("Zeta") > cis("alpha"));
+    }
+
+    #[test]
+    fn total_order_consistent_with_eq() {
+        let words = [
+            "beta", "Alpha", "_under", "alpha", "Zeta", "b", "ALPHA", "beta2", "", "Beta", "zeta",
+            "a",
+        ];
+        let mut sorted: Vec<_> = [7, 2, 10, 0, 5, 11, 3, 9, 1, 6, 4, 8]
+            .iter()
+            .map(|&i| CaseInsensitiveString::from(words[i]))
+            .collect();
+        sorted.sort();
+        for pair in sorted.windows(2) {
+            assert!(pair[0] <= pair[1], "{pair:?}");
+            assert_eq!(pair[0] == pair[1], pair[0].cmp(&pair[1]) == Ordering::Equal);
+        }
+        for a in &sorted {
+            for b in &sorted {
+                assert_eq!(a == b, a.cmp(b) == Ordering::Equal, "{a:?} {b:?}");
+                assert_eq!(a.cmp(b), b.cmp(a).reverse(), "{a:?} {b:?}");
+            }
+        }
+        let lower: Vec<_> = sorted.iter().map(|s| s.to_ascii_lowercase()).collect();
+        assert_eq!(
+            lower,
+            [
+                "", "_under", "a", "alpha", "alpha", "alpha", "b", "beta", "beta", "beta2", "zeta",
+                "zeta"
+            ]
+        );
+    }
+
+    fn hash_of(s: &str) -> u64 {
+        use std::{collections::hash_map::DefaultHasher, hash::BuildHasher};
+
+        std::hash::BuildHasherDefault::<DefaultHasher>::default()
+            .hash_one(CaseInsensitiveStr::new(s))
+    }
+
+    #[test]
+    fn unicode_case_folding() {
+        let pairs = [
+            ("Straße", "STRASSE"),
+            ("straße", "STRAẞE"),
+            ("ΟΔΟΣ", "οδος"),
+            ("οδοσ", "ΟΔΟΣ"),
+            ("Москва", "МОСКВА"),
+            ("ёлка", "ЁЛКА"),
+            ("Rust", "rust"),
+        ];
+        for (a, b) in pairs {
+            let (ca, cb) = (
+                CaseInsensitiveString::from(a),
+                CaseInsensitiveString::from(b),
+            );
+            assert_eq!(ca, cb, "{a} {b}");
+            assert_eq!(ca.cmp(&cb), Ordering::Equal, "{a} {b}");
+            assert_eq!(hash_of(a), hash_of(b), "{a} {b}");
+        }
+        assert_ne!(CaseInsensitiveString::from("Straße"), "Strase".into());
+        assert_ne!(CaseInsensitiveString::from("Москва"), "Moskva".into());
+        assert!(CaseInsensitiveString::from("ΑΛΦΑ") < "βήτα".into());
+        assert!(CaseInsensitiveString::from("Zeta") < "Ωμέγα".into());
+        // ASCII and non-ASCII strings hash alike when they fold alike
+        assert_eq!(hash_of("strasse"), hash_of("STRAßE"));
+        assert_eq!(hash_of("kelvin"), hash_of("\u{212a}elvin"));
+    }
+
+    /// Xorshift generator, enough to draw test strings reproducibly
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        /// String of up to 6 characters, often folding to those of other strings
+        fn string(&mut self) -> String {
+            const PIECES: [&str; 16] = [
+                "a", "A", "s", "S", "ss", "ß", "ẞ", "σ", "Σ", "ς", "ё", "Ё", "k", "\u{212a}", "_",
+                "é",
+            ];
+            (0..self.below(7))
+                .map(|_| PIECES[self.below(PIECES.len())])
+                .collect()
+        }
+    }
+
+    #[test]
+    fn equal_strings_hash_and_order_alike() {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        let mut equal_pairs = 0;
+        for _ in 0..20_000 {
+            let (a, b) = (random.string(), random.string());
+            let (ca, cb) = (CaseInsensitiveStr::new(&a), CaseInsensitiveStr::new(&b));
+            assert_eq!(ca == cb, ca.cmp(cb) == Ordering::Equal, "{a:?} {b:?}");
+            assert_eq!(ca.cmp(cb), cb.cmp(ca).reverse(), "{a:?} {b:?}");
+            if ca == cb {
+                assert_eq!(hash_of(&a), hash_of(&b), "{a:?} {b:?}");
+                equal_pairs += 1;
+            }
+        }
+        // The pieces fold to few characters, so many pairs differ only by case
+        assert!(equal_pairs > 100, "{equal_pairs}");
+    }
 }
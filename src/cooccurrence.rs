@@ -0,0 +1,71 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use crate::{CaseInsensitiveStr, Index};
+
+impl Index {
+    /// Returns the `n` terms found in the most documents alongside the word `term`, analyzed
+    /// like queries, with the number of such documents. Ties are broken alphabetically. Empty if
+    /// `term` is not a single term, such as a stop word.
+    pub fn cooccurrence<'a>(&'a self, term: &str, n: usize) -> Vec<(Cow<'a, str>, usize)> {
+        let Some(term) = self.analyzer.word_term(term) else {
+            return Vec::new();
+        };
+        let term = CaseInsensitiveStr::new(&term);
+        let mut counts: HashMap<&CaseInsensitiveStr, usize> = HashMap::new();
+        for document in self.documents.values() {
+            if !document.term_frequency.contains_key(term) {
+                continue;
+            }
+            for other in document.term_frequency.keys() {
+                let other = CaseInsensitiveStr::new(other);
+                if other != term {
+                    *counts.entry(other).or_default() += 1;
+                }
+            }
+        }
+
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by(|(t1, c1), (t2, c2)| c2.cmp(c1).then_with(|| t1.cmp(t2)));
+        counts.truncate(n);
+        counts
+            .into_iter()
+            .map(|(t, c)| (self.analyzer.word(t), c))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil::TempDir, Analyzer, IndexOptions, PorterStemmer};
+
+    #[test]
+    fn most_frequent_neighbours_first() {
+        let dir = TempDir::new("cooccurrence");
+        dir.write("a.txt", "rust cargo borrow");
+        dir.write("b.txt", "Rust cargo clippy");
+        dir.write("c.txt", "rust Cargo borrow");
+        dir.write("d.txt", "rust unsafe");
+        dir.write("e.txt", "python pip clippy clippy");
+
+        let index = Index::new(dir.path());
+        let terms = index.cooccurrence("rust", 3);
+        assert_eq!(terms.len(), 3);
+        assert!(terms[0].0.eq_ignore_ascii_case("cargo"));
+        assert_eq!(terms[0].1, 3);
+        assert_eq!(terms[1], (Cow::Borrowed("borrow"), 2));
+        assert_eq!(terms[2], (Cow::Borrowed("clippy"), 1));
+        assert_eq!(index.cooccurrence("rust", 10).len(), 4);
+        assert!(index.cooccurrence("missing", 10).is_empty());
+
+        let options = IndexOptions {
+            analyzer: Analyzer::default().with_stemmer(PorterStemmer),
+            ..Default::default()
+        };
+        let stemmed = Index::new_with_options(dir.path(), options);
+        assert!(stemmed
+            .cooccurrence("borrowing", 10)
+            .contains(&(Cow::from("rust"), 2)));
+        assert!(stemmed.cooccurrence("rust cargo", 10).is_empty());
+    }
+}
@@ -0,0 +1,373 @@
+//! Append-only index storage.
+//!
+//! A delta log starts with a snapshot of an index, followed by updates (documents added or
+//! removed) appended as they happen, so an update never rewrites the whole file. Loading replays
+//! the log, and compaction rewrites it as a plain snapshot once updates pile up.
+//!
+//! Layout: the `MAGIC` bytes, then length-prefixed records (`u32` little endian length followed
+//! by the record). The first record holds the index settings, every following one an [`Update`].
+//! Records are encoded as JSON, so new fields follow the same compatibility rules as the JSON
+//! index: bincode, used by the binary format, needs the optional `binary` feature and cannot skip
+//! the fields missing from older records. Documents dominate the size of the log either way, and
+//! an update only ever encodes the documents it adds.
+
+use std::{
+    collections::HashMap,
+    fs::{rename, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{check_boost, Analyzer, Document, Index, IndexOptions};
+
+const MAGIC: &[u8; 8] = b"IDXDELT1";
+
+/// A change to the set of indexed documents
+#[derive(Debug, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
+pub enum Update {
+    /// Adds a document, replacing the one previously stored under the same path
+    Add {
+        path: PathBuf,
+        document: Document,
+    },
+    Remove {
+        path: PathBuf,
+    },
+    /// See [`Index::set_boost`]
+    SetBoost {
+        path: PathBuf,
+        boost: f64,
+    },
+}
+
+impl Update {
+    /// Fails for the updates [`Index::apply_update`] rejects
+    fn check(&self) -> io::Result<()> {
+        match self {
+            Self::SetBoost { boost, .. } => check_boost(*boost),
+            Self::Add { .. } | Self::Remove { .. } => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    analyzer: Analyzer,
+    #[serde(default)]
+    boosts: HashMap<PathBuf, f64>,
+    #[serde(default)]
+    options: IndexOptions,
+    #[serde(default)]
+    root: Option<PathBuf>,
+}
+
+fn write_record<W: Write, T: Serialize>(mut writer: W, record: &T) -> io::Result<()> {
+    let bytes = serde_json::to_vec(record).map_err(|err| io::Error::other(err.to_string()))?;
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "record too large"))?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(&bytes)
+}
+
+/// Reads the next record, `None` at the end of the log. A record cut short, as left by an
+/// interrupted append, is reported and treated as the end of the log.
+fn read_record<R: Read, T: DeserializeOwned>(mut reader: R) -> io::Result<Option<T>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut bytes = vec![0u8; u32::from_le_bytes(len) as usize];
+    match reader.read_exact(&mut bytes) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            log::warn!("ignoring truncated record at the end of the delta log");
+            return Ok(None);
+        }
+        Err(e) => return Err(e),
+    }
+    serde_json::from_slice(&bytes)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+}
+
+/// Length of the complete records of the delta log `file`, with its header, leaving out a record
+/// cut short at its end
+fn complete_len(file: &mut File) -> io::Result<u64> {
+    let total = file.metadata()?.len();
+    let mut magic = [0u8; MAGIC.len()];
+    file.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a delta log index",
+        ));
+    }
+    let mut end = MAGIC.len() as u64;
+    let mut len = [0u8; 4];
+    while end + len.len() as u64 <= total {
+        file.seek(SeekFrom::Start(end))?;
+        file.read_exact(&mut len)?;
+        let next = end + len.len() as u64 + u64::from(u32::from_le_bytes(len));
+        if next > total {
+            break;
+        }
+        end = next;
+    }
+    Ok(end)
+}
+
+impl Index {
+    /// Applies `update` to the in-memory index. Fails on invalid boosts, like
+    /// [`Index::set_boost`].
+    pub fn apply_update(&mut self, update: Update) -> io::Result<()> {
+        match update {
+            Update::Add { path, document } => {
+                self.insert_document(path, document);
+            }
+            Update::Remove { path } => {
+                self.remove_document(&path);
+            }
+            Update::SetBoost { path, boost } => self.set_boost(path, boost)?,
+        }
+        Ok(())
+    }
+
+    /// Writes the index as a delta log snapshot
+    pub fn save_delta<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        write_record(
+            &mut writer,
+            &Header {
+                analyzer: self.analyzer.clone(),
+                boosts: self.boosts.clone(),
+                options: self.options.clone(),
+                root: self.root.clone(),
+            },
+        )?;
+        for (path, document) in &self.documents {
+            write_record(&mut writer, &UpdateRef::Add { path, document })?;
+        }
+        writer.flush()
+    }
+
+    /// Loads an index from a delta log, replaying every update
+    pub fn load_delta<R: Read>(reader: R) -> io::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let mut magic = [0u8; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a delta log index",
+            ));
+        }
+        let header: Header = read_record(&mut reader)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "missing delta log header")
+        })?;
+
+        let mut index = Self {
+            documents: Default::default(),
+            analyzer: header.analyzer,
+            boosts: header.boosts,
+            options: header.options,
+            root: header.root,
+            postings: Default::default(),
+        };
+        index.check_boosts()?;
+        while let Some(update) = read_record::<_, Update>(&mut reader)? {
+            update
+                .check()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            index.apply_update(update)?;
+        }
+        Ok(index)
+    }
+
+    /// Applies `update` and appends it to the delta log at `log`, without rewriting the rest of
+    /// the file
+    ///
+    /// A record cut short at the end of the log, as left by an interrupted append, is dropped
+    /// first so the new record does not end up framed inside it. Fails without writing anything
+    /// on invalid boosts.
+    pub fn append_update(&mut self, log: impl AsRef<Path>, update: Update) -> io::Result<()> {
+        update.check()?;
+        let mut file = OpenOptions::new().read(true).write(true).open(log)?;
+        let end = complete_len(&mut file)?;
+        if end < file.metadata()?.len() {
+            log::warn!("dropping truncated record at the end of the delta log");
+            file.set_len(end)?;
+        }
+        file.seek(SeekFrom::Start(end))?;
+        let mut file = BufWriter::new(file);
+        write_record(&mut file, &update)?;
+        file.flush()?;
+        self.apply_update(update)
+    }
+
+    /// Replays the delta log at `log` and replaces it with a snapshot of the resulting index.
+    /// The new log is written next to the old one and renamed over it, so an interruption leaves
+    /// the original log intact.
+    pub fn compact_file(log: impl AsRef<Path>) -> io::Result<Self> {
+        let log = log.as_ref();
+        let index = Self::load_delta(File::open(log)?)?;
+
+        let mut tmp = log.as_os_str().to_owned();
+        tmp.push(".compact");
+        let tmp = PathBuf::from(tmp);
+        index.save_delta(File::create(&tmp)?)?;
+        rename(&tmp, log)?;
+        Ok(index)
+    }
+}
+
+/// Borrowed counterpart of [`Update`], to write a snapshot without cloning documents
+#[derive(Serialize)]
+enum UpdateRef<'a> {
+    Add {
+        path: &'a Path,
+        document: &'a Document,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{metadata, remove_file, write};
+
+    use super::*;
+    use crate::{testutil::TempDir, tokenizer::TextTokenizer, SourceKind};
+
+    #[test]
+    fn compacted_log_matches_rebuild() {
+        let corpus = TempDir::new("delta-corpus");
+        let a = corpus.write("a.txt", "rust tokenizer");
+        let b = corpus.write("b.txt", "xml parser");
+        corpus.write("c.txt", "binary format");
+
+        let store = TempDir::new("delta-store");
+        let log = store.path().join("index.delta");
+        let mut index = Index::new(corpus.path());
+        index.save_delta(File::create(&log).unwrap()).unwrap();
+        let snapshot_len = metadata(&log).unwrap().len();
+
+        let d = corpus.write("d.txt", "delta updates appended");
+        write(&a, "rust tokenizer rewritten").unwrap();
+        remove_file(&b).unwrap();
+        for path in [&d, &a] {
+            let document = Document::build(path, TextTokenizer::default())
+                .unwrap()
+                .with_kind(SourceKind::Text);
+            let update = Update::Add {
+                path: path.clone(),
+                document,
+            };
+            index.append_update(&log, update).unwrap();
+        }
+        index
+            .append_update(&log, Update::Remove { path: b.clone() })
+            .unwrap();
+        let boost = Update::SetBoost {
+            path: d.clone(),
+            boost: 2f64,
+        };
+        index.append_update(&log, boost).unwrap();
+        assert!(metadata(&log).unwrap().len() > snapshot_len);
+
+        let mut rebuilt = Index::new(corpus.path());
+        rebuilt.set_boost(&d, 2f64).unwrap();
+        assert_eq!(index, rebuilt);
+        assert_eq!(
+            Index::load_delta(File::open(&log).unwrap()).unwrap(),
+            rebuilt
+        );
+
+        let compacted = Index::compact_file(&log).unwrap();
+        assert_eq!(compacted, rebuilt);
+        assert_eq!(
+            Index::load_delta(File::open(&log).unwrap()).unwrap(),
+            rebuilt
+        );
+    }
+
+    #[test]
+    fn ignores_truncated_tail() {
+        let corpus = TempDir::new("delta-truncated");
+        corpus.write("a.txt", "rust");
+        let index = Index::new(corpus.path());
+
+        let mut bytes = Vec::new();
+        index.save_delta(&mut bytes).unwrap();
+        let complete = bytes.len();
+        write_record(
+            &mut bytes,
+            &Update::Remove {
+                path: "a.txt".into(),
+            },
+        )
+        .unwrap();
+        bytes.truncate(complete + 6);
+
+        assert_eq!(Index::load_delta(&bytes[..]).unwrap(), index);
+        assert!(Index::load_delta(&b"not a log"[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_boosts() {
+        let corpus = TempDir::new("delta-boosts");
+        let a = corpus.write("a.txt", "rust");
+        let mut index = Index::new(corpus.path());
+
+        let mut bytes = Vec::new();
+        index.save_delta(&mut bytes).unwrap();
+        let boost = || Update::SetBoost {
+            path: a.clone(),
+            boost: -1f64,
+        };
+        write_record(&mut bytes, &boost()).unwrap();
+        let err = Index::load_delta(&bytes[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let store = TempDir::new("delta-boosts-store");
+        let log = store.path().join("index.delta");
+        index.save_delta(File::create(&log).unwrap()).unwrap();
+        assert!(index.append_update(&log, boost()).is_err());
+        assert_eq!(Index::load_delta(File::open(&log).unwrap()).unwrap(), index);
+    }
+
+    #[test]
+    fn appends_after_truncated_tail() {
+        let corpus = TempDir::new("delta-append-truncated");
+        let a = corpus.write("a.txt", "rust");
+        let b = corpus.write("b.txt", "xml");
+        let mut index = Index::new(corpus.path());
+
+        let store = TempDir::new("delta-append-store");
+        let log = store.path().join("index.delta");
+        index.save_delta(File::create(&log).unwrap()).unwrap();
+        index
+            .append_update(&log, Update::Remove { path: a.clone() })
+            .unwrap();
+        let len = metadata(&log).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&log)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+
+        let mut index = Index::load_delta(File::open(&log).unwrap()).unwrap();
+        assert!(index.document(&a).is_some());
+        index
+            .append_update(&log, Update::Remove { path: b.clone() })
+            .unwrap();
+        let loaded = Index::load_delta(File::open(&log).unwrap()).unwrap();
+        assert_eq!(loaded, index);
+        assert!(loaded.document(&a).is_some());
+        assert!(loaded.document(&b).is_none());
+    }
+}
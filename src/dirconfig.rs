@@ -0,0 +1,225 @@
+//! Per-directory settings.
+//!
+//! A `.indexer.toml` file overrides the tokenizer settings of [`IndexOptions`] for the files of
+//! its directory and every subdirectory, a nested file overriding in turn the settings it sets:
+//!
+//! ```toml
+//! # text, xml, html, csv, markdown, json, code or pdf (with the `pdf` feature): used for every
+//! # file regardless of its extension
+//! tokenizer = "csv"
+//! csv_delimiter = ";"
+//! # the first row names the columns, and only the `name` and fourth columns are indexed
+//! csv_header = true
+//! csv_columns = ["name", 3]
+//! capture_titles = true
+//! min_term_frequency = 2
+//! ```
+
+use std::{fs::read_to_string, io, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    tokenizer::{Column, Delimiter},
+    IndexOptions,
+};
+
+/// Name of the per-directory settings file, never indexed itself
+pub const CONFIG_FILE: &str = ".indexer.toml";
+
+/// Built-in tokenizer, as selected by a settings file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TokenizerKind {
+    Text,
+    Xml,
+    Html,
+    Csv,
+    Markdown,
+    Json,
+    Code,
+    #[cfg(feature = "pdf")]
+    Pdf,
+}
+
+/// Content of a settings file, unset fields being inherited
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DirectoryConfig {
+    tokenizer: Option<TokenizerKind>,
+    csv_delimiter: Option<String>,
+    csv_header: Option<bool>,
+    csv_columns: Option<Vec<Column>>,
+    capture_titles: Option<bool>,
+    min_term_frequency: Option<usize>,
+}
+
+/// Settings in effect for the files of a directory
+#[derive(Debug, Clone)]
+pub struct DirectorySettings {
+    /// Tokenizer forced for every file, `None` picking it from the extension
+    pub(crate) tokenizer: Option<TokenizerKind>,
+    pub(crate) csv_delimiter: Delimiter,
+    pub(crate) csv_header: bool,
+    pub(crate) csv_columns: Option<Vec<Column>>,
+    pub(crate) capture_titles: bool,
+    pub(crate) min_term_frequency: usize,
+}
+
+impl From<&IndexOptions> for DirectorySettings {
+    fn from(options: &IndexOptions) -> Self {
+        Self {
+            tokenizer: None,
+            csv_delimiter: options.csv_delimiter,
+            csv_header: options.csv_header,
+            csv_columns: options.csv_columns.clone(),
+            capture_titles: options.capture_titles,
+            min_term_frequency: options.min_term_frequency,
+        }
+    }
+}
+
+impl DirectorySettings {
+    /// See [`IndexOptions::csv_delimiter`]
+    pub fn csv_delimiter(&self) -> Delimiter {
+        self.csv_delimiter
+    }
+
+    /// See [`IndexOptions::csv_header`]
+    pub fn csv_header(&self) -> bool {
+        self.csv_header
+    }
+
+    /// See [`IndexOptions::csv_columns`]
+    pub fn csv_columns(&self) -> Option<&[Column]> {
+        self.csv_columns.as_deref()
+    }
+
+    /// See [`IndexOptions::capture_titles`]
+    pub fn capture_titles(&self) -> bool {
+        self.capture_titles
+    }
+
+    /// Settings of the directory of the file at `path`, applying the settings files of all its
+    /// ancestors
+    pub(crate) fn for_file(path: &Path, options: &IndexOptions) -> Self {
+        Self::for_file_within(path, None, options)
+    }
+
+    /// Like [`DirectorySettings::for_file`], only applying the settings files of `top` and the
+    /// directories beneath it when `path` is beneath `top`, as when walking `top`
+    pub(crate) fn for_file_within(path: &Path, top: Option<&Path>, options: &IndexOptions) -> Self {
+        let within = top.filter(|top| path.starts_with(top));
+        let dirs: Vec<_> = path
+            .parent()
+            .into_iter()
+            .flat_map(Path::ancestors)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .take_while(|dir| within.is_none_or(|top| dir.starts_with(top)))
+            .collect();
+        dirs.into_iter()
+            .rev()
+            .fold(Self::from(options), |settings, dir| settings.enter(dir))
+    }
+
+    /// Settings of `dir`, whose parent directory uses `self`
+    pub(crate) fn enter(&self, dir: &Path) -> Self {
+        let path = dir.join(CONFIG_FILE);
+        match self.read(&path) {
+            Ok(Some(settings)) => {
+                log::info!("using settings from {path}", path = path.display());
+                settings
+            }
+            Ok(None) => self.clone(),
+            Err(e) => {
+                log::error!("ignoring {path}: {e}", path = path.display());
+                self.clone()
+            }
+        }
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Option<Self>> {
+        let content = match read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let config: DirectoryConfig = toml::from_str(&content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        let mut settings = self.clone();
+        if let Some(tokenizer) = config.tokenizer {
+            settings.tokenizer = Some(tokenizer);
+        }
+        if let Some(delimiter) = config.csv_delimiter {
+            settings.csv_delimiter = delimiter
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        if let Some(csv_header) = config.csv_header {
+            settings.csv_header = csv_header;
+        }
+        if let Some(csv_columns) = config.csv_columns {
+            settings.csv_columns = Some(csv_columns);
+        }
+        if let Some(capture_titles) = config.capture_titles {
+            settings.capture_titles = capture_titles;
+        }
+        if let Some(min_term_frequency) = config.min_term_frequency {
+            settings.min_term_frequency = min_term_frequency;
+        }
+        Ok(Some(settings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{testutil::TempDir, Index};
+
+    #[test]
+    fn settings_apply_to_subtree() {
+        let dir = TempDir::new("dirconfig");
+        let root_csv = dir.write("values.csv", "alpha;beta;alpha");
+        let root_log = dir.write("notes.log", "alpha;beta;alpha");
+        let root_readme = dir.write("readme.txt", "Heading\nbody");
+        dir.write(
+            "data/.indexer.toml",
+            "tokenizer = \"csv\"\ncsv_delimiter = \";\"\n",
+        );
+        let data_csv = dir.write("data/values.csv", "alpha;beta;alpha");
+        let data_log = dir.write("data/notes.log", "alpha;beta;alpha");
+        dir.write("data/sub/.indexer.toml", "min_term_frequency = 2\n");
+        let sub_csv = dir.write("data/sub/values.csv", "alpha;beta;alpha");
+        dir.write("docs/.indexer.toml", "capture_titles = true\n");
+        let docs_readme = dir.write("docs/nested/readme.txt", "Heading\nbody");
+        dir.write("broken/.indexer.toml", "tokenizer = \"docx\"\n");
+        let broken_csv = dir.write("broken/values.csv", "alpha;beta;alpha");
+        dir.write(
+            "people/.indexer.toml",
+            "csv_header = true\ncsv_columns = [\"name\", 2]\n",
+        );
+        let people_csv = dir.write("people/people.csv", "id,name,city,motto\n7,alice,paris,hi");
+
+        let index = Index::new(dir.path());
+        let document = |path| index.document(path).unwrap();
+        for path in [&root_csv, &root_log, &broken_csv] {
+            assert_eq!(document(path).count, 5, "{path:?}");
+        }
+        for path in [&data_csv, &data_log] {
+            assert_eq!(document(path).count, 3, "{path:?}");
+            assert!(document(path).contains("beta"));
+        }
+        assert_eq!(document(&sub_csv).count, 3);
+        assert!(!document(&sub_csv).contains("beta"));
+        assert!(document(&sub_csv).contains("alpha"));
+        assert_eq!(document(&people_csv).count, 2);
+        assert!(document(&people_csv).contains("alice"));
+        assert!(document(&people_csv).contains("paris"));
+        assert_eq!(document(&root_readme).title(), None);
+        assert_eq!(document(&docs_readme).title(), Some("Heading"));
+        assert!(index
+            .documents
+            .keys()
+            .all(|p| !p.ends_with(super::CONFIG_FILE)));
+    }
+}
@@ -0,0 +1,183 @@
+//! Files and directories left out of an index.
+//!
+//! Patterns of [`IndexOptions::exclude`] follow the `.gitignore` syntax and match paths relative
+//! to the indexed root: `target/` excludes every directory named `target`, `/build` only the one
+//! at the root, and `*.log` every log file. With [`IndexOptions::gitignore`], the `.gitignore`
+//! files of the tree apply too, each to its own directory and subdirectories, and `.git`
+//! directories are skipped. Excluded directories are not entered, so a later `!pattern` cannot
+//! bring back the files beneath them.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::IndexOptions;
+
+const GITIGNORE: &str = ".gitignore";
+
+/// Patterns along with the directory they are relative to
+#[derive(Debug)]
+struct Patterns {
+    dir: PathBuf,
+    gitignore: Gitignore,
+}
+
+impl Patterns {
+    /// Whether the patterns exclude (`Some(true)`) or explicitly keep (`Some(false)`) the entry
+    /// at `path`
+    fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.dir).ok()?;
+        let matched = self.gitignore.matched(relative, is_dir);
+        (!matched.is_none()).then(|| matched.is_ignore())
+    }
+}
+
+/// `.gitignore` files applying to a directory, outermost first
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Ignores(Vec<Arc<Patterns>>);
+
+/// Rules deciding which entries of a tree are skipped
+#[derive(Debug)]
+pub(crate) struct Exclusions {
+    patterns: Patterns,
+    gitignore: bool,
+}
+
+impl Exclusions {
+    /// Rules of `options` for the tree at `root`. Invalid patterns are passed to `on_error`, and
+    /// skipped.
+    pub fn new(
+        root: &Path,
+        options: &IndexOptions,
+        mut on_error: impl FnMut(PathBuf, io::Error),
+    ) -> Self {
+        let mut builder = GitignoreBuilder::new("");
+        for pattern in &options.exclude {
+            if let Err(e) = builder.add_line(None, pattern) {
+                on_error(root.to_path_buf(), invalid_input(e));
+            }
+        }
+        let gitignore = builder.build().unwrap_or_else(|e| {
+            on_error(root.to_path_buf(), invalid_input(e));
+            Gitignore::empty()
+        });
+        Self {
+            patterns: Patterns {
+                dir: root.to_path_buf(),
+                gitignore,
+            },
+            gitignore: options.gitignore,
+        }
+    }
+
+    /// `.gitignore` files applying to `dir`, whose parent directory uses `parent`
+    pub fn enter(
+        &self,
+        dir: &Path,
+        parent: &Ignores,
+        mut on_error: impl FnMut(PathBuf, io::Error),
+    ) -> Ignores {
+        let path = dir.join(GITIGNORE);
+        if !self.gitignore || !path.is_file() {
+            return parent.clone();
+        }
+        let (gitignore, error) = Gitignore::new(&path);
+        if let Some(e) = error {
+            on_error(path, invalid_input(e));
+        }
+        let mut ignores = parent.clone();
+        ignores.0.push(Arc::new(Patterns {
+            dir: dir.to_path_buf(),
+            gitignore,
+        }));
+        ignores
+    }
+
+    /// Whether the entry at `path`, in a directory using `ignores`, is skipped. The patterns of
+    /// [`IndexOptions::exclude`] take precedence over `.gitignore` files, and nested `.gitignore`
+    /// files over those of their ancestors.
+    pub fn excludes(&self, path: &Path, is_dir: bool, ignores: &Ignores) -> bool {
+        if self.gitignore && is_dir && path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        std::iter::once(&self.patterns)
+            .chain(ignores.0.iter().rev().map(|p| &**p))
+            .find_map(|p| p.matched(path, is_dir))
+            .unwrap_or(false)
+    }
+}
+
+fn invalid_input(e: ignore::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{testutil::TempDir, Index, IndexOptions};
+
+    fn indexed(dir: &TempDir, options: IndexOptions) -> BTreeSet<String> {
+        let index = Index::new_with_options(dir.path(), options);
+        index
+            .documents
+            .keys()
+            .map(|p| p.strip_prefix(dir.path()).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn patterns_skip_files_and_directories() {
+        let dir = TempDir::new("exclude");
+        dir.write("src/main.txt", "kept");
+        dir.write("target/debug/out.txt", "generated");
+        dir.write("nested/target/out.txt", "generated");
+        dir.write("build/out.txt", "generated");
+        dir.write("nested/build/kept.txt", "kept");
+        dir.write("config.json", r#"{"key": "value"}"#);
+        dir.write("nested/data.json", r#"{"key": "value"}"#);
+        dir.write("nested/keep.json", r#"{"key": "value"}"#);
+
+        let options = IndexOptions {
+            exclude: ["target/", "/build", "*.json", "!keep.json"]
+                .map(String::from)
+                .to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(
+            indexed(&dir, options),
+            BTreeSet::from(
+                ["src/main.txt", "nested/build/kept.txt", "nested/keep.json"].map(String::from)
+            )
+        );
+        assert_eq!(indexed(&dir, IndexOptions::default()).len(), 8);
+    }
+
+    #[test]
+    fn gitignore_files_apply_to_their_subtree() {
+        let dir = TempDir::new("gitignore");
+        dir.write(".gitignore", "*.log\n");
+        dir.write("a.txt", "kept");
+        dir.write("a.log", "ignored");
+        dir.write(".git/HEAD.txt", "ignored");
+        dir.write("sub/.gitignore", "!important.log\nscratch/\n");
+        dir.write("sub/important.log", "kept");
+        dir.write("sub/other.log", "ignored");
+        dir.write("sub/scratch/b.txt", "ignored");
+        dir.write("other/scratch/c.txt", "kept");
+
+        let options = IndexOptions {
+            gitignore: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            indexed(&dir, options),
+            BTreeSet::from(["a.txt", "sub/important.log", "other/scratch/c.txt"].map(String::from))
+        );
+        assert!(indexed(&dir, IndexOptions::default()).contains("sub/scratch/b.txt"));
+    }
+}
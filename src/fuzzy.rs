@@ -0,0 +1,140 @@
+//! Matching of misspelled query terms.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use crate::{
+    case_insensitive_string::fold, parse_query, CaseInsensitiveStr, Index, QueryLeaf, SearchOptions,
+};
+
+impl Index {
+    /// Like [`Index::search`], but query terms found in no document are replaced by the indexed
+    /// terms within `max_distance` edits (insertions, deletions or substitutions of a character,
+    /// ignoring case), so that `tokeniser` matches `tokenizer`. A term `d` edits away weighs
+    /// `1 / (d + 1)` as much as the exact term would. Operators apply as in [`Index::search`],
+    /// misspelled terms standing for their close terms, but negated terms and phrases are
+    /// matched as written.
+    ///
+    /// Each misspelled term is compared with every indexed term, which is linear in the size of
+    /// the vocabulary.
+    pub fn search_fuzzy<'a>(&'a self, query: &'_ str, max_distance: u8) -> Vec<(&'a Path, f64)> {
+        let options = SearchOptions::default();
+        let (terms, filter) =
+            parse_query(query, &self.analyzer, &options, &self.vocabulary()).unwrap_or_default();
+        let mut vocabulary: Option<HashSet<&CaseInsensitiveStr>> = None;
+        let mut weighted = Vec::with_capacity(terms.len());
+        // Close terms of each misspelled term, which stand for it in the filter too
+        let mut corrections: HashMap<String, Vec<String>> = HashMap::new();
+        for (term, boost) in terms {
+            if max_distance == 0 || self.document_frequency(&term) > 0 {
+                let idf = self.idf(&term, options.idf);
+                weighted.push((term, idf * boost));
+                continue;
+            }
+            let vocabulary = vocabulary.get_or_insert_with(|| {
+                self.documents
+                    .values()
+                    .flat_map(|d| d.term_frequency.keys())
+                    .map(|t| CaseInsensitiveStr::new(t))
+                    .collect()
+            });
+            for &candidate in vocabulary.iter() {
+                if let Some(d) = distance(&term, candidate, max_distance.into()) {
+                    let weight = self.idf(candidate, options.idf) * boost / (d as f64 + 1f64);
+                    weighted.push((Cow::Borrowed(&**candidate), weight));
+                    corrections
+                        .entry(term.to_string())
+                        .or_default()
+                        .push(candidate.to_string());
+                }
+            }
+        }
+        // Phrases are matched exactly
+        let filter = filter.and_then(|f| {
+            f.filter_map(&mut |leaf: QueryLeaf| {
+                if leaf.phrase {
+                    return Some(leaf);
+                }
+                let terms = leaf
+                    .terms
+                    .into_iter()
+                    .flat_map(|t| {
+                        let close = corrections.get(&*t).into_iter().flatten();
+                        let close: Vec<_> = close.map(|c| Cow::Owned(c.clone())).collect();
+                        std::iter::once(t).chain(close)
+                    })
+                    .collect();
+                Some(QueryLeaf { terms, ..leaf })
+            })
+        });
+        self.rank(&weighted, filter.as_ref(), &options)
+    }
+}
+
+/// Levenshtein distance between `a` and `b` ignoring case, if at most `max`
+fn distance(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<_> = a.chars().flat_map(fold).collect();
+    let b: Vec<_> = b.chars().flat_map(fold).collect();
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        if current.iter().all(|&d| d > max) {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    Some(previous[b.len()]).filter(|&d| d <= max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(distance("tokeniser", "Tokenizer", 2), Some(1));
+        assert_eq!(distance("kitten", "sitting", 3), Some(3));
+        assert_eq!(distance("kitten", "sitting", 2), None);
+        assert_eq!(distance("rust", "rust", 0), Some(0));
+        assert_eq!(distance("", "ab", 2), Some(2));
+        assert_eq!(distance("a", "abcd", 2), None);
+    }
+
+    #[test]
+    fn misspelled_terms_match_close_terms() {
+        let dir = TempDir::new("fuzzy");
+        let tokenizer = dir.write("tokenizer.txt", "tokenizer lexer");
+        dir.write("parser.txt", "parser");
+        for i in 0..6 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let index = Index::new(dir.path());
+
+        assert!(index.search("tokeniser").is_empty());
+        assert!(index.search_fuzzy("tokeniser", 0).is_empty());
+        let results = index.search_fuzzy("tokeniser", 1);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, tokenizer);
+        assert!(results[0].1 < index.search("tokenizer")[0].1);
+        assert_eq!(index.search_fuzzy("lexer", 2), index.search("lexer"));
+        assert_eq!(index.search_fuzzy("pasrer", 2).len(), 1);
+        assert!(index.search_fuzzy("tokeniser -lexer", 1).is_empty());
+        assert!(index.search_fuzzy("tokeniser AND pasrer", 2).is_empty());
+        let both = index.search_fuzzy("tokeniser AND lexr", 1);
+        assert_eq!(both.len(), 1);
+        assert_eq!(both[0].0, tokenizer);
+        assert_eq!(index.search_fuzzy("pasrer -tokeniser", 2).len(), 1);
+    }
+}
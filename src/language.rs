@@ -0,0 +1,158 @@
+//! Detection of the language of documents, see [`IndexOptions::detect_language`].
+//!
+//! [`IndexOptions::detect_language`]: crate::IndexOptions::detect_language
+
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    fmt,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Analyzer, CaseInsensitiveStr, CaseInsensitiveString, FrenchStemmer, PorterStemmer, Stemmer,
+};
+
+/// Number of bytes at the start of a file the language of the document is detected from
+pub const LANGUAGE_SAMPLE: u64 = 16 * 1024;
+
+/// Language with a built-in stemmer and list of stop words
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    /// Stemmed with [`PorterStemmer`], see [`Analyzer::english_stop_words`]
+    English,
+    /// Stemmed with [`FrenchStemmer`], see [`Analyzer::french_stop_words`]
+    French,
+}
+
+impl Language {
+    /// Language of `text`, `None` if it is not a supported one or the guess is not reliable,
+    /// which is typical of short or mixed-language texts
+    pub fn detect(text: &str) -> Option<Self> {
+        let info = whatlang::detect(text).filter(|info| info.is_reliable())?;
+        match info.lang() {
+            whatlang::Lang::Eng => Some(Self::English),
+            whatlang::Lang::Fra => Some(Self::French),
+            _ => None,
+        }
+    }
+
+    /// Language of the first [`LANGUAGE_SAMPLE`] bytes of the file at `path`
+    pub(crate) fn detect_file(path: &Path) -> io::Result<Option<Self>> {
+        let mut sample = Vec::new();
+        File::open(path)?
+            .take(LANGUAGE_SAMPLE)
+            .read_to_end(&mut sample)?;
+        Ok(Self::detect(&String::from_utf8_lossy(&sample)))
+    }
+
+    pub(crate) fn stem(self, word: &str) -> Cow<'_, str> {
+        match self {
+            Self::English => PorterStemmer.stem(word),
+            Self::French => FrenchStemmer.stem(word),
+        }
+    }
+
+    pub(crate) fn is_stop_word(self, word: &str) -> bool {
+        static ENGLISH: OnceLock<BTreeSet<CaseInsensitiveString<'static>>> = OnceLock::new();
+        static FRENCH: OnceLock<BTreeSet<CaseInsensitiveString<'static>>> = OnceLock::new();
+        let stop_words = match self {
+            Self::English => {
+                ENGLISH.get_or_init(|| Analyzer::english_stop_words().into_iter().collect())
+            }
+            Self::French => {
+                FRENCH.get_or_init(|| Analyzer::french_stop_words().into_iter().collect())
+            }
+        };
+        stop_words.contains(CaseInsensitiveStr::new(word))
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::English => "english",
+            Self::French => "french",
+        })
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "english" | "en" => Ok(Self::English),
+            "french" | "fr" => Ok(Self::French),
+            _ => Err(format!("unknown language {s:?}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil::TempDir, Index, IndexOptions, SearchOptions};
+
+    const FRENCH: &str = "Les chevaux galopaient dans la prairie pendant que les enfants \
+        regardaient le coucher du soleil depuis la colline.";
+    const ENGLISH: &str = "The horses were galloping across the meadow while the children \
+        watched the sunset from the top of the hill.";
+
+    #[test]
+    fn detects_languages() {
+        assert_eq!(Language::detect(FRENCH), Some(Language::French));
+        assert_eq!(Language::detect(ENGLISH), Some(Language::English));
+        assert_eq!(Language::detect("ok"), None);
+        assert_eq!("fr".parse(), Ok(Language::French));
+        assert_eq!(Language::English.to_string(), "english");
+    }
+
+    #[test]
+    fn documents_are_stemmed_in_their_language() {
+        let dir = TempDir::new("language");
+        let french = dir.write("french.txt", FRENCH);
+        let english = dir.write("english.txt", ENGLISH);
+        let short = dir.write("short.txt", "galloping chevaux");
+        let options = IndexOptions {
+            detect_language: true,
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options);
+        let document = |path| index.document(path).unwrap();
+        assert_eq!(document(&french).language(), Some(Language::French));
+        assert_eq!(document(&english).language(), Some(Language::English));
+        assert_eq!(document(&short).language(), None);
+
+        assert!(document(&french).contains("cheval"));
+        assert!(!document(&french).contains("les"));
+        assert!(document(&english).contains("gallop"));
+        assert!(!document(&english).contains("the"));
+        assert!(document(&short).contains("galloping"));
+        assert!(document(&short).contains("chevaux"));
+
+        let hinted = |language| SearchOptions {
+            language: Some(language),
+            ..Default::default()
+        };
+        let paths = |results: Vec<(&Path, f64)>| -> Vec<_> {
+            results.into_iter().map(|(p, _)| p.to_owned()).collect()
+        };
+        assert_eq!(
+            paths(index.search_with_options("chevaux", &hinted(Language::French))),
+            [french]
+        );
+        assert_eq!(paths(index.search("chevaux")), [short]);
+        assert_eq!(
+            paths(index.search_with_options("galloped", &hinted(Language::English))),
+            [english]
+        );
+    }
+}
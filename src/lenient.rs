@@ -0,0 +1,237 @@
+use std::{collections::HashMap, fmt, io, path::PathBuf};
+
+use serde::{
+    de::{DeserializeSeed, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use serde_json::{Map, Value};
+
+use crate::{check_boost, Document, Index};
+
+/// A part of an index file that could not be loaded
+#[derive(Debug)]
+pub struct CorruptEntry {
+    /// Document the entry belonged to, `None` when the damage is not tied to a single document
+    /// (truncated file, syntax error, ...)
+    pub path: Option<PathBuf>,
+    pub error: String,
+}
+
+/// Fields of the index settings, see [`Index`]
+const SETTINGS: [&str; 4] = ["analyzer", "boosts", "options", "root"];
+
+#[derive(Default)]
+struct Recovered {
+    documents: HashMap<PathBuf, Document>,
+    fields: Map<String, Value>,
+    corrupt: Vec<CorruptEntry>,
+    /// Whether the settings were all read before the documents
+    settings_read: bool,
+}
+
+/// Loads every well-formed document of a JSON index. A document whose entry does not match the
+/// expected schema is skipped and reported. A syntax error (e.g. a truncated file) stops parsing:
+/// documents read up to that point are kept, the rest is reported as a single corrupt entry.
+///
+/// Settings are written before the documents, but files written by older versions have them
+/// after, or have none at all: settings which may have been cut off are reported as a corrupt
+/// entry, and default ones are used instead.
+pub(crate) fn load<R: io::Read>(reader: R) -> io::Result<(Index, Vec<CorruptEntry>)> {
+    let mut recovered = Recovered::default();
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    let result = IndexSeed(&mut recovered)
+        .deserialize(&mut de)
+        .and_then(|()| de.end());
+    if let Err(e) = result {
+        if e.is_io() {
+            return Err(e.into());
+        }
+        let lost: Vec<_> = SETTINGS
+            .into_iter()
+            .filter(|s| !recovered.fields.contains_key(*s))
+            .collect();
+        if !recovered.settings_read && !lost.is_empty() {
+            recovered.corrupt.push(CorruptEntry {
+                path: None,
+                error: format!("settings {lost} may be lost", lost = lost.join(", ")),
+            });
+        }
+        recovered.corrupt.push(CorruptEntry {
+            path: None,
+            error: e.to_string(),
+        });
+    }
+
+    let Recovered {
+        documents,
+        mut fields,
+        mut corrupt,
+        ..
+    } = recovered;
+    fields.insert("documents".into(), Value::Object(Map::new()));
+    // Posting lists may refer to skipped documents, they are built again from those recovered
+    fields.remove("inverted");
+    let mut index: Index = serde_json::from_value(Value::Object(fields))
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    index.documents = documents;
+    index
+        .boosts
+        .retain(|path, boost| match check_boost(*boost) {
+            Ok(()) => true,
+            Err(e) => {
+                corrupt.push(CorruptEntry {
+                    path: Some(path.clone()),
+                    error: e.to_string(),
+                });
+                false
+            }
+        });
+    Ok((index, corrupt))
+}
+
+struct IndexSeed<'a>(&'a mut Recovered);
+
+impl<'de> DeserializeSeed<'de> for IndexSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for IndexSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an index")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "documents" {
+                self.0.settings_read = self.0.fields.contains_key("analyzer");
+                map.next_value_seed(DocumentsSeed(self.0))?;
+            } else {
+                let value = map.next_value()?;
+                self.0.fields.insert(key, value);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct DocumentsSeed<'a>(&'a mut Recovered);
+
+impl<'de> DeserializeSeed<'de> for DocumentsSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DocumentsSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of documents")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(path) = map.next_key::<PathBuf>()? {
+            let value: Value = map.next_value()?;
+            match Document::deserialize(value) {
+                Ok(document) => {
+                    self.0.documents.insert(path, document);
+                }
+                Err(e) => self.0.corrupt.push(CorruptEntry {
+                    path: Some(path),
+                    error: e.to_string(),
+                }),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil::TempDir, Analyzer, IndexOptions, PorterStemmer};
+
+    const INDEX: &str = r#"{"documents":{
+        "a.txt":{"term_frequency":{"rust":2,"index":1},"count":3},
+        "b.txt":{"term_frequency":{"rust":"two"},"count":1},
+        "c.txt":{"term_frequency":{"xml":1},"count":1},
+        "d.txt":{"term_frequency":{"xml":1},"count":1}
+    }}"#;
+
+    #[test]
+    fn skips_malformed_document() {
+        assert!(Index::load(INDEX.as_bytes()).is_err());
+
+        let (index, corrupt) = Index::load_lenient(INDEX.as_bytes()).unwrap();
+        let mut paths: Vec<_> = index.documents.keys().cloned().collect();
+        paths.sort();
+        assert_eq!(paths, ["a.txt", "c.txt", "d.txt"].map(PathBuf::from));
+        assert_eq!(corrupt.len(), 1);
+        assert_eq!(corrupt[0].path.as_deref(), Some("b.txt".as_ref()));
+        assert_eq!(index.search("rust")[0].0, PathBuf::from("a.txt"));
+    }
+
+    #[test]
+    fn keeps_documents_before_truncation() {
+        // Files without settings, as written before there were any
+        let truncated = &INDEX[..INDEX.find("\"c.txt\"").unwrap() + 20];
+        let (index, corrupt) = Index::load_lenient(truncated.as_bytes()).unwrap();
+        assert_eq!(index.documents.len(), 1);
+        assert!(index.analyzer.is_standard());
+        assert_eq!(index.search("rust")[0].0, PathBuf::from("a.txt"));
+        assert_eq!(corrupt.len(), 3);
+        assert!(corrupt[1].error.contains("analyzer, boosts, options, root"));
+
+        let index = format!(r#"{{"analyzer":{{}},{}"#, &INDEX[1..]);
+        let truncated = &index[..index.find("\"c.txt\"").unwrap() + 20];
+        let (index, corrupt) = Index::load_lenient(truncated.as_bytes()).unwrap();
+        assert_eq!(index.documents.len(), 1);
+        assert!(index.documents.contains_key(&PathBuf::from("a.txt")));
+        assert_eq!(corrupt.len(), 2);
+        assert!(corrupt[1].path.is_none());
+    }
+
+    #[test]
+    fn keeps_settings_before_truncation() {
+        let dir = TempDir::new("lenient-settings");
+        let jogging = dir.write("jogging.txt", "running shoes");
+        for i in 0..4 {
+            dir.write(format!("other{i}.txt"), format!("other notes {i}"));
+        }
+        let options = IndexOptions {
+            analyzer: Analyzer::default().with_stemmer(PorterStemmer),
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options);
+        let mut saved = Vec::new();
+        index.save(&mut saved).unwrap();
+        let saved = String::from_utf8(saved).unwrap();
+
+        let other = saved.find("other3.txt").unwrap();
+        let (recovered, corrupt) = Index::load_lenient(&saved.as_bytes()[..other]).unwrap();
+        assert_eq!(corrupt.len(), 1);
+        assert_eq!(recovered.analyzer, index.analyzer);
+        assert_eq!(recovered.search("runs")[0].0, jogging);
+
+        // Older versions wrote the settings after the documents
+        let documents = saved.find(r#""documents""#).unwrap();
+        let settings = &saved[1..documents - 1];
+        let older = format!("{{{},{settings}}}", &saved[documents..saved.len() - 1]);
+        assert_eq!(Index::load(older.as_bytes()).unwrap(), index);
+        let cut = older.find("other3.txt").unwrap();
+        let (recovered, corrupt) = Index::load_lenient(&older.as_bytes()[..cut]).unwrap();
+        assert!(recovered.analyzer.is_standard());
+        assert!(corrupt[0].error.contains("analyzer, boosts, options, root"));
+        let cut = older.len() - 2;
+        let (_, corrupt) = Index::load_lenient(&older.as_bytes()[..cut]).unwrap();
+        assert!(corrupt[0].error.contains("settings boosts, options, root"));
+    }
+}
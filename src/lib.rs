@@ -1,29 +1,139 @@
 use std::{
-    collections::{BTreeSet, HashMap},
-    fs::{metadata, read_dir, File},
+    borrow::Cow,
+    cell::Cell,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{metadata, read_dir, File, Metadata},
     io::{self, BufReader},
     path::{Path, PathBuf},
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+    time::{Instant, SystemTime},
 };
 
-use serde::{Deserialize, Serialize};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize, Serializer};
 
+mod analyzer;
+#[cfg(feature = "binary")]
+mod binary;
+mod bm25;
 mod case_insensitive_string;
+mod cooccurrence;
+mod delta;
+mod dirconfig;
+mod exclude;
+mod fuzzy;
+mod language;
+mod lenient;
+mod mapped;
+mod merge;
+mod multi;
+mod phrase;
+mod postings;
+pub mod query;
+mod registry;
+mod scoring;
+mod sharded;
+mod similar;
+mod snippet;
+mod stale;
+pub mod stemmer;
+mod stream;
+mod term_map;
+#[cfg(test)]
+mod testutil;
 pub mod tokenizer;
+mod update;
+mod vocabulary;
 
-pub use crate::case_insensitive_string::CaseInsensitiveString;
-use crate::tokenizer::{TextTokenizer, Tokenizer, XmlTokenizer};
+use crate::analyzer::{unmark, NgramWindow};
+pub use crate::analyzer::{Analyzer, NGRAM_SEPARATOR};
+pub use crate::case_insensitive_string::{CaseInsensitiveStr, CaseInsensitiveString};
+pub use crate::delta::Update;
+pub use crate::dirconfig::DirectorySettings;
+use crate::exclude::{Exclusions, Ignores};
+pub use crate::language::{Language, LANGUAGE_SAMPLE};
+pub use crate::lenient::CorruptEntry;
+pub use crate::mapped::MappedIndex;
+pub use crate::merge::MergePolicy;
+pub use crate::multi::MultiIndex;
+use crate::postings::Postings;
+use crate::query::QueryError;
+pub use crate::registry::{SourceKind, TokenizerFactory, TokenizerRegistry};
+pub use crate::scoring::{
+    DeadlineResults, DirectoryCap, Field, FieldMatch, IdfScheme, MatchMode, PositionBoost,
+    RankMode, SearchHit, SearchOptions, TermForms, TermLimitPolicy, TfScheme, AUTO_CUTOFF_MIN_DROP,
+    AUTO_CUTOFF_WINDOW, DEADLINE_CHECK_INTERVAL,
+};
+pub use crate::sharded::ShardedIndex;
+pub use crate::similar::SIMILARITY_TERMS;
+pub use crate::stale::{StalePolicy, Verification};
+pub use crate::stemmer::{FrenchStemmer, PorterStemmer, Stemmer, StemmerKind};
+pub use crate::stream::SearchStream;
+use crate::tokenizer::{Column, Delimiter, Lexer, LexerOptions, TermCollector, Tokenizer};
+pub use crate::update::UpdateStats;
+
+/// Identity of a directory, the same whichever path reaches it
+#[cfg(unix)]
+type DirectoryId = (u64, u64);
+#[cfg(not(unix))]
+type DirectoryId = PathBuf;
+
+#[cfg(unix)]
+fn directory_id(_path: &Path, metadata: &Metadata) -> io::Result<DirectoryId> {
+    use std::os::unix::fs::MetadataExt;
+    Ok((metadata.dev(), metadata.ino()))
+}
 
-fn traverse_tree(p: impl AsRef<Path>, mut callback: impl FnMut(PathBuf)) {
-    let mut inodes = BTreeSet::new();
+#[cfg(not(unix))]
+fn directory_id(path: &Path, _metadata: &Metadata) -> io::Result<DirectoryId> {
+    path.canonicalize()
+}
+
+/// Calls `callback` on every file beneath `p`, along with the state of its directory. The state
+/// of a directory is computed by `enter` from the state of its parent, `root` being the parent
+/// state of `p`. Directories nested more than [`IndexOptions::max_depth`] levels beneath `p`,
+/// and the entries excluded by `options` (see [`exclude`]), are skipped. Symbolic links are
+/// skipped, or followed with [`IndexOptions::follow_symlinks`], each directory being visited
+/// once however many paths lead to it so that links pointing back up the tree do not loop.
+/// Directories and entries that cannot be read are passed to `on_error`, and skipped.
+fn traverse_tree<S>(
+    p: impl AsRef<Path>,
+    root: S,
+    mut enter: impl FnMut(&Path, &S) -> S,
+    mut callback: impl FnMut(PathBuf, &S),
+    mut on_error: impl FnMut(PathBuf, io::Error),
+    options: &IndexOptions,
+) {
+    let exclusions = Exclusions::new(p.as_ref(), options, &mut on_error);
+    let mut visited = HashSet::new();
     let mut to_visit = Vec::new();
-    to_visit.push(p.as_ref().to_path_buf());
+    to_visit.push((
+        p.as_ref().to_path_buf(),
+        Arc::new(root),
+        0,
+        Ignores::default(),
+    ));
 
-    while let Some(p) = to_visit.pop() {
+    while let Some((p, parent, depth, ignores)) = to_visit.pop() {
+        match metadata(&p).and_then(|m| directory_id(&p, &m)) {
+            Ok(id) => {
+                if !visited.insert(id) {
+                    continue;
+                }
+            }
+            Err(e) => {
+                on_error(p, e);
+                continue;
+            }
+        }
+        let state = Arc::new(enter(&p, &parent));
+        let ignores = exclusions.enter(&p, &ignores, &mut on_error);
         let rd = match read_dir(&p) {
             Ok(p) => p,
             Err(e) => {
-                log::error!("cannot read {path}: {e}", path = p.display());
+                on_error(p, e);
                 continue;
             }
         };
@@ -31,166 +141,3429 @@ fn traverse_tree(p: impl AsRef<Path>, mut callback: impl FnMut(PathBuf)) {
             let entry = match entry {
                 Ok(e) => e,
                 Err(e) => {
-                    log::error!("cannot process entry: {e}");
+                    on_error(p.clone(), e);
                     continue;
                 }
             };
             let ft = match entry.file_type() {
-                Ok(ft) => ft,
-                Err(e) => {
-                    log::error!(
-                        "cannot get filetype for {path}: {e}",
+                Ok(ft) if ft.is_symlink() && !options.follow_symlinks => {
+                    log::info!(
+                        "skipping symbolic link {path}",
                         path = entry.path().display()
                     );
                     continue;
                 }
+                Ok(ft) if ft.is_symlink() => metadata(entry.path()).map(|m| m.file_type()),
+                ft => ft,
+            };
+            let ft = match ft {
+                Ok(ft) => ft,
+                Err(e) => {
+                    on_error(entry.path(), e);
+                    continue;
+                }
             };
 
+            let path = entry.path();
+            if exclusions.excludes(&path, ft.is_dir(), &ignores) {
+                continue;
+            }
             if ft.is_dir() {
-                let path = entry.path();
-                let was_not_present = inodes.insert(path.clone());
-                if was_not_present {
-                    to_visit.push(path);
+                if options.max_depth.is_none_or(|max| depth < max) {
+                    to_visit.push((path, Arc::clone(&state), depth + 1, ignores.clone()));
                 }
             } else if ft.is_file() {
-                callback(entry.path());
+                if entry.file_name() == dirconfig::CONFIG_FILE {
+                    continue;
+                }
+                callback(path, &state);
             }
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// File metadata of an indexed document, as recorded when it was indexed, see
+/// [`Index::search_filtered`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DocumentMeta<'a> {
+    pub path: &'a Path,
+    /// Modification time of the file, unknown if it could not be read
+    pub mtime: Option<SystemTime>,
+    /// Size of the file in bytes, unknown in indexes written before it was recorded
+    pub size: Option<u64>,
+}
+
+impl<'a> DocumentMeta<'a> {
+    fn new(path: &'a Path, document: &Document) -> Self {
+        Self {
+            path,
+            mtime: document.modified,
+            size: document.size,
+        }
+    }
+}
+
+/// Values attached to terms, sorted ignoring case so that they are saved in the same order
+/// whatever the order they were inserted in, and found by prefix with a range
+pub(crate) type TermMap<T> = BTreeMap<CaseInsensitiveString<'static>, T>;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Document {
-    term_frequency: HashMap<CaseInsensitiveString<'static>, usize>,
+    #[serde(deserialize_with = "term_map::deserialize")]
+    term_frequency: TermMap<usize>,
     count: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    /// Positions of each term, only recorded with [`IndexOptions::track_positions`]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "term_map::deserialize_optional"
+    )]
+    positions: Option<TermMap<Vec<usize>>>,
+    /// Words each term was derived from, only recorded with [`IndexOptions::keep_term_forms`]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "term_map::deserialize_optional"
+    )]
+    forms: Option<TermMap<Vec<String>>>,
+    /// Modification time of the file when it was indexed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<SystemTime>,
+    /// Size of the file in bytes when it was indexed, unknown in indexes written before it was
+    /// recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    /// Tokenizer the document was indexed with, unknown in indexes written before it was
+    /// recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kind: Option<SourceKind>,
+    /// Language detected with [`IndexOptions::detect_language`], if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<Language>,
+    /// Number of occurrences of the most frequent term, for [`TfScheme::Augmented`]
+    #[serde(skip)]
+    max_count: Cached<usize>,
+}
+
+/// Value derived from the rest of a [`Document`], computed when first needed and ignored by
+/// comparisons
+#[derive(Debug, Default)]
+pub(crate) struct Cached<T>(OnceLock<T>);
+
+impl<T> Cached<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self(OnceLock::from(value))
+    }
+}
+
+impl<T> PartialEq for Cached<T> {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
 }
 
 impl Document {
-    pub fn build<P: AsRef<Path>>(filename: P, mut tokenizer: impl Tokenizer) -> io::Result<Self> {
-        let mut file = BufReader::new(File::open(filename)?);
-        let mut term_frequency = HashMap::new();
+    pub fn build<P: AsRef<Path>>(filename: P, tokenizer: impl Tokenizer) -> io::Result<Self> {
+        Self::build_with(filename, tokenizer, TermCollector::default())
+    }
+
+    /// Like [`Document::build`], also recording the position of every term
+    pub fn build_with_positions<P: AsRef<Path>>(
+        filename: P,
+        tokenizer: impl Tokenizer,
+    ) -> io::Result<Self> {
+        Self::build_with(
+            filename,
+            tokenizer,
+            TermCollector::default().with_positions(),
+        )
+    }
 
-        let count = tokenizer.tokenize(&mut file, &mut term_frequency)?;
+    /// Like [`Document::build`], keeping whatever `terms` records besides term counts
+    pub fn build_with<P: AsRef<Path>>(
+        filename: P,
+        mut tokenizer: impl Tokenizer,
+        mut terms: TermCollector,
+    ) -> io::Result<Self> {
+        let file = File::open(filename)?;
+        let metadata = file.metadata().ok();
+        let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+        let size = metadata.map(|m| m.len());
+        let count = tokenizer.tokenize(&mut BufReader::new(file), &mut terms)?;
 
         Ok(Self {
-            term_frequency,
+            term_frequency: terms.term_frequency,
             count,
+            title: tokenizer.title().map(str::to_owned),
+            positions: terms.positions,
+            forms: terms.forms,
+            modified,
+            size,
+            kind: None,
+            language: None,
+            max_count: Cached::default(),
         })
     }
 
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Records the tokenizer the document was built with, unknown after [`Document::build`]
+    pub fn with_kind(mut self, kind: SourceKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    fn with_language(mut self, language: Option<Language>) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Language of the document, only detected with [`IndexOptions::detect_language`] and
+    /// `None` when it could not be told reliably
+    pub fn language(&self) -> Option<Language> {
+        self.language
+    }
+
+    /// Modification time of the file when it was indexed
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// Size of the file in bytes when it was indexed
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Tokenizer the document was indexed with. Documents whose tokenizer is unknown, such as
+    /// those of indexes written before it was recorded, are deemed [`SourceKind::Text`].
+    pub fn kind(&self) -> SourceKind {
+        self.kind.unwrap_or_default()
+    }
+
+    fn title_contains(&self, term: &str, analyzer: &Analyzer) -> bool {
+        self.title
+            .as_deref()
+            .is_some_and(|title| title_contains(title, term, analyzer))
+    }
+
     pub fn term_frequency(&self, term: &str) -> f64 {
-        match self.term_frequency.get(&term.into()) {
-            Some(c) => *c as f64 / self.count as f64,
+        self.weighted_term_frequency(term, TfScheme::Proportional)
+    }
+
+    /// Term frequency weighted according to `scheme`
+    pub fn weighted_term_frequency(&self, term: &str, scheme: TfScheme) -> f64 {
+        match self.term_frequency.get(CaseInsensitiveStr::new(term)) {
+            Some(c) => scheme.weight(*c, self.count, self.max_count()),
             None => 0f64,
         }
     }
 
+    /// Number of occurrences of the most frequent term of the document
+    pub fn max_count(&self) -> usize {
+        *self
+            .max_count
+            .0
+            .get_or_init(|| self.term_frequency.values().copied().max().unwrap_or(0))
+    }
+
+    /// Whether the document contains the term `term`. Words of a case-sensitive index go
+    /// through [`Analyzer::key`] first.
     pub fn contains(&self, term: &str) -> bool {
-        self.term_frequency.contains_key(&term.into())
+        self.term_frequency
+            .contains_key(CaseInsensitiveStr::new(term))
+    }
+
+    /// Every term of this document along with the number of times it was found, in no
+    /// particular order
+    pub fn terms(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.term_frequency.iter().map(|(t, c)| (&**t, *c))
+    }
+
+    /// Number of tokens of this document, the sum of the counts of [`Document::terms`] unless
+    /// terms were dropped by [`IndexOptions::min_term_frequency`] or added by
+    /// [`IndexOptions::index_file_type`]
+    pub fn token_count(&self) -> usize {
+        self.count
+    }
+
+    /// Positions of `term` in this document, empty when positions were not recorded
+    fn positions(&self, term: &str) -> &[usize] {
+        self.positions
+            .as_ref()
+            .and_then(|p| p.get(CaseInsensitiveStr::new(term)))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether this document matches the boolean expression of a query
+    fn matches(&self, filter: &QueryFilter<'_>) -> bool {
+        filter.matches(&|leaf| self.matches_leaf(leaf))
+    }
+
+    /// Whether this document contains the terms of a word or phrase of a query
+    fn matches_leaf(&self, leaf: &QueryLeaf<'_>) -> bool {
+        match (leaf.phrase, &self.positions) {
+            (true, Some(_)) => self.phrase_count(&leaf.terms) > 0,
+            (true, None) => leaf.terms.iter().all(|t| self.contains(t)),
+            (false, _) => leaf.terms.iter().any(|t| self.contains(t)),
+        }
+    }
+
+    /// Number of `terms` found in this document
+    fn coverage(&self, terms: &[&str]) -> usize {
+        terms.iter().filter(|t| self.contains(t)).count()
+    }
+
+    /// Words of this document matched by each of the query `terms` found in it, the terms of
+    /// `analyzer`
+    fn term_forms(&self, terms: &[(Cow<'_, str>, f64)], analyzer: &Analyzer) -> Vec<TermForms> {
+        let mut matched: Vec<TermForms> = Vec::new();
+        for (t, _) in terms {
+            let term = analyzer.word(t);
+            if matched.iter().any(|m| m.term == term) {
+                continue;
+            }
+            let forms = self.forms(t);
+            if !forms.is_empty() {
+                matched.push(TermForms {
+                    term: term.into_owned(),
+                    forms: forms.into_iter().map(str::to_owned).collect(),
+                });
+            }
+        }
+        matched
+    }
+
+    /// Part of the score of this document due to the query term `term` of `analyzer` of idf
+    /// `idf`, before the document boost
+    fn term_score(
+        &self,
+        term: &str,
+        idf: f64,
+        options: &SearchOptions,
+        analyzer: &Analyzer,
+    ) -> f64 {
+        let tf = self.weighted_term_frequency(term, options.tf);
+        let boost = if self.title_contains(term, analyzer) {
+            options.title_boost
+        } else {
+            1f64
+        };
+        let position_boost = options
+            .position_boost
+            .map_or(1f64, |b| b.factor(self.positions(term)));
+        tf * idf * boost * position_boost
+    }
+
+    /// Contribution of each of the query `terms` of `analyzer` found in this document to its
+    /// score, with the document `boost`, in query order
+    fn contributions(
+        &self,
+        terms: &[(Cow<'_, str>, f64)],
+        options: &SearchOptions,
+        analyzer: &Analyzer,
+        boost: f64,
+    ) -> Vec<(String, f64)> {
+        let mut contributions: Vec<(String, f64)> = Vec::new();
+        for (t, idf) in terms {
+            let score = boost * self.term_score(t, *idf, options, analyzer);
+            if score == 0f64 {
+                continue;
+            }
+            let t = analyzer.word(t);
+            match contributions.iter_mut().find(|(term, _)| *term == t) {
+                Some((_, total)) => *total += score,
+                None => contributions.push((t.into_owned(), score)),
+            }
+        }
+        contributions
+    }
+
+    /// Groups the query `terms` of `analyzer` found in this document by field
+    fn field_matches(
+        &self,
+        terms: &[(Cow<'_, str>, f64)],
+        options: &SearchOptions,
+        analyzer: &Analyzer,
+    ) -> Vec<FieldMatch> {
+        let mut title: Vec<String> = Vec::new();
+        let mut body: Vec<String> = Vec::new();
+        for (t, _) in terms {
+            let word = analyzer.word(t);
+            if self.title_contains(t, analyzer) && !title.iter().any(|x| *x == word) {
+                title.push(word.to_string());
+            }
+            if self.contains(t) && !body.iter().any(|x| *x == word) {
+                body.push(word.to_string());
+            }
+        }
+
+        let mut fields = Vec::new();
+        if !title.is_empty() {
+            fields.push(FieldMatch {
+                field: Field::Title,
+                boost: options.title_boost,
+                terms: title,
+            });
+        }
+        if !body.is_empty() {
+            fields.push(FieldMatch {
+                field: Field::Body,
+                boost: 1f64,
+                terms: body,
+            });
+        }
+        fields
+    }
+
+    /// Adds the `extension:` and `mime:` terms of `path`. They do not count in the document
+    /// length.
+    fn add_file_type_terms(&mut self, path: &Path) {
+        let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+            return;
+        };
+        let mut terms = vec![query::field_term("extension", extension)];
+        if let Some(mime) = mime_type(extension) {
+            terms.push(query::field_term("mime", mime));
+        }
+        for term in terms {
+            *self.term_frequency.entry(term.into()).or_default() += 1;
+        }
+        self.max_count = Cached::default();
+    }
+
+    /// Adds the words of each component of `path`, split at punctuation, underscores and case
+    /// changes and analyzed by `analyzer`: `src/authHandler.rs` adds `src`, `auth`, `handler`
+    /// and `rs`. Unlike the file type terms, they count in the document length.
+    fn add_path_terms(&mut self, path: &Path, analyzer: &Analyzer) {
+        let path = path.to_string_lossy();
+        let options = LexerOptions {
+            split_identifiers: true,
+        };
+        let mut terms = Vec::new();
+        for word in Lexer::new_with_options(&path, options) {
+            if word.chars().any(char::is_alphanumeric) {
+                analyzer.analyze(word, |term| terms.push(term.into_owned()));
+            }
+        }
+        self.count += terms.len();
+        for term in terms {
+            *self.term_frequency.entry(term.into()).or_default() += 1;
+        }
+        self.max_count = Cached::default();
+    }
+
+    /// Keeps only the terms for which `f(term, count)` returns `true`. The document length is
+    /// left untouched.
+    fn retain_terms(&mut self, mut f: impl FnMut(&str, usize) -> bool) {
+        self.term_frequency.retain(|t, c| f(t, *c));
+        self.max_count = Cached::default();
+        if let Some(ref mut positions) = self.positions {
+            positions.retain(|t, _| self.term_frequency.contains_key(t));
+        }
+        if let Some(ref mut forms) = self.forms {
+            forms.retain(|t, _| self.term_frequency.contains_key(t));
+        }
+    }
+
+    /// Words of the document `term` was derived from. Without
+    /// [`IndexOptions::keep_term_forms`], only the spelling the term was first seen with is known.
+    pub fn forms(&self, term: &str) -> Vec<&str> {
+        let term = CaseInsensitiveStr::new(term);
+        match self.forms {
+            Some(ref forms) => forms
+                .get(term)
+                .map(|f| f.iter().map(String::as_str).collect())
+                .unwrap_or_default(),
+            None => self
+                .term_frequency
+                .get_key_value(term)
+                .map(|(t, _)| vec![&**t])
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// MIME type of files with the given extension
+fn mime_type(extension: &str) -> Option<&'static str> {
+    Some(match extension.to_ascii_lowercase().as_str() {
+        "txt" | "text" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "rs" => "text/x-rust",
+        "csv" => "text/csv",
+        "tsv" => "text/tab-separated-values",
+        "html" | "htm" => "text/html",
+        "xml" => "application/xml",
+        "xhtml" => "application/xhtml+xml",
+        "json" => "application/json",
+        "pdf" => "application/pdf",
+        _ => return None,
+    })
+}
+
+/// Fails for document boosts that are negative, infinite or NaN, see [`Index::set_boost`]
+fn check_boost(factor: f64) -> io::Result<()> {
+    if factor.is_finite() && factor >= 0f64 {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid boost {factor}"),
+        ))
+    }
+}
+
+/// Whether the term `term` of `analyzer` is one of the words of `title`
+fn title_contains(title: &str, term: &str, analyzer: &Analyzer) -> bool {
+    let term = CaseInsensitiveStr::new(term);
+    tokenizer::Lexer::new(title).any(|w| CaseInsensitiveStr::new(&analyzer.key(w)) == term)
+}
+
+/// Terms of a word or phrase of a query
+struct QueryLeaf<'q> {
+    terms: Vec<Cow<'q, str>>,
+    /// Whether documents must contain the terms consecutively to match, rather than any of
+    /// them. Documents without positions only need to contain all of them.
+    phrase: bool,
+    /// Multiplier of the contribution of the terms to scores
+    boost: f64,
+}
+
+/// Boolean expression documents must match
+type QueryFilter<'q> = query::Node<QueryLeaf<'q>>;
+
+/// Term of a query with its boost
+type BoostedTerm<'q> = (Cow<'q, str>, f64);
+
+/// Indexed terms starting with a prefix, to expand the prefixes of queries with
+type Vocabulary<'v> = &'v dyn Fn(&str) -> Vec<String>;
+
+/// Expansion of prefixes for searches without a vocabulary: a prefix only matches itself
+fn no_vocabulary(prefix: &str) -> Vec<String> {
+    vec![prefix.to_owned()]
+}
+
+/// Analyzes `query` into the terms scoring documents, enforcing the term limit of `options`,
+/// and the boolean expression documents must match (see [`query::parse`]). Negated terms do not
+/// score documents. Each prefix is replaced by the terms of `vocabulary` starting with it, each
+/// of them counting toward the term limit. The n-grams of consecutive words go with the last
+/// one. Phrases only match documents where their words follow each other, when positions were
+/// recorded. Each term comes with its boost, 1 unless written like `rust^2`.
+fn parse_query<'q>(
+    query: &'q str,
+    index_analyzer: &Analyzer,
+    options: &SearchOptions,
+    vocabulary: Vocabulary,
+) -> Result<(Vec<BoostedTerm<'q>>, Option<QueryFilter<'q>>), QueryError> {
+    let analyzer = &*options.analyzer(index_analyzer);
+    if analyzer.is_case_sensitive() != index_analyzer.is_case_sensitive() {
+        return Err(QueryError::CaseSensitivityMismatch);
+    }
+    // Consecutive words of the query form n-grams, see `Analyzer::with_ngram_range`
+    let mut window = NgramWindow::default();
+    let filter = query::parse(query)?.and_then(|node| {
+        node.filter_map(&mut |query::Leaf { part, boost }| {
+            if !matches!(part, query::Part::Text(_)) {
+                window = NgramWindow::default();
+            }
+            let (terms, phrase) = match part {
+                query::Part::Text(text) => {
+                    let mut terms = Vec::new();
+                    analyzer.analyze_in_window(text, &mut window, |t, _, _| terms.push(t));
+                    (terms, false)
+                }
+                query::Part::Phrase(text) => (analyzer.phrase_terms(text), true),
+                query::Part::Field(term) => (vec![Cow::Borrowed(term)], false),
+                query::Part::Prefix(prefix) => {
+                    let terms = vocabulary(&analyzer.key(prefix));
+                    (terms.into_iter().map(Cow::Owned).collect(), false)
+                }
+            };
+            (!terms.is_empty()).then_some(QueryLeaf {
+                terms,
+                phrase,
+                boost,
+            })
+        })
+    });
+    let mut terms: Vec<_> = filter
+        .iter()
+        .flat_map(|f| f.positive_leaves())
+        .flat_map(|leaf| leaf.terms.iter().map(|t| (t.clone(), leaf.boost)))
+        .collect();
+    if let Some(max) = options.max_query_terms {
+        if terms.len() > max {
+            match options.term_limit_policy {
+                TermLimitPolicy::Truncate => terms.truncate(max),
+                TermLimitPolicy::Reject => {
+                    return Err(QueryError::TooManyTerms {
+                        count: terms.len(),
+                        max,
+                    })
+                }
+            }
+        }
+    }
+    Ok((terms, filter))
+}
+
+/// Analyzes `query` into the terms scoring documents, enforcing the term limit of `options`
+fn analyze_query<'q>(
+    query: &'q str,
+    index_analyzer: &Analyzer,
+    options: &SearchOptions,
+    vocabulary: Vocabulary,
+) -> Result<Vec<Cow<'q, str>>, QueryError> {
+    parse_query(query, index_analyzer, options, vocabulary)
+        .map(|(terms, _)| terms.into_iter().map(|(t, _)| t).collect())
+}
+
+/// Pairs each of `terms` with its idf
+fn weigh_terms<'q>(
+    terms: impl IntoIterator<Item = Cow<'q, str>>,
+    options: &SearchOptions,
+    idf: impl Fn(&str) -> f64,
+) -> Vec<(Cow<'q, str>, f64)> {
+    weigh_boosted_terms(terms.into_iter().map(|t| (t, 1f64)), options, idf)
+}
+
+/// Pairs each of `terms` with its idf times its boost
+fn weigh_boosted_terms<'q>(
+    terms: impl IntoIterator<Item = BoostedTerm<'q>>,
+    options: &SearchOptions,
+    idf: impl Fn(&str) -> f64,
+) -> Vec<(Cow<'q, str>, f64)> {
+    terms
+        .into_iter()
+        .map(|(t, boost)| (idf(&t), t, boost))
+        .filter(|(idf, _, _)| !options.drop_zero_idf || *idf > 0f64)
+        .map(|(idf, t, boost)| (t, idf * boost))
+        .collect()
+}
+
+/// Whether a document matches the query of `terms` and `filter`: the boolean expression of the
+/// query, and with [`MatchMode::All`] every term, or every positive leaf of the expression
+fn query_matcher<'q>(
+    terms: &'q [(Cow<'_, str>, f64)],
+    filter: Option<&'q QueryFilter<'_>>,
+    options: &SearchOptions,
+) -> impl Fn(&Document) -> bool + 'q {
+    let matcher = QueryMatcher::new(terms, filter, options);
+    move |d| matcher.matches(|l| d.matches_leaf(l), |t| d.contains(t))
+}
+
+/// What [`query_matcher`] checks, also for documents not held as [`Document`]s such as those of
+/// a [`MappedIndex`]
+struct QueryMatcher<'q> {
+    filter: Option<&'q QueryFilter<'q>>,
+    /// Leaves which must all match with [`MatchMode::All`]
+    leaves: Vec<&'q QueryLeaf<'q>>,
+    /// Terms which must all match with [`MatchMode::All`], for queries without a filter
+    distinct: Vec<&'q str>,
+    all: bool,
+}
+
+impl<'q> QueryMatcher<'q> {
+    fn new(
+        terms: &'q [(Cow<'_, str>, f64)],
+        filter: Option<&'q QueryFilter<'q>>,
+        options: &SearchOptions,
+    ) -> Self {
+        Self {
+            filter,
+            leaves: filter.map(|f| f.positive_leaves()).unwrap_or_default(),
+            distinct: distinct_terms(terms),
+            all: options.match_mode == MatchMode::All,
+        }
+    }
+
+    /// Whether a document matches, `leaf` telling whether it matches a leaf and `contains`
+    /// whether it contains a term
+    fn matches(
+        &self,
+        leaf: impl Fn(&QueryLeaf<'_>) -> bool,
+        contains: impl Fn(&str) -> bool,
+    ) -> bool {
+        match self.filter {
+            Some(f) => f.matches(&leaf) && (!self.all || self.leaves.iter().all(|l| leaf(l))),
+            None => !self.all || self.distinct.iter().all(|t| contains(t)),
+        }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Query terms without repetitions
+fn distinct_terms<'t>(terms: &'t [(Cow<'_, str>, f64)]) -> Vec<&'t str> {
+    let mut distinct: Vec<&str> = Vec::new();
+    for (t, _) in terms {
+        if !distinct
+            .iter()
+            .any(|d| CaseInsensitiveStr::new(d) == CaseInsensitiveStr::new(t))
+        {
+            distinct.push(t);
+        }
+    }
+    distinct
+}
+
+/// Ranking order of search results: decreasing score, then increasing path
+fn compare_results((path1, score1): &(&Path, f64), (path2, score2): &(&Path, f64)) -> Ordering {
+    compare_scores(*score2, *score1).then_with(|| path1.cmp(path2))
+}
+
+/// Total order of scores, NaN being lower than any other score
+fn compare_scores(score1: f64, score2: f64) -> Ordering {
+    match (score1.is_nan(), score2.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => score1.total_cmp(&score2),
+    }
+}
+
+/// Sorts search results in ranking order
+fn sort_results(results: &mut [(&Path, f64)]) {
+    results.sort_by(compare_results);
+}
+
+/// Sorts search results, then applies the filters of `options` working on the ranked list.
+/// `coverage` gives the number of distinct query terms found in a document.
+fn rank_results(
+    results: &mut Vec<(&Path, f64)>,
+    options: &SearchOptions,
+    coverage: impl Fn(&Path) -> usize,
+) {
+    match options.rank {
+        RankMode::Score => sort_results(results),
+        RankMode::Coverage => {
+            let mut ranked: Vec<_> = results.drain(..).map(|r| (coverage(r.0), r)).collect();
+            ranked.sort_by(|(c1, r1), (c2, r2)| c2.cmp(c1).then_with(|| compare_results(r1, r2)));
+            results.extend(ranked.into_iter().map(|(_, r)| r));
+        }
+    }
+    if let Some(cap) = options.directory_cap {
+        cap.apply(results, |(path, _)| path);
+    }
+    if options.auto_cutoff {
+        scoring::auto_cutoff(results, |(_, score)| *score);
+    }
+}
+
+/// Writes `map` sorted by path, so that the same index is always saved the same way
+fn sorted_by_path<V: Serialize, S: Serializer>(
+    map: &HashMap<PathBuf, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Index {
+    /// Analyzer the documents were indexed with, also used for queries by default. Written even
+    /// when standard, and like the other settings before the documents, so that
+    /// [`Index::load_lenient`] knows it from a truncated file.
+    #[serde(default)]
+    analyzer: Analyzer,
+    /// Score multipliers set with [`Index::set_boost`]
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted_by_path"
+    )]
+    boosts: HashMap<PathBuf, f64>,
+    /// Options the index was built with, to index files again the same way. The analyzer is
+    /// stored in its own field.
+    #[serde(default, skip_serializing_if = "IndexOptions::is_default")]
+    options: IndexOptions,
+    /// Directory the index was built from, as given, to walk it again. Unknown for indexes of
+    /// explicit paths, and those written before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    root: Option<PathBuf>,
+    #[serde(serialize_with = "sorted_by_path")]
     documents: HashMap<PathBuf, Document>,
+    /// Inverted index of the documents, see [`postings`]
+    #[serde(
+        rename = "inverted",
+        default,
+        skip_serializing_if = "Postings::is_unbuilt"
+    )]
+    postings: Postings,
 }
 
 macro_rules! apply_tokenizer {
-    ($tokenizer:expr, $path:ident, $index:ident) => {{
+    ($tokenizer:expr, $path:ident, $options:ident, $settings:ident, $name:expr) => {{
         let tokenizer = $tokenizer;
         let p = $path;
-        match Document::build(&p, tokenizer) {
-            Ok(d) => {
-                log::info!("processed {path}", path = p.display());
-                $index.documents.insert(p, d);
+        Document::build_with(p, tokenizer, $options.term_collector()).map(|mut d| {
+            d.retain_terms(|t, c| {
+                c >= $settings.min_term_frequency
+                    && unmark(t).chars().count() >= $options.min_token_len
+            });
+            if $options.index_file_type {
+                d.add_file_type_terms(p);
             }
-            Err(e) => {
-                log::error!("processing {path}: {e}", path = p.display());
+            if let Some((name, analyzer)) = $name {
+                d.add_path_terms(name, analyzer);
             }
-        }
+            log::info!("processed {path}", path = p.display());
+            d
+        })
     }};
 }
 
-impl Index {
-    pub fn new(p: impl AsRef<Path>) -> Self {
-        let mut index = Self {
-            documents: HashMap::new(),
+/// Progress of the construction of an [`Index`], see [`Index::new_with_progress`]
+#[derive(Debug, Clone, Copy)]
+pub struct Progress<'a> {
+    /// Number of files processed so far, `current_path` included
+    pub processed: usize,
+    /// Number of files to process
+    pub total: usize,
+    /// File just processed
+    pub current_path: &'a Path,
+}
+
+/// Settings used while building an [`Index`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexOptions {
+    /// Terms appearing fewer times than this within a document are not indexed for that
+    /// document. This drops typos and incidental words, but also rare terms that were genuinely
+    /// relevant: a document mentioning a topic only once will not be found by it. The document
+    /// length still accounts for the dropped occurrences.
+    pub min_term_frequency: usize,
+    /// Terms shorter than this many characters, such as `a` and `is` with 3, are not indexed.
+    /// Like with [`IndexOptions::min_term_frequency`], the document length still accounts for
+    /// them, so the frequencies of the other terms are relative to the whole text. Case marks
+    /// are not counted, and the terms of paths and file types are kept whatever their length.
+    pub min_token_len: usize,
+    /// Keep the first non-empty line of text documents as their title
+    pub capture_titles: bool,
+    /// Analyzer splitting documents into terms
+    #[serde(skip)]
+    pub analyzer: Analyzer,
+    /// Cell separator of `.csv` files
+    pub csv_delimiter: Delimiter,
+    /// Whether the first row of delimited files names their columns, and is not indexed
+    pub csv_header: bool,
+    /// Columns of delimited files to index, every column by default
+    pub csv_columns: Option<Vec<Column>>,
+    /// Add the extension and MIME type of each file to its terms, searchable as `extension:rs`
+    /// or `mime:text/markdown`. The MIME type is derived from the extension.
+    pub index_file_type: bool,
+    /// Record the position of every term in documents, needed by phrase queries. This stores one
+    /// integer per token on top of the per-term counts, which typically makes the index several
+    /// times larger.
+    pub track_positions: bool,
+    /// Record the distinct words of a document each term was derived from, so searches can
+    /// report what they actually matched (see [`SearchHit::forms`])
+    pub keep_term_forms: bool,
+    /// Number of directory levels indexed beneath the root, `Some(0)` only indexing the files
+    /// directly in it. Unlimited by default.
+    pub max_depth: Option<usize>,
+    /// Files larger than this many bytes are skipped, with a warning, without being read
+    pub max_file_size: Option<u64>,
+    /// Files and directories to skip, in the `.gitignore` syntax and relative to the indexed
+    /// root: `target/` skips every directory named `target`, `/build` only the one at the root,
+    /// and `*.log` every log file. They take precedence over `.gitignore` files.
+    pub exclude: Vec<String>,
+    /// Also skip the files and directories ignored by the `.gitignore` files of the indexed
+    /// tree, and `.git` directories
+    pub gitignore: bool,
+    /// Index the targets of symbolic links to files and directories, instead of skipping the
+    /// links. A directory reached through several paths is only indexed once.
+    pub follow_symlinks: bool,
+    /// Detect the language of each document from its first [`LANGUAGE_SAMPLE`] bytes, and stem
+    /// it and drop its stop words as usual in that [`Language`], instead of with the stemmer of
+    /// the analyzer. Documents whose language is not supported or cannot be told reliably are
+    /// not stemmed. Queries are analyzed in the language of [`SearchOptions::language`].
+    pub detect_language: bool,
+    /// Add the words of the path of each file, relative to the indexed root, to its terms, so
+    /// that `auth_handler.rs` is found by `auth` whatever its content. Files indexed from
+    /// explicit paths only add the words of their name.
+    pub index_paths: bool,
+}
+
+impl Default for IndexOptions {
+    fn default() -> Self {
+        Self {
+            min_term_frequency: 1,
+            min_token_len: 0,
+            capture_titles: false,
+            analyzer: Analyzer::default(),
+            csv_delimiter: Delimiter::default(),
+            csv_header: false,
+            csv_columns: None,
+            index_file_type: false,
+            track_positions: false,
+            keep_term_forms: false,
+            max_depth: None,
+            max_file_size: None,
+            exclude: Vec::new(),
+            gitignore: false,
+            follow_symlinks: false,
+            detect_language: false,
+            index_paths: false,
+        }
+    }
+}
+
+impl IndexOptions {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn term_collector(&self) -> TermCollector {
+        let mut terms = TermCollector::default();
+        if self.track_positions {
+            terms = terms.with_positions();
+        }
+        if self.keep_term_forms {
+            terms = terms.with_forms();
+        }
+        terms
+    }
+
+    /// Whether the file at `p` is small enough to be indexed, warning otherwise
+    fn within_size_limit(&self, p: &Path) -> io::Result<bool> {
+        let Some(max) = self.max_file_size else {
+            return Ok(true);
         };
-        traverse_tree(p, |p| match p.extension().and_then(|e| e.to_str()) {
-            Some("xhtml") | Some("xml") => apply_tokenizer!(XmlTokenizer::default(), p, index),
-            Some("text") | Some("txt") => apply_tokenizer!(TextTokenizer::default(), p, index),
-            Some("rs") => apply_tokenizer!(TextTokenizer::default(), p, index),
-            Some(ext) => {
-                log::warn!("No handler for {ext:?} documents, falling back to text");
-                apply_tokenizer!(TextTokenizer::default(), p, index)
-            }
-            None => {
+        let size = metadata(p)?.len();
+        if size > max {
+            log::warn!(
+                "skipping {path}: {size} bytes, over the {max} bytes limit",
+                path = p.display()
+            );
+        }
+        Ok(size <= max)
+    }
+
+    /// Builds the document of the file at `p` with the tokenizer selected by `s`, or else the
+    /// built-in tokenizer `previous` it was indexed with, or else by its extension in
+    /// `registry`. Files without a tokenizer are skipped, returning `None`, as are those
+    /// previously indexed with a custom tokenizer that `registry` does not provide. Paths are
+    /// relative to `root` for [`IndexOptions::index_paths`].
+    fn build_document(
+        &self,
+        p: &Path,
+        root: Option<&Path>,
+        analyzer: &Arc<Analyzer>,
+        s: &DirectorySettings,
+        registry: &TokenizerRegistry,
+        previous: Option<SourceKind>,
+    ) -> io::Result<Option<Document>> {
+        let options = self;
+        let (analyzer, language) = if self.detect_language {
+            let language = Language::detect_file(p)?;
+            let analyzer = Arc::new(analyzer.for_language(language));
+            (Cow::Owned(analyzer), language)
+        } else {
+            (Cow::Borrowed(analyzer), None)
+        };
+        let analyzer = &*analyzer;
+        let extension = p.extension().and_then(|e| e.to_str());
+        let registered = extension.and_then(|ext| registry.get(ext));
+        let kept = previous
+            .filter(|&kind| registered.is_none_or(|(k, _)| *k != kind))
+            .and_then(|kind| Some((kind, kind.tokenizer_kind()?)));
+        let (kind, tokenizer) = match (s.tokenizer, kept, extension) {
+            (Some(kind), _, _) => (kind.into(), registry::tokenizer(kind, analyzer, s)),
+            (None, Some((kind, built_in)), _) => (kind, registry::tokenizer(built_in, analyzer, s)),
+            (None, None, Some(ext)) => match (registered, registry.fallback()) {
+                (Some((kind, factory)), _) => (*kind, factory(analyzer, s)),
+                (None, Some((kind, fallback))) => {
+                    log::warn!("No handler for {ext:?} documents, using the fallback tokenizer");
+                    (*kind, fallback(analyzer, s))
+                }
+                (None, None) => {
+                    log::warn!(
+                        "No handler for {ext:?} documents, skipping {path}",
+                        path = p.display()
+                    );
+                    return Ok(None);
+                }
+            },
+            (None, None, None) => {
                 log::error!("Unknown document type {path}", path = p.display());
+                return Ok(None);
             }
+        };
+        if previous == Some(SourceKind::Custom) && kind != SourceKind::Custom {
+            log::warn!(
+                "keeping {path} as indexed by a custom tokenizer missing from the registry",
+                path = p.display()
+            );
+            return Ok(None);
+        }
+        let name = self.index_paths.then(|| {
+            let name = match root.and_then(|root| p.strip_prefix(root).ok()) {
+                Some(relative) => relative,
+                None => p.file_name().map_or(p, Path::new),
+            };
+            (name, &**analyzer)
         });
-        index
+        apply_tokenizer!(tokenizer, p, options, s, name)
+            .map(|d| Some(d.with_kind(kind).with_language(language)))
     }
+}
 
-    fn idf(&self, term: &str) -> f64 {
-        let n = self.documents.len() as f64;
-        let d = self.documents.values().filter(|d| d.contains(term)).count() as f64;
-        assert!(n >= d);
-        (n / (d + 1f64)).log2()
+impl Index {
+    pub fn new(p: impl AsRef<Path>) -> Self {
+        Self::new_with_options(p, IndexOptions::default())
     }
 
-    pub fn search<'a>(&'a self, terms: &'_ str) -> Vec<(&'a Path, f64)> {
-        let terms = tokenizer::Lexer::new(terms)
-            .map(|t| (t, self.idf(t)))
-            .collect::<Vec<_>>();
-        let mut results: Vec<_> = self
-            .documents
-            .iter()
-            .map(move |(filename, d)| {
-                (
-                    filename.as_path(),
-                    terms
-                        .iter()
-                        .map(|(t, idf)| {
-                            let tf = d.term_frequency(t);
-                            tf * *idf
-                        })
-                        .sum::<f64>(),
-                )
-            })
-            .filter(|(_, score)| score != &0f64)
-            .collect();
-        results.sort_by(|(_, score1), (_, score2)| score1.partial_cmp(score2).unwrap());
-        results.reverse();
-        results
+    /// Indexes every file beneath `p`. Files are processed in parallel, on as many threads as
+    /// there are CPUs unless `RAYON_NUM_THREADS` says otherwise.
+    pub fn new_with_options(p: impl AsRef<Path>, options: IndexOptions) -> Self {
+        Self::new_with_progress(p, options, |_| {})
     }
 
-    pub fn load<R: io::Read>(reader: R) -> io::Result<Self> {
-        serde_json::from_reader(reader)
-            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))
+    /// Like [`Index::new_with_options`], calling `progress` after each file is processed,
+    /// whether it could be indexed or not. Files are listed before any is processed, so the
+    /// total is known from the first call. Calls come from the threads processing files, one at
+    /// a time.
+    pub fn new_with_progress(
+        p: impl AsRef<Path>,
+        options: IndexOptions,
+        progress: impl FnMut(Progress) + Send,
+    ) -> Self {
+        Self::build_logged(p, options, TokenizerRegistry::standard(), progress)
     }
 
-    pub fn save<W: io::Write>(&self, writer: W) -> io::Result<()> {
-        serde_json::to_writer(writer, self)
-            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))
+    /// Like [`Index::new`], but also returns the files and directories that could not be read
+    /// or indexed, along with the reason. Fails if `p` itself cannot be read.
+    pub fn try_new(p: impl AsRef<Path>) -> io::Result<(Self, Vec<(PathBuf, io::Error)>)> {
+        Self::try_new_with_options(p, IndexOptions::default())
     }
 
-    pub fn last_modified_file(&self) -> io::Result<(&Path, SystemTime)> {
-        let mut mtime = UNIX_EPOCH;
-        let mut filename = None;
-        for f in self.documents.keys() {
-            let m = metadata(f)?.modified()?;
-            if m > mtime {
-                filename = Some(f.as_path());
-                mtime = m;
-            }
+    /// Like [`Index::try_new`], with the options of [`Index::new_with_options`]
+    pub fn try_new_with_options(
+        p: impl AsRef<Path>,
+        options: IndexOptions,
+    ) -> io::Result<(Self, Vec<(PathBuf, io::Error)>)> {
+        read_dir(&p)?;
+        Ok(Self::build(
+            p,
+            options,
+            TokenizerRegistry::standard(),
+            |_| {},
+        ))
+    }
+
+    /// Like [`Index::new`], picking the tokenizer of each file from its extension in `registry`
+    /// rather than [`TokenizerRegistry::default_registry`]
+    pub fn with_tokenizer_registry(p: impl AsRef<Path>, registry: &TokenizerRegistry) -> Self {
+        Self::new_with_registry(p, IndexOptions::default(), registry)
+    }
+
+    /// Like [`Index::new_with_options`], picking the tokenizer of each file from its extension in
+    /// `registry`. The registry is not kept: [`Index::update`] and the reindexing of stale
+    /// documents use the built-in tokenizer each document was indexed with, see
+    /// [`Document::kind`], and the default registry for new documents and those indexed with a
+    /// [`SourceKind::Custom`] tokenizer.
+    pub fn new_with_registry(
+        p: impl AsRef<Path>,
+        options: IndexOptions,
+        registry: &TokenizerRegistry,
+    ) -> Self {
+        Self::build_logged(p, options, registry, |_| {})
+    }
+
+    /// Like [`Index::build`], logging the failures
+    fn build_logged(
+        p: impl AsRef<Path>,
+        options: IndexOptions,
+        registry: &TokenizerRegistry,
+        progress: impl FnMut(Progress) + Send,
+    ) -> Self {
+        let (index, failures) = Self::build(p, options, registry, progress);
+        for (path, e) in failures {
+            log::warn!("cannot read {path}: {e}", path = path.display());
         }
-        if let Some(filename) = filename {
-            Ok((filename, mtime))
-        } else {
-            Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Index does not contains any file",
-            ))
+        index
+    }
+
+    /// Indexes every file beneath `p`, also returning the files and directories that could not
+    /// be read or indexed
+    fn build(
+        p: impl AsRef<Path>,
+        options: IndexOptions,
+        registry: &TokenizerRegistry,
+        progress: impl FnMut(Progress) + Send,
+    ) -> (Self, Vec<(PathBuf, io::Error)>) {
+        let mut index = Self::empty(options);
+        index.root = Some(p.as_ref().to_path_buf());
+        let root = DirectorySettings::from(&index.options);
+        let mut files = Vec::new();
+        let mut failures = Vec::new();
+        traverse_tree(
+            p,
+            root,
+            |dir, parent| parent.enter(dir),
+            |p, s| files.push((p, s.clone())),
+            |p, e| failures.push((p, e)),
+            &index.options,
+        );
+        index.index_files(files, registry, progress, &mut failures);
+        (index, failures)
+    }
+
+    /// Indexes the files `paths`, e.g. listed by `git ls-files`, instead of every file beneath a
+    /// directory. Paths are kept as given. Entries that do not exist or are not files are
+    /// logged and skipped, like files that cannot be read.
+    pub fn from_paths(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self::from_paths_with_options(paths, IndexOptions::default())
+    }
+
+    /// Like [`Index::from_paths`], with the options of [`Index::new_with_options`]. Options
+    /// selecting the files of a directory (depth, exclusions, `.gitignore` files and symbolic
+    /// links) do not apply to explicit paths, but the size limit and the settings files of
+    /// their directories do.
+    pub fn from_paths_with_options(
+        paths: impl IntoIterator<Item = PathBuf>,
+        options: IndexOptions,
+    ) -> Self {
+        let mut index = Self::empty(options);
+        let mut failures = Vec::new();
+        let mut settings: HashMap<PathBuf, DirectorySettings> = HashMap::new();
+        let files = paths
+            .into_iter()
+            .filter_map(|p| match metadata(&p) {
+                Ok(m) if m.is_file() => {
+                    let dir = p.parent().unwrap_or(Path::new("")).to_path_buf();
+                    let s = settings
+                        .entry(dir)
+                        .or_insert_with(|| DirectorySettings::for_file(&p, &index.options))
+                        .clone();
+                    Some((p, s))
+                }
+                Ok(_) => {
+                    let e = io::Error::new(io::ErrorKind::InvalidInput, "not a regular file");
+                    failures.push((p, e));
+                    None
+                }
+                Err(e) => {
+                    failures.push((p, e));
+                    None
+                }
+            })
+            .collect();
+        index.index_files(files, TokenizerRegistry::standard(), |_| {}, &mut failures);
+        for (path, e) in failures {
+            log::warn!("cannot read {path}: {e}", path = path.display());
+        }
+        index
+    }
+
+    /// Index without documents, to be built with `options`
+    fn empty(options: IndexOptions) -> Self {
+        Self {
+            documents: HashMap::new(),
+            analyzer: options.analyzer.clone(),
+            boosts: HashMap::new(),
+            options: IndexOptions {
+                analyzer: Analyzer::default(),
+                ..options
+            },
+            root: None,
+            postings: Default::default(),
         }
     }
+
+    /// Indexes `files`, each with the settings of its directory, and skips those over the size
+    /// limit. The files that cannot be read or indexed are added to `failures`.
+    fn index_files(
+        &mut self,
+        mut files: Vec<(PathBuf, DirectorySettings)>,
+        registry: &TokenizerRegistry,
+        progress: impl FnMut(Progress) + Send,
+        failures: &mut Vec<(PathBuf, io::Error)>,
+    ) {
+        files.retain(|(p, _)| match self.options.within_size_limit(p) {
+            Ok(within) => within,
+            Err(e) => {
+                failures.push((p.clone(), e));
+                false
+            }
+        });
+        // Documents are independent from each other, and collected in a map keyed by path, so
+        // the index does not depend on the order they are built in
+        let analyzer = Arc::new(self.analyzer.clone());
+        let options = &self.options;
+        let root = self.root.as_deref();
+        let total = files.len();
+        let progress = Mutex::new((0, progress));
+        let documents: Vec<_> = files
+            .into_par_iter()
+            .map(|(p, s)| {
+                let document = options.build_document(&p, root, &analyzer, &s, registry, None);
+                let mut progress = progress.lock().unwrap_or_else(PoisonError::into_inner);
+                let (processed, callback) = &mut *progress;
+                *processed += 1;
+                callback(Progress {
+                    processed: *processed,
+                    total,
+                    current_path: &p,
+                });
+                drop(progress);
+                (p, document)
+            })
+            .collect();
+        for (p, document) in documents {
+            match document {
+                Ok(Some(document)) => {
+                    self.documents.insert(p, document);
+                }
+                Ok(None) => {}
+                Err(e) => failures.push((p, e)),
+            }
+        }
+        self.build_postings();
+    }
+
+    /// Options the index was built with
+    pub fn options(&self) -> IndexOptions {
+        IndexOptions {
+            analyzer: self.analyzer.clone(),
+            ..self.options.clone()
+        }
+    }
+
+    /// Directory the index was built from, as given to [`Index::new`], `None` for indexes
+    /// built from explicit paths or saved before it was recorded
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// [`Index::root`], for operations walking it again
+    fn known_root(&self) -> io::Result<&Path> {
+        self.root().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the index does not record the directory it was built from",
+            )
+        })
+    }
+
+    /// Indexes the file at `p` with the tokenizer selected by `s`, or else the built-in
+    /// tokenizer it was previously indexed with, or else by its extension in `registry`
+    fn add_file(
+        &mut self,
+        p: PathBuf,
+        analyzer: &Arc<Analyzer>,
+        s: &DirectorySettings,
+        registry: &TokenizerRegistry,
+    ) {
+        let previous = self.documents.get(&p).and_then(|d| d.kind);
+        match self
+            .options
+            .build_document(&p, self.root.as_deref(), analyzer, s, registry, previous)
+        {
+            Ok(Some(document)) => {
+                self.insert_document(p, document);
+            }
+            Ok(None) => {}
+            Err(e) => log::error!("processing {path}: {e}", path = p.display()),
+        }
+    }
+
+    fn document_frequency(&self, term: &str) -> usize {
+        self.postings()
+            .get(CaseInsensitiveStr::new(term))
+            .map_or(0, Vec::len)
+    }
+
+    fn idf(&self, term: &str, scheme: IdfScheme) -> f64 {
+        scheme.weight(self.documents.len(), self.document_frequency(term))
+    }
+
+    pub fn search<'a>(&'a self, terms: &'_ str) -> Vec<(&'a Path, f64)> {
+        self.search_with_options(terms, &SearchOptions::default())
+    }
+
+    /// Ranks the documents matching `terms`. Queries understand `AND`, `OR`, `NOT`, parentheses,
+    /// `+required` and `-prohibited` terms, see [`Index::try_search_with_options`]. A query
+    /// rejected by [`SearchOptions::term_limit_policy`] has no results, see
+    /// [`Index::try_search_with_options`] to get the error instead.
+    pub fn search_with_options<'a>(
+        &'a self,
+        terms: &'_ str,
+        options: &SearchOptions,
+    ) -> Vec<(&'a Path, f64)> {
+        self.try_search_with_options(terms, options)
+            .unwrap_or_else(|e| {
+                log::warn!("{e}");
+                Vec::new()
+            })
+    }
+
+    /// Ranks the documents matching the query `terms`, or fails if the query is rejected by
+    /// [`SearchOptions::term_limit_policy`].
+    ///
+    /// Words are joined by `OR`, `AND` binding tighter and `NOT` tighter still, and parentheses
+    /// group them: `xml OR rust AND NOT c` is `xml OR (rust AND (NOT c))`. `-word` excludes the
+    /// documents containing `word`, and `+word` requires it, the words without `+` then only
+    /// adding to scores. `word*` stands for every indexed term starting with `word`, which is
+    /// not stemmed nor otherwise analyzed. Matching documents are scored by tf-idf over the words
+    /// which are not negated, so a query with negated words only has no results.
+    pub fn try_search_with_options<'a>(
+        &'a self,
+        terms: &'_ str,
+        options: &SearchOptions,
+    ) -> Result<Vec<(&'a Path, f64)>, QueryError> {
+        let (terms, filter) = parse_query(terms, &self.analyzer, options, &self.vocabulary())?;
+        let terms = weigh_boosted_terms(terms, options, |t| self.idf(t, options.idf));
+        Ok(self.rank(&terms, filter.as_ref(), options))
+    }
+
+    /// Ranks the documents matching `terms`, already tokenized: unlike [`Index::search`], the
+    /// terms are neither analyzed nor parsed as a query
+    pub fn search_terms<'a>(&'a self, terms: &[&str]) -> Vec<(&'a Path, f64)> {
+        self.search_terms_with_options(terms, &SearchOptions::default())
+    }
+
+    /// Like [`Index::search_terms`]. The analyzer and term limit of `options` only apply to
+    /// queries, not to these terms.
+    pub fn search_terms_with_options<'a>(
+        &'a self,
+        terms: &[&str],
+        options: &SearchOptions,
+    ) -> Vec<(&'a Path, f64)> {
+        let terms = weigh_terms(terms.iter().map(|&t| self.analyzer.key(t)), options, |t| {
+            self.idf(t, options.idf)
+        });
+        self.rank(&terms, None, options)
+    }
+
+    /// Ranks the documents matching `terms`, requiring all of them or any depending on `mode`
+    pub fn search_with_mode<'a>(&'a self, terms: &'_ str, mode: MatchMode) -> Vec<(&'a Path, f64)> {
+        let options = SearchOptions {
+            match_mode: mode,
+            ..Default::default()
+        };
+        self.search_with_options(terms, &options)
+    }
+
+    /// Returns the `count` best results of [`Index::search`], selected while scoring documents
+    /// rather than by sorting every result, see [`Index::search_page_with_options`]
+    pub fn search_top<'a>(&'a self, terms: &'_ str, count: usize) -> Vec<(&'a Path, f64)> {
+        self.search_page(terms, 0, count)
+    }
+
+    /// Returns the results of [`Index::search_top`] for each of `queries`, in order. The idf of
+    /// a term found in several queries is only computed once, and the queries are then ranked
+    /// in parallel with the `parallel-batch` feature.
+    pub fn search_batch<'a>(&'a self, queries: &[&str], count: usize) -> Vec<Vec<(&'a Path, f64)>> {
+        let options = SearchOptions::default();
+        let vocabulary = self.vocabulary();
+        let parsed: Vec<_> = queries
+            .iter()
+            .map(|query| {
+                parse_query(query, &self.analyzer, &options, &vocabulary)
+                    .map_err(|e| log::warn!("{e}"))
+                    .ok()
+            })
+            .collect();
+        let mut idf = TermMap::new();
+        for (terms, _) in parsed.iter().flatten() {
+            for (term, _) in terms {
+                if !idf.contains_key(CaseInsensitiveStr::new(term)) {
+                    idf.insert(term.to_string().into(), self.idf(term, options.idf));
+                }
+            }
+        }
+        let rank = |query: Option<(Vec<BoostedTerm>, Option<QueryFilter>)>| {
+            let Some((terms, filter)) = query else {
+                return Vec::new();
+            };
+            let terms = weigh_boosted_terms(terms, &options, |t| idf[CaseInsensitiveStr::new(t)]);
+            self.rank_page(&terms, filter.as_ref(), &options, |_, _| true, 0, count)
+        };
+        #[cfg(feature = "parallel-batch")]
+        let parsed = parsed.into_par_iter();
+        #[cfg(not(feature = "parallel-batch"))]
+        let parsed = parsed.into_iter();
+        parsed.map(rank).collect()
+    }
+
+    /// Returns `count` results of [`Index::search`] from the `offset`-th, i.e. results
+    /// `offset + 1` to `offset + count`
+    pub fn search_page<'a>(
+        &'a self,
+        terms: &'_ str,
+        offset: usize,
+        count: usize,
+    ) -> Vec<(&'a Path, f64)> {
+        self.search_page_with_options(terms, &SearchOptions::default(), offset, count)
+    }
+
+    /// Like [`Index::search_page`], with `options`. Unless ranking by coverage, capping results
+    /// per directory or cutting them off automatically, the results before `offset + count` are
+    /// selected while scoring documents, without sorting every result.
+    pub fn search_page_with_options<'a>(
+        &'a self,
+        terms: &'_ str,
+        options: &SearchOptions,
+        offset: usize,
+        count: usize,
+    ) -> Vec<(&'a Path, f64)> {
+        self.try_search_page_with_options(terms, options, offset, count)
+            .unwrap_or_else(|e| {
+                log::warn!("{e}");
+                Vec::new()
+            })
+    }
+
+    /// Like [`Index::search_page_with_options`], failing on invalid queries like
+    /// [`Index::try_search_with_options`]
+    pub fn try_search_page_with_options<'a>(
+        &'a self,
+        terms: &'_ str,
+        options: &SearchOptions,
+        offset: usize,
+        count: usize,
+    ) -> Result<Vec<(&'a Path, f64)>, QueryError> {
+        let (terms, filter) = parse_query(terms, &self.analyzer, options, &self.vocabulary())?;
+        let terms = weigh_boosted_terms(terms, options, |t| self.idf(t, options.idf));
+        Ok(self.rank_page(&terms, filter.as_ref(), options, |_, _| true, offset, count))
+    }
+
+    /// Like [`Index::search`], giving up on scoring documents once `deadline` has passed: the
+    /// documents scored so far are then ranked and the results flagged as partial. The clock is
+    /// checked for each prefix expanded and each term weighed, then every
+    /// [`DEADLINE_CHECK_INTERVAL`] documents.
+    pub fn search_with_deadline<'a>(
+        &'a self,
+        terms: &'_ str,
+        deadline: Instant,
+    ) -> DeadlineResults<'a> {
+        self.try_search_with_deadline(terms, &SearchOptions::default(), deadline)
+            .unwrap_or_else(|e| {
+                log::warn!("{e}");
+                DeadlineResults {
+                    results: Vec::new(),
+                    partial: false,
+                }
+            })
+    }
+
+    /// Like [`Index::search_with_deadline`], with the options and errors of
+    /// [`Index::try_search_with_options`]
+    pub fn try_search_with_deadline<'a>(
+        &'a self,
+        terms: &'_ str,
+        options: &SearchOptions,
+        deadline: Instant,
+    ) -> Result<DeadlineResults<'a>, QueryError> {
+        let expired = Cell::new(false);
+        let check = || {
+            if !expired.get() {
+                expired.set(Instant::now() >= deadline);
+            }
+            !expired.get()
+        };
+        // Past the deadline, prefixes only match themselves and terms no longer count
+        let vocabulary = self.vocabulary();
+        let expand = |prefix: &str| {
+            if check() {
+                vocabulary(prefix)
+            } else {
+                no_vocabulary(prefix)
+            }
+        };
+        let (terms, filter) = parse_query(terms, &self.analyzer, options, &expand)?;
+        let terms = weigh_boosted_terms(terms, options, |t| {
+            if check() {
+                self.idf(t, options.idf)
+            } else {
+                0f64
+            }
+        });
+        let scored = Cell::new(0usize);
+        let in_time = |_: &Path, _: &Document| {
+            if scored.get().is_multiple_of(DEADLINE_CHECK_INTERVAL) {
+                check();
+            }
+            scored.set(scored.get() + 1);
+            !expired.get()
+        };
+        let results = self.rank_page(&terms, filter.as_ref(), options, in_time, 0, usize::MAX);
+        Ok(DeadlineResults {
+            results,
+            partial: expired.get(),
+        })
+    }
+
+    /// Like [`Index::search`], only scoring the documents whose metadata satisfies `predicate`,
+    /// e.g. files modified recently. The metadata is the one recorded when documents were
+    /// indexed, files are not read again.
+    pub fn search_filtered<'a>(
+        &'a self,
+        terms: &'_ str,
+        predicate: impl Fn(&DocumentMeta) -> bool,
+    ) -> Vec<(&'a Path, f64)> {
+        let options = SearchOptions::default();
+        match parse_query(terms, &self.analyzer, &options, &self.vocabulary()) {
+            Ok((terms, filter)) => {
+                let terms = weigh_boosted_terms(terms, &options, |t| self.idf(t, options.idf));
+                let keep = |p: &Path, d: &Document| predicate(&DocumentMeta::new(p, d));
+                self.rank_page(&terms, filter.as_ref(), &options, keep, 0, usize::MAX)
+            }
+            Err(e) => {
+                log::warn!("{e}");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Scores the documents matching `filter` and [`SearchOptions::match_mode`] against `terms`,
+    /// each paired with its idf, and ranks them
+    fn rank<'a>(
+        &'a self,
+        terms: &[(Cow<'_, str>, f64)],
+        filter: Option<&QueryFilter<'_>>,
+        options: &SearchOptions,
+    ) -> Vec<(&'a Path, f64)> {
+        self.rank_page(terms, filter, options, |_, _| true, 0, usize::MAX)
+    }
+
+    /// Like [`Index::rank`], only scoring the documents for which `keep` returns `true`, and
+    /// keeping `count` results from the `offset`-th
+    fn rank_page<'a>(
+        &'a self,
+        terms: &[(Cow<'_, str>, f64)],
+        filter: Option<&QueryFilter<'_>>,
+        options: &SearchOptions,
+        keep: impl Fn(&Path, &Document) -> bool,
+        offset: usize,
+        count: usize,
+    ) -> Vec<(&'a Path, f64)> {
+        let matches = query_matcher(terms, filter, options);
+        self.rank_where(
+            terms,
+            |p, d| keep(p, d) && matches(d),
+            options,
+            offset,
+            count,
+        )
+    }
+
+    /// Scores the documents for which `keep` returns `true` against `terms`, each paired with
+    /// its idf, ranks them and keeps `count` results from the `offset`-th. When results are
+    /// ranked by score alone, only the best `offset + count` are kept while scoring.
+    fn rank_where<'a>(
+        &'a self,
+        terms: &[(Cow<'_, str>, f64)],
+        keep: impl Fn(&Path, &Document) -> bool,
+        options: &SearchOptions,
+        offset: usize,
+        count: usize,
+    ) -> Vec<(&'a Path, f64)> {
+        let scored = self.score_where(terms, options, keep);
+        let end = offset.saturating_add(count);
+        let mut results = if options.ranks_by_score() && end != usize::MAX {
+            stream::top_results(scored, end)
+        } else {
+            let mut results: Vec<_> = scored.collect();
+            let distinct = distinct_terms(terms);
+            rank_results(&mut results, options, |p| {
+                self.documents[p].coverage(&distinct)
+            });
+            results
+        };
+        results.truncate(end);
+        results.drain(..offset.min(results.len()));
+        results
+    }
+
+    /// Like [`Index::search`], only returning the documents indexed with one of the tokenizers
+    /// `kinds`, see [`Document::kind`]
+    pub fn search_in_kinds<'a>(
+        &'a self,
+        terms: &'_ str,
+        kinds: &[SourceKind],
+    ) -> Vec<(&'a Path, f64)> {
+        let options = SearchOptions::default();
+        let (terms, filter) = parse_query(terms, &self.analyzer, &options, &self.vocabulary())
+            .unwrap_or_else(|e| {
+                log::warn!("{e}");
+                (Vec::new(), None)
+            });
+        let terms = weigh_boosted_terms(terms, &options, |t| self.idf(t, options.idf));
+        let keep = |_: &Path, d: &Document| {
+            kinds.contains(&d.kind()) && filter.as_ref().is_none_or(|f| d.matches(f))
+        };
+        self.rank_where(&terms, keep, &options, 0, usize::MAX)
+    }
+
+    /// Like [`Index::search_with_options`], but also reports which fields of each document the
+    /// query terms were found in, and how much each term adds to its score
+    pub fn search_hits<'a>(
+        &'a self,
+        terms: &'_ str,
+        options: &SearchOptions,
+    ) -> Vec<SearchHit<'a>> {
+        let (terms, filter) = parse_query(terms, &self.analyzer, options, &self.vocabulary())
+            .unwrap_or_else(|e| {
+                log::warn!("{e}");
+                (Vec::new(), None)
+            });
+        let terms = weigh_boosted_terms(terms, options, |t| self.idf(t, options.idf));
+        self.rank(&terms, filter.as_ref(), options)
+            .into_iter()
+            .map(|(path, score)| {
+                let document = &self.documents[path];
+                SearchHit {
+                    path,
+                    score,
+                    fields: document.field_matches(&terms, options, &self.analyzer),
+                    forms: document.term_forms(&terms, &self.analyzer),
+                    contributions: document.contributions(
+                        &terms,
+                        options,
+                        &self.analyzer,
+                        self.boost(path),
+                    ),
+                    snippets: Vec::new(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn document(&self, path: &Path) -> Option<&Document> {
+        self.documents.get(path)
+    }
+
+    /// Removes the document of `path` from the index. Document frequencies, and thus the idf of
+    /// every term, are derived from the remaining documents. The boost of `path` is kept in case
+    /// it is indexed again.
+    pub fn remove_document(&mut self, path: &Path) -> Option<Document> {
+        let document = self.documents.remove(path)?;
+        self.postings.remove(path, &document);
+        Some(document)
+    }
+
+    /// Multiplies the score of the document at `path` by `factor` in every search, `1.0` being
+    /// neutral. The boost is kept if the document is re-indexed. Fails for negative or infinite
+    /// factors and NaN.
+    pub fn set_boost(&mut self, path: impl Into<PathBuf>, factor: f64) -> io::Result<()> {
+        check_boost(factor)?;
+        let path = path.into();
+        if factor == 1f64 {
+            self.boosts.remove(&path);
+        } else {
+            self.boosts.insert(path, factor);
+        }
+        Ok(())
+    }
+
+    /// Fails for the boosts of a loaded index that [`Index::set_boost`] rejects, which would
+    /// turn rankings upside down
+    pub(crate) fn check_boosts(&self) -> io::Result<()> {
+        for boost in self.boosts.values() {
+            check_boost(*boost)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    pub fn boost(&self, path: &Path) -> f64 {
+        self.boosts.get(path).copied().unwrap_or(1f64)
+    }
+
+    /// Returns the documents that do not contain the word `term`, analyzed like queries, sorted
+    /// by path. Empty if `term` is not a single term, such as a stop word.
+    pub fn documents_without(&self, term: &str) -> Vec<&Path> {
+        let Some(term) = self.analyzer.word_term(term) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<_> = self
+            .documents
+            .iter()
+            .filter(|(_, d)| !d.contains(&term))
+            .map(|(p, _)| p.as_path())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Scores every document against `terms`, each paired with its idf, and keeps the finite
+    /// non-zero ones, as they are computed. Results are not sorted. Only the documents containing
+    /// one of `terms` are visited.
+    fn score<'a, 'q>(
+        &'a self,
+        terms: &'q [(Cow<'q, str>, f64)],
+        options: &'q SearchOptions,
+    ) -> impl Iterator<Item = (&'a Path, f64)> + use<'a, 'q> {
+        self.score_where(terms, options, |_, _| true)
+    }
+
+    /// Like [`Index::score`], skipping the documents for which `keep` returns `false` before
+    /// scoring them
+    fn score_where<'a, 'q, K: Fn(&Path, &Document) -> bool + 'q>(
+        &'a self,
+        terms: &'q [(Cow<'q, str>, f64)],
+        options: &'q SearchOptions,
+        keep: K,
+    ) -> impl Iterator<Item = (&'a Path, f64)> + use<'a, 'q, K> {
+        self.candidates(terms.iter().map(|(t, _)| &**t))
+            .into_iter()
+            .filter_map(|p| self.documents.get_key_value(p))
+            .map(|(p, d)| (p.as_path(), d))
+            .filter(move |(p, d)| keep(p, d))
+            .map(move |(filename, d)| {
+                (
+                    filename,
+                    self.boost(filename)
+                        * terms
+                            .iter()
+                            .map(|(t, idf)| d.term_score(t, *idf, options, &self.analyzer))
+                            .sum::<f64>(),
+                )
+            })
+            .filter(|(_, score)| score.is_finite() && score != &0f64)
+    }
+
+    pub fn load<R: io::Read>(reader: R) -> io::Result<Self> {
+        let mut index: Self =
+            serde_json::from_reader(reader).map_err(|err| io::Error::other(err.to_string()))?;
+        index.check_postings();
+        index.check_boosts()?;
+        Ok(index)
+    }
+
+    /// Like [`Index::load`], but skips the parts of a damaged index file that cannot be read
+    /// instead of failing. Returns the recovered index along with the skipped entries.
+    pub fn load_lenient<R: io::Read>(reader: R) -> io::Result<(Self, Vec<CorruptEntry>)> {
+        lenient::load(reader)
+    }
+
+    pub fn save<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        serde_json::to_writer(writer, self).map_err(|err| io::Error::other(err.to_string()))
+    }
+
+    /// Like [`Index::load`], for indexes saved with [`Index::save_compressed`]
+    pub fn load_compressed<R: io::Read>(reader: R) -> io::Result<Self> {
+        Self::load(GzDecoder::new(reader))
+    }
+
+    /// Like [`Index::save`], compressing the JSON with gzip
+    pub fn save_compressed<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        self.save(&mut encoder)?;
+        encoder.finish()?.flush()
+    }
+
+    /// Returns the indexed file modified last, along with its modification time. Files that
+    /// cannot be read, e.g. deleted since they were indexed, are skipped.
+    pub fn last_modified_file(&self) -> io::Result<(PathBuf, SystemTime)> {
+        if self.documents.is_empty() {
+            return Err(io::Error::other("Index does not contains any file"));
+        }
+        let mut last = None;
+        for f in self.documents.keys() {
+            let m = match metadata(f).and_then(|m| m.modified()) {
+                Ok(m) => m,
+                Err(e) => {
+                    log::warn!("skipping {path}: {e}", path = f.display());
+                    continue;
+                }
+            };
+            if last.as_ref().is_none_or(|&(_, mtime)| m > mtime) {
+                last = Some((f, m));
+            }
+        }
+        last.map(|(f, m)| (f.clone(), m))
+            .ok_or_else(|| io::Error::other("None of the indexed files can be read"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, time::Duration};
+
+    use super::*;
+    use crate::testutil::{capture_logs, TempDir};
+
+    #[test]
+    fn min_term_frequency_drops_rare_terms() {
+        let dir = TempDir::new("min-tf");
+        let path = dir.write("doc.txt", "rust rust typo");
+
+        let index = Index::new_with_options(
+            dir.path(),
+            IndexOptions {
+                min_term_frequency: 2,
+                ..Default::default()
+            },
+        );
+        let document = &index.documents[&path];
+        assert!(document.contains("rust"));
+        assert!(!document.contains("typo"));
+        assert_eq!(document.term_frequency("rust"), 2f64 / 3f64);
+
+        let index = Index::new(dir.path());
+        assert!(index.documents[&path].contains("typo"));
+    }
+
+    #[test]
+    fn min_token_len_drops_short_terms() {
+        let dir = TempDir::new("min-token-len");
+        let path = dir.write("doc.txt", "a rust crate is fast");
+        let index = Index::new_with_options(
+            dir.path(),
+            IndexOptions {
+                min_token_len: 3,
+                ..Default::default()
+            },
+        );
+        let document = &index.documents[&path];
+        assert!(!document.contains("a"));
+        assert!(!document.contains("is"));
+        assert!(document.contains("rust") && document.contains("crate"));
+        // Dropped terms still count in the length of the document
+        assert_eq!(document.term_frequency("rust"), 1f64 / 5f64);
+        assert!(Index::new(dir.path()).documents[&path].contains("is"));
+
+        // Case marks do not make words longer
+        dir.write("doc.txt", "Is Rust fast");
+        let index = Index::new_with_options(
+            dir.path(),
+            IndexOptions {
+                min_token_len: 3,
+                analyzer: Analyzer::default().with_case_sensitivity(true),
+                ..Default::default()
+            },
+        );
+        let document = &index.documents[&path];
+        assert!(!document.contains(&index.analyzer.key("Is")));
+        assert!(document.contains(&index.analyzer.key("Rust")));
+    }
+
+    #[test]
+    fn documents_without_term() {
+        let dir = TempDir::new("without");
+        let licensed = dir.write("licensed.rs", "// SPDX License MIT\nfn main() {}");
+        let a = dir.write("a.rs", "fn main() {}");
+        let b = dir.write("sub/b.txt", "no header here");
+
+        let index = Index::new(dir.path());
+        assert_eq!(
+            index.documents_without("license"),
+            [a.as_path(), b.as_path()]
+        );
+        assert_eq!(index.documents_without("main"), [b.as_path()]);
+        assert!(!index
+            .documents_without("LICENSE")
+            .contains(&licensed.as_path()));
+        assert!(index.documents_without("main()").is_empty());
+
+        let options = IndexOptions {
+            analyzer: Analyzer::default()
+                .with_stemmer(PorterStemmer)
+                .with_stop_words(Analyzer::english_stop_words()),
+            ..Default::default()
+        };
+        let stemmed = Index::new_with_options(dir.path(), options);
+        assert_eq!(
+            stemmed.documents_without("licenses"),
+            [a.as_path(), b.as_path()]
+        );
+        assert!(stemmed.documents_without("here").is_empty());
+    }
+
+    #[test]
+    fn log_tf_is_sublinear() {
+        let dir = TempDir::new("log-tf");
+        let many = dir.write("many.txt", "foo ".repeat(100));
+        let once = dir.write("once.txt", format!("foo {}", "bar ".repeat(99)));
+        dir.write("c.txt", "baz");
+        dir.write("d.txt", "baz");
+        let index = Index::new(dir.path());
+
+        let ratio = |options: &SearchOptions| {
+            let results: HashMap<_, _> = index
+                .search_with_options("foo", options)
+                .into_iter()
+                .collect();
+            results[many.as_path()] / results[once.as_path()]
+        };
+        assert!((ratio(&SearchOptions::default()) - 100f64).abs() < 1e-9);
+        let log = ratio(&SearchOptions {
+            tf: TfScheme::Log,
+            ..Default::default()
+        });
+        assert!(log > 1f64 && log < 10f64);
+    }
+
+    #[test]
+    fn augmented_tf_is_relative_to_the_most_frequent_term() {
+        let dir = TempDir::new("augmented-tf");
+        let many = dir.write("many.txt", "foo ".repeat(100));
+        let once = dir.write("once.txt", format!("foo {}", "bar ".repeat(99)));
+        let short = dir.write("short.txt", "foo bar");
+        dir.write("c.txt", "baz");
+        dir.write("d.txt", "baz");
+        dir.write("e.txt", "baz");
+        let index = Index::new(dir.path());
+        assert_eq!(index.documents[&once].max_count(), 99);
+
+        let augmented = SearchOptions {
+            tf: TfScheme::Augmented,
+            ..Default::default()
+        };
+        let results: HashMap<_, _> = index
+            .search_with_options("foo", &augmented)
+            .into_iter()
+            .collect();
+        // Both are the most frequent term of their document, whatever its length
+        assert_eq!(results[many.as_path()], results[short.as_path()]);
+        let ratio = results[many.as_path()] / results[once.as_path()];
+        assert!((ratio - 2f64 / (1f64 + 1f64 / 99f64)).abs() < 1e-9);
+        assert_eq!("augmented".parse(), Ok(TfScheme::Augmented));
+        assert_eq!(TfScheme::Augmented.weight(1, 10, 4), 0.625);
+        assert_eq!(TfScheme::Proportional.weight(3, 10, 4), 0.3);
+    }
+
+    #[test]
+    fn bigrams_match_cooccurring_words() {
+        let dir = TempDir::new("ngrams");
+        let together = dir.write("together.txt", "notes on machine learning");
+        let apart = dir.write("apart.txt", "learning to fix a machine");
+        dir.write("garden.txt", "gardening notes");
+        dir.write("kitchen.txt", "cooking notes");
+        let options = IndexOptions {
+            analyzer: Analyzer::default().with_ngram_range(1, 2),
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options);
+        assert!(index.documents[&together].contains("machine learning"));
+        assert!(!index.documents[&apart].contains("machine learning"));
+
+        let paths = |results: Vec<(&Path, f64)>| -> Vec<PathBuf> {
+            results.into_iter().map(|(p, _)| p.to_owned()).collect()
+        };
+        let both = paths(index.search("machine learning"));
+        assert_eq!(both.len(), 2);
+        assert_eq!(both[0], together);
+        let bigrams = SearchOptions {
+            query_analyzer: Some(Analyzer::default().with_ngram_range(2, 2)),
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(index.search_with_options("Machine Learning", &bigrams)),
+            [together]
+        );
+        assert!(index
+            .search_with_options("learning machine", &bigrams)
+            .is_empty());
+    }
+
+    #[test]
+    fn boosts_scale_term_scores() {
+        let dir = TempDir::new("boosts");
+        let both = dir.write("both.txt", "rust parser");
+        let rust = dir.write("rust.txt", "rust compiler");
+        dir.write("garden.txt", "gardening notes");
+        dir.write("kitchen.txt", "cooking notes");
+        dir.write("ocean.txt", "sailing notes");
+        let index = Index::new(dir.path());
+        let score = |query| index.search(query)[0].1;
+        assert!(score("rust") > 0f64);
+        assert_eq!(score("rust^2"), 2f64 * score("rust"));
+        assert_eq!(score("rust^1"), score("rust"));
+
+        let paths = |query| -> Vec<PathBuf> {
+            index
+                .search(query)
+                .into_iter()
+                .map(|(p, _)| p.to_owned())
+                .collect()
+        };
+        assert_eq!(paths("rust parser^0"), paths("rust"));
+        assert_eq!(paths("rust^3 parser")[0], both);
+        assert!(paths("rust^3 parser").contains(&rust));
+
+        // Other rankings weigh boosted terms alike
+        let options = SearchOptions::default();
+        let top_scores: [&dyn Fn(&str) -> f64; 3] = [
+            &|q| index.search_bm25(q, 1.2, 0.75)[0].1,
+            &|q| index.search_fuzzy(q, 1)[0].1,
+            &|q| index.search_phrase(q, &options)[0].1,
+        ];
+        for score in top_scores {
+            assert_eq!(score("rust^2"), 2f64 * score("rust"));
+        }
+        assert_eq!(
+            index.search_phrase("rust^2", &options),
+            index.search("rust^2")
+        );
+        let cosine = |query| {
+            let results = index.search_cosine(query);
+            results.into_iter().find(|(p, _)| *p == rust).unwrap().1
+        };
+        assert!(cosine("rust^3 parser") > cosine("rust parser"));
+    }
+
+    #[test]
+    fn query_analyzer_expands_synonyms() {
+        let dir = TempDir::new("query-analyzer");
+        let automobile = dir.write("automobile.txt", "a red automobile");
+        dir.write("bike.txt", "a blue bike");
+        dir.write("boat.txt", "a green boat");
+
+        let index = Index::new(dir.path());
+        assert!(index.search("car").is_empty());
+
+        let options = SearchOptions {
+            query_analyzer: Some(Analyzer::default().with_synonyms("car", ["automobile"])),
+            ..Default::default()
+        };
+        let results = index.search_with_options("car", &options);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, automobile.as_path());
+        assert!(!index.documents[&automobile].contains("car"));
+    }
+
+    #[test]
+    fn case_sensitive_search() {
+        let dir = TempDir::new("case-sensitive");
+        let language = dir.write("language.txt", "Rust programming");
+        let corrosion = dir.write("corrosion.txt", "rust on iron");
+        for i in 0..4 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let options = IndexOptions {
+            analyzer: Analyzer::default().with_case_sensitivity(true),
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options);
+        fn paths(results: Vec<(&Path, f64)>) -> Vec<&Path> {
+            results.into_iter().map(|(p, _)| p).collect()
+        }
+        assert_eq!(paths(index.search("Rust")), [language.as_path()]);
+        assert_eq!(paths(index.search("rust")), [corrosion.as_path()]);
+        assert_eq!(paths(index.search_terms(&["Rust"])), [language.as_path()]);
+        assert_eq!(index.search("Rust rust").len(), 2);
+        assert!(index
+            .documents_without("Rust")
+            .contains(&corrosion.as_path()));
+
+        let mut saved = Vec::new();
+        index.save(&mut saved).unwrap();
+        let loaded = Index::load(&saved[..]).unwrap();
+        assert_eq!(paths(loaded.search("Rust")), [language.as_path()]);
+
+        let insensitive = Index::new(dir.path());
+        assert_eq!(insensitive.search("Rust"), insensitive.search("rust"));
+        assert_eq!(insensitive.search("rust").len(), 2);
+
+        let options = SearchOptions {
+            query_analyzer: Some(Analyzer::default()),
+            ..Default::default()
+        };
+        assert_eq!(
+            index.try_search_with_options("Rust", &options),
+            Err(QueryError::CaseSensitivityMismatch)
+        );
+    }
+
+    #[test]
+    fn case_sensitive_terms_are_shown_unmarked() {
+        let dir = TempDir::new("case-sensitive-terms");
+        dir.write("notes.md", "# Rust notes\nrust Rust XML");
+        dir.write("other.md", "Rust Rustacean");
+        for i in 0..4 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let options = IndexOptions {
+            analyzer: Analyzer::default().with_case_sensitivity(true),
+            keep_term_forms: true,
+            capture_titles: true,
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options);
+
+        assert_eq!(index.top_terms(2)[1], (Cow::from("Rust"), 2));
+        assert_eq!(index.terms_with_prefix("Rust"), ["Rust", "Rustacean"]);
+        assert_eq!(index.terms_with_prefix("rust"), ["rust"]);
+        assert!(index
+            .cooccurrence("XML", 10)
+            .contains(&(Cow::from("Rust"), 1)));
+        assert!(index.cooccurrence("xml", 10).is_empty());
+
+        let hits = index.search_hits("Rust", &SearchOptions::default());
+        let notes = hits.iter().find(|h| h.path.ends_with("notes.md")).unwrap();
+        assert_eq!(notes.forms[0].term, "Rust");
+        assert_eq!(notes.contributions[0].0, "Rust");
+        assert_eq!(notes.fields[0].field, Field::Title);
+        assert_eq!(notes.fields[0].terms, ["Rust"]);
+        let hits = index.search_hits("rust", &SearchOptions::default());
+        assert!(hits[0].fields.iter().all(|f| f.field != Field::Title));
+    }
+
+    #[test]
+    fn root_survives_save_and_load() {
+        let dir = TempDir::new("root");
+        let file = dir.write("notes.txt", "rust notes");
+        let index = Index::new(dir.path());
+        let mut saved = Vec::new();
+        index.save(&mut saved).unwrap();
+        let mut loaded = Index::load(&saved[..]).unwrap();
+        assert_eq!(loaded.root(), Some(dir.path()));
+        assert_eq!(loaded.refresh().unwrap().skipped, 1);
+
+        // Indexes saved before the root was recorded, or built from explicit paths, lack it
+        let mut json: serde_json::Value = serde_json::from_slice(&saved).unwrap();
+        assert!(json.as_object_mut().unwrap().remove("root").is_some());
+        let mut old = Index::load(json.to_string().as_bytes()).unwrap();
+        assert_eq!(old.root(), None);
+        assert_eq!(
+            old.refresh().unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(Index::from_paths([file]).root(), None);
+    }
+
+    #[test]
+    fn saves_identical_json() {
+        let dir = TempDir::new("identical-json");
+        for i in 0..20 {
+            dir.write(
+                format!("doc{i}.txt"),
+                format!("Rust {i} borrow checker, the XML parser and rust bindings {i}"),
+            );
+        }
+        let options = || IndexOptions {
+            analyzer: Analyzer::default()
+                .with_synonyms("rust", ["ferris", "crab"])
+                .with_stop_words(Analyzer::english_stop_words()),
+            track_positions: true,
+            keep_term_forms: true,
+            ..Default::default()
+        };
+        let save = || {
+            let mut index = Index::new_with_options(dir.path(), options());
+            index.set_boost(dir.path().join("doc3.txt"), 2.0).unwrap();
+            index.set_boost(dir.path().join("doc7.txt"), 0.5).unwrap();
+            let mut saved = Vec::new();
+            index.save(&mut saved).unwrap();
+            saved
+        };
+        assert_eq!(save(), save());
+    }
+
+    #[test]
+    fn idf_schemes() {
+        let dir = TempDir::new("idf-schemes");
+        let both = dir.write("a.txt", "common rare");
+        let common = dir.write("b.txt", "common common");
+        dir.write("c.txt", "common other");
+        dir.write("d.txt", "common other");
+        let index = Index::new(dir.path());
+        let score = |query, idf| {
+            let options = SearchOptions {
+                idf,
+                ..Default::default()
+            };
+            index.search_with_options(query, &options)
+        };
+
+        assert!(score("common", IdfScheme::Log)
+            .iter()
+            .all(|(_, s)| *s < 0f64));
+        assert!(score("common", IdfScheme::Clamped).is_empty());
+        let smoothed = score("common", IdfScheme::Smoothed);
+        assert_eq!(smoothed.len(), 4);
+        assert!(smoothed.iter().all(|(_, s)| *s > 0f64 && *s < 0.2));
+        assert_eq!(smoothed[0].0, common);
+
+        let rare = |idf| score("common rare", idf)[0].0;
+        assert_eq!(rare(IdfScheme::Clamped), both);
+        assert_eq!(rare(IdfScheme::Smoothed), both);
+
+        // Frequencies are capped by the number of documents instead of panicking
+        for scheme in [IdfScheme::Log, IdfScheme::Clamped, IdfScheme::Smoothed] {
+            assert_eq!(scheme.weight(2, 5), scheme.weight(2, 2));
+        }
+    }
+
+    #[test]
+    fn drop_zero_idf_terms() {
+        let dir = TempDir::new("zero-idf");
+        dir.write("a.txt", "common rare rare");
+        dir.write("b.txt", "common rare filler filler");
+        dir.write("c.txt", "common other");
+        dir.write("d.txt", "common other");
+        let index = Index::new(dir.path());
+        assert!(index.idf("common", IdfScheme::Log) <= 0f64);
+
+        let options = SearchOptions {
+            drop_zero_idf: true,
+            ..Default::default()
+        };
+        let dropped = index.search_with_options("common rare", &options);
+        assert_eq!(dropped, index.search("rare"));
+        assert!(index.search_with_options("common", &options).is_empty());
+
+        let kept = index.search("common rare");
+        let order = |r: &[(&Path, f64)]| r.iter().map(|(p, _)| p.to_path_buf()).collect::<Vec<_>>();
+        assert_eq!(order(&kept[..2]), order(&dropped));
+        assert!(kept[0].1 < dropped[0].1);
+    }
+
+    #[test]
+    fn boosted_document_ranks_first() {
+        let dir = TempDir::new("boost");
+        let a = dir.write("a.txt", "rust");
+        let b = dir.write("b.txt", "rust");
+        dir.write("c.txt", "other");
+        dir.write("d.txt", "other");
+        let mut index = Index::new(dir.path());
+        let results = index.search("rust");
+        assert_eq!(results[0].1, results[1].1);
+
+        for pinned in [&a, &b] {
+            index.set_boost(pinned, 1.5).unwrap();
+            let results = index.search("rust");
+            assert_eq!(results[0].0, pinned.as_path());
+            assert_eq!(results[0].1, 1.5 * results[1].1);
+            index.set_boost(pinned, 1f64).unwrap();
+        }
+
+        index.set_boost(&a, 3f64).unwrap();
+        let mut saved = Vec::new();
+        index.save(&mut saved).unwrap();
+        let loaded = Index::load(&saved[..]).unwrap();
+        assert_eq!(loaded.boost(&a), 3f64);
+        assert_eq!(loaded.boost(&b), 1f64);
+
+        for factor in [-1f64, f64::NAN, f64::INFINITY] {
+            assert!(index.set_boost(&a, factor).is_err());
+        }
+        assert_eq!(index.boost(&a), 3f64);
+
+        // A negative boost read from a file would turn rankings upside down
+        index.boosts.insert(a.clone(), -3f64);
+        saved.clear();
+        index.save(&mut saved).unwrap();
+        let err = Index::load(&saved[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let (loaded, corrupt) = Index::load_lenient(&saved[..]).unwrap();
+        assert_eq!(loaded.boost(&a), 1f64);
+        assert_eq!(corrupt[0].path.as_deref(), Some(a.as_path()));
+    }
+
+    #[test]
+    fn unreadable_directories_are_logged() {
+        let dir = TempDir::new("unreadable");
+        let missing = dir.path().join("missing");
+        let records = capture_logs(|| {
+            assert!(Index::new(&missing).documents.is_empty());
+        });
+        let message = format!("cannot read {path}", path = missing.display());
+        assert!(
+            records
+                .iter()
+                .any(|(level, m)| *level == log::Level::Warn && m.starts_with(&message)),
+            "{records:?}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_skipped_or_followed() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new("symlink-loop");
+        let top = dir.write("top.txt", "top");
+        dir.write("sub/nested.txt", "nested");
+        symlink(dir.path(), dir.path().join("sub/up")).unwrap();
+        symlink(dir.path().join("sub"), dir.path().join("alias")).unwrap();
+        let linked = dir.path().join("linked.txt");
+        symlink(&top, &linked).unwrap();
+
+        let index = Index::new_with_options(
+            dir.path(),
+            IndexOptions {
+                follow_symlinks: true,
+                ..Default::default()
+            },
+        );
+        let paths: BTreeSet<_> = index.documents.keys().collect();
+        assert_eq!(paths.len(), 3, "{paths:?}");
+        assert!(paths.contains(&top) && paths.contains(&linked));
+        // Through sub or alias, whichever is visited first
+        assert!(paths.iter().any(|p| p.ends_with("nested.txt")));
+
+        let mut index = None;
+        let records = capture_logs(|| index = Some(Index::new(dir.path())));
+        let paths: BTreeSet<_> = index.unwrap().documents.into_keys().collect();
+        assert_eq!(
+            paths,
+            BTreeSet::from([top, dir.path().join("sub/nested.txt")])
+        );
+        let message = format!("skipping symbolic link {path}", path = linked.display());
+        assert!(records.iter().any(|(_, m)| *m == message), "{records:?}");
+    }
+
+    #[test]
+    fn max_depth_limits_traversal() {
+        let dir = TempDir::new("max-depth");
+        let top = dir.write("top.txt", "top");
+        let one = dir.write("a/one.txt", "one");
+        dir.write("a/b/two.txt", "two");
+        let depth = |max_depth| {
+            let index = Index::new_with_options(
+                dir.path(),
+                IndexOptions {
+                    max_depth,
+                    ..Default::default()
+                },
+            );
+            index.documents.into_keys().collect::<BTreeSet<_>>()
+        };
+
+        assert_eq!(depth(Some(0)), BTreeSet::from([top.clone()]));
+        assert_eq!(depth(Some(1)), BTreeSet::from([top, one]));
+        assert_eq!(depth(None).len(), 3);
+    }
+
+    #[test]
+    fn max_file_size_skips_large_files() {
+        let dir = TempDir::new("max-file-size");
+        let small = dir.write("small.txt", "small");
+        let large = dir.write("large.txt", "large ".repeat(100));
+        let mut index = None;
+        let records = capture_logs(|| {
+            index = Some(Index::new_with_options(
+                dir.path(),
+                IndexOptions {
+                    max_file_size: Some(100),
+                    ..Default::default()
+                },
+            ));
+        });
+        let paths: Vec<_> = index.unwrap().documents.into_keys().collect();
+        assert_eq!(paths, [small]);
+        let message = format!("skipping {path}", path = large.display());
+        assert!(
+            records
+                .iter()
+                .any(|(level, m)| *level == log::Level::Warn && m.starts_with(&message)),
+            "{records:?}"
+        );
+    }
+
+    #[test]
+    fn try_new_reports_failures() {
+        let dir = TempDir::new("try-new");
+        let good = dir.write("good.txt", "readable text");
+        let binary = dir.write("sub/binary.txt", [b'a', 0xff, 0xfe, b'b']);
+        let broken = dir.write("broken.json", r#"{"key": "#);
+
+        let (index, failures) = Index::try_new(dir.path()).unwrap();
+        assert!(index.document(&good).is_some());
+        assert_eq!(index.documents.len(), 1);
+        let mut failed: Vec<_> = failures.iter().map(|(p, _)| p.as_path()).collect();
+        failed.sort();
+        assert_eq!(failed, [broken.as_path(), binary.as_path()]);
+        assert!(failures
+            .iter()
+            .all(|(_, e)| e.kind() == io::ErrorKind::InvalidData));
+        assert_eq!(index, Index::new(dir.path()));
+
+        let missing = Index::try_new(dir.path().join("missing")).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn progress_is_reported_for_every_file() {
+        let dir = TempDir::new("progress");
+        let mut paths = BTreeSet::new();
+        for i in 0..20 {
+            paths.insert(dir.write(format!("d{}/f{i}.txt", i % 3), "some text"));
+        }
+        paths.insert(dir.write("skipped.bin", [0xff, 0xfe]));
+
+        let mut reports = Vec::new();
+        let index = Index::new_with_progress(dir.path(), IndexOptions::default(), |p| {
+            reports.push((p.processed, p.total, p.current_path.to_owned()))
+        });
+        assert_eq!(index, Index::new(dir.path()));
+        assert_eq!(index.documents.len(), 20);
+        assert_eq!(reports.len(), 21);
+        for (n, (processed, total, _)) in reports.iter().enumerate() {
+            assert_eq!((*processed, *total), (n + 1, 21));
+        }
+        let reported: BTreeSet<_> = reports.into_iter().map(|(_, _, p)| p).collect();
+        assert_eq!(reported, paths);
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        let dir = TempDir::new("compressed");
+        dir.write("a.txt", "rust tokenizer rust");
+        dir.write("b.md", "# Notes\nxml parser");
+        let index = Index::new_with_options(
+            dir.path(),
+            IndexOptions {
+                track_positions: true,
+                ..Default::default()
+            },
+        );
+
+        let mut compressed = Vec::new();
+        index.save_compressed(&mut compressed).unwrap();
+        assert_eq!(&compressed[..2], [0x1f, 0x8b]);
+        let mut plain = Vec::new();
+        index.save(&mut plain).unwrap();
+        assert!(compressed.len() < plain.len());
+
+        assert_eq!(Index::load_compressed(&compressed[..]).unwrap(), index);
+        assert!(Index::load_compressed(&plain[..]).is_err());
+    }
+
+    #[test]
+    fn file_type_terms() {
+        let dir = TempDir::new("file-type");
+        let readme = dir.write("README.md", "# Readme");
+        let notes = dir.write("docs/notes.MD", "some notes about md");
+        dir.write("main.rs", "fn main() {}");
+        dir.write("notes.txt", "md");
+        dir.write("data.csv", "a,b");
+
+        let plain = Index::new(dir.path());
+        assert!(plain.search("extension:md").is_empty());
+
+        let index = Index::new_with_options(
+            dir.path(),
+            IndexOptions {
+                index_file_type: true,
+                ..Default::default()
+            },
+        );
+        let markdown = |query| {
+            let mut paths: Vec<_> = index.search(query).into_iter().map(|(p, _)| p).collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(
+            markdown("extension:md"),
+            [readme.as_path(), notes.as_path()]
+        );
+        assert_eq!(markdown("mime:text/markdown"), markdown("extension:md"));
+        assert_eq!(
+            index.documents[&readme].count,
+            plain.documents[&readme].count
+        );
+    }
+
+    #[test]
+    fn path_terms() {
+        let dir = TempDir::new("path-terms");
+        let handler = dir.write("server/auth_handler.rs", "fn handle() {}");
+        let config = dir.write("loadConfig.txt", "settings");
+        dir.write("notes.txt", "gardening notes");
+        dir.write("recipes.txt", "cooking recipes");
+
+        assert!(Index::new(dir.path()).search("auth").is_empty());
+        let options = IndexOptions {
+            index_paths: true,
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options.clone());
+        let paths = |query| -> Vec<_> { index.search(query).into_iter().map(|(p, _)| p).collect() };
+        assert_eq!(paths("auth"), [handler.as_path()]);
+        assert_eq!(paths("server"), [handler.as_path()]);
+        assert_eq!(paths("config"), [config.as_path()]);
+        // The root is not part of the indexed paths
+        assert!(paths("indexer").is_empty());
+
+        let explicit = Index::from_paths_with_options([handler.clone()], options);
+        assert!(explicit.documents[&handler].contains("auth"));
+        assert!(!explicit.documents[&handler].contains("server"));
+    }
+
+    #[test]
+    fn title_is_boosted() {
+        let dir = TempDir::new("title");
+        let titled = dir.write("titled.txt", "\nRust notes\nsome body text");
+        let plain = dir.write("plain.txt", "Some notes\nbody about rust");
+        dir.write("c.txt", "unrelated");
+        dir.write("d.txt", "unrelated");
+
+        let index = Index::new(dir.path());
+        assert_eq!(index.document(&titled).unwrap().title(), None);
+        let results = index.search("rust");
+        assert_eq!(results[0].1, results[1].1);
+
+        let index = Index::new_with_options(
+            dir.path(),
+            IndexOptions {
+                capture_titles: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(index.document(&titled).unwrap().title(), Some("Rust notes"));
+        assert_eq!(index.document(&plain).unwrap().title(), Some("Some notes"));
+        let results = index.search("rust");
+        assert_eq!(results[0].0, titled.as_path());
+        assert!(results[0].1 > results[1].1);
+    }
+
+    #[test]
+    fn hits_report_matched_fields() {
+        let dir = TempDir::new("hits");
+        let titled = dir.write("titled.txt", "Rust notes\nrust is a language");
+        let plain = dir.write("plain.txt", "Notes\nabout rust");
+        dir.write("c.txt", "unrelated");
+        dir.write("d.txt", "unrelated");
+        let index = Index::new_with_options(
+            dir.path(),
+            IndexOptions {
+                capture_titles: true,
+                ..Default::default()
+            },
+        );
+
+        let options = SearchOptions {
+            title_boost: 3f64,
+            ..Default::default()
+        };
+        let hits = index.search_hits("rust notes", &options);
+        assert_eq!(
+            hits.iter().map(|h| (h.path, h.score)).collect::<Vec<_>>(),
+            index.search_with_options("rust notes", &options)
+        );
+
+        let hit = hits.iter().find(|h| h.path == titled).unwrap();
+        assert_eq!(
+            hit.fields,
+            [
+                FieldMatch {
+                    field: Field::Title,
+                    boost: 3f64,
+                    terms: vec!["rust".into(), "notes".into()],
+                },
+                FieldMatch {
+                    field: Field::Body,
+                    boost: 1f64,
+                    terms: vec!["rust".into(), "notes".into()],
+                },
+            ]
+        );
+        let hit = hits.iter().find(|h| h.path == plain).unwrap();
+        assert_eq!(hit.fields[0].field, Field::Title);
+        assert_eq!(hit.fields[0].terms, ["notes"]);
+        assert_eq!(hit.fields[1].terms, ["rust", "notes"]);
+    }
+
+    #[test]
+    fn directory_cap_diversifies_results() {
+        let dir = TempDir::new("directory-cap");
+        for i in 0..4 {
+            dir.write(format!("big/a/{i}.txt"), "rust rust rust");
+            dir.write(format!("big/b/{i}.txt"), "rust rust filler");
+        }
+        let small = dir.write("small/rust.txt", "rust filler filler filler");
+        for i in 0..10 {
+            dir.write(format!("other/{i}.txt"), "filler");
+        }
+        let index = Index::new(dir.path());
+        assert_eq!(index.search("rust").len(), 9);
+
+        let directories = |results: &[(&Path, f64)], depth: usize| {
+            let mut counts = HashMap::new();
+            for (path, _) in results {
+                let directory: PathBuf = path.components().take(depth).collect();
+                *counts.entry(directory).or_insert(0) += 1;
+            }
+            counts
+        };
+        let depth = dir.path().components().count() + 1;
+
+        let mut options = SearchOptions {
+            directory_cap: Some(DirectoryCap {
+                max: 2,
+                depth: None,
+            }),
+            ..Default::default()
+        };
+        let results = index.search_with_options("rust", &options);
+        assert_eq!(results.len(), 5);
+        assert!(directories(&results, depth + 1).values().all(|&c| c <= 2));
+        assert_eq!(results[4].0, small.as_path());
+
+        options.directory_cap = Some(DirectoryCap {
+            max: 2,
+            depth: Some(depth),
+        });
+        let results = index.search_with_options("rust", &options);
+        assert_eq!(results.len(), 3);
+        assert!(directories(&results, depth).values().all(|&c| c <= 2));
+        assert!(results[..2]
+            .iter()
+            .all(|(p, _)| p.starts_with(dir.path().join("big/a"))));
+        assert_eq!(results[2].0, small.as_path());
+    }
+
+    #[test]
+    fn auto_cutoff_truncates_at_gap() {
+        let dir = TempDir::new("auto-cutoff");
+        let relevant = [
+            dir.write("a.txt", "rust"),
+            dir.write("b.txt", "rust rust rust filler"),
+            dir.write("c.txt", "rust rust filler"),
+        ];
+        for i in 0..4 {
+            let content = format!("rust {}", "filler ".repeat(10 + i));
+            dir.write(format!("noise{i}.txt"), content);
+        }
+        for i in 0..10 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let index = Index::new(dir.path());
+
+        let mut options = SearchOptions::default();
+        let all = index.search_with_options("rust", &options);
+        assert_eq!(all.len(), 7);
+        options.auto_cutoff = true;
+        let results = index.search_with_options("rust", &options);
+        assert_eq!(results, all[..3]);
+        for path in &relevant {
+            assert!(results.iter().any(|(p, _)| p == path));
+        }
+
+        let uniform = TempDir::new("auto-cutoff-uniform");
+        for i in 0..4 {
+            uniform.write(format!("{i}.txt"), "rust filler");
+        }
+        for i in 0..10 {
+            uniform.write(format!("other{i}.txt"), "other");
+        }
+        let index = Index::new(uniform.path());
+        assert_eq!(index.search_with_options("rust", &options).len(), 4);
+    }
+
+    #[test]
+    fn query_term_limit() {
+        let dir = TempDir::new("term-limit");
+        dir.write("a.txt", "alpha");
+        dir.write("b.txt", "beta");
+        dir.write("c.txt", "gamma");
+        for i in 0..6 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let index = Index::new(dir.path());
+
+        let mut options = SearchOptions {
+            max_query_terms: Some(2),
+            ..Default::default()
+        };
+        let results = index
+            .try_search_with_options("alpha beta gamma", &options)
+            .unwrap();
+        assert_eq!(results, index.search("alpha beta"));
+
+        options.term_limit_policy = TermLimitPolicy::Reject;
+        assert_eq!(
+            index.try_search_with_options("alpha beta gamma", &options),
+            Err(QueryError::TooManyTerms { count: 3, max: 2 })
+        );
+        assert!(index
+            .search_with_options("alpha beta gamma", &options)
+            .is_empty());
+        assert_eq!(
+            index.try_search_page_with_options("alpha beta gamma", &options, 0, 1),
+            Err(QueryError::TooManyTerms { count: 3, max: 2 })
+        );
+        assert_eq!(
+            index.try_search_with_options("alpha beta", &options),
+            Ok(index.search("alpha beta"))
+        );
+    }
+
+    #[test]
+    fn hits_report_matched_forms() {
+        let dir = TempDir::new("forms");
+        let content = "Rust notes: RUST is fast, my Car runs rust";
+        let path = dir.write("a.txt", content);
+        for i in 0..4 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let options = IndexOptions {
+            analyzer: Analyzer::default().with_synonyms("car", ["automobile"]),
+            keep_term_forms: true,
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options);
+
+        let hits = index.search_hits("rust automobile missing", &SearchOptions::default());
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            hits[0].forms,
+            [
+                TermForms {
+                    term: "rust".into(),
+                    forms: vec!["Rust".into(), "RUST".into(), "rust".into()],
+                },
+                TermForms {
+                    term: "automobile".into(),
+                    forms: vec!["Car".into()],
+                },
+            ]
+        );
+        let words: Vec<_> = tokenizer::Lexer::new(content).collect();
+        for form in hits[0].forms.iter().flat_map(|f| &f.forms) {
+            assert!(words.contains(&form.as_str()), "{form}");
+        }
+
+        let index = Index::new(dir.path());
+        assert_eq!(index.document(&path).unwrap().forms("RUST"), ["Rust"]);
+    }
+
+    #[test]
+    fn empty_documents_rank_last() {
+        let dir = TempDir::new("empty-document");
+        let empty = dir.write("empty.txt", "");
+        dir.write("rust.txt", "rust");
+        for i in 0..8 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let mut index = Index::new(dir.path());
+        // Not produced by the tokenizers, but found in hand-edited index files
+        let document = index.documents_mut().get_mut(&empty).unwrap();
+        assert_eq!(document.count, 0);
+        document
+            .term_frequency
+            .insert(CaseInsensitiveString::from("rust"), 1);
+        assert_eq!(document.term_frequency("rust"), 0f64);
+
+        let results = index.search("rust");
+        assert!(!results.is_empty());
+        assert!(results
+            .iter()
+            .position(|(p, _)| *p == empty)
+            .is_none_or(|i| i == results.len() - 1));
+
+        let mut results = [
+            (Path::new("a"), f64::NAN),
+            (Path::new("b"), 1f64),
+            (Path::new("c"), 2f64),
+            (Path::new("d"), f64::NAN),
+        ];
+        sort_results(&mut results);
+        let paths: Vec<_> = results.iter().map(|(p, _)| p.to_str().unwrap()).collect();
+        assert_eq!(paths, ["c", "b", "a", "d"]);
+    }
+
+    #[test]
+    fn document_terms() {
+        let dir = TempDir::new("document-terms");
+        let path = dir.write("a.txt", "Rust rust borrow checker\nrust");
+        let index = Index::new(dir.path());
+        let document = index.document(&path).unwrap();
+
+        let mut terms: Vec<_> = document
+            .terms()
+            .map(|(t, c)| (t.to_lowercase(), c))
+            .collect();
+        terms.sort();
+        assert_eq!(
+            terms,
+            [
+                ("borrow".into(), 1),
+                ("checker".into(), 1),
+                ("rust".into(), 3)
+            ]
+        );
+        assert_eq!(document.token_count(), 5);
+        assert_eq!(
+            document.terms().map(|(_, c)| c).sum::<usize>(),
+            document.token_count()
+        );
+    }
+
+    #[test]
+    fn match_all_terms() {
+        let dir = TempDir::new("match-mode");
+        let both = dir.write("both.txt", "rust parser for the xml format");
+        let partial = dir.write("partial.txt", "rust rust rust borrow checker");
+        for i in 0..8 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let index = Index::new_with_options(
+            dir.path(),
+            IndexOptions {
+                analyzer: Analyzer::default().with_stop_words(Analyzer::english_stop_words()),
+                ..Default::default()
+            },
+        );
+        fn paths<'a>(results: &[(&'a Path, f64)]) -> Vec<&'a Path> {
+            results.iter().map(|(p, _)| *p).collect()
+        }
+
+        let any = index.search_with_mode("the rust xml", MatchMode::Any);
+        assert_eq!(any, index.search("the rust xml"));
+        assert!(paths(&any).contains(&partial.as_path()));
+        let all = index.search_with_mode("the rust xml", MatchMode::All);
+        assert_eq!(paths(&all), [both.as_path()]);
+        assert!(index
+            .search_with_mode("rust -xml", MatchMode::All)
+            .iter()
+            .all(|(p, _)| *p == partial));
+
+        let options = SearchOptions {
+            match_mode: MatchMode::All,
+            ..Default::default()
+        };
+        let terms = index.search_terms_with_options(&["rust", "xml"], &options);
+        assert_eq!(paths(&terms), [both.as_path()]);
+    }
+
+    #[test]
+    fn search_pages() {
+        let dir = TempDir::new("pages");
+        for i in 0..25 {
+            let content = format!("{} {}", "rust ".repeat(i % 6 + 1), "filler ".repeat(i % 4));
+            dir.write(format!("sub{}/{i}.txt", i % 3), content);
+            dir.write(format!("other{i}.txt"), "nothing");
+        }
+        let index = Index::new(dir.path());
+        let coverage = SearchOptions {
+            rank: RankMode::Coverage,
+            ..Default::default()
+        };
+        let capped = SearchOptions {
+            directory_cap: Some(DirectoryCap {
+                max: 5,
+                depth: None,
+            }),
+            ..Default::default()
+        };
+        for options in [SearchOptions::default(), coverage, capped] {
+            let all = index.search_with_options("rust filler", &options);
+            for (offset, count) in [(0, 10), (10, 10), (20, 10), (3, 0), (0, usize::MAX)] {
+                let page = index.search_page_with_options("rust filler", &options, offset, count);
+                let end = offset.saturating_add(count).min(all.len());
+                assert_eq!(page, all[offset.min(end)..end], "{offset} {count}");
+            }
+        }
+
+        assert_eq!(index.search_page("rust", 10, 10).len(), 10);
+        assert!(index.search_page("rust", 25, 10).is_empty());
+        assert!(index.search_page("rust", usize::MAX, 10).is_empty());
+        assert!(index.search_page("missing", 0, 10).is_empty());
+    }
+
+    #[test]
+    fn search_top_results() {
+        let dir = TempDir::new("top");
+        for i in 0..40 {
+            dir.write(
+                format!("{i}.txt"),
+                "rust ".repeat(i % 9 + 1) + &"word ".repeat(i % 5),
+            );
+            dir.write(format!("other{i}.txt"), "nothing");
+        }
+        let index = Index::new(dir.path());
+        let all = index.search("rust word");
+        assert!(all.len() > 20);
+        for count in [0, 1, 10, all.len(), all.len() + 5] {
+            assert_eq!(
+                index.search_top("rust word", count),
+                all[..count.min(all.len())]
+            );
+        }
+    }
+
+    #[test]
+    fn search_batch_matches_single_searches() {
+        let dir = TempDir::new("batch");
+        dir.write("a.txt", "rust borrow checker");
+        dir.write("b.txt", "rust rust macros");
+        dir.write("c.txt", "xml parser");
+        dir.write("d.txt", "csv parser notes");
+        dir.write("e.txt", "gardening notes");
+        let index = Index::new(dir.path());
+        let queries = [
+            "rust",
+            "parser -xml",
+            "rust AND macros",
+            "rust^2 notes",
+            "pars*",
+            "",
+            "missing",
+            "rust",
+        ];
+        for count in [1, 3, usize::MAX] {
+            let expected: Vec<_> = queries.iter().map(|q| index.search_top(q, count)).collect();
+            assert_eq!(index.search_batch(&queries, count), expected);
+        }
+        let all: Vec<_> = queries.iter().map(|q| index.search(q)).collect();
+        assert_eq!(index.search_batch(&queries, usize::MAX), all);
+        assert!(index.search_batch(&[], 10).is_empty());
+    }
+
+    #[test]
+    fn search_with_deadline() {
+        let dir = TempDir::new("deadline");
+        for i in 0..200 {
+            dir.write(format!("{i}.txt"), "rust ".repeat(i % 7 + 1));
+            dir.write(format!("other{i}.txt"), "nothing");
+        }
+        let index = Index::new(dir.path());
+
+        let start = Instant::now();
+        let expired = index.search_with_deadline("rust", start);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(expired.partial);
+        assert!(expired.results.is_empty());
+
+        let later = Instant::now() + Duration::from_secs(3600);
+        let complete = index.search_with_deadline("rust", later);
+        assert!(!complete.partial);
+        assert_eq!(complete.results, index.search("rust"));
+        let missing = index.search_with_deadline("missing", later);
+        assert!(!missing.partial && missing.results.is_empty());
+
+        // Prefixes are not expanded past the deadline
+        let expired = index.search_with_deadline("ru*", start);
+        assert!(expired.partial && expired.results.is_empty());
+        assert_eq!(
+            index.search_with_deadline("ru*", later).results,
+            index.search("rust")
+        );
+    }
+
+    #[test]
+    fn index_explicit_paths() {
+        let dir = TempDir::new("from-paths");
+        let a = dir.write("a.txt", "rust notes");
+        let c = dir.write("sub/c.md", "# Title\nxml parser");
+        dir.write("b.txt", "not listed");
+        let missing = dir.path().join("missing.txt");
+        let paths = [
+            a.clone(),
+            missing.clone(),
+            c.clone(),
+            dir.path().join("sub"),
+            a.clone(),
+        ];
+
+        let mut index = None;
+        let logs = capture_logs(|| index = Some(Index::from_paths(paths)));
+        let index = index.unwrap();
+        assert_eq!(
+            index.documents.keys().collect::<BTreeSet<_>>(),
+            BTreeSet::from([&a, &c])
+        );
+        assert_eq!(index.document(&c).unwrap().kind(), SourceKind::Markdown);
+        let warnings: Vec<_> = logs
+            .iter()
+            .filter(|(level, _)| *level == log::Level::Warn)
+            .map(|(_, message)| message.as_str())
+            .collect();
+        assert_eq!(warnings.len(), 2, "{warnings:?}");
+        assert!(warnings[0].contains(&missing.display().to_string()));
+        assert!(warnings[1].contains("not a regular file"));
+    }
+
+    #[test]
+    fn prefix_queries() {
+        let dir = TempDir::new("prefix");
+        let tokenizer = dir.write("tokenizer.txt", "the Tokenizer splits text");
+        let tokenize = dir.write("tokenize.txt", "tokenize then tokens");
+        let token = dir.write("token.txt", "a token");
+        dir.write("tool.txt", "a tool");
+        for i in 0..6 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let index = Index::new(dir.path());
+        fn paths(results: Vec<(&Path, f64)>) -> BTreeSet<PathBuf> {
+            results.into_iter().map(|(p, _)| p.to_path_buf()).collect()
+        }
+
+        assert_eq!(
+            index.terms_with_prefix("TOKEN"),
+            ["token", "tokenize", "Tokenizer", "tokens"]
+        );
+        assert_eq!(
+            paths(index.search("token*")),
+            BTreeSet::from([tokenizer.clone(), tokenize.clone(), token.clone()])
+        );
+        assert_eq!(
+            paths(index.search("Tokeni* -then")),
+            BTreeSet::from([tokenizer])
+        );
+        // Expanded terms score like the terms of the query
+        assert_eq!(
+            index.search("tokenizer* tokens*"),
+            index.search("tokenizer tokens")
+        );
+        assert!(index.search("zzz*").is_empty());
+        assert_eq!(paths(index.search("zzz* token")), BTreeSet::from([token]));
+        assert!(index.terms_with_prefix("zzz").is_empty());
+    }
+
+    #[test]
+    fn contributions_add_up_to_scores() {
+        let dir = TempDir::new("contributions");
+        let notes = dir.write("notes.md", "# Rust notes\nrust borrow checker, xml parser");
+        dir.write("xml.txt", "xml xml parser and rust bindings");
+        dir.write("borrow.txt", "borrow checker errors");
+        for i in 0..6 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let mut index = Index::new_with_options(
+            dir.path(),
+            IndexOptions {
+                capture_titles: true,
+                track_positions: true,
+                ..Default::default()
+            },
+        );
+        index.set_boost(&notes, 1.5).unwrap();
+        let options = SearchOptions {
+            title_boost: 2f64,
+            position_boost: Some(PositionBoost::default()),
+            ..Default::default()
+        };
+
+        let hits = index.search_hits("rust xml borrow rust missing", &options);
+        assert_eq!(hits.len(), 3);
+        for hit in &hits {
+            let total: f64 = hit.contributions.iter().map(|(_, c)| c).sum();
+            assert!((total - hit.score).abs() < 1e-9 * hit.score, "{hit:?}");
+            assert!(hit
+                .contributions
+                .iter()
+                .all(|(t, c)| t != "missing" && *c > 0f64));
+        }
+        let notes_hit = hits.iter().find(|h| h.path == notes).unwrap();
+        let terms: Vec<_> = notes_hit
+            .contributions
+            .iter()
+            .map(|(t, _)| t.as_str())
+            .collect();
+        assert_eq!(terms, ["rust", "xml", "borrow"]);
+    }
+
+    #[test]
+    fn search_filtered_by_metadata() {
+        let dir = TempDir::new("filtered");
+        let now = SystemTime::now();
+        let week = Duration::from_secs(7 * 24 * 3600);
+        let recent = dir.write("recent.txt", "rust notes");
+        let old = dir.write("old.txt", "rust notes from long ago");
+        File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(now - 2 * week)
+            .unwrap();
+        for i in 0..8 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let index = Index::new(dir.path());
+        let meta = DocumentMeta::new(&old, index.document(&old).unwrap());
+        assert_eq!(meta.size, Some(24));
+        assert!(meta.mtime.is_some_and(|m| m < now - week));
+
+        let paths = |results: Vec<(&Path, f64)>| -> Vec<PathBuf> {
+            results.into_iter().map(|(p, _)| p.to_path_buf()).collect()
+        };
+        let this_week = |m: &DocumentMeta| m.mtime.is_some_and(|t| t >= now - week);
+        assert_eq!(paths(index.search_filtered("rust", this_week)), [recent]);
+        assert_eq!(
+            index.search_filtered("rust", |_| true),
+            index.search("rust")
+        );
+        assert!(index
+            .search_filtered("rust", |m| m.size.is_some_and(|s| s > 100))
+            .is_empty());
+
+        // Documents of older indexes have no size
+        let mut json: serde_json::Value = serde_json::to_value(&index).unwrap();
+        for document in json["documents"].as_object_mut().unwrap().values_mut() {
+            document.as_object_mut().unwrap().remove("size");
+        }
+        let loaded: Index = serde_json::from_value(json).unwrap();
+        assert_eq!(loaded.document(&old).unwrap().size(), None);
+        assert!(loaded
+            .search_filtered("rust", |m| m.size.is_some())
+            .is_empty());
+    }
+
+    #[test]
+    fn coverage_ranks_before_score() {
+        let dir = TempDir::new("coverage");
+        let broad = dir.write("broad.txt", "alpha beta and many unrelated words in here");
+        let narrow = dir.write("narrow.txt", "gamma");
+        for i in 0..8 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let index = Index::new(dir.path());
+
+        let results = index.search("alpha beta gamma");
+        assert_eq!(results[0].0, narrow.as_path());
+        assert!(results[0].1 > results[1].1);
+
+        let options = SearchOptions {
+            rank: RankMode::Coverage,
+            ..Default::default()
+        };
+        let results = index.search_with_options("alpha beta gamma gamma", &options);
+        assert_eq!(
+            results.iter().map(|(p, _)| *p).collect::<Vec<_>>(),
+            [broad.as_path(), narrow.as_path()]
+        );
+    }
+
+    #[test]
+    fn search_terms_matches_search() {
+        let dir = TempDir::new("search-terms");
+        dir.write("a.txt", "Rust notes\nrust borrow checker");
+        dir.write("b.txt", "borrow a car");
+        dir.write("c.txt", "checker board");
+        for i in 0..4 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let index = Index::new(dir.path());
+
+        let results = index.search("RUST, borrow... checker!");
+        assert_eq!(results.len(), 3);
+        assert_eq!(results, index.search_terms(&["rust", "borrow", "checker"]));
+
+        let options = SearchOptions {
+            tf: TfScheme::Log,
+            rank: RankMode::Coverage,
+            ..Default::default()
+        };
+        assert_eq!(
+            index.search_with_options("borrow checker", &options),
+            index.search_terms_with_options(&["borrow", "checker"], &options)
+        );
+        assert!(index.search_terms(&["borrow checker"]).is_empty());
+    }
+
+    #[test]
+    fn boolean_operators() {
+        let dir = TempDir::new("boolean");
+        let a = dir.write("a.txt", "rust tokenizer");
+        let b = dir.write("b.txt", "rust rust rust xml");
+        let c = dir.write("c.txt", "tokenizer xml");
+        dir.write("d.txt", "python");
+        for i in 0..6 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let index = Index::new(dir.path());
+        let paths = |query| {
+            let mut paths: Vec<_> = index.search(query).into_iter().map(|(p, _)| p).collect();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(index.search("rust")[0].0, b);
+        assert_eq!(paths("rust AND tokenizer"), [&a]);
+        assert_eq!(paths("rust -xml"), [&a]);
+        assert_eq!(paths("xml OR rust AND tokenizer"), [&a, &b, &c]);
+        assert_eq!(paths("(xml OR rust) && tokenizer"), [&a, &c]);
+        assert_eq!(paths("rust AND NOT (xml OR python)"), [&a]);
+        let required = index.search("tokenizer +rust");
+        assert_eq!(required.len(), 2);
+        assert_eq!(required[0].0, a);
+        assert!(index.search("NOT xml").is_empty());
+        assert_eq!(
+            index
+                .search_hits("rust -xml", &SearchOptions::default())
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn phrases_combine_with_operators() {
+        let dir = TempDir::new("boolean-phrases");
+        let phrase = dir.write("phrase.txt", "machine learning in rust");
+        let apart = dir.write("apart.txt", "learning about the machine in rust");
+        let python = dir.write("python.txt", "machine learning in python");
+        for i in 0..6 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let options = IndexOptions {
+            track_positions: true,
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options);
+        let paths = |query| {
+            let mut paths: Vec<_> = index.search(query).into_iter().map(|(p, _)| p).collect();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(paths(r#""machine learning""#), [&phrase, &python]);
+        assert_eq!(paths(r#""machine learning" -python"#), [&phrase]);
+        assert_eq!(paths(r#"rust AND NOT "machine learning""#), [&apart]);
+        assert_eq!(
+            paths(r#"("learning machine" OR python) rust"#),
+            [&apart, &phrase, &python]
+        );
+        assert_eq!(paths(r#"+"learning about" rust"#), [&apart]);
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let results = index.search_with_deadline(r#"rust AND "machine learning""#, deadline);
+        assert!(!results.partial);
+        assert_eq!(results.results.len(), 1);
+        assert_eq!(results.results[0].0, phrase);
+        // Without positions, phrases match documents containing all their words
+        assert_eq!(
+            Index::new(dir.path())
+                .search(r#""machine learning" AND rust"#)
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn position_boost_favors_early_matches() {
+        let dir = TempDir::new("position-boost");
+        let filler = "lorem ipsum dolor sit amet ".repeat(40);
+        let filler = filler.trim_end();
+        let early = dir.write("early.txt", format!("rust is great {filler}"));
+        let late = dir.write("late.txt", format!("{filler} rust is great"));
+        for i in 0..4 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let options = IndexOptions {
+            track_positions: true,
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options);
+
+        let results = index.search("rust");
+        assert_eq!(results[0].1, results[1].1);
+
+        let options = SearchOptions {
+            position_boost: Some(PositionBoost::default()),
+            ..Default::default()
+        };
+        let results = index.search_with_options("rust", &options);
+        assert_eq!(
+            results.iter().map(|(p, _)| *p).collect::<Vec<_>>(),
+            [early.as_path(), late.as_path()]
+        );
+        assert!(results[0].1 > 1.5 * results[1].1);
+
+        let unpositioned = Index::new(dir.path());
+        let results = unpositioned.search_with_options("rust", &options);
+        assert_eq!(results[0].1, results[1].1);
+
+        // They would leave scores undefined
+        for (weight, half_life) in [(1f64, 0f64), (1f64, f64::NAN), (-1f64, 50f64)] {
+            assert!(PositionBoost::new(weight, half_life).is_err());
+        }
+        let boost = PositionBoost::with_half_life(10f64).unwrap();
+        assert_eq!(boost.weight(), PositionBoost::default().weight());
+    }
+
+    #[test]
+    fn remove_document() {
+        let dir = TempDir::new("remove-document");
+        let removed = dir.write("removed.txt", "alpha beta beta");
+        let kept = dir.write("kept.txt", "beta gamma");
+        let mut index = Index::new(dir.path());
+        let document = index.remove_document(&removed).unwrap();
+        assert!(document.contains("alpha"));
+        assert!(index.remove_document(&removed).is_none());
+
+        std::fs::remove_file(&removed).unwrap();
+        let expected = Index::new(dir.path());
+        assert_eq!(index, expected);
+        for term in ["alpha", "beta", "gamma"] {
+            assert_eq!(
+                index.document_frequency(term),
+                expected.document_frequency(term),
+                "{term}"
+            );
+        }
+        assert_eq!(index.document_frequency("alpha"), 0);
+        assert_eq!(index.documents_without("alpha"), [kept.as_path()]);
+    }
+
+    #[test]
+    fn last_modified_file_skips_missing_files() {
+        let dir = TempDir::new("last-modified");
+        let old = dir.write("old.txt", "old");
+        let new = dir.write("new.txt", "new");
+        let removed = dir.write("removed.txt", "removed");
+        let now = SystemTime::now();
+        for (path, age) in [(&old, 20), (&new, 10), (&removed, 0)] {
+            File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(now - std::time::Duration::from_secs(age))
+                .unwrap();
+        }
+        let index = Index::new(dir.path());
+        assert_eq!(index.last_modified_file().unwrap().0, removed);
+
+        std::fs::remove_file(&removed).unwrap();
+        let (path, mtime) = index.last_modified_file().unwrap();
+        assert_eq!(path, new);
+        assert_eq!(mtime, now - std::time::Duration::from_secs(10));
+
+        std::fs::remove_file(&old).unwrap();
+        std::fs::remove_file(&new).unwrap();
+        assert!(index.last_modified_file().is_err());
+        assert!(Index::new(dir.path()).last_modified_file().is_err());
+    }
+
+    #[test]
+    fn markdown_files_are_indexed() {
+        let dir = TempDir::new("markdown");
+        let path = dir.write("README.md", "# Indexer\n\nSee [the docs](https://docs.rs).");
+        let options = IndexOptions {
+            capture_titles: true,
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options);
+        let document = index.document(&path).unwrap();
+        assert_eq!(document.title(), Some("Indexer"));
+        assert!(document.contains("docs"));
+        assert!(!document.contains("#"));
+        assert!(!document.contains("https"));
+    }
+
+    #[test]
+    fn html_files_are_indexed() {
+        let dir = TempDir::new("html");
+        let path = dir.write(
+            "page.html",
+            "<title>Home</title><p>Hello<br>world<script>track()</script>",
+        );
+        let index = Index::new(dir.path());
+        let document = index.document(&path).unwrap();
+        assert!(document.contains("hello"));
+        assert!(document.contains("world"));
+        assert!(!document.contains("track"));
+        assert!(!document.contains("p"));
+    }
+
+    #[test]
+    fn documents_record_their_source_kind() {
+        let dir = TempDir::new("source-kind");
+        let page = dir.write("page.html", "<p>rust pages</p>");
+        let notes = dir.write("notes.md", "# rust notes");
+        let data = dir.write("data.tsv", "rust\tdata");
+        let other = dir.write("other.cfg", "rust settings");
+        for i in 0..4 {
+            dir.write(format!("filler{i}.txt"), "filler");
+        }
+        let mut index = Index::new(dir.path());
+        let kind = |index: &Index, path| index.document(path).unwrap().kind();
+        assert_eq!(kind(&index, &page), SourceKind::Html);
+        assert_eq!(kind(&index, &notes), SourceKind::Markdown);
+        assert_eq!(kind(&index, &data), SourceKind::Csv);
+        assert_eq!(kind(&index, &other), SourceKind::Text);
+
+        let paths = |results: Vec<(&Path, f64)>| {
+            let mut paths: Vec<_> = results.into_iter().map(|(p, _)| p.to_owned()).collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(
+            paths(index.search_in_kinds("rust", &[SourceKind::Html, SourceKind::Csv])),
+            [data.clone(), page.clone()]
+        );
+        assert!(index
+            .search_in_kinds("rust", &[SourceKind::Json])
+            .is_empty());
+
+        // Kinds survive a round trip, and documents of older indexes are deemed text
+        let mut saved = Vec::new();
+        index.save(&mut saved).unwrap();
+        let loaded = Index::load(&saved[..]).unwrap();
+        assert_eq!(loaded, index);
+        index.documents.get_mut(&page).unwrap().kind = None;
+        assert_eq!(kind(&index, &page), SourceKind::Text);
+
+        // Documents are indexed again with their recorded tokenizer
+        index.documents.get_mut(&notes).unwrap().kind = Some(SourceKind::Text);
+        index.reindex([notes.clone()]);
+        assert_eq!(kind(&index, &notes), SourceKind::Text);
+        assert!(index.document(&notes).unwrap().contains("#"));
+    }
+
+    #[test]
+    fn json_files_are_indexed() {
+        let dir = TempDir::new("json");
+        let path = dir.write(
+            "config.json",
+            r#"{"server": {"name": "primary", "aliases": ["main", "primary"], "port": 8080}}"#,
+        );
+        let index = Index::new(dir.path());
+        let document = index.document(&path).unwrap();
+        assert_eq!(document.count, 3);
+        assert!(document.contains("primary"));
+        assert!(!document.contains("server"));
+        assert!(!document.contains("8080"));
+    }
+
+    #[test]
+    fn stemmed_queries_match_stemmed_documents() {
+        let dir = TempDir::new("stemming");
+        let runs = dir.write("runs.txt", "she runs daily");
+        let running = dir.write("running.txt", "Running shoes");
+        for i in 0..4 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        assert!(Index::new(dir.path()).search("run").is_empty());
+
+        let options = IndexOptions {
+            analyzer: Analyzer::default().with_stemmer(PorterStemmer),
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options);
+        for query in ["run", "running", "RUNS"] {
+            let mut paths: Vec<_> = index.search(query).into_iter().map(|(p, _)| p).collect();
+            paths.sort();
+            assert_eq!(paths, [running.as_path(), runs.as_path()], "{query}");
+        }
+    }
+
+    #[test]
+    fn parallel_build_matches_sequential() {
+        let dir = TempDir::new("parallel");
+        for i in 0..300 {
+            let content = format!(
+                "Title {i}\nshared term{} words {}",
+                i % 7,
+                "word ".repeat(i % 5)
+            );
+            dir.write(format!("d{}/f{i}.txt", i % 10), content.trim_end());
+        }
+        dir.write("d3/.indexer.toml", "min_term_frequency = 2");
+        let options = IndexOptions {
+            capture_titles: true,
+            track_positions: true,
+            ..Default::default()
+        };
+        let parallel = Index::new_with_options(dir.path(), options.clone());
+        assert_eq!(parallel.documents.len(), 300);
+
+        let mut sequential = Index {
+            documents: HashMap::new(),
+            analyzer: Analyzer::default(),
+            boosts: HashMap::new(),
+            options,
+            root: Some(dir.path().to_owned()),
+            postings: Default::default(),
+        };
+        sequential.update(dir.path()).unwrap();
+        assert_eq!(parallel, sequential);
+    }
 }
@@ -1,18 +1,32 @@
+#[cfg(feature = "server")]
+use std::time::{Duration, Instant};
 use std::{
-    fs::{metadata, File},
-    io::{self, BufReader, BufWriter},
-    path::Path,
+    collections::HashMap,
+    fmt,
+    fs::{canonicalize, metadata, rename, File},
+    io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    str::FromStr,
     time::SystemTime,
 };
 
 use clap::{Parser, Subcommand};
+use flate2::read::GzDecoder;
 
-use indexer::Index;
+use indexer::{
+    tokenizer::{Column, Delimiter},
+    Analyzer, CorruptEntry, DirectoryCap, Document, IdfScheme, Index, IndexOptions, Language,
+    MappedIndex, MatchMode, MultiIndex, PorterStemmer, PositionBoost, Progress, RankMode,
+    SearchOptions, StalePolicy, TermLimitPolicy, TfScheme,
+};
 
 #[derive(Debug, Parser)]
 #[command(author, version, about)]
 struct Options {
-    /// Index file to use
+    /// Index file to use, a `.mmap` file being searched in place without loading it, a `.gz`
+    /// file being compressed with gzip and a `.bin` file using a binary format. When searching,
+    /// a directory of index files is searched as a whole
     #[arg(
         short = 'i',
         long = "index",
@@ -21,6 +35,10 @@ struct Options {
     )]
     index_file: String,
 
+    /// Skip the damaged parts of the index file instead of failing to load it
+    #[arg(long, default_value_t = false)]
+    lenient: bool,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -33,9 +51,103 @@ enum Command {
         #[arg(short, long, default_value_t = false)]
         force: bool,
 
-        /// Directory to index
-        #[arg(default_value = ".")]
-        directory: String,
+        /// Only process the files added, modified or deleted since the index file was built,
+        /// with the options it was built with
+        #[arg(short, long, default_value_t = false, conflicts_with = "force")]
+        update: bool,
+
+        /// Only index terms appearing at least this many times in a document
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        min_term_frequency: usize,
+
+        /// Only index terms at least this many characters long
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        min_token_len: usize,
+
+        /// Keep the first non-empty line of text documents as their title
+        #[arg(long, default_value_t = false)]
+        titles: bool,
+
+        /// Cell separator of CSV files: a single character, "tab" or "auto"
+        #[arg(long, value_name = "DELIMITER", default_value = ",")]
+        csv_delimiter: Delimiter,
+
+        /// The first row of CSV files names their columns, and is not indexed
+        #[arg(long, default_value_t = false)]
+        csv_header: bool,
+
+        /// Only index these CSV columns, given by position from 0 or header name
+        #[arg(long, value_name = "COLUMN", value_delimiter = ',')]
+        csv_columns: Option<Vec<Column>>,
+
+        /// Make extensions and MIME types searchable as `extension:rs` or `mime:text/plain`
+        #[arg(long, default_value_t = false)]
+        file_type: bool,
+
+        /// Also index the words of file paths, so that "auth" finds `auth_handler.rs`
+        #[arg(long, default_value_t = false)]
+        paths: bool,
+
+        /// Record term positions, needed by quoted phrases in queries (larger index)
+        #[arg(long, default_value_t = false)]
+        positions: bool,
+
+        /// Record the words each term was derived from, to report what searches matched
+        #[arg(long, default_value_t = false)]
+        forms: bool,
+
+        /// Do not index common English words such as "the" or "of"
+        #[arg(long, default_value_t = false)]
+        stop_words: bool,
+
+        /// Reduce English words to their stem, so that "running" matches "runs"
+        #[arg(long, default_value_t = false)]
+        stem: bool,
+
+        /// Keep words differing only in case, such as "Rust" and "rust", apart
+        #[arg(long, default_value_t = false)]
+        case_sensitive: bool,
+
+        /// Detect the language of each file, English or French, and stem it and drop its stop
+        /// words accordingly. Files in other languages are not stemmed. The language of queries
+        /// is not detected: search with --language to stem them likewise
+        #[arg(long, default_value_t = false)]
+        detect_language: bool,
+
+        /// Also index runs of up to N consecutive words as single terms, so that a query for
+        /// "machine learning" ranks documents where both words follow each other higher
+        #[arg(long, value_name = "N", default_value_t = NonZeroUsize::MIN)]
+        ngrams: NonZeroUsize,
+
+        /// Only index files at most DEPTH directories beneath the root
+        #[arg(long, value_name = "DEPTH")]
+        max_depth: Option<usize>,
+
+        /// Skip files larger than BYTES
+        #[arg(long, value_name = "BYTES")]
+        max_file_size: Option<u64>,
+
+        /// Skip files and directories matching PATTERN, in the .gitignore syntax and relative to
+        /// the directory to index. Can be repeated
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+
+        /// Skip the files and directories ignored by .gitignore files, and .git directories
+        #[arg(long, default_value_t = false)]
+        gitignore: bool,
+
+        /// Index the targets of symbolic links instead of skipping them
+        #[arg(long, default_value_t = false)]
+        follow_symlinks: bool,
+
+        /// Index the files listed on the standard input, one per line, e.g. by `git ls-files`,
+        /// instead of walking a directory
+        #[arg(long, default_value_t = false, conflicts_with_all = ["update", "directory"])]
+        stdin: bool,
+
+        /// Directory to index, by default the current directory, or with --update the directory
+        /// the index was built from
+        directory: Option<String>,
     },
 
     /// search for terms
@@ -44,14 +156,152 @@ enum Command {
         #[arg(short, long, default_value_t = 10)]
         count: usize,
 
+        /// Number of best results to skip, to display the next page
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        /// Print how much each query term found in a result adds to its score
+        #[arg(long, default_value_t = false)]
+        explain: bool,
+
+        /// Output format: text, json (an array of objects with a path and a score) or csv
+        /// (`path,score` lines after a header)
+        #[arg(long, value_name = "FORMAT", default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+
+        /// Term frequency weighting: proportional, log to dampen repeated terms, or augmented
+        /// to weigh terms against the most frequent one of each document
+        #[arg(long, value_name = "SCHEME", default_value_t = TfScheme::Proportional)]
+        tf: TfScheme,
+
+        /// Inverse document frequency weighting: log, clamped to ignore terms found in nearly
+        /// every document, or smoothed to keep every term weight positive
+        #[arg(long, value_name = "SCHEME", default_value_t = IdfScheme::Log)]
+        idf: IdfScheme,
+
+        /// Ignore query terms present in (nearly) every document
+        #[arg(long, default_value_t = false)]
+        drop_zero_idf: bool,
+
+        /// Show at most N results from the same directory
+        #[arg(long, value_name = "N")]
+        max_per_directory: Option<usize>,
+
+        /// Group results by their first DEPTH path components instead of their parent directory
+        #[arg(long, value_name = "DEPTH", requires = "max_per_directory")]
+        directory_depth: Option<usize>,
+
+        /// Stop at the first large drop in scores among the top results
+        #[arg(long, default_value_t = false)]
+        auto_cutoff: bool,
+
+        /// Maximum number of terms in a query
+        #[arg(long, value_name = "N")]
+        max_query_terms: Option<usize>,
+
+        /// What to do with longer queries: truncate or reject
+        #[arg(long, value_name = "POLICY", default_value_t = TermLimitPolicy::Truncate)]
+        on_long_query: TermLimitPolicy,
+
+        /// Result order: score, or coverage to rank documents matching more query terms first
+        #[arg(long, value_name = "MODE", default_value_t = RankMode::Score)]
+        rank: RankMode,
+
+        /// Documents to return: those containing any query term, or all of them
+        #[arg(long = "match", value_name = "MODE", default_value_t = MatchMode::Any)]
+        match_mode: MatchMode,
+
+        /// Weigh query terms found near the start of documents higher, the boost halving every
+        /// TOKENS tokens. Needs an index built with --positions
+        #[arg(long, value_name = "TOKENS", value_parser = parse_half_life)]
+        position_half_life: Option<PositionBoost>,
+
+        /// What to do with files changed since the index file was written: warn, update the
+        /// index file, or error. Only single index files other than `.mmap` ones are checked,
+        /// update and error fail for the others
+        #[arg(long, value_name = "POLICY", default_value_t = StalePolicy::Warn)]
+        on_stale: StalePolicy,
+
+        /// Stem the query and drop its stop words as in LANGUAGE, english or french, to match
+        /// the documents of an index built with --detect-language. Without it, the query is not
+        /// stemmed in the language of each document, so it may miss words stemmed in them
+        #[arg(long, value_name = "LANGUAGE")]
+        language: Option<Language>,
+
+        /// List the documents that do not contain TERM instead of ranking documents
+        #[arg(long, value_name = "TERM", conflicts_with = "query")]
+        missing: Option<String>,
+
         /// Query
-        query: String,
+        #[arg(required_unless_present = "missing")]
+        query: Option<String>,
     },
 
     /// Checks that files references in index file are up to date
     Check,
+
+    /// Compares the index to the files on disk: unchanged, modified and deleted documents, and
+    /// files of the indexed directory missing from the index
+    Verify {
+        /// Exit with an error if the index does not match the files
+        #[arg(long, default_value_t = false)]
+        strict: bool,
+
+        /// Directory to compare the index to, by default the one it was built from
+        directory: Option<String>,
+    },
+
+    /// Prints the number of documents and distinct terms, and the terms found in the most
+    /// documents: candidates for a stop word list
+    Stats {
+        /// Number of terms to list
+        #[arg(long, value_name = "N", default_value_t = 20)]
+        top: usize,
+    },
+
+    /// Removes the documents whose file no longer exists from the index
+    Prune,
+
+    /// Multiplies the score of a document in every search (1.0 is neutral)
+    Boost {
+        /// Indexed document, as stored in the index
+        path: PathBuf,
+
+        /// Score multiplier
+        factor: f64,
+    },
+
+    /// Loads the index once, then reads queries from the standard input, one per line, until its
+    /// end or `:quit`. `:count N` changes the number of results displayed and `:again` runs the
+    /// last query again
+    Interactive {
+        /// Maximum number of results to display
+        #[arg(short, long, default_value_t = 10)]
+        count: usize,
+    },
+
+    /// Loads the index once and answers searches over HTTP: `GET /search?q=QUERY&count=N`
+    /// returns `[{"path": ..., "score": ...}]`, and `GET /healthz` whether the server is up
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        address: String,
+
+        /// Maximum number of results of a response, whatever `count` asks for
+        #[arg(long, value_name = "N", default_value_t = 100)]
+        max_count: usize,
+
+        /// Stop scoring documents after MS milliseconds and answer with the best results found
+        /// so far, flagged by an `X-Partial-Results: true` header. Only applies to single index
+        /// files, neither memory-mapped nor sharded
+        #[arg(long, value_name = "MS")]
+        timeout: Option<u64>,
+    },
 }
 
+/// Whether a file exists at `path`. Symbolic links are followed, so a link reports the type of
+/// its target, and a dangling link is an error rather than a missing file.
 fn file_exists(path: impl AsRef<Path>) -> io::Result<bool> {
     let path = path.as_ref();
     match metadata(path) {
@@ -59,19 +309,21 @@ fn file_exists(path: impl AsRef<Path>) -> io::Result<bool> {
             if m.is_file() {
                 Ok(true)
             } else if m.is_dir() {
-                Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("{p} points to a directory", p = path.display()),
-                ))
-            } else if m.is_symlink() {
-                unreachable!()
+                Err(io::Error::other(format!(
+                    "{p} points to a directory",
+                    p = path.display()
+                )))
             } else {
-                Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("{p} points to an unknown type", p = path.display()),
-                ))
+                Err(io::Error::other(format!(
+                    "{p} points to an unknown type",
+                    p = path.display()
+                )))
             }
         }
+        Err(e) if e.kind() == io::ErrorKind::NotFound && path.is_symlink() => Err(io::Error::new(
+            e.kind(),
+            format!("{p} is a dangling symbolic link", p = path.display()),
+        )),
         Err(e) => {
             if e.kind() == io::ErrorKind::NotFound {
                 Ok(false)
@@ -82,11 +334,484 @@ fn file_exists(path: impl AsRef<Path>) -> io::Result<bool> {
     }
 }
 
+/// Parses the half-life of a [`PositionBoost`], in tokens
+fn parse_half_life(s: &str) -> Result<PositionBoost, String> {
+    let half_life = s.parse::<f64>().map_err(|e| e.to_string())?;
+    PositionBoost::with_half_life(half_life).map_err(|e| e.to_string())
+}
+
 fn get_last_modified_time(path: impl AsRef<Path>) -> io::Result<SystemTime> {
     let m = metadata(path)?;
     m.modified()
 }
 
+/// Whether the index file at `path` is compressed with gzip
+fn is_compressed(path: impl AsRef<Path>) -> bool {
+    path.as_ref().extension().is_some_and(|e| e == "gz")
+}
+
+/// Whether the index file at `path` uses the binary format of [`Index::save_bin`]
+fn is_binary(path: impl AsRef<Path>) -> bool {
+    path.as_ref().extension().is_some_and(|e| e == "bin")
+}
+
+/// Error for `.bin` index files when built without binary support
+#[cfg(not(feature = "binary"))]
+fn binary_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "binary index files need the `binary` feature",
+    )
+}
+
+fn load_index(path: impl AsRef<Path>, lenient: bool) -> io::Result<Index> {
+    if is_binary(&path) {
+        // Not mapped: the index file is rewritten in place by other commands
+        #[cfg(feature = "binary")]
+        return Index::load_bin(File::open(path)?);
+        #[cfg(not(feature = "binary"))]
+        return Err(binary_unsupported());
+    }
+    let file = BufReader::new(File::open(&path)?);
+    let reader: Box<dyn Read> = if is_compressed(&path) {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    if !lenient {
+        return Index::load(reader);
+    }
+    let (index, corrupt) = Index::load_lenient(reader)?;
+    log_corrupt(&corrupt);
+    Ok(index)
+}
+
+/// Warns about the parts of index files skipped by lenient loading
+fn log_corrupt(corrupt: &[CorruptEntry]) {
+    for entry in corrupt {
+        match entry.path {
+            Some(ref p) => log::warn!(
+                "skipped corrupt entry {p}: {e}",
+                p = p.display(),
+                e = entry.error
+            ),
+            None => log::warn!("skipped corrupt data: {e}", e = entry.error),
+        }
+    }
+}
+
+/// Writes `index` at `path`, in binary if its name ends with `.bin`, compressed if it ends with
+/// `.gz`
+fn save_index(index: &Index, path: impl AsRef<Path>) -> io::Result<()> {
+    if is_binary(&path) {
+        #[cfg(feature = "binary")]
+        return index.save_bin(File::create(path)?);
+        #[cfg(not(feature = "binary"))]
+        return Err(binary_unsupported());
+    }
+    let writer = BufWriter::new(File::create(&path)?);
+    if is_compressed(&path) {
+        index.save_compressed(writer)
+    } else {
+        index.save(writer)
+    }
+}
+
+/// Writes `index` at `path` in the [`MappedIndex`] layout. Processes may have the old file
+/// mapped, so it is never modified: the index is written next to it and renamed over it.
+fn save_mapped(index: &Index, path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    index.save_mapped(File::create(&tmp)?)?;
+    rename(&tmp, path)
+}
+
+/// Prints the progress of [`Index::new_with_progress`] on a single line of the standard error,
+/// when it is a terminal
+fn progress_printer() -> impl FnMut(Progress) + Send {
+    let terminal = io::stderr().is_terminal();
+    let mut shown = None;
+    move |progress| {
+        let percent = progress.processed * 100 / progress.total;
+        if !terminal || shown == Some(percent) {
+            return;
+        }
+        shown = Some(percent);
+        eprint!(
+            "\rIndexed {processed}/{total} files ({percent}%)",
+            processed = progress.processed,
+            total = progress.total
+        );
+        if progress.processed == progress.total {
+            eprintln!();
+        }
+    }
+}
+
+/// Analyzer of a new index
+fn build_analyzer(
+    stop_words: bool,
+    stem: bool,
+    case_sensitive: bool,
+    ngrams: NonZeroUsize,
+) -> Analyzer {
+    let mut analyzer = Analyzer::default()
+        .with_case_sensitivity(case_sensitive)
+        .with_ngram_range(1, ngrams.get());
+    if stop_words {
+        analyzer = analyzer.with_stop_words(Analyzer::english_stop_words());
+    }
+    if stem {
+        analyzer = analyzer.with_stemmer(PorterStemmer);
+    }
+    analyzer
+}
+
+/// Whether the index file at `path` uses the [`MappedIndex`] layout
+fn is_mapped(path: impl AsRef<Path>) -> bool {
+    path.as_ref().extension().is_some_and(|e| e == "mmap")
+}
+
+/// A single index file, or a directory of index files searched together
+enum Searchable {
+    Single(Box<Index>),
+    Mapped(MappedIndex),
+    Shards(MultiIndex),
+}
+
+impl Searchable {
+    fn load(path: impl AsRef<Path>, lenient: bool) -> io::Result<Self> {
+        let path = path.as_ref();
+        if metadata(path)?.is_dir() {
+            if !lenient {
+                return Ok(Self::Shards(MultiIndex::load_dir(path)?));
+            }
+            let (index, corrupt) = MultiIndex::load_dir_lenient(path)?;
+            log_corrupt(&corrupt);
+            Ok(Self::Shards(index))
+        } else if is_mapped(path) {
+            Ok(Self::Mapped(MappedIndex::open(path)?))
+        } else {
+            Ok(Self::Single(Box::new(load_index(path, lenient)?)))
+        }
+    }
+
+    fn search(&self, query: &str, options: &SearchOptions) -> io::Result<Vec<(&Path, f64)>> {
+        let results = match self {
+            Self::Single(index) => index.try_search_with_options(query, options),
+            Self::Mapped(index) => index.try_search_with_options(query, options),
+            Self::Shards(index) => index.try_search_with_options(query, options),
+        };
+        results.map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
+    }
+
+    /// The `count` results of [`Searchable::search`] following the first `offset` ones, without
+    /// sorting every result for single index files
+    fn search_page(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        offset: usize,
+        count: usize,
+    ) -> io::Result<Vec<(&Path, f64)>> {
+        match self {
+            Self::Single(index) => index
+                .try_search_page_with_options(query, options, offset, count)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string())),
+            _ => Ok(page(self.search(query, options)?, offset, count)),
+        }
+    }
+
+    /// Like [`Searchable::search`], giving up on scoring documents once `deadline` has passed,
+    /// see `Index::search_with_deadline`. Whether results are partial comes along with them.
+    #[cfg(feature = "server")]
+    fn search_until(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        deadline: Instant,
+    ) -> io::Result<(Vec<(&Path, f64)>, bool)> {
+        match self {
+            Self::Single(index) => index
+                .try_search_with_deadline(query, options, deadline)
+                .map(|r| (r.results, r.partial))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string())),
+            _ => self.search(query, options).map(|results| (results, false)),
+        }
+    }
+
+    /// Contribution of each query term to the score of each result, see `SearchHit::contributions`
+    fn explain(&self, query: &str, options: &SearchOptions) -> HashMap<&Path, Vec<(String, f64)>> {
+        match self {
+            Self::Single(index) => index
+                .search_hits(query, options)
+                .into_iter()
+                .map(|hit| (hit.path, hit.contributions))
+                .collect(),
+            _ => {
+                log::warn!("scores are only explained for single index files");
+                HashMap::new()
+            }
+        }
+    }
+
+    fn title(&self, path: &Path) -> Option<&str> {
+        match self {
+            Self::Single(index) => index.document(path).and_then(Document::title),
+            Self::Mapped(index) => index.title(path),
+            Self::Shards(index) => index.document(path).and_then(Document::title),
+        }
+    }
+
+    fn documents_without(&self, term: &str) -> Vec<&Path> {
+        match self {
+            Self::Single(index) => index.documents_without(term),
+            Self::Mapped(index) => index.documents_without(term),
+            Self::Shards(index) => index.documents_without(term),
+        }
+    }
+}
+
+/// How search results are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    /// `path: score` lines, followed by the title of the document if known
+    Text,
+    /// An array of `{"path": ..., "score": ...}` objects
+    Json,
+    /// `path,score` lines after a header
+    Csv,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Text => "text",
+            Self::Json => "json",
+            Self::Csv => "csv",
+        })
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!("unknown output format {s:?}")),
+        }
+    }
+}
+
+/// Prints `results`, the page of the results of `query` following the first `offset` ones
+fn print_results(
+    index: &Searchable,
+    query: &str,
+    results: Vec<(&Path, f64)>,
+    offset: usize,
+    explanations: &HashMap<&Path, Vec<(String, f64)>>,
+) {
+    if results.is_empty() && offset == 0 {
+        println!("No match for query {query:?}");
+    } else if results.is_empty() {
+        println!("No more than {offset} results for query {query:?}");
+    }
+    for (p, s) in results {
+        match index.title(p) {
+            Some(title) => println!("{path}: {s} ({title})", path = p.display()),
+            None => println!("{path}: {s}", path = p.display()),
+        }
+        for (term, contribution) in explanations.get(p).into_iter().flatten() {
+            println!("    {term}: {contribution}");
+        }
+    }
+}
+
+/// Answers the queries read from `input` until its end or `:quit`, see `Command::Interactive`
+fn interactive(index: &Searchable, input: impl BufRead, mut count: usize) -> io::Result<()> {
+    let prompt = io::stdin().is_terminal();
+    let show_prompt = || -> io::Result<()> {
+        if prompt {
+            print!("> ");
+            io::stdout().flush()?;
+        }
+        Ok(())
+    };
+    let mut last: Option<String> = None;
+    show_prompt()?;
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        let query = match line.split_once(' ').unwrap_or((line, "")) {
+            ("", _) => None,
+            (":quit", _) => return Ok(()),
+            (":count", n) => {
+                match n.trim().parse() {
+                    Ok(n) => count = n,
+                    Err(_) => eprintln!("Invalid count {n:?}"),
+                }
+                None
+            }
+            (":again", _) if last.is_none() => {
+                eprintln!("No query to run again");
+                None
+            }
+            (":again", _) => last.clone(),
+            (command, _) if command.starts_with(':') => {
+                eprintln!("Unknown command {command}, expected :count N, :again or :quit");
+                None
+            }
+            _ => Some(line.to_owned()),
+        };
+        if let Some(query) = query {
+            match index.search_page(&query, &SearchOptions::default(), 0, count) {
+                Ok(results) => print_results(index, &query, results, 0, &HashMap::new()),
+                Err(e) => eprintln!("{e}"),
+            }
+            last = Some(query);
+        }
+        show_prompt()?;
+    }
+    if prompt {
+        println!();
+    }
+    Ok(())
+}
+
+/// The `count` results following the first `offset` ones
+fn page(results: Vec<(&Path, f64)>, offset: usize, count: usize) -> Vec<(&Path, f64)> {
+    results.into_iter().skip(offset).take(count).collect()
+}
+
+/// Writes `results` as a JSON array, also listing the contribution of each query term to the
+/// results that are explained
+fn write_json(
+    mut out: impl io::Write,
+    results: &[(&Path, f64)],
+    explanations: &HashMap<&Path, Vec<(String, f64)>>,
+) -> io::Result<()> {
+    let results: Vec<_> = results
+        .iter()
+        .map(|&(path, score)| {
+            let mut result = serde_json::json!({ "path": path.to_string_lossy(), "score": score });
+            if let Some(contributions) = explanations.get(path) {
+                result["contributions"] = contributions.iter().cloned().collect();
+            }
+            result
+        })
+        .collect();
+    serde_json::to_writer(&mut out, &results)?;
+    writeln!(out)
+}
+
+/// Writes `results` as `path,score` CSV records after a header, paths being quoted as needed
+fn write_csv(out: impl io::Write, results: &[(&Path, f64)]) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(["path", "score"])?;
+    for (path, score) in results {
+        writer.write_record([&*path.to_string_lossy(), &score.to_string()])?;
+    }
+    writer.flush()
+}
+
+/// Answers the requests of `address` until the process is stopped
+#[cfg(feature = "server")]
+fn serve(
+    index: &Searchable,
+    address: &str,
+    max_count: usize,
+    timeout: Option<Duration>,
+) -> io::Result<()> {
+    let server = tiny_http::Server::http(address).map_err(|e| io::Error::other(e.to_string()))?;
+    log::info!("listening on {address}");
+    let content_type =
+        tiny_http::Header::from_bytes("Content-Type", "application/json").expect("valid header");
+    let partial_results =
+        tiny_http::Header::from_bytes("X-Partial-Results", "true").expect("valid header");
+    for request in server.incoming_requests() {
+        let (status, body, partial) =
+            route(index, request.method(), request.url(), max_count, timeout);
+        log::debug!(
+            "{method} {url}: {status}",
+            method = request.method(),
+            url = request.url()
+        );
+        let mut response = tiny_http::Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(content_type.clone());
+        if partial {
+            log::warn!("{url}: timed out, partial results", url = request.url());
+            response.add_header(partial_results.clone());
+        }
+        if let Err(e) = request.respond(response) {
+            log::warn!("sending response: {e}");
+        }
+    }
+    Ok(())
+}
+
+/// Status code and body of the response to `method url`, and whether the search timed out with
+/// partial results
+#[cfg(feature = "server")]
+fn route(
+    index: &Searchable,
+    method: &tiny_http::Method,
+    url: &str,
+    max_count: usize,
+    timeout: Option<Duration>,
+) -> (u16, serde_json::Value, bool) {
+    use serde_json::json;
+
+    if *method != tiny_http::Method::Get {
+        return (405, json!({ "error": "only GET is supported" }), false);
+    }
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    match path {
+        "/healthz" => (200, json!({ "status": "ok" }), false),
+        "/search" => {
+            let mut q = None;
+            let mut count = 10;
+            for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                match &*key {
+                    "q" => q = Some(value.into_owned()),
+                    "count" => match value.parse() {
+                        Ok(c) => count = c,
+                        Err(_) => return (400, json!({ "error": "invalid count" }), false),
+                    },
+                    _ => {}
+                }
+            }
+            let Some(q) = q else {
+                return (400, json!({ "error": "missing q parameter" }), false);
+            };
+            let options = SearchOptions::default();
+            let results = match timeout {
+                Some(timeout) => index.search_until(&q, &options, Instant::now() + timeout),
+                None => index.search(&q, &options).map(|results| (results, false)),
+            };
+            match results {
+                Ok((results, partial)) => {
+                    let hits: Vec<_> = results
+                        .into_iter()
+                        .take(count.min(max_count))
+                        .map(|(path, score)| {
+                            json!({ "path": path.to_string_lossy(), "score": score })
+                        })
+                        .collect();
+                    (200, json!(hits), partial)
+                }
+                Err(e) => (400, json!({ "error": e.to_string() }), false),
+            }
+        }
+        _ => (404, json!({ "error": "not found" }), false),
+    }
+}
+
 fn main() -> io::Result<()> {
     env_logger::init();
     let options = Options::parse();
@@ -95,30 +820,172 @@ fn main() -> io::Result<()> {
         Command::Build {
             ref directory,
             force,
+            update,
+            min_term_frequency,
+            min_token_len,
+            titles,
+            csv_delimiter,
+            csv_header,
+            ref csv_columns,
+            file_type,
+            paths,
+            positions,
+            forms,
+            stop_words,
+            stem,
+            case_sensitive,
+            detect_language,
+            ngrams,
+            max_depth,
+            max_file_size,
+            ref exclude,
+            gitignore,
+            follow_symlinks,
+            stdin,
         } => {
-            if force || !file_exists(&options.index_file)? {
-                log::info!("Computing index for {directory}...");
-                let index = Index::new(directory);
-                let f = File::create(&options.index_file)?;
-                index.save(BufWriter::new(f))?;
+            if update && !is_mapped(&options.index_file) && file_exists(&options.index_file)? {
+                let mut index = load_index(&options.index_file, options.lenient)?;
+                let stats = match directory {
+                    Some(directory) => index.update(directory)?,
+                    None => index.refresh().map_err(|e| {
+                        io::Error::new(e.kind(), format!("{e}, give the directory to update"))
+                    })?,
+                };
+                log::info!(
+                    "{added} added, {removed} removed, {updated} updated, {skipped} unchanged",
+                    added = stats.added,
+                    removed = stats.removed,
+                    updated = stats.updated,
+                    skipped = stats.skipped
+                );
+                save_index(&index, &options.index_file)?;
+                log::info!("Saved index at {path}", path = &options.index_file);
+            } else if force || update || !file_exists(&options.index_file)? {
+                let index_options = IndexOptions {
+                    min_term_frequency,
+                    min_token_len,
+                    capture_titles: titles,
+                    csv_delimiter,
+                    csv_header,
+                    csv_columns: csv_columns.clone(),
+                    index_file_type: file_type,
+                    index_paths: paths,
+                    track_positions: positions,
+                    keep_term_forms: forms,
+                    max_depth,
+                    max_file_size,
+                    exclude: exclude.clone(),
+                    gitignore,
+                    follow_symlinks,
+                    detect_language,
+                    analyzer: build_analyzer(stop_words, stem, case_sensitive, ngrams),
+                };
+                let index = if stdin {
+                    let paths = io::stdin()
+                        .lines()
+                        .filter(|line| line.as_ref().map_or(true, |l| !l.is_empty()))
+                        .map(|line| line.map(PathBuf::from))
+                        .collect::<io::Result<Vec<_>>>()?;
+                    log::info!("Computing index for {n} files...", n = paths.len());
+                    Index::from_paths_with_options(paths, index_options)
+                } else {
+                    let directory = directory.as_deref().unwrap_or(".");
+                    log::info!("Computing index for {directory}...");
+                    Index::new_with_progress(directory, index_options, progress_printer())
+                };
+                if is_mapped(&options.index_file) {
+                    save_mapped(&index, &options.index_file)?;
+                } else {
+                    save_index(&index, &options.index_file)?;
+                }
                 log::info!("Saved index at {path}", path = &options.index_file);
             } else {
                 log::warn!("Index already exists");
             }
         }
-        Command::Search { count, ref query } => {
-            let index = Index::load(BufReader::new(File::open(&options.index_file)?))?;
-            let results = index.search(query);
-            if results.is_empty() {
-                println!("No match for query {query:?}");
+        Command::Search {
+            count,
+            offset,
+            explain,
+            format,
+            tf,
+            idf,
+            drop_zero_idf,
+            max_per_directory,
+            directory_depth,
+            auto_cutoff,
+            max_query_terms,
+            on_long_query,
+            rank,
+            match_mode,
+            position_half_life,
+            on_stale,
+            language,
+            ref missing,
+            ref query,
+        } => {
+            let mut index = Searchable::load(&options.index_file, options.lenient)?;
+            if let Searchable::Single(ref mut index) = index {
+                let index_time = get_last_modified_time(&options.index_file)?;
+                let stale = index.handle_stale(index_time, on_stale)?;
+                if on_stale == StalePolicy::Update && !stale.is_empty() {
+                    save_index(index, &options.index_file)?;
+                    log::info!("Updated index at {path}", path = &options.index_file);
+                }
+            } else if on_stale == StalePolicy::Warn {
+                log::warn!(
+                    "cannot check whether {path} is stale, only single index files can be",
+                    path = &options.index_file
+                );
+            } else {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                        "--on-stale {on_stale} needs a single index file, not {path}",
+                        path = &options.index_file
+                    ),
+                ));
             }
-            for (p, s) in results.into_iter().take(count) {
-                println!("{path}: {s}", path = p.display());
+            if let Some(term) = missing {
+                for p in index.documents_without(term) {
+                    println!("{path}", path = p.display());
+                }
+            } else if let Some(query) = query {
+                let search_options = SearchOptions {
+                    tf,
+                    idf,
+                    drop_zero_idf,
+                    directory_cap: max_per_directory.map(|max| DirectoryCap {
+                        max,
+                        depth: directory_depth,
+                    }),
+                    auto_cutoff,
+                    max_query_terms,
+                    term_limit_policy: on_long_query,
+                    rank,
+                    match_mode,
+                    position_boost: position_half_life,
+                    language,
+                    ..Default::default()
+                };
+                let results = index.search_page(query, &search_options, offset, count)?;
+                let explanations = if explain {
+                    index.explain(query, &search_options)
+                } else {
+                    HashMap::new()
+                };
+                match format {
+                    OutputFormat::Text => {
+                        print_results(&index, query, results, offset, &explanations)
+                    }
+                    OutputFormat::Json => write_json(io::stdout().lock(), &results, &explanations)?,
+                    OutputFormat::Csv => write_csv(io::stdout().lock(), &results)?,
+                }
             }
         }
         Command::Check => {
             let index_time = get_last_modified_time(&options.index_file)?;
-            let index = Index::load(BufReader::new(File::open(&options.index_file)?))?;
+            let index = load_index(&options.index_file, options.lenient)?;
             let (filename, mtime) = index.last_modified_file()?;
             if index_time >= mtime {
                 println!("Index file {f} is up to date", f = &options.index_file);
@@ -130,7 +997,214 @@ fn main() -> io::Result<()> {
                 );
             }
         }
+        Command::Stats { top } => {
+            let index = load_index(&options.index_file, options.lenient)?;
+            let documents = index.document_count();
+            println!("{documents} documents");
+            println!("{terms} distinct terms", terms = index.vocabulary_size());
+            for (term, count) in index.top_terms(top) {
+                let share = 100f64 * count as f64 / documents as f64;
+                println!("{count:>8} {share:>5.1}% {term}");
+            }
+        }
+        Command::Verify {
+            strict,
+            ref directory,
+        } => {
+            let index_time = get_last_modified_time(&options.index_file)?;
+            let index = load_index(&options.index_file, options.lenient)?;
+            let mut verification = match directory {
+                Some(directory) => index.verify_root(directory, index_time)?,
+                None => index.verify(index_time).map_err(|e| {
+                    io::Error::new(e.kind(), format!("{e}, give the directory to compare to"))
+                })?,
+            };
+            // The index file may be written beneath the indexed directory
+            let index_file = canonicalize(&options.index_file)?;
+            verification
+                .unindexed
+                .retain(|p| canonicalize(p).is_ok_and(|p| p != index_file));
+            for (label, paths) in [
+                ("modified", &verification.modified),
+                ("missing", &verification.missing),
+                ("unindexed", &verification.unindexed),
+            ] {
+                for path in paths {
+                    println!("{label}: {path}", path = path.display());
+                }
+            }
+            println!(
+                "{unchanged} unchanged, {modified} modified, {missing} missing, \
+                 {unindexed} unindexed",
+                unchanged = verification.unchanged.len(),
+                modified = verification.modified.len(),
+                missing = verification.missing.len(),
+                unindexed = verification.unindexed.len()
+            );
+            if strict && verification.has_drift() {
+                return Err(io::Error::other(format!(
+                    "{f} does not match the files it was built from",
+                    f = &options.index_file
+                )));
+            }
+        }
+        Command::Prune => {
+            let mut index = load_index(&options.index_file, options.lenient)?;
+            let pruned = index.prune(|p| {
+                file_exists(p).unwrap_or_else(|e| {
+                    log::warn!("keeping {path}: {e}", path = p.display());
+                    true
+                })
+            });
+            for path in &pruned {
+                log::info!("pruned {path}", path = path.display());
+            }
+            if !pruned.is_empty() {
+                save_index(&index, &options.index_file)?;
+            }
+            println!("Pruned {n} documents", n = pruned.len());
+        }
+        Command::Boost { ref path, factor } => {
+            let mut index = load_index(&options.index_file, options.lenient)?;
+            if index.document(path).is_none() {
+                log::warn!("{path} is not indexed yet", path = path.display());
+            }
+            index.set_boost(path, factor)?;
+            save_index(&index, &options.index_file)?;
+        }
+        Command::Interactive { count } => {
+            let index = Searchable::load(&options.index_file, options.lenient)?;
+            if let Searchable::Single(ref index) = index {
+                index.build_postings();
+            }
+            interactive(&index, io::stdin().lock(), count)?;
+        }
+        #[cfg(feature = "server")]
+        Command::Serve {
+            ref address,
+            max_count,
+            timeout,
+        } => {
+            let index = Searchable::load(&options.index_file, options.lenient)?;
+            match index {
+                Searchable::Single(ref index) => index.build_postings(),
+                _ if timeout.is_some() => log::warn!(
+                    "--timeout only applies to single index files, searches of {f} run to the end",
+                    f = options.index_file
+                ),
+                _ => {}
+            }
+            serve(
+                &index,
+                address,
+                max_count,
+                timeout.map(Duration::from_millis),
+            )?;
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "server")]
+    use std::fs;
+
+    #[cfg(feature = "server")]
+    use tiny_http::Method;
+
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_half_lives() {
+        let search = |half_life| {
+            Options::try_parse_from(["indexer", "search", "--position-half-life", half_life, "x"])
+        };
+        assert!(search("12.5").is_ok());
+        for half_life in ["0", "-3", "inf", "NaN", "soon"] {
+            assert!(search(half_life).is_err(), "{half_life}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_non_utf8_paths_as_json() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let path = Path::new(OsStr::from_bytes(b"caf\xe9.txt"));
+        let mut out = Vec::new();
+        write_json(&mut out, &[(path, 1.0)], &HashMap::new()).unwrap();
+        let results: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(results[0]["path"], "caf\u{fffd}.txt");
+    }
+
+    #[cfg(feature = "server")]
+    fn searchable(name: &str, files: usize) -> Searchable {
+        let dir = std::env::temp_dir().join(format!("indexer-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for i in 0..files {
+            fs::write(dir.join(format!("rust{i}.txt")), "rust ".repeat(i + 1)).unwrap();
+            fs::write(dir.join(format!("other{i}.txt")), "other").unwrap();
+        }
+        let index = Index::new(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        Searchable::Single(Box::new(index))
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn routes_requests() {
+        let index = searchable("route", 8);
+        let get = |url: &str| route(&index, &Method::Get, url, 5, None);
+
+        let (status, body, partial) = get("/search?q=rust&count=3");
+        assert_eq!((status, partial), (200, false));
+        let hits = body.as_array().unwrap();
+        assert_eq!(hits.len(), 3);
+        assert!(hits
+            .iter()
+            .all(|h| h["path"].as_str().unwrap().contains("rust")));
+        assert_eq!(
+            get("/search?q=rust&count=100").1.as_array().unwrap().len(),
+            5
+        );
+        assert_eq!(get("/search?q=rust").1.as_array().unwrap().len(), 5);
+        assert_eq!(get("/search?q=missing").1, serde_json::json!([]));
+
+        assert_eq!(get("/healthz").0, 200);
+        assert_eq!(get("/search").0, 400);
+        assert_eq!(get("/search?q=rust&count=-1").0, 400);
+        assert_eq!(get("/search?q=rust&count=x").0, 400);
+        assert_eq!(get(&format!("/search?q={}", "(".repeat(100))).0, 400);
+        assert_eq!(get("/missing").0, 404);
+        assert_eq!(
+            route(&index, &Method::Post, "/search?q=rust", 5, None).0,
+            405
+        );
+    }
+
+    #[cfg(all(unix, feature = "server"))]
+    #[test]
+    fn routes_non_utf8_paths() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = std::env::temp_dir().join(format!("indexer-route-utf8-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(OsStr::from_bytes(b"caf\xe9.txt")), "rust").unwrap();
+        for i in 0..4 {
+            fs::write(dir.join(format!("other{i}.txt")), "other").unwrap();
+        }
+        let index = Searchable::Single(Box::new(Index::new(&dir)));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let (status, body, _) = route(&index, &Method::Get, "/search?q=rust", 5, None);
+        assert_eq!(status, 200);
+        assert!(body[0]["path"]
+            .as_str()
+            .unwrap()
+            .ends_with("caf\u{fffd}.txt"));
+    }
+}
@@ -0,0 +1,595 @@
+//! Read-only index layout searched in place.
+//!
+//! Everything is stored in flat tables of fixed-size little endian records, so a memory-mapped
+//! file can be searched without deserializing it: terms are sorted for binary search, and
+//! strings are borrowed straight from the file. Layout:
+//!
+//! - header: `MAGIC`, then the number of documents, terms and postings and the length of the
+//!   analyzer settings (`u32` each), then the analyzer settings as JSON
+//! - documents: path and title (string pool offset and length, title length `u32::MAX` when
+//!   absent), length and number of occurrences of the most frequent term (`u64` each) and
+//!   boost (`f64`), sorted by path
+//! - terms: term (string pool offset and length), first posting and number of postings, sorted
+//!   by case folded term
+//! - postings: document number and term count (`u32` each), sorted by document
+//! - string pool: UTF-8 paths, titles and terms
+
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{self, Write},
+    path::Path,
+};
+
+use memmap2::Mmap;
+
+use crate::{
+    distinct_terms, parse_query, query::QueryError, rank_results, title_contains,
+    weigh_boosted_terms, Analyzer, CaseInsensitiveStr, Index, QueryLeaf, QueryMatcher,
+    SearchOptions,
+};
+
+/// The version was bumped when terms were ordered by Unicode case folding instead of ASCII
+/// lowercasing, then when documents recorded the count of their most frequent term
+const MAGIC: &[u8; 8] = b"IDXMMAP3";
+const HEADER_LEN: usize = MAGIC.len() + 4 * 4;
+const DOCUMENT_LEN: usize = 40;
+const TERM_LEN: usize = 16;
+const POSTING_LEN: usize = 8;
+const NO_TITLE: u32 = u32::MAX;
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn to_u32(n: usize) -> io::Result<u32> {
+    u32::try_from(n).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "index too large"))
+}
+
+/// A term and its (document number, count) postings
+type TermPostings<'a> = (&'a str, Vec<(u32, u32)>);
+
+/// String pool under construction
+#[derive(Default)]
+struct Pool(Vec<u8>);
+
+impl Pool {
+    fn push(&mut self, s: &str) -> io::Result<(u32, u32)> {
+        let offset = to_u32(self.0.len())?;
+        self.0.extend_from_slice(s.as_bytes());
+        Ok((offset, to_u32(s.len())?))
+    }
+}
+
+impl Index {
+    /// Writes the index in the layout read by [`MappedIndex`]. Paths must be valid UTF-8.
+    pub fn save_mapped<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut paths: Vec<_> = self.documents.iter().collect();
+        paths.sort_by_key(|(path, _)| *path);
+
+        let mut pool = Pool::default();
+        let mut documents = Vec::with_capacity(paths.len() * DOCUMENT_LEN);
+        // Keyed ignoring case: terms differing only by case are merged, as in the in-memory index
+        let mut terms: BTreeMap<&CaseInsensitiveStr, TermPostings> = BTreeMap::new();
+        for (n, (path, document)) in paths.iter().enumerate() {
+            let path_str = path.to_str().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("non UTF-8 path {path}", path = path.display()),
+                )
+            })?;
+            let (path_offset, path_len) = pool.push(path_str)?;
+            let (title_offset, title_len) = match document.title {
+                Some(ref title) => pool.push(title)?,
+                None => (0, NO_TITLE),
+            };
+            documents.extend_from_slice(&path_offset.to_le_bytes());
+            documents.extend_from_slice(&path_len.to_le_bytes());
+            documents.extend_from_slice(&title_offset.to_le_bytes());
+            documents.extend_from_slice(&title_len.to_le_bytes());
+            documents.extend_from_slice(&(document.count as u64).to_le_bytes());
+            documents.extend_from_slice(&(document.max_count() as u64).to_le_bytes());
+            documents.extend_from_slice(&self.boost(path).to_le_bytes());
+
+            let n = to_u32(n)?;
+            for (term, &count) in &document.term_frequency {
+                terms
+                    .entry(CaseInsensitiveStr::new(term))
+                    .or_insert_with(|| (term, Vec::new()))
+                    .1
+                    .push((n, to_u32(count)?));
+            }
+        }
+
+        let mut term_table = Vec::with_capacity(terms.len() * TERM_LEN);
+        let mut postings = Vec::new();
+        let mut posting_count = 0;
+        for (term, term_postings) in terms.values() {
+            let (offset, len) = pool.push(term)?;
+            term_table.extend_from_slice(&offset.to_le_bytes());
+            term_table.extend_from_slice(&len.to_le_bytes());
+            term_table.extend_from_slice(&to_u32(posting_count)?.to_le_bytes());
+            term_table.extend_from_slice(&to_u32(term_postings.len())?.to_le_bytes());
+            for (document, count) in term_postings {
+                postings.extend_from_slice(&document.to_le_bytes());
+                postings.extend_from_slice(&count.to_le_bytes());
+            }
+            posting_count += term_postings.len();
+        }
+
+        let analyzer =
+            serde_json::to_vec(&self.analyzer).map_err(|err| io::Error::other(err.to_string()))?;
+        let mut writer = io::BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        for n in [paths.len(), terms.len(), posting_count, analyzer.len()] {
+            writer.write_all(&to_u32(n)?.to_le_bytes())?;
+        }
+        writer.write_all(&analyzer)?;
+        writer.write_all(&documents)?;
+        writer.write_all(&term_table)?;
+        writer.write_all(&postings)?;
+        writer.write_all(&pool.0)?;
+        writer.flush()
+    }
+}
+
+/// An index written by [`Index::save_mapped`], searched directly from its bytes: opening it only
+/// reads the header, and a search only touches the entries of the query terms and the documents
+/// they appear in.
+#[derive(Debug)]
+pub struct MappedIndex<B = Mmap> {
+    data: B,
+    analyzer: Analyzer,
+    document_count: usize,
+    term_count: usize,
+    documents: usize,
+    terms: usize,
+    postings: usize,
+    pool: usize,
+}
+
+/// Entry of the document table
+struct DocumentEntry<'a> {
+    path: &'a Path,
+    title: Option<&'a str>,
+    count: usize,
+    max_count: usize,
+    boost: f64,
+}
+
+impl MappedIndex<Mmap> {
+    /// Memory-maps the index file at `path`.
+    ///
+    /// The file must not be modified while it is mapped: its content would change under the
+    /// borrowed views handed out by the index, which is undefined behavior. Indexes are written
+    /// to a new file and never updated in place by this crate, but other processes must follow
+    /// the same rule.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: see above, the file is not modified while mapped
+        let data = unsafe { Mmap::map(&file)? };
+        Self::from_bytes(data)
+    }
+}
+
+impl<B: AsRef<[u8]>> MappedIndex<B> {
+    /// Reads the header of an index held in `data`, checking that its tables fit in it
+    pub fn from_bytes(data: B) -> io::Result<Self> {
+        let bytes = data.as_ref();
+        if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data("not a mapped index"));
+        }
+        let header = |i: usize| read_u32(bytes, MAGIC.len() + 4 * i) as usize;
+        let (document_count, term_count, posting_count, analyzer_len) =
+            (header(0), header(1), header(2), header(3));
+
+        let documents = HEADER_LEN + analyzer_len;
+        let terms = documents + document_count * DOCUMENT_LEN;
+        let postings = terms + term_count * TERM_LEN;
+        let pool = postings + posting_count * POSTING_LEN;
+        if bytes.len() < pool {
+            return Err(invalid_data("truncated mapped index"));
+        }
+        let analyzer = serde_json::from_slice(&bytes[HEADER_LEN..documents])
+            .map_err(|err| invalid_data(&err.to_string()))?;
+
+        Ok(Self {
+            data,
+            analyzer,
+            document_count,
+            term_count,
+            documents,
+            terms,
+            postings,
+            pool,
+        })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    fn u32_at(&self, offset: usize) -> u32 {
+        read_u32(self.bytes(), offset)
+    }
+
+    fn pool_bytes(&self, offset: u32, len: u32) -> &[u8] {
+        let start = self.pool + offset as usize;
+        self.bytes()
+            .get(start..start + len as usize)
+            .unwrap_or_default()
+    }
+
+    fn pool_str(&self, offset: u32, len: u32) -> &str {
+        std::str::from_utf8(self.pool_bytes(offset, len)).unwrap_or_else(|_| {
+            log::warn!("invalid string in mapped index");
+            ""
+        })
+    }
+
+    fn document_entry(&self, n: usize) -> DocumentEntry<'_> {
+        let base = self.documents + n * DOCUMENT_LEN;
+        let title_len = self.u32_at(base + 12);
+        let bytes = &self.bytes()[base + 16..base + DOCUMENT_LEN];
+        DocumentEntry {
+            path: Path::new(self.pool_str(self.u32_at(base), self.u32_at(base + 4))),
+            title: (title_len != NO_TITLE).then(|| self.pool_str(self.u32_at(base + 8), title_len)),
+            count: u64::from_le_bytes(bytes[..8].try_into().unwrap()) as usize,
+            max_count: u64::from_le_bytes(bytes[8..16].try_into().unwrap()) as usize,
+            boost: f64::from_le_bytes(bytes[16..].try_into().unwrap()),
+        }
+    }
+
+    /// The `n`-th term of the term table
+    fn term_at(&self, n: usize) -> &str {
+        let base = self.terms + n * TERM_LEN;
+        let term = self.pool_bytes(self.u32_at(base), self.u32_at(base + 4));
+        std::str::from_utf8(term).unwrap_or_default()
+    }
+
+    /// Terms starting with the word `prefix`, ignoring case unless the index is case sensitive,
+    /// sorted
+    pub fn terms_with_prefix(&self, prefix: &str) -> Vec<Cow<'_, str>> {
+        self.keys_with_prefix(&self.analyzer.key(prefix))
+            .into_iter()
+            .map(|t| self.analyzer.word(t))
+            .collect()
+    }
+
+    /// Terms starting with the term `prefix`, ignoring case, sorted. The term table is sorted,
+    /// so they are found by a binary search, then read in sequence.
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<&str> {
+        let prefix_key = CaseInsensitiveStr::new(prefix);
+        let (mut low, mut high) = (0, self.term_count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if CaseInsensitiveStr::new(self.term_at(mid)) < prefix_key {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        (low..self.term_count)
+            .map(|n| self.term_at(n))
+            .take_while(|t| CaseInsensitiveStr::new(t).starts_with(prefix))
+            .collect()
+    }
+
+    /// Postings of `term`, as (document number, count) pairs sorted by document
+    fn postings(&self, term: &str) -> &[u8] {
+        let term = CaseInsensitiveStr::new(term);
+        let (mut low, mut high) = (0, self.term_count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let base = self.terms + mid * TERM_LEN;
+            match CaseInsensitiveStr::new(self.term_at(mid)).cmp(term) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => {
+                    let first = self.postings + self.u32_at(base + 8) as usize * POSTING_LEN;
+                    let len = self.u32_at(base + 12) as usize * POSTING_LEN;
+                    return self.bytes().get(first..first + len).unwrap_or_default();
+                }
+            }
+        }
+        &[]
+    }
+
+    /// (document number, count) pairs of `postings`, without the document numbers beyond the
+    /// document table that a corrupt file may hold
+    fn posting_entries<'a>(&self, postings: &'a [u8]) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let document_count = self.document_count;
+        posting_entries(postings).filter(move |&(n, _)| {
+            let valid = n < document_count;
+            if !valid {
+                log::warn!("invalid document number {n} in mapped index");
+            }
+            valid
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.document_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.document_count == 0
+    }
+
+    /// Number of documents containing the word `term`
+    pub fn document_frequency(&self, term: &str) -> usize {
+        self.term_document_frequency(&self.analyzer.key(term))
+    }
+
+    /// Number of documents containing the term `term`
+    fn term_document_frequency(&self, term: &str) -> usize {
+        self.postings(term).len() / POSTING_LEN
+    }
+
+    /// Number of the document at `path` in the document table
+    fn document_number(&self, path: &Path) -> Option<usize> {
+        let (mut low, mut high) = (0, self.document_count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.document_entry(mid).path.cmp(path) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// Title of the document at `path`, if any
+    pub fn title(&self, path: &Path) -> Option<&str> {
+        self.document_number(path)
+            .and_then(|n| self.document_entry(n).title)
+    }
+
+    /// See [`Index::documents_without`]
+    pub fn documents_without(&self, term: &str) -> Vec<&Path> {
+        let Some(term) = self.analyzer.word_term(term) else {
+            return Vec::new();
+        };
+        let with: BTreeSet<_> = self
+            .posting_entries(self.postings(&term))
+            .map(|(n, _)| n)
+            .collect();
+        (0..self.document_count)
+            .filter(|n| !with.contains(n))
+            .map(|n| self.document_entry(n).path)
+            .collect()
+    }
+
+    pub fn search(&self, terms: &str) -> Vec<(&Path, f64)> {
+        self.search_with_options(terms, &SearchOptions::default())
+    }
+
+    /// See [`Index::search_with_options`], of which this gives the same results, except that
+    /// positions are not stored: phrases match the documents containing all of their words, as
+    /// in indexes built without [`IndexOptions::track_positions`](crate::IndexOptions)
+    pub fn search_with_options(&self, terms: &str, options: &SearchOptions) -> Vec<(&Path, f64)> {
+        self.try_search_with_options(terms, options)
+            .unwrap_or_else(|e| {
+                log::warn!("{e}");
+                Vec::new()
+            })
+    }
+
+    pub fn try_search_with_options(
+        &self,
+        terms: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<(&Path, f64)>, QueryError> {
+        let vocabulary = |prefix: &str| {
+            let terms = self.keys_with_prefix(prefix);
+            terms.into_iter().map(str::to_owned).collect()
+        };
+        let (terms, filter) = parse_query(terms, &self.analyzer, options, &vocabulary)?;
+        let terms = weigh_boosted_terms(terms, options, |t| {
+            options
+                .idf
+                .weight(self.document_count, self.term_document_frequency(t))
+        });
+        let postings: Vec<_> = terms
+            .iter()
+            .map(|(term, idf)| (term, *idf, self.postings(term)))
+            .collect();
+        let distinct: Vec<_> = distinct_terms(&terms)
+            .into_iter()
+            .map(|t| self.postings(t))
+            .collect();
+        let matcher = QueryMatcher::new(&terms, filter.as_ref(), options);
+        let contains = |n: usize| move |t: &str| posting_count(self.postings(t), n) > 0;
+        // Positions are not stored: phrases match the documents containing all of their words
+        let leaf = |n: usize| {
+            move |l: &QueryLeaf<'_>| {
+                if l.phrase {
+                    l.terms.iter().all(|t| contains(n)(t))
+                } else {
+                    l.terms.iter().any(|t| contains(n)(t))
+                }
+            }
+        };
+        let candidates: BTreeSet<_> = postings
+            .iter()
+            .flat_map(|(_, _, p)| self.posting_entries(p).map(|(n, _)| n))
+            .filter(|&n| matcher.matches(leaf(n), contains(n)))
+            .collect();
+
+        let mut results: Vec<_> = candidates
+            .into_iter()
+            .map(|n| {
+                let document = self.document_entry(n);
+                let score = postings
+                    .iter()
+                    .map(|(term, idf, p)| {
+                        let count = posting_count(p, n);
+                        let tf = options.tf.weight(count, document.count, document.max_count);
+                        let boost = match document.title {
+                            Some(title) if title_contains(title, term, &self.analyzer) => {
+                                options.title_boost
+                            }
+                            _ => 1f64,
+                        };
+                        tf * idf * boost
+                    })
+                    .sum::<f64>();
+                (document.path, document.boost * score)
+            })
+            .filter(|(_, score)| score != &0f64)
+            .collect();
+        rank_results(&mut results, options, |path| {
+            self.document_number(path).map_or(0, |n| {
+                distinct.iter().filter(|p| posting_count(p, n) > 0).count()
+            })
+        });
+        Ok(results)
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn posting_entries(postings: &[u8]) -> impl Iterator<Item = (usize, usize)> + '_ {
+    postings
+        .chunks_exact(POSTING_LEN)
+        .map(|p| (read_u32(p, 0) as usize, read_u32(p, 4) as usize))
+}
+
+/// Count of the posting of document `n`, `0` if absent
+fn posting_count(postings: &[u8], n: usize) -> usize {
+    let (mut low, mut high) = (0, postings.len() / POSTING_LEN);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match (read_u32(postings, mid * POSTING_LEN) as usize).cmp(&n) {
+            Ordering::Less => low = mid + 1,
+            Ordering::Greater => high = mid,
+            Ordering::Equal => return read_u32(postings, mid * POSTING_LEN + 4) as usize,
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil::TempDir, IndexOptions, MatchMode, RankMode, TfScheme};
+
+    #[test]
+    fn matches_in_memory_index() {
+        let corpus = TempDir::new("mapped-corpus");
+        let notes = corpus.write("notes.txt", "Rust notes\nrust borrow checker notes");
+        corpus.write("b.txt", "XML parser and Rust bindings");
+        corpus.write("c.txt", "rust rust rust");
+        corpus.write("sub/d.txt", "borrow checker errors");
+        for i in 0..8 {
+            corpus.write(format!("filler{i}.txt"), "nothing to see");
+        }
+        let mut index = Index::new_with_options(
+            corpus.path(),
+            IndexOptions {
+                capture_titles: true,
+                ..Default::default()
+            },
+        );
+        index.set_boost(&notes, 1.5).unwrap();
+
+        let store = TempDir::new("mapped-store");
+        let path = store.path().join("index.mmap");
+        index.save_mapped(File::create(&path).unwrap()).unwrap();
+        let mapped = MappedIndex::open(&path).unwrap();
+        assert_eq!(mapped.len(), index.documents.len());
+
+        let log = SearchOptions {
+            tf: TfScheme::Log,
+            rank: RankMode::Coverage,
+            ..Default::default()
+        };
+        let all = SearchOptions {
+            match_mode: MatchMode::All,
+            ..Default::default()
+        };
+        let augmented = SearchOptions {
+            tf: TfScheme::Augmented,
+            ..Default::default()
+        };
+        for query in [
+            "rust",
+            "RUST borrow",
+            "notes checker",
+            "xml",
+            "missing",
+            "che* bo*",
+            "rust -xml",
+            "rust AND xml",
+            "\"borrow checker\" OR xml",
+        ] {
+            assert_eq!(mapped.search(query), index.search(query), "{query}");
+            for options in [&log, &all, &augmented] {
+                assert_eq!(
+                    mapped.search_with_options(query, options),
+                    index.search_with_options(query, options)
+                );
+            }
+        }
+        assert_eq!(mapped.search("rust -xml").len(), 2);
+        assert_eq!(mapped.search("rust AND xml").len(), 1);
+        assert_eq!(mapped.document_frequency("Rust"), 3);
+        assert_eq!(mapped.terms_with_prefix("B"), index.terms_with_prefix("B"));
+        assert_eq!(mapped.terms_with_prefix("B"), ["bindings", "borrow"]);
+        assert_eq!(mapped.title(&notes), Some("Rust notes"));
+        assert_eq!(
+            mapped.documents_without("rust"),
+            index.documents_without("rust")
+        );
+
+        let mut bytes = Vec::new();
+        index.save_mapped(&mut bytes).unwrap();
+        bytes.truncate(bytes.len() - bytes.len() / 2);
+        assert!(MappedIndex::from_bytes(&bytes[..]).is_err());
+        assert!(MappedIndex::from_bytes(&b"not an index"[..]).is_err());
+    }
+
+    #[test]
+    fn skips_corrupt_postings() {
+        let corpus = TempDir::new("mapped-corrupt");
+        corpus.write("a.txt", "rust notes");
+        corpus.write("b.txt", "xml notes");
+        let index = Index::new(corpus.path());
+        let mut bytes = Vec::new();
+        index.save_mapped(&mut bytes).unwrap();
+
+        // Points every posting past the document table
+        let mapped = MappedIndex::from_bytes(&bytes[..]).unwrap();
+        let (postings, pool) = (mapped.postings, mapped.pool);
+        for posting in bytes[postings..pool].chunks_exact_mut(POSTING_LEN) {
+            posting[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        }
+        let mapped = MappedIndex::from_bytes(&bytes[..]).unwrap();
+        assert!(mapped.search("rust notes").is_empty());
+        assert_eq!(mapped.documents_without("rust").len(), 2);
+    }
+
+    #[test]
+    fn search_does_not_copy_the_index() {
+        let corpus = TempDir::new("mapped-alloc");
+        for i in 0..200 {
+            corpus.write(format!("{i}.txt"), format!("word{i} common term{}", i % 10));
+        }
+        let index = Index::new(corpus.path());
+        let mut bytes = Vec::new();
+        index.save_mapped(&mut bytes).unwrap();
+
+        let before = crate::testutil::allocations();
+        let mapped = MappedIndex::from_bytes(&bytes[..]).unwrap();
+        let results = mapped.search("word42");
+        let allocations = crate::testutil::allocations() - before;
+        assert_eq!(results, index.search("word42"));
+        // A handful of small buffers, not one per document or term
+        assert!(allocations < 50, "{allocations} allocations");
+    }
+}
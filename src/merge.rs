@@ -0,0 +1,123 @@
+use std::{fmt, io, str::FromStr};
+
+use crate::Index;
+
+/// What [`Index::merge_with_policy`] does with a document indexed under the same path in both
+/// indexes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep the document of the index merged in
+    #[default]
+    Replace,
+    /// Fail, leaving the index unchanged
+    Error,
+}
+
+impl fmt::Display for MergePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Replace => "replace",
+            Self::Error => "error",
+        })
+    }
+}
+
+impl FromStr for MergePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "replace" => Ok(Self::Replace),
+            "error" => Ok(Self::Error),
+            _ => Err(format!("unknown merge policy {s:?}")),
+        }
+    }
+}
+
+impl Index {
+    /// Adds the documents of `other` to the index, those of `other` replacing the documents
+    /// indexed under the same path, see [`Index::merge_with_policy`]
+    pub fn merge(&mut self, other: Index) -> io::Result<()> {
+        self.merge_with_policy(other, MergePolicy::Replace)
+    }
+
+    /// Adds the documents and boosts of `other` to the index. Both indexes must have been built
+    /// with the same analyzer, for their terms to match. The posting lists, hence the number of
+    /// documents containing each term, are updated document by document: a replaced document no
+    /// longer counts for its terms.
+    pub fn merge_with_policy(&mut self, other: Index, policy: MergePolicy) -> io::Result<()> {
+        if other.analyzer != self.analyzer {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot merge indexes built with different analyzers",
+            ));
+        }
+        if policy == MergePolicy::Error {
+            if let Some(path) = other
+                .documents
+                .keys()
+                .find(|p| self.documents.contains_key(*p))
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{path} is indexed in both indexes", path = path.display()),
+                ));
+            }
+        }
+        for (path, document) in other.documents {
+            self.insert_document(path, document);
+        }
+        self.boosts.extend(other.boosts);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil::TempDir, CaseInsensitiveStr};
+
+    #[test]
+    fn merged_indexes_match_a_single_build() {
+        let dir = TempDir::new("merge");
+        dir.write("a/rust.txt", "rust borrow checker");
+        dir.write("a/xml.txt", "xml parser in rust");
+        dir.write("b/notes.txt", "rust notes");
+        dir.write("b/csv.txt", "csv reader");
+        let mut merged = Index::new(dir.path().join("a"));
+        merged.merge(Index::new(dir.path().join("b"))).unwrap();
+        let whole = Index::new(dir.path());
+        assert_eq!(merged.documents, whole.documents);
+        assert_eq!(merged.postings(), whole.postings());
+        assert_eq!(
+            merged.document_frequencies()[CaseInsensitiveStr::new("rust")],
+            ("rust", 3)
+        );
+        assert_eq!(merged.search("rust xml"), whole.search("rust xml"));
+    }
+
+    #[test]
+    fn replaced_documents_no_longer_count() {
+        let dir = TempDir::new("merge-conflict");
+        let path = dir.write("doc.txt", "rust borrow checker");
+        dir.write("other.txt", "rust notes");
+        let mut index = Index::new(dir.path());
+        dir.write("doc.txt", "xml parser");
+        let mut newer = Index::new(dir.path());
+        newer.set_boost(&path, 2.0).unwrap();
+
+        let error = index
+            .merge_with_policy(Index::new(dir.path()), MergePolicy::Error)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert!(!index.documents_without("borrow").contains(&path.as_path()));
+
+        index.merge(newer).unwrap();
+        let frequencies = index.document_frequencies();
+        assert_eq!(frequencies[CaseInsensitiveStr::new("rust")], ("rust", 1));
+        assert!(!frequencies.contains_key(CaseInsensitiveStr::new("borrow")));
+        assert_eq!(frequencies[CaseInsensitiveStr::new("xml")], ("xml", 1));
+        assert_eq!(index.postings(), Index::new(dir.path()).postings());
+        assert_eq!(index.boosts[&path], 2.0);
+    }
+}
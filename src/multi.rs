@@ -0,0 +1,316 @@
+use std::{
+    fs::{read_dir, File},
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use flate2::read::GzDecoder;
+
+use crate::{
+    distinct_terms, parse_query, query::QueryError, query_matcher, rank_results,
+    weigh_boosted_terms, Analyzer, CaseInsensitiveStr, CorruptEntry, Document, IdfScheme, Index,
+    SearchOptions,
+};
+
+/// Several indexes searched as one. Document frequencies are summed across shards so the idf of
+/// a term is the same as if all documents lived in a single index.
+#[derive(Debug, Default)]
+pub struct MultiIndex {
+    shards: Vec<Index>,
+}
+
+impl MultiIndex {
+    pub fn new(shards: Vec<Index>) -> Self {
+        Self { shards }
+    }
+
+    /// Loads every `*.json` and `*.json.gz` index file found directly inside `dir`, and every
+    /// `*.bin` one with the `binary` feature. Fails if there is none.
+    pub fn load_dir(dir: impl AsRef<Path>) -> io::Result<Self> {
+        Self::load_dir_with(dir.as_ref(), None)
+    }
+
+    /// Like [`MultiIndex::load_dir`], skipping the damaged parts of JSON index files like
+    /// [`Index::load_lenient`]. Returns the shards along with the skipped entries.
+    pub fn load_dir_lenient(dir: impl AsRef<Path>) -> io::Result<(Self, Vec<CorruptEntry>)> {
+        let mut corrupt = Vec::new();
+        let multi = Self::load_dir_with(dir.as_ref(), Some(&mut corrupt))?;
+        Ok((multi, corrupt))
+    }
+
+    /// Loads the index files of `dir`, leniently when there is somewhere to report `corrupt`
+    /// entries
+    fn load_dir_with(dir: &Path, mut corrupt: Option<&mut Vec<CorruptEntry>>) -> io::Result<Self> {
+        let mut paths = Vec::new();
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && is_index_file(&path) {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no index file in {dir}", dir = dir.display()),
+            ));
+        }
+        paths.sort();
+
+        let mut shards = Vec::with_capacity(paths.len());
+        for path in paths {
+            log::info!("loading shard {path}", path = path.display());
+            let file = File::open(&path)?;
+            #[cfg(feature = "binary")]
+            if path.extension().is_some_and(|e| e == "bin") {
+                shards.push(Index::load_bin(file)?);
+                continue;
+            }
+            let file = BufReader::new(file);
+            let reader: Box<dyn Read> = if is_compressed(&path) {
+                Box::new(GzDecoder::new(file))
+            } else {
+                Box::new(file)
+            };
+            match corrupt.as_deref_mut() {
+                Some(corrupt) => {
+                    let (index, skipped) = Index::load_lenient(reader)?;
+                    corrupt.extend(skipped);
+                    shards.push(index);
+                }
+                None => shards.push(Index::load(reader)?),
+            }
+        }
+        Ok(Self::new(shards))
+    }
+
+    pub fn shards(&self) -> &[Index] {
+        &self.shards
+    }
+
+    fn idf(&self, term: &str, scheme: IdfScheme) -> f64 {
+        let (n, d) = self.shards.iter().fold((0, 0), |(n, d), shard| {
+            (
+                n + shard.documents.len(),
+                d + shard.document_frequency(term),
+            )
+        });
+        scheme.weight(n, d)
+    }
+
+    pub fn document(&self, path: &Path) -> Option<&Document> {
+        self.shards.iter().find_map(|shard| shard.document(path))
+    }
+
+    /// Returns the documents of all shards that do not contain `term`, sorted by path
+    pub fn documents_without(&self, term: &str) -> Vec<&Path> {
+        let mut paths: Vec<_> = self
+            .shards
+            .iter()
+            .flat_map(|shard| shard.documents_without(term))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    pub fn search<'a>(&'a self, terms: &'_ str) -> Vec<(&'a Path, f64)> {
+        self.search_with_options(terms, &SearchOptions::default())
+    }
+
+    /// See [`Index::search_with_options`]
+    pub fn search_with_options<'a>(
+        &'a self,
+        terms: &'_ str,
+        options: &SearchOptions,
+    ) -> Vec<(&'a Path, f64)> {
+        self.try_search_with_options(terms, options)
+            .unwrap_or_else(|e| {
+                log::warn!("{e}");
+                Vec::new()
+            })
+    }
+
+    /// Like [`MultiIndex::search_with_options`], failing on invalid queries and on shards
+    /// built with different analyzers, whose terms would not match the analyzed query alike
+    pub fn try_search_with_options<'a>(
+        &'a self,
+        terms: &'_ str,
+        options: &SearchOptions,
+    ) -> Result<Vec<(&'a Path, f64)>, QueryError> {
+        let analyzer = self
+            .shards
+            .first()
+            .map_or(Analyzer::standard(), |shard| &shard.analyzer);
+        if self
+            .shards
+            .iter()
+            .any(|shard| shard.analyzer.is_case_sensitive() != analyzer.is_case_sensitive())
+        {
+            return Err(QueryError::CaseSensitivityMismatch);
+        }
+        if self.shards.iter().any(|shard| shard.analyzer != *analyzer) {
+            return Err(QueryError::AnalyzerMismatch);
+        }
+        let vocabulary = |prefix: &str| {
+            let mut terms: Vec<String> = self
+                .shards
+                .iter()
+                .flat_map(|shard| shard.keys_with_prefix(prefix))
+                .map(str::to_owned)
+                .collect();
+            terms.sort_by(|t1, t2| CaseInsensitiveStr::new(t1).cmp(CaseInsensitiveStr::new(t2)));
+            terms.dedup_by(|t1, t2| CaseInsensitiveStr::new(t1) == CaseInsensitiveStr::new(t2));
+            terms
+        };
+        let (terms, filter) = parse_query(terms, analyzer, options, &vocabulary)?;
+        let terms = weigh_boosted_terms(terms, options, |t| self.idf(t, options.idf));
+        let matches = query_matcher(&terms, filter.as_ref(), options);
+        let mut results: Vec<_> = self
+            .shards
+            .iter()
+            .flat_map(|shard| shard.score_where(&terms, options, |_, d| matches(d)))
+            .collect();
+        let distinct = distinct_terms(&terms);
+        rank_results(&mut results, options, |p| {
+            self.document(p).map_or(0, |d| d.coverage(&distinct))
+        });
+        Ok(results)
+    }
+}
+
+fn is_index_file(path: &Path) -> bool {
+    let binary = cfg!(feature = "binary");
+    matches!(path.extension().and_then(|e| e.to_str()), Some("json"))
+        || is_compressed(path)
+        || binary && path.extension().is_some_and(|e| e == "bin")
+}
+
+/// Whether `path` names a JSON index file compressed with gzip, see [`Index::save_compressed`]
+fn is_compressed(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.ends_with(".json.gz"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::BufWriter};
+
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn load_dir_federates_shards() {
+        let corpus = TempDir::new("multi-corpus");
+        corpus.write("a/rust.txt", "rust tokenizer index");
+        corpus.write("a/xml.txt", "xml parser events");
+        corpus.write("b/rust.txt", "rust rust borrow checker");
+        corpus.write("b/other.txt", "nothing relevant here");
+
+        let shards = TempDir::new("multi-shards");
+        let index = Index::new(corpus.path().join("a"));
+        let f = File::create(shards.path().join("a.json")).unwrap();
+        index.save(BufWriter::new(f)).unwrap();
+        let index = Index::new(corpus.path().join("b"));
+        let f = File::create(shards.path().join("b.json.gz")).unwrap();
+        index.save_compressed(BufWriter::new(f)).unwrap();
+        shards.write("notes.txt", "not an index");
+
+        let multi = MultiIndex::load_dir(shards.path()).unwrap();
+        assert_eq!(multi.shards().len(), 2);
+        let (lenient, corrupt) = MultiIndex::load_dir_lenient(shards.path()).unwrap();
+        assert_eq!(lenient.shards().len(), 2);
+        assert!(corrupt.is_empty());
+        let err = MultiIndex::load_dir(corpus.path().join("a")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let combined = Index::new(corpus.path());
+        assert_eq!(multi.search("rust"), combined.search("rust"));
+        let results = multi.search("rust");
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .any(|(p, _)| p.starts_with(corpus.path().join("a"))));
+        assert!(results
+            .iter()
+            .any(|(p, _)| p.starts_with(corpus.path().join("b"))));
+    }
+
+    #[test]
+    fn ranks_like_a_combined_index() {
+        let corpus = TempDir::new("multi-combined");
+        corpus.write("a/rust.txt", "rust tokenizer index");
+        corpus.write("a/xml.txt", "xml parser events");
+        corpus.write("a/csv.txt", "csv parser rows");
+        corpus.write("a/json.txt", "json parser values");
+        corpus.write("b/rust.txt", "rust rust borrow checker");
+        corpus.write("b/more.txt", "rust borrow notes");
+        corpus.write("b/other.txt", "nothing relevant here");
+        let shards = vec![
+            Index::new(corpus.path().join("a")),
+            Index::new(corpus.path().join("b")),
+        ];
+        // Too common within their shard, these terms have no weight there
+        assert!(shards[0].search("parser").is_empty());
+        assert!(shards[1].search("rust").is_empty());
+        let multi = MultiIndex::new(shards);
+        let combined = Index::new(corpus.path());
+        assert_eq!(combined.search("parser").len(), 3);
+
+        let all = SearchOptions {
+            match_mode: crate::MatchMode::All,
+            ..Default::default()
+        };
+        for query in [
+            "rust",
+            "parser",
+            "rust parser",
+            "rust -borrow",
+            "rust AND borrow",
+            "+borrow rust",
+            "rust^2 parser",
+            "pars* rust",
+            "missing",
+        ] {
+            assert_eq!(multi.search(query), combined.search(query), "{query}");
+            assert_eq!(
+                multi.search_with_options(query, &all),
+                combined.search_with_options(query, &all),
+                "{query}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_mixed_case_sensitivity() {
+        let corpus = TempDir::new("multi-case");
+        corpus.write("a/rust.txt", "Rust tokenizer");
+        corpus.write("b/rust.txt", "rust borrow checker");
+        let sensitive = Index::new_with_options(
+            corpus.path().join("a"),
+            crate::IndexOptions {
+                analyzer: Analyzer::default().with_case_sensitivity(true),
+                ..Default::default()
+            },
+        );
+        let multi = MultiIndex::new(vec![sensitive, Index::new(corpus.path().join("b"))]);
+        assert_eq!(
+            multi.try_search_with_options("rust", &SearchOptions::default()),
+            Err(QueryError::CaseSensitivityMismatch)
+        );
+
+        // Documents of a stemmed shard would only match stemmed query terms
+        let stemmed = Index::new_with_options(
+            corpus.path().join("a"),
+            crate::IndexOptions {
+                analyzer: Analyzer::default().with_stemmer(crate::PorterStemmer),
+                ..Default::default()
+            },
+        );
+        let multi = MultiIndex::new(vec![stemmed, Index::new(corpus.path().join("b"))]);
+        assert_eq!(
+            multi.try_search_with_options("rust", &SearchOptions::default()),
+            Err(QueryError::AnalyzerMismatch)
+        );
+        assert!(multi.search("rust").is_empty());
+    }
+}
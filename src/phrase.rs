@@ -0,0 +1,230 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    distinct_terms, parse_query, rank_results, weigh_boosted_terms, CaseInsensitiveStr, Document,
+    Index, SearchOptions,
+};
+
+impl Document {
+    /// Number of times `terms` appear consecutively in the document. Always `0` when positions
+    /// were not recorded.
+    pub fn phrase_count(&self, terms: &[impl AsRef<str>]) -> usize {
+        let Some(ref positions) = self.positions else {
+            return 0;
+        };
+        let Some((first, rest)) = terms.split_first() else {
+            return 0;
+        };
+        let Some(starts) = positions.get(CaseInsensitiveStr::new(first.as_ref())) else {
+            return 0;
+        };
+        let mut following = Vec::with_capacity(rest.len());
+        for term in rest {
+            match positions.get(CaseInsensitiveStr::new(term.as_ref())) {
+                Some(p) => following.push(p),
+                None => return 0,
+            }
+        }
+        starts
+            .iter()
+            .filter(|&&start| {
+                following
+                    .iter()
+                    .enumerate()
+                    .all(|(i, p)| p.binary_search(&(start + i + 1)).is_ok())
+            })
+            .count()
+    }
+}
+
+impl Index {
+    /// Counts the occurrences of `phrase` in the whole corpus. Returns the total count and the
+    /// count of every document containing the phrase, most occurrences first. Requires an index
+    /// built with [`IndexOptions::track_positions`](crate::IndexOptions).
+    pub fn phrase_occurrences(&self, phrase: &str) -> (usize, Vec<(&Path, usize)>) {
+        let terms = self.analyzer.phrase_terms(phrase);
+        let mut documents: Vec<_> = self
+            .documents
+            .iter()
+            .map(|(path, d)| (path.as_path(), d.phrase_count(&terms)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        documents.sort_by(|(p1, c1), (p2, c2)| c2.cmp(c1).then_with(|| p1.cmp(p2)));
+        (documents.iter().map(|(_, c)| c).sum(), documents)
+    }
+}
+
+impl Index {
+    /// Like [`Index::search_with_options`], but the terms of a quoted span of the query, such as
+    /// `"machine learning"`, only count for documents where they appear consecutively. Other
+    /// terms are scored as usual. Without [`IndexOptions::track_positions`](crate::IndexOptions),
+    /// no document contains a phrase. A query rejected by the term limit of `options` has no
+    /// results.
+    pub fn search_phrase<'a>(
+        &'a self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Vec<(&'a Path, f64)> {
+        let (terms, filter) = match parse_query(query, &self.analyzer, options, &self.vocabulary())
+        {
+            Ok((terms, Some(filter))) => (terms, filter),
+            Ok((_, None)) => return Vec::new(),
+            Err(e) => {
+                log::warn!("{e}");
+                return Vec::new();
+            }
+        };
+
+        let idf = |t: &str| self.idf(t, options.idf);
+        let mut scores: HashMap<&Path, f64> = HashMap::new();
+        let mut loose = Vec::new();
+        for leaf in filter.positive_leaves() {
+            let boosted = leaf.terms.iter().map(|t| (t.clone(), leaf.boost));
+            if !leaf.phrase {
+                loose.extend(boosted);
+                continue;
+            }
+            let terms = weigh_boosted_terms(boosted, options, idf);
+            for (path, score) in self.score(&terms, options) {
+                if self.documents[path].phrase_count(&leaf.terms) > 0 {
+                    *scores.entry(path).or_default() += score;
+                }
+            }
+        }
+        let loose = weigh_boosted_terms(loose, options, idf);
+        for (path, score) in self.score(&loose, options) {
+            *scores.entry(path).or_default() += score;
+        }
+
+        let mut results: Vec<_> = scores
+            .into_iter()
+            .filter(|(p, _)| self.documents[*p].matches(&filter))
+            .collect();
+        let terms = weigh_boosted_terms(terms, options, idf);
+        let distinct = distinct_terms(&terms);
+        rank_results(&mut results, options, |p| {
+            self.documents[p].coverage(&distinct)
+        });
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+
+    use super::*;
+    use crate::{testutil::TempDir, tokenizer::Lexer, Analyzer, IndexOptions, SearchOptions};
+
+    fn brute_force(path: &Path, phrase: &[&str]) -> usize {
+        let content = read_to_string(path).unwrap();
+        let tokens: Vec<_> = Lexer::new(&content).collect();
+        tokens
+            .windows(phrase.len())
+            .filter(|w| w.iter().zip(phrase).all(|(a, b)| a.eq_ignore_ascii_case(b)))
+            .count()
+    }
+
+    #[test]
+    fn counts_match_source_files() {
+        let dir = TempDir::new("phrase");
+        let files = [
+            dir.write("a.txt", "machine learning is fun. Machine Learning again"),
+            dir.write("b.txt", "learning machine, machine learning"),
+            dir.write("c.txt", "machine translation and deep learning"),
+            dir.write(
+                "d.txt",
+                "machine machine learning learning machine learning",
+            ),
+        ];
+        let index = Index::new_with_options(
+            dir.path(),
+            IndexOptions {
+                track_positions: true,
+                ..Default::default()
+            },
+        );
+
+        let (total, documents) = index.phrase_occurrences("machine learning");
+        let expected: Vec<_> = files
+            .iter()
+            .map(|f| (f.as_path(), brute_force(f, &["machine", "learning"])))
+            .filter(|(_, c)| *c > 0)
+            .collect();
+        assert_eq!(total, expected.iter().map(|(_, c)| c).sum::<usize>());
+        assert_eq!(total, 5);
+        assert_eq!(documents.len(), expected.len());
+        for (path, count) in expected {
+            assert!(documents.contains(&(path, count)));
+        }
+        assert_eq!(documents[0].0, files[0].as_path());
+        assert_eq!(documents[1].0, files[3].as_path());
+
+        assert_eq!(index.phrase_occurrences("learning machine").0, 2);
+        assert_eq!(index.phrase_occurrences("deep machine").0, 0);
+        assert_eq!(Index::new(dir.path()).phrase_occurrences("machine").0, 0);
+    }
+
+    #[test]
+    fn quoted_terms_must_be_consecutive() {
+        let dir = TempDir::new("search-phrase");
+        let phrase = dir.write("phrase.txt", "machine learning rocks");
+        let apart = dir.write("apart.txt", "learning about the machine");
+        let rust = dir.write("rust.txt", "rust about nothing");
+        for i in 0..4 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let options = IndexOptions {
+            track_positions: true,
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options);
+        let search = |query| {
+            index
+                .search_phrase(query, &SearchOptions::default())
+                .into_iter()
+                .map(|(p, _)| p)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(search(r#""machine learning""#), [phrase.as_path()]);
+        assert_eq!(search(r#""learning machine""#), [] as [&Path; 0]);
+        assert_eq!(
+            search(r#""machine learning" rust"#),
+            [phrase.as_path(), rust.as_path()]
+        );
+        assert_eq!(search("machine learning").len(), 2);
+        assert!(search("machine learning").contains(&apart.as_path()));
+        assert_eq!(
+            index.search_phrase("machine learning", &SearchOptions::default()),
+            index.search("machine learning")
+        );
+        assert!(Index::new(dir.path())
+            .search_phrase(r#""machine learning""#, &SearchOptions::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn ngrams_keep_words_consecutive() {
+        let dir = TempDir::new("phrase-ngrams");
+        let phrase = dir.write("phrase.txt", "deep machine learning! rocks");
+        dir.write("apart.txt", "learning about the machine");
+        let ngrams = |min| IndexOptions {
+            track_positions: true,
+            analyzer: Analyzer::default()
+                .with_ngram_range(min, 3)
+                .with_synonyms("machine", ["computer"]),
+            ..Default::default()
+        };
+        for min in [1, 2] {
+            let index = Index::new_with_options(dir.path(), ngrams(min));
+            let count = |query| index.phrase_occurrences(query).0;
+            assert_eq!(count("machine learning"), 1, "min {min}");
+            assert_eq!(count("deep machine learning"), 1, "min {min}");
+            assert_eq!(count("learning machine"), 0, "min {min}");
+            let terms = &index.documents[&phrase].term_frequency;
+            assert!(terms.contains_key(CaseInsensitiveStr::new("learning rocks")));
+            assert!(!terms.contains_key(CaseInsensitiveStr::new("learning !")));
+        }
+    }
+}
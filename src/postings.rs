@@ -0,0 +1,376 @@
+//! Inverted index: the documents containing each term, along with its number of occurrences.
+//!
+//! Searches only visit the documents listed for the query terms, instead of looking each term
+//! up in every document. The lists are updated along with the documents, and saved with JSON
+//! indexes. Indexes saved before they existed, or in formats that do not store them, build
+//! them on their first search.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{CaseInsensitiveStr, Document, Index, TermMap};
+
+/// Documents containing each term, sorted by path, with the number of times the term is found
+/// in each
+pub(crate) type PostingLists = TermMap<Vec<(PathBuf, usize)>>;
+
+/// Posting lists of the documents of an index, once built. They are derived from the
+/// documents, hence equal whether built or not.
+#[derive(Default)]
+pub(crate) struct Postings(OnceLock<PostingLists>);
+
+impl fmt::Debug for Postings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.get() {
+            Some(lists) => write!(f, "Postings({} terms)", lists.len()),
+            None => f.write_str("Postings(not built)"),
+        }
+    }
+}
+
+impl PartialEq for Postings {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Serialize for Postings {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.get().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Postings {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self(OnceLock::from(PostingLists::deserialize(
+            deserializer,
+        )?)))
+    }
+}
+
+impl Postings {
+    /// Drops the posting lists, to build them again from the documents on the next search
+    pub fn clear(&mut self) {
+        self.0 = OnceLock::new();
+    }
+
+    pub fn is_unbuilt(&self) -> bool {
+        self.0.get().is_none()
+    }
+
+    /// Lists `document` under each of its terms
+    pub fn add(&mut self, path: &Path, document: &Document) {
+        let Some(lists) = self.0.get_mut() else {
+            return;
+        };
+        for (term, count) in &document.term_frequency {
+            let list = lists.entry(term.clone()).or_default();
+            match list.binary_search_by(|(p, _)| p.as_path().cmp(path)) {
+                Ok(i) => list[i].1 = *count,
+                Err(i) => list.insert(i, (path.to_path_buf(), *count)),
+            }
+        }
+    }
+
+    /// Undoes [`Postings::add`], dropping the lists left empty
+    pub fn remove(&mut self, path: &Path, document: &Document) {
+        let Some(lists) = self.0.get_mut() else {
+            return;
+        };
+        for term in document.term_frequency.keys() {
+            let Some(list) = lists.get_mut(term) else {
+                continue;
+            };
+            if let Ok(i) = list.binary_search_by(|(p, _)| p.as_path().cmp(path)) {
+                list.remove(i);
+            }
+            if list.is_empty() {
+                lists.remove(term);
+            }
+        }
+    }
+}
+
+/// Whether `lists` list exactly the terms of `documents`, with their counts
+fn lists_documents(lists: &PostingLists, documents: &HashMap<PathBuf, Document>) -> bool {
+    let mut entries = 0;
+    for (term, list) in lists {
+        if !list.windows(2).all(|w| w[0].0 < w[1].0) {
+            return false;
+        }
+        for (path, count) in list {
+            let listed = documents.get(path).and_then(|d| d.term_frequency.get(term));
+            if listed != Some(count) {
+                return false;
+            }
+        }
+        entries += list.len();
+    }
+    entries
+        == documents
+            .values()
+            .map(|d| d.term_frequency.len())
+            .sum::<usize>()
+}
+
+fn build(documents: &HashMap<PathBuf, Document>) -> PostingLists {
+    let mut postings = PostingLists::new();
+    for (path, document) in documents {
+        for (term, count) in &document.term_frequency {
+            postings
+                .entry(term.clone())
+                .or_default()
+                .push((path.clone(), *count));
+        }
+    }
+    for list in postings.values_mut() {
+        list.sort_unstable();
+    }
+    postings
+}
+
+impl Index {
+    /// Builds the posting lists of the index if not done yet, e.g. for an index saved by an
+    /// older version, rather than on the first search
+    pub fn build_postings(&self) {
+        self.postings();
+    }
+
+    /// Drops saved posting lists that do not match the documents, e.g. edited by hand, so that
+    /// they are built again from the documents
+    pub(crate) fn check_postings(&mut self) {
+        if let Some(lists) = self.postings.0.get() {
+            if !lists_documents(lists, &self.documents) {
+                log::warn!("the inverted index does not match the documents, rebuilding it");
+                self.postings.clear();
+            }
+        }
+    }
+
+    /// Posting lists of the index, built on first use
+    pub(crate) fn postings(&self) -> &PostingLists {
+        self.postings.0.get_or_init(|| build(&self.documents))
+    }
+
+    /// Documents of the index, dropping the posting lists that changes to their terms would
+    /// leave out of date. Prefer [`Index::insert_document`] and [`Index::remove_document`],
+    /// which update them.
+    pub(crate) fn documents_mut(&mut self) -> &mut HashMap<PathBuf, Document> {
+        self.postings.clear();
+        &mut self.documents
+    }
+
+    /// Adds or replaces the document of `path`, updating the posting lists
+    pub(crate) fn insert_document(
+        &mut self,
+        path: PathBuf,
+        document: Document,
+    ) -> Option<Document> {
+        if let Some(previous) = self.documents.get(&path) {
+            self.postings.remove(&path, previous);
+        }
+        self.postings.add(&path, &document);
+        self.documents.insert(path, document)
+    }
+
+    /// Documents containing any of `terms`, sorted
+    pub(crate) fn candidates<'t>(&self, terms: impl IntoIterator<Item = &'t str>) -> Vec<&Path> {
+        let postings = self.postings();
+        let mut paths: Vec<&Path> = terms
+            .into_iter()
+            .filter_map(|t| postings.get(CaseInsensitiveStr::new(t)))
+            .flatten()
+            .map(|(p, _)| p.as_path())
+            .collect();
+        paths.sort_unstable();
+        paths.dedup();
+        paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        parse_query, query_matcher, rank_results, testutil::TempDir, weigh_boosted_terms,
+        SearchOptions, TfScheme,
+    };
+
+    fn is_up_to_date(index: &Index) -> bool {
+        !index.postings.is_unbuilt() && *index.postings() == build(&index.documents)
+    }
+
+    /// Results of `query` scoring every document, rather than the candidates of the postings
+    fn full_scan<'a>(
+        index: &'a Index,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Vec<(&'a Path, f64)> {
+        let vocabulary = index.vocabulary();
+        let (terms, filter) = parse_query(query, &index.analyzer, options, &vocabulary).unwrap();
+        let terms = weigh_boosted_terms(terms, options, |t| index.idf(t, options.idf));
+        let matches = query_matcher(&terms, filter.as_ref(), options);
+        let mut results: Vec<_> = index
+            .documents
+            .iter()
+            .filter(|(_, d)| matches(d))
+            .map(|(p, d)| {
+                let score: f64 = terms
+                    .iter()
+                    .map(|(t, idf)| d.term_score(t, *idf, options, &index.analyzer))
+                    .sum();
+                (p.as_path(), index.boost(p) * score)
+            })
+            .filter(|(_, score)| *score != 0f64)
+            .collect();
+        rank_results(&mut results, options, |_| 0);
+        results
+    }
+
+    #[test]
+    fn searches_match_full_scans() {
+        let dir = TempDir::new("postings-scan");
+        dir.write("notes.txt", "Rust notes\nrust borrow checker");
+        dir.write("xml.txt", "XML parser and rust bindings");
+        dir.write("sub/errors.txt", "borrow checker errors");
+        for i in 0..6 {
+            dir.write(format!("filler{i}.txt"), "nothing to see");
+        }
+        let index = Index::new(dir.path());
+        let log = SearchOptions {
+            tf: TfScheme::Log,
+            ..Default::default()
+        };
+        for query in [
+            "rust",
+            "RUST borrow",
+            "checker -errors",
+            "xml OR missing",
+            "nothing",
+            "bor*",
+        ] {
+            for options in [&SearchOptions::default(), &log] {
+                assert_eq!(
+                    index.search_with_options(query, options),
+                    full_scan(&index, query, options),
+                    "{query}"
+                );
+            }
+        }
+    }
+
+    /// Compares searches over a large index through the postings and by scoring every
+    /// document, run with `cargo test --release -- --ignored --nocapture`. Only reports the
+    /// timings.
+    #[test]
+    #[ignore]
+    fn postings_against_full_scans() {
+        let dir = TempDir::new("postings-bench");
+        for i in 0..5_000 {
+            let words: Vec<_> = (0..100)
+                .map(|j| format!("term{}", (i * 7 + j * 13) % 20_000))
+                .collect();
+            dir.write(format!("doc{i}.txt"), words.join(" "));
+        }
+        let index = Index::new(dir.path());
+        let queries: Vec<_> = (0..100)
+            .map(|i| format!("term{} term{}", i * 37, i * 91))
+            .collect();
+        let options = SearchOptions::default();
+
+        let start = Instant::now();
+        let postings: Vec<_> = queries.iter().map(|q| index.search(q)).collect();
+        let postings_time = start.elapsed();
+        let start = Instant::now();
+        let scans: Vec<_> = queries
+            .iter()
+            .map(|q| full_scan(&index, q, &options))
+            .collect();
+        let scan_time = start.elapsed();
+        assert_eq!(postings, scans);
+        assert!(
+            postings_time < scan_time,
+            "postings: {postings_time:?}, full scans: {scan_time:?}"
+        );
+    }
+
+    #[test]
+    fn postings_follow_document_changes() {
+        let dir = TempDir::new("postings");
+        let notes = dir.write("notes.txt", "Rust notes\nrust borrow checker");
+        dir.write("xml.txt", "XML parser and rust bindings");
+        dir.write("sub/errors.txt", "borrow checker errors");
+        let mut index = Index::new(dir.path());
+        assert!(is_up_to_date(&index));
+        assert_eq!(index.document_frequency("BORROW"), 2);
+        assert_eq!(index.candidates(["xml", "errors", "missing"]).len(), 2);
+
+        index.remove_document(&notes);
+        assert!(is_up_to_date(&index));
+        assert_eq!(index.document_frequency("borrow"), 1);
+        assert_eq!(index.document_frequency("notes"), 0);
+
+        dir.write("notes.txt", "garbage collector notes");
+        dir.write("sub/more.txt", "rust notes");
+        index.reindex([notes, dir.path().join("sub/more.txt")]);
+        assert!(is_up_to_date(&index));
+        assert_eq!(index.document_frequency("notes"), 2);
+        assert_eq!(index.search("collector").len(), 1);
+    }
+
+    #[test]
+    fn postings_are_saved_with_the_index() {
+        let dir = TempDir::new("postings-saved");
+        dir.write("notes.txt", "Rust notes\nrust borrow checker");
+        dir.write("xml.txt", "XML parser and rust bindings");
+        let index = Index::new(dir.path());
+        let mut saved = Vec::new();
+        index.save(&mut saved).unwrap();
+        let loaded = Index::load(&saved[..]).unwrap();
+        assert!(is_up_to_date(&loaded));
+
+        // Indexes saved before the posting lists existed build them on their first search
+        let mut json: Value = serde_json::from_slice(&saved).unwrap();
+        assert!(json.as_object_mut().unwrap().remove("inverted").is_some());
+        let old = Index::load(json.to_string().as_bytes()).unwrap();
+        assert!(old.postings.is_unbuilt());
+        assert_eq!(old.search("rust borrow"), index.search("rust borrow"));
+        assert!(is_up_to_date(&old));
+    }
+
+    #[test]
+    fn stale_postings_are_rebuilt() {
+        let dir = TempDir::new("postings-stale");
+        dir.write("notes.txt", "Rust notes\nrust borrow checker");
+        dir.write("xml.txt", "XML parser and rust bindings");
+        dir.write("csv.txt", "CSV parser");
+        let index = Index::new(dir.path());
+        let mut saved = Vec::new();
+        index.save(&mut saved).unwrap();
+        let json: Value = serde_json::from_slice(&saved).unwrap();
+
+        let edited = |edit: &dyn Fn(&mut Value)| {
+            let mut json = json.clone();
+            edit(&mut json["inverted"]);
+            let loaded = Index::load(json.to_string().as_bytes()).unwrap();
+            assert!(loaded.postings.is_unbuilt());
+            assert_eq!(loaded.search("rust python"), index.search("rust python"));
+        };
+        let stale = dir.path().join("stale.txt");
+        edited(&|inverted| inverted["python"] = serde_json::json!([[stale, 1]]));
+        edited(&|inverted| inverted["parser"][0][1] = 5.into());
+        edited(&|inverted| {
+            inverted.as_object_mut().unwrap().remove("checker");
+        });
+    }
+}
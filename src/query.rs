@@ -0,0 +1,557 @@
+use std::{error::Error, fmt};
+
+/// Fields of the terms added by [`IndexOptions::index_file_type`](crate::IndexOptions). In a
+/// query, a word like `extension:md` is looked up as is instead of being analyzed.
+pub const FIELDS: [&str; 2] = ["extension", "mime"];
+
+/// Builds the term stored for `value` in `field`
+pub(crate) fn field_term(field: &str, value: &str) -> String {
+    format!("{field}:{}", value.to_ascii_lowercase())
+}
+
+fn is_field_term(word: &str) -> bool {
+    word.split_once(':')
+        .is_some_and(|(field, value)| !value.is_empty() && FIELDS.contains(&field))
+}
+
+/// Piece of a query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Part<'q> {
+    /// Free text, to be analyzed
+    Text(&'q str),
+    /// A `field:value` term, used verbatim
+    Field(&'q str),
+    /// The start of terms, written with a trailing `*` as in `token*`
+    Prefix(&'q str),
+    /// Text between double quotes, to be analyzed, whose terms must be consecutive
+    Phrase(&'q str),
+}
+
+/// Leaf of a parsed query, see [`parse`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Leaf<'q> {
+    pub part: Part<'q>,
+    /// Multiplier of the contribution of the terms of `part` to scores, written `rust^2`. Field
+    /// terms and phrases are never boosted.
+    pub boost: f64,
+}
+
+impl<'q> From<Part<'q>> for Leaf<'q> {
+    fn from(part: Part<'q>) -> Self {
+        Self { part, boost: 1.0 }
+    }
+}
+
+/// Splits `word^boost` into the word and its boost, a finite and non-negative number. Other
+/// words, such as `a^b`, are literal text with a boost of 1.
+fn split_boost(word: &str) -> (&str, f64) {
+    match word.rsplit_once('^') {
+        Some((term, boost)) if !term.is_empty() => match boost.parse::<f64>() {
+            Ok(boost) if boost.is_finite() && boost >= 0.0 => (term, boost),
+            _ => (word, 1.0),
+        },
+        _ => (word, 1.0),
+    }
+}
+
+/// Splits `query` into free text, phrases and field terms, in order. An unmatched quote is
+/// ignored.
+pub(crate) fn parts(query: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let segments: Vec<_> = query.split('"').collect();
+    let closed = segments.len() - (segments.len() + 1) % 2;
+    for (i, segment) in segments.into_iter().enumerate() {
+        if i % 2 == 1 && i < closed {
+            if !segment.trim().is_empty() {
+                parts.push(Part::Phrase(segment));
+            }
+        } else {
+            split_fields(segment, &mut parts);
+        }
+    }
+    parts
+}
+
+/// Splits `text` into free text and field terms
+fn split_fields<'q>(text: &'q str, parts: &mut Vec<Part<'q>>) {
+    let mut start = 0;
+    for word in text.split_whitespace() {
+        if !is_field_term(word) {
+            continue;
+        }
+        let offset = word.as_ptr() as usize - text.as_ptr() as usize;
+        if !text[start..offset].trim().is_empty() {
+            parts.push(Part::Text(&text[start..offset]));
+        }
+        parts.push(Part::Field(word));
+        start = offset + word.len();
+    }
+    if !text[start..].trim().is_empty() {
+        parts.push(Part::Text(&text[start..]));
+    }
+}
+
+/// Boolean expression over the leaves of a query
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Node<L> {
+    Leaf(L),
+    /// Matches the documents matched by every node
+    And(Vec<Node<L>>),
+    /// Matches the documents matched by any node
+    Or(Vec<Node<L>>),
+    Not(Box<Node<L>>),
+    /// Matches every document: these nodes only contribute to scores, like the terms without `+`
+    /// of a query with required terms
+    Optional(Vec<Node<L>>),
+}
+
+impl<L> Node<L> {
+    /// Replaces every leaf by `f(leaf)`, removing the leaves for which it returns `None` and
+    /// the nodes left without children
+    pub fn filter_map<M>(self, f: &mut impl FnMut(L) -> Option<M>) -> Option<Node<M>> {
+        let mut children = |nodes: Vec<Self>| {
+            let nodes: Vec<_> = nodes.into_iter().filter_map(|n| n.filter_map(f)).collect();
+            (!nodes.is_empty()).then_some(nodes)
+        };
+        match self {
+            Self::Leaf(leaf) => f(leaf).map(Node::Leaf),
+            Self::And(nodes) => children(nodes).map(Node::And),
+            Self::Or(nodes) => children(nodes).map(Node::Or),
+            Self::Optional(nodes) => children(nodes).map(Node::Optional),
+            Self::Not(node) => node.filter_map(f).map(|n| Node::Not(Box::new(n))),
+        }
+    }
+
+    /// Whether a document matches, `leaf` telling whether it matches a leaf
+    pub fn matches(&self, leaf: &impl Fn(&L) -> bool) -> bool {
+        match self {
+            Self::Leaf(l) => leaf(l),
+            Self::And(nodes) => nodes.iter().all(|n| n.matches(leaf)),
+            Self::Or(nodes) => nodes.iter().any(|n| n.matches(leaf)),
+            Self::Not(node) => !node.matches(leaf),
+            Self::Optional(_) => true,
+        }
+    }
+
+    /// Every leaf, negated or not, in query order
+    pub fn leaves(&self) -> Vec<&L> {
+        let mut leaves = Vec::new();
+        self.walk(false, &mut |leaf, _| leaves.push(leaf));
+        leaves
+    }
+
+    /// Leaves which are not negated, in query order
+    pub fn positive_leaves(&self) -> Vec<&L> {
+        let mut leaves = Vec::new();
+        self.walk(false, &mut |leaf, negated| {
+            if !negated {
+                leaves.push(leaf)
+            }
+        });
+        leaves
+    }
+
+    /// Calls `visit` with every leaf and whether it is negated
+    fn walk<'n>(&'n self, negated: bool, visit: &mut impl FnMut(&'n L, bool)) {
+        match self {
+            Node::Leaf(leaf) => visit(leaf, negated),
+            Node::And(nodes) | Node::Or(nodes) | Node::Optional(nodes) => {
+                nodes.iter().for_each(|n| n.walk(negated, visit))
+            }
+            Node::Not(node) => node.walk(!negated, visit),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'q> {
+    Leaf(Leaf<'q>),
+    And,
+    Or,
+    Not,
+    Required,
+    Prohibited,
+    Open,
+    Close,
+}
+
+fn tokens(query: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for part in parts(query) {
+        let Part::Text(text) = part else {
+            tokens.push(Token::Leaf(part.into()));
+            continue;
+        };
+        for mut word in text.split_whitespace() {
+            loop {
+                let token = match word.chars().next() {
+                    Some('(') => Token::Open,
+                    Some('+') if word.len() > 1 => Token::Required,
+                    Some('-') if word.len() > 1 => Token::Prohibited,
+                    _ => break,
+                };
+                tokens.push(token);
+                word = &word[1..];
+            }
+            let closing = word.len() - word.trim_end_matches(')').len();
+            word = &word[..word.len() - closing];
+            tokens.extend(match word {
+                "" => None,
+                "AND" | "&&" => Some(Token::And),
+                "OR" | "||" => Some(Token::Or),
+                "NOT" => Some(Token::Not),
+                "+" => Some(Token::Required),
+                "-" => Some(Token::Prohibited),
+                _ => {
+                    let (word, boost) = split_boost(word);
+                    let part = match word {
+                        _ if is_field_term(word) => Part::Field(word),
+                        _ if word.len() > 1 && word.ends_with('*') => {
+                            Part::Prefix(&word[..word.len() - 1])
+                        }
+                        _ => Part::Text(word),
+                    };
+                    Some(Token::Leaf(Leaf { part, boost }))
+                }
+            });
+            tokens.extend((0..closing).map(|_| Token::Close));
+        }
+    }
+    tokens
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Occurrence {
+    Optional,
+    Required,
+    Prohibited,
+}
+
+/// Recursive descent parser. `NOT` binds tighter than `AND`, which binds tighter than `OR`,
+/// adjacent clauses being implicitly joined by `OR`.
+struct Parser<'q> {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token<'q>>>,
+    /// Number of groups and unary operators the parser is in
+    depth: usize,
+    /// Whether they were nested more than [`MAX_QUERY_DEPTH`] deep
+    too_deep: bool,
+}
+
+impl<'q> Parser<'q> {
+    /// Clauses up to the end of the query or of the parenthesized group
+    fn group(&mut self, nested: bool) -> Option<Node<Leaf<'q>>> {
+        let mut optional = Vec::new();
+        let mut required = Vec::new();
+        let mut prohibited = Vec::new();
+        while let Some(token) = self.tokens.peek() {
+            match token {
+                Token::Close => {
+                    self.tokens.next();
+                    if nested {
+                        break;
+                    }
+                }
+                Token::Or | Token::And => {
+                    self.tokens.next();
+                }
+                _ => match self.clause() {
+                    Some((Occurrence::Optional, node)) => optional.push(node),
+                    Some((Occurrence::Required, node)) => required.push(node),
+                    Some((Occurrence::Prohibited, node)) => {
+                        prohibited.push(Node::Not(Box::new(node)))
+                    }
+                    None => {}
+                },
+            }
+        }
+
+        let mut nodes = required;
+        match optional.len() {
+            0 => {}
+            1 if nodes.is_empty() => nodes.push(optional.remove(0)),
+            _ if nodes.is_empty() => nodes.push(Node::Or(optional)),
+            _ => nodes.push(Node::Optional(optional)),
+        }
+        nodes.extend(prohibited);
+        match nodes.len() {
+            0 => None,
+            1 => nodes.pop(),
+            _ => Some(Node::And(nodes)),
+        }
+    }
+
+    /// Unary clauses joined by `AND`
+    fn clause(&mut self) -> Option<(Occurrence, Node<Leaf<'q>>)> {
+        let (occurrence, first) = self.unary()?;
+        if self.tokens.peek() != Some(&Token::And) {
+            return Some((occurrence, first));
+        }
+        let negate = |(occurrence, node)| match occurrence {
+            Occurrence::Prohibited => Node::Not(Box::new(node)),
+            _ => node,
+        };
+        let mut nodes = vec![negate((occurrence, first))];
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            if let Some(clause) = self.unary() {
+                nodes.push(negate(clause));
+            }
+        }
+        Some((Occurrence::Optional, Node::And(nodes)))
+    }
+
+    fn unary(&mut self) -> Option<(Occurrence, Node<Leaf<'q>>)> {
+        while self
+            .tokens
+            .next_if(|t| matches!(t, Token::And | Token::Or))
+            .is_some()
+        {}
+        if self.tokens.peek()? == &Token::Close {
+            return None;
+        }
+        match self.tokens.next()? {
+            Token::Not | Token::Prohibited => {
+                let (occurrence, node) = self.nested(Self::unary)?;
+                match occurrence {
+                    Occurrence::Prohibited => Some((Occurrence::Optional, node)),
+                    _ => Some((Occurrence::Prohibited, node)),
+                }
+            }
+            Token::Required => self
+                .nested(Self::unary)
+                .map(|(_, node)| (Occurrence::Required, node)),
+            Token::Open => self
+                .nested(|p| p.group(true))
+                .map(|node| (Occurrence::Optional, node)),
+            Token::Leaf(part) => Some((Occurrence::Optional, Node::Leaf(part))),
+            Token::Close | Token::And | Token::Or => unreachable!(),
+        }
+    }
+
+    /// Parses one level deeper with `parse`, or gives up on the rest of the query when that is
+    /// more than [`MAX_QUERY_DEPTH`] levels deep
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        if self.depth == MAX_QUERY_DEPTH {
+            self.too_deep = true;
+            self.tokens.by_ref().for_each(drop);
+            return None;
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+}
+
+/// Largest number of parenthesized groups and unary operators (`NOT`, `+` and `-`) a query
+/// can nest
+pub const MAX_QUERY_DEPTH: usize = 32;
+
+/// Parses the boolean operators of `query`: `AND`, `OR`, `NOT` and parentheses, as well as
+/// `+term` for required terms, `-term` for prohibited ones and `term*` for the terms starting
+/// with `term`. Terms without operators are joined by `OR`, unless the query has required
+/// terms, in which case they only add to scores. A `^N` suffix, as in `rust^2`, multiplies the
+/// contribution of a term to scores by `N`. `None` for an empty query, an error when the query
+/// nests more than [`MAX_QUERY_DEPTH`] levels deep.
+pub(crate) fn parse(query: &str) -> Result<Option<Node<Leaf<'_>>>, QueryError> {
+    let mut parser = Parser {
+        tokens: tokens(query).into_iter().peekable(),
+        depth: 0,
+        too_deep: false,
+    };
+    let node = parser.group(false);
+    match parser.too_deep {
+        true => Err(QueryError::TooDeep {
+            max: MAX_QUERY_DEPTH,
+        }),
+        false => Ok(node),
+    }
+}
+
+/// A query that cannot be run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    /// The query has more terms than [`SearchOptions::max_query_terms`](crate::SearchOptions)
+    TooManyTerms { count: usize, max: usize },
+    /// Case-sensitive and case-insensitive analyzers are mixed, see
+    /// [`Analyzer::with_case_sensitivity`](crate::Analyzer::with_case_sensitivity)
+    CaseSensitivityMismatch,
+    /// Indexes searched together were built with different analyzers, see
+    /// [`MultiIndex`](crate::MultiIndex)
+    AnalyzerMismatch,
+    /// The query nests groups or unary operators more than `max` levels deep, see
+    /// [`MAX_QUERY_DEPTH`]
+    TooDeep { max: usize },
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyTerms { count, max } => {
+                write!(f, "query has {count} terms, at most {max} are allowed")
+            }
+            Self::CaseSensitivityMismatch => {
+                f.write_str("case-sensitive and case-insensitive analyzers cannot be mixed")
+            }
+            Self::AnalyzerMismatch => {
+                f.write_str("indexes built with different analyzers cannot be searched together")
+            }
+            Self::TooDeep { max } => write!(f, "query is nested more than {max} levels deep"),
+        }
+    }
+}
+
+impl Error for QueryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_field_terms() {
+        assert_eq!(
+            parts("rust extension:md notes mime:text/plain"),
+            [
+                Part::Text("rust "),
+                Part::Field("extension:md"),
+                Part::Text(" notes "),
+                Part::Field("mime:text/plain"),
+            ]
+        );
+        assert_eq!(
+            parts("title:x extension:"),
+            [Part::Text("title:x extension:")]
+        );
+        assert!(parts("  ").is_empty());
+    }
+
+    fn leaf(word: &str) -> Node<Leaf<'_>> {
+        Node::Leaf(Part::Text(word).into())
+    }
+
+    fn not(node: Node<Leaf<'_>>) -> Node<Leaf<'_>> {
+        Node::Not(Box::new(node))
+    }
+
+    #[test]
+    fn parses_boolean_operators() {
+        assert_eq!(parse("rust").unwrap(), Some(leaf("rust")));
+        assert_eq!(
+            parse("rust xml").unwrap(),
+            Some(Node::Or(vec![leaf("rust"), leaf("xml")]))
+        );
+        assert_eq!(
+            parse("rust AND tokenizer").unwrap(),
+            Some(Node::And(vec![leaf("rust"), leaf("tokenizer")]))
+        );
+        assert_eq!(
+            parse("rust -xml").unwrap(),
+            Some(Node::And(vec![leaf("rust"), not(leaf("xml"))]))
+        );
+        assert_eq!(
+            parse("xml OR rust AND NOT tokenizer").unwrap(),
+            Some(Node::Or(vec![
+                leaf("xml"),
+                Node::And(vec![leaf("rust"), not(leaf("tokenizer"))])
+            ]))
+        );
+        assert_eq!(
+            parse("(xml OR rust) AND tokenizer").unwrap(),
+            Some(Node::And(vec![
+                Node::Or(vec![leaf("xml"), leaf("rust")]),
+                leaf("tokenizer")
+            ]))
+        );
+        assert_eq!(
+            parse(r#"a b +c -"d e" -extension:md"#).unwrap(),
+            Some(Node::And(vec![
+                leaf("c"),
+                Node::Optional(vec![leaf("a"), leaf("b")]),
+                not(Node::Leaf(Part::Phrase("d e").into())),
+                not(Node::Leaf(Part::Field("extension:md").into())),
+            ]))
+        );
+        assert_eq!(
+            parse("token* -(tok*)").unwrap(),
+            Some(Node::And(vec![
+                Node::Leaf(Part::Prefix("token").into()),
+                not(Node::Leaf(Part::Prefix("tok").into())),
+            ]))
+        );
+        assert_eq!(parse("*").unwrap(), Some(leaf("*")));
+        assert_eq!(parse("NOT NOT a").unwrap(), Some(leaf("a")));
+        assert_eq!(
+            parse("a AND ) OR").unwrap(),
+            Some(Node::And(vec![leaf("a")]))
+        );
+        assert_eq!(parse("( AND").unwrap(), None);
+        assert_eq!(
+            parse("(a OR b ) AND c").unwrap(),
+            Some(Node::And(vec![
+                Node::Or(vec![leaf("a"), leaf("b")]),
+                leaf("c")
+            ]))
+        );
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(parse(&nested(MAX_QUERY_DEPTH)).unwrap(), Some(leaf("a")));
+        let too_deep = Err(QueryError::TooDeep {
+            max: MAX_QUERY_DEPTH,
+        });
+        assert_eq!(parse(&nested(MAX_QUERY_DEPTH + 1)), too_deep);
+        assert_eq!(parse(&"(".repeat(100_000)), too_deep);
+        assert_eq!(parse(&format!("{}a", "NOT ".repeat(100_000))), too_deep);
+        assert_eq!(parse(&format!("{}a", "-".repeat(100_000))), too_deep);
+        assert_eq!(
+            parse(&format!("{}a", "AND ".repeat(100_000))).unwrap(),
+            Some(leaf("a"))
+        );
+    }
+
+    #[test]
+    fn parses_boosts() {
+        let boosted = |part, boost| Node::Leaf(Leaf { part, boost });
+        assert_eq!(
+            parse("rust^2 (xml^0.5) tok*^4").unwrap(),
+            Some(Node::Or(vec![
+                boosted(Part::Text("rust"), 2.0),
+                boosted(Part::Text("xml"), 0.5),
+                boosted(Part::Prefix("tok"), 4.0),
+            ]))
+        );
+        for literal in ["rust^", "rust^x", "rust^-1", "rust^inf", "^2", "a^b^"] {
+            assert_eq!(parse(literal).unwrap(), Some(leaf(literal)), "{literal}");
+        }
+        assert_eq!(
+            parse("a^b^2").unwrap(),
+            Some(boosted(Part::Text("a^b"), 2.0))
+        );
+    }
+
+    #[test]
+    fn positive_leaves() {
+        let node = parse("a -(b NOT c) +d").unwrap().unwrap();
+        let leaves: Vec<_> = node.positive_leaves().into_iter().map(|l| l.part).collect();
+        assert_eq!(leaves, [Part::Text("d"), Part::Text("a"), Part::Text("c")]);
+    }
+
+    #[test]
+    fn splits_phrases() {
+        assert_eq!(
+            parts(r#"rust "machine learning" extension:md "deep"x ""#),
+            [
+                Part::Text("rust "),
+                Part::Phrase("machine learning"),
+                Part::Field("extension:md"),
+                Part::Phrase("deep"),
+                Part::Text("x "),
+            ]
+        );
+        assert_eq!(
+            parts(r#"a "b c" "d e"#),
+            [Part::Text("a "), Part::Phrase("b c"), Part::Text("d e")]
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! Choice of the tokenizer of each file from its extension.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "pdf")]
+use crate::tokenizer::PdfTokenizer;
+use crate::{
+    dirconfig::TokenizerKind,
+    tokenizer::{
+        CodeTokenizer, CsvTokenizer, Delimiter, DynTokenizer, HtmlTokenizer, JsonTokenizer,
+        MarkdownTokenizer, TextTokenizer, XmlTokenizer,
+    },
+    Analyzer, DirectorySettings,
+};
+
+/// Creates the tokenizer of a file from the analyzer of the index and the settings of the
+/// directory of the file
+pub type TokenizerFactory =
+    Arc<dyn Fn(&Arc<Analyzer>, &DirectorySettings) -> Box<dyn DynTokenizer> + Send + Sync>;
+
+/// Tokenizer a document was indexed with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    #[default]
+    Text,
+    Xml,
+    Html,
+    Csv,
+    Markdown,
+    Json,
+    Code,
+    Pdf,
+    /// Tokenizer registered with [`TokenizerRegistry::with_extension`] or
+    /// [`TokenizerRegistry::with_fallback`]
+    Custom,
+}
+
+impl SourceKind {
+    /// Built-in tokenizer of this kind, if not custom
+    pub(crate) fn tokenizer_kind(self) -> Option<TokenizerKind> {
+        match self {
+            Self::Text => Some(TokenizerKind::Text),
+            Self::Xml => Some(TokenizerKind::Xml),
+            Self::Html => Some(TokenizerKind::Html),
+            Self::Csv => Some(TokenizerKind::Csv),
+            Self::Markdown => Some(TokenizerKind::Markdown),
+            Self::Json => Some(TokenizerKind::Json),
+            Self::Code => Some(TokenizerKind::Code),
+            #[cfg(feature = "pdf")]
+            Self::Pdf => Some(TokenizerKind::Pdf),
+            #[cfg(not(feature = "pdf"))]
+            Self::Pdf => None,
+            Self::Custom => None,
+        }
+    }
+}
+
+impl From<TokenizerKind> for SourceKind {
+    fn from(kind: TokenizerKind) -> Self {
+        match kind {
+            TokenizerKind::Text => Self::Text,
+            TokenizerKind::Xml => Self::Xml,
+            TokenizerKind::Html => Self::Html,
+            TokenizerKind::Csv => Self::Csv,
+            TokenizerKind::Markdown => Self::Markdown,
+            TokenizerKind::Json => Self::Json,
+            TokenizerKind::Code => Self::Code,
+            #[cfg(feature = "pdf")]
+            TokenizerKind::Pdf => Self::Pdf,
+        }
+    }
+}
+
+/// Tokenizers of files by extension. The `tokenizer` set by a `.indexer.toml` file takes
+/// precedence, see [`Index::new_with_registry`](crate::Index::new_with_registry).
+#[derive(Clone, Default)]
+pub struct TokenizerRegistry {
+    extensions: HashMap<String, (SourceKind, TokenizerFactory)>,
+    /// Tokenizer of the files whose extension is not registered, which are skipped if unset
+    fallback: Option<(SourceKind, TokenizerFactory)>,
+}
+
+impl fmt::Debug for TokenizerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut extensions: Vec<_> = self.extensions.keys().collect();
+        extensions.sort();
+        f.debug_struct("TokenizerRegistry")
+            .field("extensions", &extensions)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl TokenizerRegistry {
+    /// Registry without any tokenizer, under which every file is skipped
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tokenizers used by [`Index::new`](crate::Index::new): XML for `xml` and `xhtml`, HTML for
+    /// `html` and `htm`, text for `txt` and `text`, Markdown for `md` and `markdown`, CSV for
+    /// `csv` and `tsv` (with a tab delimiter), JSON for `json`, source code for `rs`, `py`, `go`
+    /// and `java`, PDF for `pdf` with the `pdf` feature, and text for any other extension
+    pub fn default_registry() -> Self {
+        let built_in = |kind| {
+            let factory: TokenizerFactory =
+                Arc::new(move |analyzer, s| tokenizer(kind, analyzer, s));
+            (SourceKind::from(kind), factory)
+        };
+        let tsv: TokenizerFactory = Arc::new(|analyzer, s| {
+            let s = DirectorySettings {
+                csv_delimiter: Delimiter::Byte(b'\t'),
+                ..s.clone()
+            };
+            tokenizer(TokenizerKind::Csv, analyzer, &s)
+        });
+        let extensions = [
+            ("xml", built_in(TokenizerKind::Xml)),
+            ("xhtml", built_in(TokenizerKind::Xml)),
+            ("html", built_in(TokenizerKind::Html)),
+            ("htm", built_in(TokenizerKind::Html)),
+            ("txt", built_in(TokenizerKind::Text)),
+            ("text", built_in(TokenizerKind::Text)),
+            ("rs", built_in(TokenizerKind::Code)),
+            ("py", built_in(TokenizerKind::Code)),
+            ("go", built_in(TokenizerKind::Code)),
+            ("java", built_in(TokenizerKind::Code)),
+            ("md", built_in(TokenizerKind::Markdown)),
+            ("markdown", built_in(TokenizerKind::Markdown)),
+            ("csv", built_in(TokenizerKind::Csv)),
+            ("tsv", (SourceKind::Csv, tsv)),
+            ("json", built_in(TokenizerKind::Json)),
+        ];
+        #[cfg(feature = "pdf")]
+        let extensions = extensions
+            .into_iter()
+            .chain([("pdf", built_in(TokenizerKind::Pdf))]);
+        Self {
+            extensions: extensions
+                .into_iter()
+                .map(|(extension, entry)| (extension.to_owned(), entry))
+                .collect(),
+            fallback: Some(built_in(TokenizerKind::Text)),
+        }
+    }
+
+    /// Shared instance of [`TokenizerRegistry::default_registry`]
+    pub(crate) fn standard() -> &'static Self {
+        static STANDARD: OnceLock<TokenizerRegistry> = OnceLock::new();
+        STANDARD.get_or_init(Self::default_registry)
+    }
+
+    /// Tokenizes the files with the extension `extension` (without its leading dot, and case
+    /// sensitive) with the tokenizers created by `factory`
+    pub fn with_extension(
+        mut self,
+        extension: impl Into<String>,
+        factory: impl Fn(&Arc<Analyzer>, &DirectorySettings) -> Box<dyn DynTokenizer>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.extensions
+            .insert(extension.into(), (SourceKind::Custom, Arc::new(factory)));
+        self
+    }
+
+    /// Tokenizes the files whose extension is not registered with the tokenizers created by
+    /// `factory`
+    pub fn with_fallback(
+        mut self,
+        factory: impl Fn(&Arc<Analyzer>, &DirectorySettings) -> Box<dyn DynTokenizer>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.fallback = Some((SourceKind::Custom, Arc::new(factory)));
+        self
+    }
+
+    /// Skips the files whose extension is not registered
+    pub fn without_fallback(mut self) -> Self {
+        self.fallback = None;
+        self
+    }
+
+    pub(crate) fn get(&self, extension: &str) -> Option<&(SourceKind, TokenizerFactory)> {
+        self.extensions.get(extension)
+    }
+
+    pub(crate) fn fallback(&self) -> Option<&(SourceKind, TokenizerFactory)> {
+        self.fallback.as_ref()
+    }
+}
+
+/// Built-in tokenizer of `kind`, configured by `s`
+pub(crate) fn tokenizer(
+    kind: TokenizerKind,
+    analyzer: &Arc<Analyzer>,
+    s: &DirectorySettings,
+) -> Box<dyn DynTokenizer> {
+    let analyzer = Arc::clone(analyzer);
+    match kind {
+        TokenizerKind::Text => Box::new(
+            TextTokenizer::default()
+                .with_analyzer(analyzer)
+                .with_title_capture(s.capture_titles),
+        ),
+        TokenizerKind::Xml => Box::new(XmlTokenizer::default().with_analyzer(analyzer)),
+        TokenizerKind::Html => Box::new(
+            HtmlTokenizer::default()
+                .with_analyzer(analyzer)
+                .with_title_capture(s.capture_titles),
+        ),
+        TokenizerKind::Csv => Box::new(
+            CsvTokenizer::default()
+                .with_analyzer(analyzer)
+                .with_delimiter(s.csv_delimiter)
+                .with_header(s.csv_header)
+                .with_columns(s.csv_columns.clone()),
+        ),
+        TokenizerKind::Markdown => Box::new(
+            MarkdownTokenizer::default()
+                .with_analyzer(analyzer)
+                .with_title_capture(s.capture_titles),
+        ),
+        TokenizerKind::Json => Box::new(JsonTokenizer::default().with_analyzer(analyzer)),
+        TokenizerKind::Code => Box::new(CodeTokenizer::default().with_analyzer(analyzer)),
+        #[cfg(feature = "pdf")]
+        TokenizerKind::Pdf => Box::new(PdfTokenizer::default().with_analyzer(analyzer)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{write, File},
+        time::{Duration, SystemTime},
+    };
+
+    use super::*;
+    use crate::{testutil::TempDir, Index};
+
+    #[test]
+    fn extensions_map_to_tokenizers() {
+        let dir = TempDir::new("registry");
+        let script = dir.write("script.py", "def main(): pass");
+        let page = dir.write("page.html", "<p>hello</p>");
+        let notes = dir.write("notes.txt", "plain notes");
+        dir.write("data.bin", "binary");
+
+        let default =
+            Index::with_tokenizer_registry(dir.path(), &TokenizerRegistry::default_registry());
+        assert_eq!(default, Index::new(dir.path()));
+
+        let registry = TokenizerRegistry::new()
+            .with_extension("py", |analyzer, s| {
+                tokenizer(TokenizerKind::Text, analyzer, s)
+            })
+            .with_extension("html", |analyzer, _| {
+                Box::new(TextTokenizer::default().with_analyzer(Arc::clone(analyzer)))
+            });
+        let index = Index::with_tokenizer_registry(dir.path(), &registry);
+        assert_eq!(index.documents.len(), 2);
+        assert!(index.document(&script).unwrap().contains("def"));
+        assert!(index.document(&page).unwrap().contains("p"));
+        assert!(index.document(&notes).is_none());
+
+        let index = Index::with_tokenizer_registry(
+            dir.path(),
+            &registry.with_fallback(|analyzer, s| tokenizer(TokenizerKind::Text, analyzer, s)),
+        );
+        assert_eq!(index.documents.len(), 4);
+    }
+
+    #[test]
+    fn updates_keep_custom_tokenizers() {
+        let dir = TempDir::new("registry-update");
+        let script = dir.write("script.py", "def main(): pass");
+        let registry = TokenizerRegistry::default_registry().with_extension("py", |analyzer, _| {
+            Box::new(
+                TextTokenizer::default()
+                    .with_analyzer(Arc::clone(analyzer))
+                    .with_title_capture(true),
+            )
+        });
+        let mut index = Index::with_tokenizer_registry(dir.path(), &registry);
+        let title = |index: &Index| index.document(&script).unwrap().title().map(str::to_owned);
+        assert_eq!(title(&index).as_deref(), Some("def main(): pass"));
+
+        let touch = |content| {
+            write(&script, content).unwrap();
+            File::options()
+                .write(true)
+                .open(&script)
+                .unwrap()
+                .set_modified(SystemTime::now() + Duration::from_secs(10))
+                .unwrap();
+        };
+        touch("def other(): pass");
+        index.update(dir.path()).unwrap();
+        index.reindex([script.clone()]);
+        assert_eq!(title(&index).as_deref(), Some("def main(): pass"));
+        index.update_with_registry(dir.path(), &registry).unwrap();
+        assert_eq!(title(&index).as_deref(), Some("def other(): pass"));
+        touch("def last(): pass");
+        index.reindex_with_registry([script.clone()], &registry);
+        assert_eq!(title(&index).as_deref(), Some("def last(): pass"));
+    }
+}
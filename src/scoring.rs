@@ -0,0 +1,461 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{Analyzer, Language};
+
+/// How the number of occurrences of a term within a document is turned into a weight
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TfScheme {
+    /// `count / document length`
+    #[default]
+    Proportional,
+    /// `1 + ln(count)`: each extra occurrence of a term matters less than the previous one, so a
+    /// document repeating a term many times is not rewarded linearly
+    Log,
+    /// `0.5 + 0.5 * count / max_count`, `max_count` being the number of occurrences of the most
+    /// frequent term of the document: long documents are not penalized, and every matched term
+    /// weighs at least half as much as the most frequent one
+    Augmented,
+}
+
+impl TfScheme {
+    /// Weight of a term seen `count` times in a document of `length` tokens, whose most frequent
+    /// term is seen `max_count` times, zero for an empty document
+    pub fn weight(self, count: usize, length: usize, max_count: usize) -> f64 {
+        if count == 0 || length == 0 {
+            return 0f64;
+        }
+        match self {
+            Self::Proportional => count as f64 / length as f64,
+            Self::Log => 1f64 + (count as f64).ln(),
+            Self::Augmented => 0.5 + 0.5 * count as f64 / max_count.max(count) as f64,
+        }
+    }
+}
+
+impl fmt::Display for TfScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Proportional => "proportional",
+            Self::Log => "log",
+            Self::Augmented => "augmented",
+        })
+    }
+}
+
+impl FromStr for TfScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "proportional" | "raw" => Ok(Self::Proportional),
+            "log" => Ok(Self::Log),
+            "augmented" => Ok(Self::Augmented),
+            _ => Err(format!("unknown tf scheme {s:?}")),
+        }
+    }
+}
+
+/// How rare terms are weighed against common ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdfScheme {
+    /// `log2(N / (n + 1))`, `N` documents of which `n` contain the term. Negative for terms found
+    /// in nearly every document, which then lower the scores of the documents containing them:
+    /// a document matching such a term in addition to others can rank below one which does not.
+    #[default]
+    Log,
+    /// [`IdfScheme::Log`] floored at zero: terms found in nearly every document neither raise
+    /// nor lower scores
+    Clamped,
+    /// `ln(1 + (N - n + 0.5) / (n + 0.5))`, as in BM25: always positive, close to zero for terms
+    /// found in every document, so every matched term raises the score a little
+    Smoothed,
+}
+
+impl IdfScheme {
+    /// Weight of a term found in `frequency` of `documents` documents. A `frequency` above
+    /// `documents` counts as `documents`.
+    pub fn weight(self, documents: usize, frequency: usize) -> f64 {
+        let (n, d) = (documents as f64, frequency.min(documents) as f64);
+        match self {
+            Self::Log => (n / (d + 1f64)).log2(),
+            Self::Clamped => (n / (d + 1f64)).log2().max(0f64),
+            Self::Smoothed => (1f64 + (n - d + 0.5) / (d + 0.5)).ln(),
+        }
+    }
+}
+
+impl fmt::Display for IdfScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Log => "log",
+            Self::Clamped => "clamped",
+            Self::Smoothed => "smoothed",
+        })
+    }
+}
+
+impl FromStr for IdfScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "log" => Ok(Self::Log),
+            "clamped" => Ok(Self::Clamped),
+            "smoothed" => Ok(Self::Smoothed),
+            _ => Err(format!("unknown idf scheme {s:?}")),
+        }
+    }
+}
+
+/// Order of search results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RankMode {
+    /// Decreasing score
+    #[default]
+    Score,
+    /// Decreasing number of distinct query terms found in the document, then decreasing score:
+    /// a document matching more of the query always ranks first
+    Coverage,
+}
+
+impl fmt::Display for RankMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Score => "score",
+            Self::Coverage => "coverage",
+        })
+    }
+}
+
+impl FromStr for RankMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "score" => Ok(Self::Score),
+            "coverage" => Ok(Self::Coverage),
+            _ => Err(format!("unknown rank mode {s:?}")),
+        }
+    }
+}
+
+/// Which documents a query matches, besides its operators
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    /// Documents containing any of the query terms
+    #[default]
+    Any,
+    /// Documents containing every query term which is not negated, stop words being dropped by
+    /// the analyzer beforehand
+    All,
+}
+
+impl fmt::Display for MatchMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Any => "any",
+            Self::All => "all",
+        })
+    }
+}
+
+impl FromStr for MatchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(Self::Any),
+            "all" => Ok(Self::All),
+            _ => Err(format!("unknown match mode {s:?}")),
+        }
+    }
+}
+
+/// What to do with a query having more terms than [`SearchOptions::max_query_terms`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TermLimitPolicy {
+    /// Only keep the first terms
+    #[default]
+    Truncate,
+    /// Fail with [`QueryError::TooManyTerms`](crate::query::QueryError)
+    Reject,
+}
+
+impl fmt::Display for TermLimitPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Truncate => "truncate",
+            Self::Reject => "reject",
+        })
+    }
+}
+
+impl FromStr for TermLimitPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "truncate" => Ok(Self::Truncate),
+            "reject" => Ok(Self::Reject),
+            _ => Err(format!("unknown term limit policy {s:?}")),
+        }
+    }
+}
+
+/// Settings used while ranking documents
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    pub tf: TfScheme,
+    pub idf: IdfScheme,
+    /// Multiplier applied to a query term found in the document title
+    pub title_boost: f64,
+    /// Analyzer used on the query instead of the one the index was built with. Useful for
+    /// query-side only processing such as synonym expansion.
+    pub query_analyzer: Option<Analyzer>,
+    /// Stem query terms and drop their stop words as usual in this language, to match the
+    /// documents detected to be written in it, see
+    /// [`IndexOptions::detect_language`](crate::IndexOptions::detect_language). Applies to
+    /// [`SearchOptions::query_analyzer`] if set.
+    pub language: Option<Language>,
+    /// Ignore query terms whose idf is zero or negative, i.e. terms found in (nearly) every
+    /// document. They carry no ranking signal and would otherwise lower every score alike.
+    pub drop_zero_idf: bool,
+    /// Keep at most this many results per directory, so one directory cannot fill the top of the
+    /// list. Applied after ranking.
+    pub directory_cap: Option<DirectoryCap>,
+    /// Only keep the results ranked above the largest relative score drop among the top ones,
+    /// see [`AUTO_CUTOFF_WINDOW`] and [`AUTO_CUTOFF_MIN_DROP`]
+    pub auto_cutoff: bool,
+    /// Maximum number of terms in an analyzed query, each one costing a pass over the documents.
+    /// `None` accepts any query.
+    pub max_query_terms: Option<usize>,
+    pub term_limit_policy: TermLimitPolicy,
+    /// Order of the results
+    pub rank: RankMode,
+    /// Weigh query terms found near the start of a document higher. Only applies to documents
+    /// indexed with [`IndexOptions::track_positions`](crate::IndexOptions).
+    pub position_boost: Option<PositionBoost>,
+    /// Whether documents must contain every query term to match
+    pub match_mode: MatchMode,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            tf: TfScheme::default(),
+            idf: IdfScheme::default(),
+            title_boost: 2f64,
+            query_analyzer: None,
+            language: None,
+            drop_zero_idf: false,
+            directory_cap: None,
+            auto_cutoff: false,
+            max_query_terms: None,
+            term_limit_policy: TermLimitPolicy::default(),
+            rank: RankMode::default(),
+            position_boost: None,
+            match_mode: MatchMode::default(),
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Whether results are ranked by score alone, so that the best ones can be selected while
+    /// scoring documents rather than by ranking every result
+    pub(crate) fn ranks_by_score(&self) -> bool {
+        self.rank == RankMode::Score && self.directory_cap.is_none() && !self.auto_cutoff
+    }
+
+    /// Analyzer of the queries searching an index built with `index_analyzer`
+    pub(crate) fn analyzer<'a>(&'a self, index_analyzer: &'a Analyzer) -> Cow<'a, Analyzer> {
+        let analyzer = self.query_analyzer.as_ref().unwrap_or(index_analyzer);
+        match self.language {
+            Some(language) => Cow::Owned(analyzer.clone().with_language(language)),
+            None => Cow::Borrowed(analyzer),
+        }
+    }
+}
+
+/// Number of top results looked at by [`SearchOptions::auto_cutoff`]
+pub const AUTO_CUTOFF_WINDOW: usize = 10;
+
+/// Smallest relative score drop between two consecutive results considered a cutoff point: `0.5`
+/// means the next result scores at most half as much
+pub const AUTO_CUTOFF_MIN_DROP: f64 = 0.5;
+
+/// Truncates ranked `results` at the largest relative score drop among the first
+/// [`AUTO_CUTOFF_WINDOW`] ones. Nothing is removed if no drop reaches [`AUTO_CUTOFF_MIN_DROP`].
+pub(crate) fn auto_cutoff<T>(results: &mut Vec<T>, score: impl Fn(&T) -> f64) {
+    let window = &results[..results.len().min(AUTO_CUTOFF_WINDOW)];
+    let cutoff = window
+        .windows(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let (s1, s2) = (score(&pair[0]), score(&pair[1]));
+            (i + 1, (s1 - s2) / s1)
+        })
+        .filter(|(_, drop)| *drop >= AUTO_CUTOFF_MIN_DROP)
+        .max_by(|(_, d1), (_, d2)| d1.total_cmp(d2));
+    if let Some((len, _)) = cutoff {
+        results.truncate(len);
+    }
+}
+
+/// Maximum number of search results sharing a directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirectoryCap {
+    pub max: usize,
+    /// Number of leading path components, the root included, identifying a directory. `None`
+    /// groups documents by their parent directory.
+    pub depth: Option<usize>,
+}
+
+impl DirectoryCap {
+    fn directory(&self, path: &Path) -> PathBuf {
+        let parent = path.parent().unwrap_or(Path::new(""));
+        match self.depth {
+            Some(depth) => parent.components().take(depth).collect(),
+            None => parent.to_owned(),
+        }
+    }
+
+    /// Drops the results of directories which already have `max` better ranked results
+    pub(crate) fn apply<T>(&self, results: &mut Vec<T>, path: impl Fn(&T) -> &Path) {
+        let mut seen: HashMap<PathBuf, usize> = HashMap::new();
+        results.retain(|r| {
+            let count = seen.entry(self.directory(path(r))).or_default();
+            *count += 1;
+            *count <= self.max
+        });
+    }
+}
+
+/// Boost of the query terms occurring near the start of a document, such as in an introduction
+/// or abstract
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionBoost {
+    /// Extra weight of an occurrence at the very start of the document: `1.0` doubles its
+    /// contribution
+    weight: f64,
+    /// Number of tokens after which the extra weight of an occurrence is halved
+    half_life: f64,
+}
+
+impl Default for PositionBoost {
+    fn default() -> Self {
+        Self {
+            weight: 1f64,
+            half_life: 50f64,
+        }
+    }
+}
+
+impl PositionBoost {
+    /// Gives occurrences at the very start of a document `weight` extra weight, halved every
+    /// `half_life` tokens. Fails unless `weight` is finite and not negative, and `half_life` is
+    /// finite and greater than 0.
+    pub fn new(weight: f64, half_life: f64) -> io::Result<Self> {
+        if !weight.is_finite() || weight < 0f64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid position weight {weight}"),
+            ));
+        }
+        if !half_life.is_finite() || half_life <= 0f64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid half-life {half_life}, must be a finite number greater than 0"),
+            ));
+        }
+        Ok(Self { weight, half_life })
+    }
+
+    /// Like [`PositionBoost::new`], with the default weight
+    pub fn with_half_life(half_life: f64) -> io::Result<Self> {
+        Self::new(Self::default().weight, half_life)
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    pub fn half_life(&self) -> f64 {
+        self.half_life
+    }
+
+    /// Multiplier of a term occurring at `positions`: `1 + weight` times the average decay of
+    /// its occurrences. Neutral without positions.
+    pub fn factor(&self, positions: &[usize]) -> f64 {
+        if positions.is_empty() {
+            return 1f64;
+        }
+        let decay: f64 = positions
+            .iter()
+            .map(|&p| 0.5f64.powf(p as f64 / self.half_life))
+            .sum();
+        1f64 + self.weight * decay / positions.len() as f64
+    }
+}
+
+/// Part of a document a query term can be found in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    /// The title extracted from the document, see
+    /// [`IndexOptions::capture_titles`](crate::IndexOptions)
+    Title,
+    /// The whole content of the document
+    Body,
+}
+
+/// Query terms found in one field of a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldMatch {
+    pub field: Field,
+    /// Multiplier applied to the contribution of the terms found in this field
+    pub boost: f64,
+    pub terms: Vec<String>,
+}
+
+/// Words of a document matched by a query term
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermForms {
+    pub term: String,
+    /// As written in the document, e.g. `Rust` and `RUST` for the term `rust`
+    pub forms: Vec<String>,
+}
+
+/// Number of documents scored between two checks of the clock by
+/// [`Index::search_with_deadline`](crate::Index::search_with_deadline)
+pub const DEADLINE_CHECK_INTERVAL: usize = 64;
+
+/// Results of a search bounded in time, see
+/// [`Index::search_with_deadline`](crate::Index::search_with_deadline)
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadlineResults<'a> {
+    pub results: Vec<(&'a Path, f64)>,
+    /// Whether the deadline passed before every matching document was scored, in which case
+    /// `results` only ranks those scored in time
+    pub partial: bool,
+}
+
+/// A ranked document along with the reasons it matched
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit<'a> {
+    pub path: &'a Path,
+    pub score: f64,
+    pub fields: Vec<FieldMatch>,
+    /// Document words matched by each query term found in the document, in query order
+    pub forms: Vec<TermForms>,
+    /// Part of `score` due to each query term found in the document, in query order, including
+    /// the document boost. They add up to `score`, up to rounding.
+    pub contributions: Vec<(String, f64)>,
+    /// Excerpts of the document around the matched words, only filled by
+    /// [`Index::search_with_snippets`](crate::Index::search_with_snippets)
+    pub snippets: Vec<String>,
+}
@@ -0,0 +1,459 @@
+//! Index storage split across several files.
+//!
+//! `documents.json` holds the index settings and the metadata of every document (length, title),
+//! and each `terms-<c>.json` file the postings of the terms starting with the character `c`, so
+//! a query only needs to read the files of its own terms. Characters other than ASCII letters and
+//! digits are written as their code point, e.g. `terms-u5f.json` for `_`.
+//!
+//! Indexes saved before [`LAYOUT_VERSION`] named files after the ASCII lowercase of the first
+//! character rather than its case folding, so the file of a term cannot be told from its name:
+//! all their files are read when they are opened.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{create_dir_all, read_dir, remove_file, File},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    case_insensitive_string::fold,
+    no_vocabulary, parse_query,
+    term_map::{self, insert_merged, MergeTerm},
+    Analyzer, Cached, Document, Index, IndexOptions, Language, SearchOptions, SourceKind, TermMap,
+};
+
+const DOCUMENTS_FILE: &str = "documents.json";
+
+/// Version of the layout, bumped when the files of terms were named by Unicode case folding.
+/// Indexes without one are of the first version.
+const LAYOUT_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct Meta {
+    #[serde(default = "first_version")]
+    version: u32,
+    #[serde(default, skip_serializing_if = "Analyzer::is_standard")]
+    analyzer: Analyzer,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    boosts: HashMap<PathBuf, f64>,
+    #[serde(default)]
+    options: IndexOptions,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    root: Option<PathBuf>,
+    documents: HashMap<PathBuf, DocumentEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DocumentEntry {
+    count: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    /// Whether term positions were recorded for this document
+    #[serde(default, skip_serializing_if = "is_false")]
+    positions: bool,
+    /// Whether the forms of terms were recorded for this document
+    #[serde(default, skip_serializing_if = "is_false")]
+    forms: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<SystemTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kind: Option<SourceKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<Language>,
+    /// See [`Document::max_count`], unknown in indexes written before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_count: Option<usize>,
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+/// Occurrences of a term in one document
+#[derive(Serialize, Deserialize)]
+struct Posting {
+    count: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    positions: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    forms: Vec<String>,
+}
+
+impl MergeTerm for Posting {
+    fn merge(&mut self, other: Self) {
+        self.count.merge(other.count);
+        self.positions.merge(other.positions);
+        self.forms.merge(other.forms);
+    }
+}
+
+type Shard = TermMap<BTreeMap<PathBuf, Posting>>;
+
+/// [`Shard`] as read from its file, see [`term_map`]
+#[derive(Deserialize)]
+#[serde(transparent)]
+struct ShardFile(#[serde(deserialize_with = "term_map::deserialize")] Shard);
+
+/// Name of the file holding the postings of `term`
+fn shard_name(term: &str) -> String {
+    // Terms equal ignoring case must share a file
+    match term.chars().flat_map(fold).next() {
+        Some(c) if c.is_ascii_alphanumeric() => format!("terms-{c}.json"),
+        Some(c) => format!("terms-u{:x}.json", c as u32),
+        None => "terms-empty.json".into(),
+    }
+}
+
+fn is_shard_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with("terms-") && n.ends_with(".json"))
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> io::Result<T> {
+    serde_json::from_reader(BufReader::new(File::open(path)?))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    serde_json::to_writer(BufWriter::new(File::create(path)?), value)
+        .map_err(|err| io::Error::other(err.to_string()))
+}
+
+impl Index {
+    /// Writes the index in `dir` split across several files: `documents.json` for the documents
+    /// and settings, and one `terms-<c>.json` file per first character of the terms. Any sharded
+    /// index previously saved in `dir` is replaced.
+    pub fn save_sharded(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        create_dir_all(dir)?;
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if is_shard_file(&path) {
+                remove_file(path)?;
+            }
+        }
+
+        let mut shards: HashMap<String, Shard> = HashMap::new();
+        let mut documents = HashMap::with_capacity(self.documents.len());
+        for (path, document) in &self.documents {
+            for (term, &count) in &document.term_frequency {
+                let positions = document
+                    .positions
+                    .as_ref()
+                    .and_then(|p| p.get(term))
+                    .cloned()
+                    .unwrap_or_default();
+                let forms = document
+                    .forms
+                    .as_ref()
+                    .and_then(|f| f.get(term))
+                    .cloned()
+                    .unwrap_or_default();
+                shards
+                    .entry(shard_name(term))
+                    .or_default()
+                    .entry(term.clone())
+                    .or_default()
+                    .insert(
+                        path.clone(),
+                        Posting {
+                            count,
+                            positions,
+                            forms,
+                        },
+                    );
+            }
+            documents.insert(
+                path.clone(),
+                DocumentEntry {
+                    count: document.count,
+                    title: document.title.clone(),
+                    positions: document.positions.is_some(),
+                    forms: document.forms.is_some(),
+                    modified: document.modified,
+                    size: document.size,
+                    kind: document.kind,
+                    language: document.language,
+                    max_count: Some(document.max_count()),
+                },
+            );
+        }
+
+        for (name, shard) in &shards {
+            write_json(&dir.join(name), shard)?;
+        }
+        let meta = Meta {
+            version: LAYOUT_VERSION,
+            analyzer: self.analyzer.clone(),
+            boosts: self.boosts.clone(),
+            options: self.options.clone(),
+            root: self.root.clone(),
+            documents,
+        };
+        write_json(&dir.join(DOCUMENTS_FILE), &meta)
+    }
+
+    /// Loads a whole index saved with [`Index::save_sharded`]. Use [`ShardedIndex`] to only read
+    /// the files needed by queries.
+    pub fn load_sharded(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut sharded = ShardedIndex::open(dir)?;
+        sharded.load_all_shards()?;
+        Ok(sharded.index)
+    }
+}
+
+/// An index saved with [`Index::save_sharded`], whose term files are read as queries need them
+#[derive(Debug)]
+pub struct ShardedIndex {
+    dir: PathBuf,
+    /// Every document, with the terms of the loaded shards only
+    index: Index,
+    loaded: HashSet<String>,
+}
+
+impl ShardedIndex {
+    /// Reads the document metadata of the index saved in `dir`, but none of its terms, unless
+    /// the index was saved with an older layout
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        let meta: Meta = read_json(&dir.join(DOCUMENTS_FILE))?;
+        let documents = meta
+            .documents
+            .into_iter()
+            .map(|(path, d)| {
+                let document = Document {
+                    term_frequency: TermMap::new(),
+                    count: d.count,
+                    title: d.title,
+                    positions: d.positions.then(TermMap::new),
+                    forms: d.forms.then(TermMap::new),
+                    modified: d.modified,
+                    size: d.size,
+                    kind: d.kind,
+                    language: d.language,
+                    max_count: d.max_count.map(Cached::new).unwrap_or_default(),
+                };
+                (path, document)
+            })
+            .collect();
+        let index = Index {
+            documents,
+            analyzer: meta.analyzer,
+            boosts: meta.boosts,
+            options: meta.options,
+            root: meta.root,
+            postings: Default::default(),
+        };
+        index.check_boosts()?;
+        let mut sharded = Self {
+            dir,
+            index,
+            loaded: HashSet::new(),
+        };
+        if meta.version < LAYOUT_VERSION {
+            log::info!("reading every term file of an older sharded index");
+            sharded.load_all_shards()?;
+        }
+        Ok(sharded)
+    }
+
+    /// Number of term files read so far
+    pub fn loaded_shards(&self) -> usize {
+        self.loaded.len()
+    }
+
+    fn load_all_shards(&mut self) -> io::Result<()> {
+        for entry in read_dir(&self.dir)? {
+            let path = entry?.path();
+            let loaded = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| self.loaded.contains(n));
+            if is_shard_file(&path) && !loaded {
+                self.load_shard(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn load_shard(&mut self, path: &Path) -> io::Result<()> {
+        let shard = match read_json(path) {
+            Ok(ShardFile(shard)) => shard,
+            // No indexed term starts with this character
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Shard::new(),
+            Err(e) => return Err(e),
+        };
+        for (term, postings) in shard {
+            for (path, posting) in postings {
+                let Some(document) = self.index.documents_mut().get_mut(&path) else {
+                    continue;
+                };
+                // Files of older layouts may hold terms equal to those of other files
+                if let Some(ref mut positions) = document.positions {
+                    insert_merged(positions, term.clone(), posting.positions);
+                }
+                if let Some(ref mut forms) = document.forms {
+                    insert_merged(forms, term.clone(), posting.forms);
+                }
+                insert_merged(&mut document.term_frequency, term.clone(), posting.count);
+            }
+        }
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            self.loaded.insert(name.to_owned());
+        }
+        Ok(())
+    }
+
+    pub fn search(&mut self, terms: &str) -> io::Result<Vec<(&Path, f64)>> {
+        self.search_with_options(terms, &SearchOptions::default())
+    }
+
+    /// Reads the term files needed by the query, then searches like
+    /// [`Index::search_with_options`]
+    pub fn search_with_options(
+        &mut self,
+        terms: &str,
+        options: &SearchOptions,
+    ) -> io::Result<Vec<(&Path, f64)>> {
+        let invalid_query = |e| io::Error::new(io::ErrorKind::InvalidInput, e);
+        // The terms starting with a prefix share the file of the prefix, which is read before
+        // expanding it with the vocabulary
+        let prefixes = RefCell::new(HashSet::new());
+        let record_prefix = |prefix: &str| {
+            prefixes.borrow_mut().insert(shard_name(prefix));
+            no_vocabulary(prefix)
+        };
+        parse_query(terms, &self.index.analyzer, options, &record_prefix).map_err(invalid_query)?;
+        self.load_shards(prefixes.into_inner())?;
+        // Negated terms do not score documents but still exclude them
+        let (_, filter) = parse_query(
+            terms,
+            &self.index.analyzer,
+            options,
+            &self.index.vocabulary(),
+        )
+        .map_err(invalid_query)?;
+        let names: HashSet<_> = filter
+            .iter()
+            .flat_map(|f| f.leaves())
+            .flat_map(|leaf| &leaf.terms)
+            .map(|term| shard_name(term))
+            .collect();
+        self.load_shards(names)?;
+        Ok(self.index.search_with_options(terms, options))
+    }
+
+    /// Reads the term files named `names` which are not loaded yet
+    fn load_shards(&mut self, names: HashSet<String>) -> io::Result<()> {
+        for name in names {
+            if !self.loaded.contains(&name) {
+                self.load_shard(&self.dir.join(name))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil::TempDir, CaseInsensitiveStr};
+
+    #[test]
+    fn round_trip_and_lazy_search() {
+        let corpus = TempDir::new("sharded-corpus");
+        corpus.write("a.txt", "Rust notes\nrust borrow checker");
+        corpus.write("b.txt", "xml parser _private");
+        corpus.write("c.txt", "rust xml bridge");
+        for i in 0..6 {
+            corpus.write(format!("filler{i}.txt"), "zebra yak");
+        }
+        let index = Index::new_with_options(
+            corpus.path(),
+            IndexOptions {
+                capture_titles: true,
+                track_positions: true,
+                keep_term_forms: true,
+                ..Default::default()
+            },
+        );
+
+        let store = TempDir::new("sharded-store");
+        store.write("terms-q.json", "{}");
+        index.save_sharded(store.path()).unwrap();
+        assert!(!store.path().join("terms-q.json").exists());
+        assert!(store.path().join("terms-u5f.json").exists());
+        assert_eq!(Index::load_sharded(store.path()).unwrap(), index);
+
+        let mut sharded = ShardedIndex::open(store.path()).unwrap();
+        assert_eq!(sharded.loaded_shards(), 0);
+        assert_eq!(sharded.search("RUST").unwrap(), index.search("RUST"));
+        assert_eq!(sharded.loaded_shards(), 1);
+        assert_eq!(
+            sharded.search("rust parser").unwrap(),
+            index.search("rust parser")
+        );
+        assert_eq!(sharded.loaded_shards(), 2);
+        assert!(sharded.search("quux").unwrap().is_empty());
+        assert_eq!(
+            sharded.search("rust -bridge").unwrap(),
+            index.search("rust -bridge")
+        );
+        assert_eq!(
+            sharded.search("+pars* bri*").unwrap(),
+            index.search("+pars* bri*")
+        );
+    }
+    #[test]
+    fn reads_older_layouts_whole() {
+        // Saved when files were named after the ASCII lowercase of the first character
+        let store = TempDir::new("sharded-v1");
+        store.write(
+            DOCUMENTS_FILE,
+            r#"{"documents": {
+                "a.txt": {"count": 4},
+                "b.txt": {"count": 1},
+                "c.txt": {"count": 1},
+                "d.txt": {"count": 1}
+            }}"#,
+        );
+        store.write("terms-u41c.json", r#"{"МОСКВА": {"a.txt": {"count": 1}}}"#);
+        store.write("terms-u43c.json", r#"{"москва": {"a.txt": {"count": 2}}}"#);
+        store.write(
+            "terms-s.json",
+            r#"{"Straße": {"a.txt": {"count": 1}}, "STRASSE": {"b.txt": {"count": 1}}}"#,
+        );
+        store.write(
+            "terms-o.json",
+            r#"{"other": {"c.txt": {"count": 1}, "d.txt": {"count": 1}}}"#,
+        );
+
+        let mut sharded = ShardedIndex::open(store.path()).unwrap();
+        assert_eq!(sharded.loaded_shards(), 4);
+        let document = sharded.index.document(Path::new("a.txt")).unwrap();
+        assert_eq!(
+            document.term_frequency[CaseInsensitiveStr::new("москва")],
+            3
+        );
+        assert_eq!(sharded.search("strasse").unwrap().len(), 2);
+        let index = Index::load_sharded(store.path()).unwrap();
+        let document = index.document(Path::new("a.txt")).unwrap();
+        assert_eq!(document.terms().map(|(_, c)| c).sum::<usize>(), 4);
+
+        index.save_sharded(store.path()).unwrap();
+        assert!(!store.path().join("terms-u41c.json").exists());
+        assert_eq!(ShardedIndex::open(store.path()).unwrap().loaded_shards(), 0);
+    }
+}
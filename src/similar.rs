@@ -0,0 +1,208 @@
+//! Vector space comparisons: documents and queries are vectors of tf-idf weights, one dimension
+//! per term, compared by the cosine of their angle. Unlike tf-idf sums, cosines do not grow
+//! with the number of terms of a document.
+
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    parse_query, query_matcher, sort_results, CaseInsensitiveStr, Document, IdfScheme, Index,
+    SearchOptions, TfScheme,
+};
+
+/// Number of terms of a document, those of highest tf-idf, compared with other documents by
+/// [`Index::similar_to`]
+pub const SIMILARITY_TERMS: usize = 25;
+
+/// Tf-idf weights of the terms of an index, with the [`IdfScheme::Smoothed`] idf, which is never
+/// negative
+struct TfIdf<'i> {
+    documents: usize,
+    frequencies: HashMap<&'i CaseInsensitiveStr, (&'i str, usize)>,
+}
+
+impl<'i> TfIdf<'i> {
+    fn new(index: &'i Index) -> Self {
+        Self {
+            documents: index.documents.len(),
+            frequencies: index.document_frequencies(),
+        }
+    }
+
+    fn idf(&self, term: &CaseInsensitiveStr) -> f64 {
+        let frequency = self.frequencies.get(term).map_or(0, |(_, f)| *f);
+        IdfScheme::Smoothed.weight(self.documents, frequency)
+    }
+
+    fn weight(&self, document: &Document, term: &CaseInsensitiveStr) -> f64 {
+        match document.term_frequency.get(term) {
+            Some(count) => {
+                TfScheme::Proportional.weight(*count, document.count, document.max_count())
+                    * self.idf(term)
+            }
+            None => 0f64,
+        }
+    }
+
+    /// Norm of the vector of `document`, summed in the order of its term map so that it does not
+    /// depend on the order of freshly built maps
+    fn norm(&self, document: &Document) -> f64 {
+        let weights = document
+            .term_frequency
+            .keys()
+            .map(|t| self.weight(document, CaseInsensitiveStr::new(t)));
+        norm(weights)
+    }
+
+    /// Cosine of the vector `query` with that of every document sharing one of its terms and
+    /// for which `keep` returns `true`, multiplied by the document boost when `boost` is set.
+    /// Results are sorted.
+    fn rank<'a>(
+        &self,
+        index: &'a Index,
+        query: &[(&CaseInsensitiveStr, f64)],
+        keep: impl Fn(&Path, &Document) -> bool,
+        boost: bool,
+    ) -> Vec<(&'a Path, f64)> {
+        let query_norm = norm(query.iter().map(|(_, w)| *w));
+        let mut results: Vec<_> = index
+            .documents
+            .iter()
+            .filter(|(p, d)| {
+                keep(p, d) && query.iter().any(|(t, _)| d.term_frequency.contains_key(*t))
+            })
+            .map(|(p, d)| {
+                let dot: f64 = query.iter().map(|(t, w)| w * self.weight(d, t)).sum();
+                let boost = if boost { index.boost(p) } else { 1f64 };
+                (p.as_path(), boost * dot / (query_norm * self.norm(d)))
+            })
+            .filter(|(_, similarity)| *similarity > 0f64)
+            .collect();
+        sort_results(&mut results);
+        results
+    }
+}
+
+fn norm(weights: impl Iterator<Item = f64>) -> f64 {
+    weights.map(|w| w * w).sum::<f64>().sqrt()
+}
+
+impl Index {
+    /// Returns the `n` documents most similar to the one at `path`, which is left out, with
+    /// their similarity. Documents are compared by the cosine of their tf-idf vectors, that of
+    /// `path` being reduced to its [`SIMILARITY_TERMS`] terms of highest tf-idf, so only the
+    /// documents sharing one of these terms are returned. Terms are weighed with the
+    /// [`IdfScheme::Smoothed`] idf, which is never negative. Empty when `path` is not indexed.
+    pub fn similar_to(&self, path: &Path, n: usize) -> Vec<(&Path, f64)> {
+        let Some(document) = self.documents.get(path) else {
+            return Vec::new();
+        };
+        let weights = TfIdf::new(self);
+        let mut query: Vec<_> = document
+            .term_frequency
+            .keys()
+            .map(|t| {
+                let t = CaseInsensitiveStr::new(t);
+                (t, weights.weight(document, t))
+            })
+            .collect();
+        query.sort_by(|(t1, w1), (t2, w2)| w2.total_cmp(w1).then_with(|| t1.cmp(t2)));
+        query.truncate(SIMILARITY_TERMS);
+
+        let mut results = weights.rank(self, &query, |p, _| p != path, false);
+        results.truncate(n);
+        results
+    }
+
+    /// Ranks the documents matching `query` by the cosine of their tf-idf vector with that of
+    /// the query, multiplied by their boost. Unlike [`Index::search`], a document does not score
+    /// higher for being longer, or for repeating its content. Operators apply as in
+    /// [`Index::search`], the terms which are not negated making up the query vector. Terms are
+    /// weighed with the [`IdfScheme::Smoothed`] idf, times their boost for terms such as `rust^2`.
+    pub fn search_cosine<'a>(&'a self, query: &'_ str) -> Vec<(&'a Path, f64)> {
+        let options = SearchOptions::default();
+        let (terms, filter) =
+            parse_query(query, &self.analyzer, &options, &self.vocabulary()).unwrap_or_default();
+        let matches = query_matcher(&terms, filter.as_ref(), &options);
+        // Occurrences of each term, with the highest of their boosts
+        let mut counts: Vec<(&CaseInsensitiveStr, usize, f64)> = Vec::new();
+        for (term, boost) in &terms {
+            let term = CaseInsensitiveStr::new(term);
+            match counts.iter_mut().find(|(t, _, _)| *t == term) {
+                Some((_, count, highest)) => {
+                    *count += 1;
+                    *highest = highest.max(*boost);
+                }
+                None => counts.push((term, 1, *boost)),
+            }
+        }
+
+        let weights = TfIdf::new(self);
+        let max_count = counts.iter().map(|(_, count, _)| *count).max().unwrap_or(0);
+        let query: Vec<_> = counts
+            .into_iter()
+            .map(|(t, count, boost)| {
+                let tf = TfScheme::Proportional.weight(count, terms.len(), max_count);
+                (t, tf * weights.idf(t) * boost)
+            })
+            .collect();
+        weights.rank(self, &query, |_, d| matches(d), true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn shared_vocabulary_is_similar() {
+        let dir = TempDir::new("similar");
+        let ownership = dir.write("ownership.txt", "rust borrow checker lifetimes ownership");
+        let borrowing = dir.write(
+            "borrowing.txt",
+            "the borrow checker enforces rust ownership",
+        );
+        let pandas = dir.write("pandas.txt", "python pandas dataframe");
+        let mixed = dir.write("mixed.txt", "rust and python bindings");
+        let index = Index::new(dir.path());
+
+        let nearest = |path| index.similar_to(path, 10);
+        assert_eq!(nearest(&ownership)[0].0, borrowing);
+        assert_eq!(nearest(&borrowing)[0].0, ownership);
+        assert!(nearest(&ownership).iter().all(|(p, _)| *p != ownership));
+        let python: Vec<_> = nearest(&pandas).into_iter().map(|(p, _)| p).collect();
+        assert_eq!(python, [mixed.as_path()]);
+        assert!(nearest(&ownership)
+            .iter()
+            .all(|(_, s)| *s > 0f64 && *s <= 1f64 + 1e-9));
+        assert_eq!(index.similar_to(&ownership, 1).len(), 1);
+        assert!(nearest(&dir.path().join("missing.txt")).is_empty());
+    }
+
+    #[test]
+    fn cosine_ignores_repetitions() {
+        let dir = TempDir::new("cosine");
+        let original = dir.write("original.txt", "rust borrow checker");
+        let doubled = dir.write("doubled.txt", "rust borrow checker rust borrow checker");
+        let other = dir.write("other.txt", "rust tutorial");
+        dir.write("unrelated.txt", "python pandas");
+        let mut index = Index::new(dir.path());
+
+        let results = index.search_cosine("rust borrow");
+        let score = |path: &Path| results.iter().find(|(p, _)| *p == path).unwrap().1;
+        assert_eq!(results.len(), 3);
+        assert!((score(&original) - score(&doubled)).abs() < 1e-12);
+        assert!(score(&other) < score(&original));
+        assert!(results.iter().all(|(_, s)| *s <= 1f64 + 1e-12));
+
+        index.set_boost(&other, 10f64).unwrap();
+        assert_eq!(index.search_cosine("rust borrow")[0].0, other);
+        assert!(index.search_cosine("missing").is_empty());
+        let without = index.search_cosine("rust -borrow");
+        assert_eq!(without.len(), 1);
+        assert_eq!(without[0].0, other);
+        let tutorial = index.search_cosine("rust AND tutorial");
+        assert_eq!(tutorial.len(), 1);
+        assert_eq!(tutorial[0].0, other);
+    }
+}
@@ -0,0 +1,186 @@
+//! Excerpts of documents around the terms matched by a query.
+
+use std::{collections::HashSet, fs, ops::Range, path::Path};
+
+use crate::{analyze_query, Analyzer, CaseInsensitiveStr, Index, SearchHit, SearchOptions};
+
+/// Marks the start and end of matched words in snippets
+const MARK: &str = "**";
+
+impl Index {
+    /// Like [`Index::search`], also returning excerpts of each document around the words matched
+    /// by the query, with up to `context_chars` characters on either side and the matched words
+    /// enclosed in `**`. Nearby matches share the same snippet.
+    ///
+    /// The index does not keep the text of documents: every matching file is read again when
+    /// building its snippets, as is without stripping markup, and a file deleted since it was
+    /// indexed has no snippets.
+    pub fn search_with_snippets<'a>(
+        &'a self,
+        query: &'_ str,
+        context_chars: usize,
+    ) -> Vec<SearchHit<'a>> {
+        let options = SearchOptions::default();
+        let terms =
+            analyze_query(query, &self.analyzer, &options, &self.vocabulary()).unwrap_or_default();
+        let terms: HashSet<_> = terms.iter().map(|t| CaseInsensitiveStr::new(t)).collect();
+        let mut hits = self.search_hits(query, &options);
+        for hit in &mut hits {
+            hit.snippets = snippets(hit.path, &self.analyzer, &terms, context_chars);
+        }
+        hits
+    }
+}
+
+/// Snippets of the file at `path`, empty if it cannot be read
+fn snippets(
+    path: &Path,
+    analyzer: &Analyzer,
+    terms: &HashSet<&CaseInsensitiveStr>,
+    context_chars: usize,
+) -> Vec<String> {
+    match fs::read(path) {
+        Ok(content) => excerpts(
+            &String::from_utf8_lossy(&content),
+            analyzer,
+            terms,
+            context_chars,
+        ),
+        Err(e) => {
+            log::debug!("no snippets for {path}: {e}", path = path.display());
+            Vec::new()
+        }
+    }
+}
+
+/// Excerpts of `text` around the words whose terms are in `terms`
+fn excerpts(
+    text: &str,
+    analyzer: &Analyzer,
+    terms: &HashSet<&CaseInsensitiveStr>,
+    context_chars: usize,
+) -> Vec<String> {
+    let mut spans: Vec<Range<usize>> = Vec::new();
+    analyzer.analyze_with_forms(text, |term, word| {
+        if terms.contains(CaseInsensitiveStr::new(&term)) {
+            let start = word.as_ptr() as usize - text.as_ptr() as usize;
+            spans.push(start..start + word.len());
+        }
+    });
+    // Synonyms share the word of their term, and n-grams span the words matched on their own
+    spans.sort_by_key(|m| m.start);
+    let mut matches: Vec<Range<usize>> = Vec::new();
+    for span in spans {
+        match matches.last_mut() {
+            Some(last) if span.start < last.end => last.end = last.end.max(span.end),
+            _ => matches.push(span),
+        }
+    }
+
+    let mut snippets = Vec::new();
+    let mut matches = matches.into_iter().peekable();
+    while let Some(first) = matches.next() {
+        let mut words = vec![first];
+        let mut end = forward(text, words[0].end, context_chars);
+        while let Some(next) = matches.next_if(|m| back(text, m.start, context_chars) <= end) {
+            end = forward(text, next.end, context_chars);
+            words.push(next);
+        }
+
+        let mut snippet = String::new();
+        let mut offset = back(text, words[0].start, context_chars);
+        for word in words {
+            push_collapsed(&mut snippet, &text[offset..word.start]);
+            snippet.push_str(MARK);
+            snippet.push_str(&text[word.clone()]);
+            snippet.push_str(MARK);
+            offset = word.end;
+        }
+        push_collapsed(&mut snippet, &text[offset..end]);
+        snippets.push(snippet.trim().to_owned());
+    }
+    snippets
+}
+
+/// Byte offset `chars` characters before `offset` in `text`, or its start
+fn back(text: &str, offset: usize, chars: usize) -> usize {
+    text[..offset]
+        .char_indices()
+        .rev()
+        .take(chars)
+        .last()
+        .map_or(offset, |(i, _)| i)
+}
+
+/// Byte offset `chars` characters after `offset` in `text`, or its end
+fn forward(text: &str, offset: usize, chars: usize) -> usize {
+    text[offset..]
+        .char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(i, _)| offset + i)
+}
+
+/// Appends `text` to `snippet`, with each run of whitespace replaced by a single space
+fn push_collapsed(snippet: &mut String, text: &str) {
+    for c in text.chars() {
+        if !c.is_whitespace() {
+            snippet.push(c);
+        } else if !snippet.ends_with(' ') {
+            snippet.push(' ');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::remove_file;
+
+    use crate::{testutil::TempDir, Analyzer, Index, IndexOptions};
+
+    #[test]
+    fn snippets_mark_matches() {
+        let dir = TempDir::new("snippets");
+        let notes = dir.write(
+            "notes.txt",
+            "Rust notes\nthe borrow checker rejects this program, and that one too, as rust does",
+        );
+        let gone = dir.write("gone.txt", "rust ahead");
+        for i in 0..4 {
+            dir.write(format!("other{i}.txt"), "other");
+        }
+        let index = Index::new(dir.path());
+        remove_file(&gone).unwrap();
+
+        let hits = index.search_with_snippets("rust checker", 12);
+        assert_eq!(hits.len(), 2);
+        let hit = |path| hits.iter().find(|h| h.path == path).unwrap();
+        assert_eq!(
+            hit(&notes).snippets,
+            [
+                "**Rust** notes the borrow **checker** rejects thi",
+                "one too, as **rust** does"
+            ]
+        );
+        assert!(hit(&gone).snippets.is_empty());
+        assert!(index
+            .search_hits("rust", &Default::default())
+            .iter()
+            .all(|h| h.snippets.is_empty()));
+    }
+
+    #[test]
+    fn ngram_matches_are_merged() {
+        let dir = TempDir::new("snippets-ngrams");
+        let models = dir.write("models.txt", "deep machine learning models");
+        let options = IndexOptions {
+            analyzer: Analyzer::default().with_ngram_range(1, 2),
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options);
+
+        let hits = index.search_with_snippets("machine learning", 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].path, models);
+        assert_eq!(hits[0].snippets, ["deep **machine learning** models"]);
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    fmt,
+    fs::metadata,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use crate::{dirconfig::DirectorySettings, traverse_tree, Index, TokenizerRegistry};
+
+/// What to do when the files of an index changed since it was written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StalePolicy {
+    /// Log the changed files and go on with the outdated index
+    #[default]
+    Warn,
+    /// Index the changed files again
+    Update,
+    /// Fail
+    Error,
+}
+
+impl fmt::Display for StalePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Warn => "warn",
+            Self::Update => "update",
+            Self::Error => "error",
+        })
+    }
+}
+
+impl FromStr for StalePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "update" => Ok(Self::Update),
+            "error" => Ok(Self::Error),
+            _ => Err(format!("unknown stale index policy {s:?}")),
+        }
+    }
+}
+
+/// How the files of an index compare to the disk, see [`Index::verify`]. Paths are sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verification {
+    /// Documents whose file was not modified since the index was written
+    pub unchanged: Vec<PathBuf>,
+    /// Documents whose file was modified since the index was written
+    pub modified: Vec<PathBuf>,
+    /// Documents whose file no longer exists
+    pub missing: Vec<PathBuf>,
+    /// Files beneath the root of the index that are not indexed
+    pub unindexed: Vec<PathBuf>,
+}
+
+impl Verification {
+    /// Whether the index no longer matches the files it was built from
+    pub fn has_drift(&self) -> bool {
+        !(self.modified.is_empty() && self.missing.is_empty() && self.unindexed.is_empty())
+    }
+}
+
+impl Index {
+    /// Returns the documents whose file was modified after `since`, or no longer exists, sorted
+    /// by path
+    pub fn stale_documents(&self, since: SystemTime) -> Vec<&Path> {
+        let mut paths: Vec<_> = self
+            .documents
+            .keys()
+            .filter(|p| match metadata(p).and_then(|m| m.modified()) {
+                Ok(mtime) => mtime > since,
+                Err(_) => true,
+            })
+            .map(PathBuf::as_path)
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Compares the documents to their files, `since` being the time the index was written,
+    /// and walks the directory the index was built from again to find the files it lacks,
+    /// tokenizing them to leave out those that could not be indexed either. Fails
+    /// for indexes that do not know their root, see [`Index::verify_root`].
+    pub fn verify(&self, since: SystemTime) -> io::Result<Verification> {
+        self.verify_root(self.known_root()?, since)
+    }
+
+    /// Like [`Index::verify`], walking `root` rather than the directory the index was built
+    /// from. `root` must be spelled like when the index was built for paths to match.
+    pub fn verify_root(
+        &self,
+        root: impl AsRef<Path>,
+        since: SystemTime,
+    ) -> io::Result<Verification> {
+        let root = root.as_ref();
+        metadata(root)?;
+
+        let mut verification = Verification::default();
+        for path in self.documents.keys() {
+            let list = match metadata(path).and_then(|m| m.modified()) {
+                Ok(mtime) if mtime > since => &mut verification.modified,
+                Ok(_) => &mut verification.unchanged,
+                Err(_) => &mut verification.missing,
+            };
+            list.push(path.clone());
+        }
+        let mut candidates = Vec::new();
+        traverse_tree(
+            root,
+            DirectorySettings::from(&self.options),
+            |dir, parent| parent.enter(dir),
+            |p, s| {
+                // Files without an extension are only indexed when a tokenizer is configured
+                let indexable = s.tokenizer.is_some() || p.extension().is_some();
+                if indexable
+                    && !self.documents.contains_key(&p)
+                    && self.options.within_size_limit(&p).unwrap_or(false)
+                {
+                    candidates.push((p, s.clone()));
+                }
+            },
+            |p, e| log::warn!("cannot read {path}: {e}", path = p.display()),
+            &self.options,
+        );
+        // Files that cannot be tokenized were left out when building the index too
+        let analyzer = Arc::new(self.analyzer.clone());
+        let registry = TokenizerRegistry::standard();
+        for (p, s) in candidates {
+            match self
+                .options
+                .build_document(&p, Some(root), &analyzer, &s, registry, None)
+            {
+                Ok(Some(_)) => verification.unindexed.push(p),
+                Ok(None) => {}
+                Err(e) => log::debug!("{path} cannot be indexed: {e}", path = p.display()),
+            }
+        }
+        verification.unchanged.sort();
+        verification.modified.sort();
+        verification.missing.sort();
+        verification.unindexed.sort();
+        Ok(verification)
+    }
+
+    /// Removes the documents for which `exists` returns `false`, typically because their file
+    /// was deleted, and returns their paths, sorted
+    pub fn prune(&mut self, mut exists: impl FnMut(&Path) -> bool) -> Vec<PathBuf> {
+        let mut missing: Vec<_> = self
+            .documents
+            .keys()
+            .filter(|p| !exists(p))
+            .cloned()
+            .collect();
+        missing.sort();
+        for path in &missing {
+            self.remove_document(path);
+        }
+        missing
+    }
+
+    /// Indexes the files at `paths` again, the same way as when the index was built. Files that
+    /// no longer exist are removed from the index. Documents indexed with custom tokenizers are
+    /// kept as they were, see [`Index::reindex_with_registry`].
+    pub fn reindex(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        self.reindex_with_registry(paths, TokenizerRegistry::standard());
+    }
+
+    /// Like [`Index::reindex`], tokenizing files with `registry`, which must be the one the
+    /// index was built with
+    pub fn reindex_with_registry(
+        &mut self,
+        paths: impl IntoIterator<Item = PathBuf>,
+        registry: &TokenizerRegistry,
+    ) {
+        let analyzer = Arc::new(self.analyzer.clone());
+        for path in paths {
+            if !path.is_file() {
+                log::info!("removing {path}", path = path.display());
+                self.remove_document(&path);
+                continue;
+            }
+            let settings = DirectorySettings::for_file_within(&path, self.root(), &self.options);
+            self.add_file(path, &analyzer, &settings, registry);
+        }
+    }
+
+    /// Looks for documents changed since `since`, the time the index was written, and handles
+    /// them according to `policy`. Returns the changed documents.
+    pub fn handle_stale(
+        &mut self,
+        since: SystemTime,
+        policy: StalePolicy,
+    ) -> io::Result<Vec<PathBuf>> {
+        let stale: Vec<_> = self
+            .stale_documents(since)
+            .into_iter()
+            .map(Path::to_path_buf)
+            .collect();
+        if stale.is_empty() {
+            return Ok(stale);
+        }
+        match policy {
+            StalePolicy::Warn => {
+                for path in &stale {
+                    log::warn!("{path} changed since it was indexed", path = path.display());
+                }
+            }
+            StalePolicy::Update => self.reindex(stale.iter().cloned()),
+            StalePolicy::Error => {
+                return Err(io::Error::other(format!(
+                    "{path} changed since the index was written ({n} stale documents)",
+                    path = stale[0].display(),
+                    n = stale.len()
+                )));
+            }
+        }
+        Ok(stale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{remove_file, write, File},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{testutil::TempDir, IndexOptions};
+
+    #[test]
+    fn policies() {
+        let dir = TempDir::new("stale");
+        let changed = dir.write("changed.txt", "Old title\nbefore");
+        let removed = dir.write("removed.txt", "before");
+        dir.write("kept.txt", "unchanged");
+        let options = IndexOptions {
+            capture_titles: true,
+            ..Default::default()
+        };
+        let index = Index::new_with_options(dir.path(), options.clone());
+        let since = SystemTime::now();
+        assert!(index.stale_documents(since).is_empty());
+
+        write(&changed, "New title\nafter").unwrap();
+        File::options()
+            .write(true)
+            .open(&changed)
+            .unwrap()
+            .set_modified(since + Duration::from_secs(10))
+            .unwrap();
+        remove_file(&removed).unwrap();
+        let expected = vec![changed.clone(), removed.clone()];
+
+        let mut warned = Index::load(&*serde_json::to_vec(&index).unwrap()).unwrap();
+        assert_eq!(
+            warned.handle_stale(since, StalePolicy::Warn).unwrap(),
+            expected
+        );
+        assert_eq!(warned, index);
+
+        let mut failed = Index::load(&*serde_json::to_vec(&index).unwrap()).unwrap();
+        assert!(failed.handle_stale(since, StalePolicy::Error).is_err());
+        assert_eq!(failed, index);
+
+        let mut updated = Index::load(&*serde_json::to_vec(&index).unwrap()).unwrap();
+        assert_eq!(
+            updated.handle_stale(since, StalePolicy::Update).unwrap(),
+            expected
+        );
+        assert_eq!(updated, Index::new_with_options(dir.path(), options));
+        assert_eq!(
+            updated.document(&changed).unwrap().title(),
+            Some("New title")
+        );
+        assert!(updated.search("before").is_empty());
+    }
+
+    #[test]
+    fn verify_reports_drift() {
+        let dir = TempDir::new("verify");
+        let kept = dir.write("kept.txt", "unchanged");
+        let changed = dir.write("changed.txt", "before");
+        let removed = dir.write("removed.txt", "before");
+        let index = Index::new(dir.path());
+        assert_eq!(index.root(), Some(dir.path()));
+        let since = SystemTime::now();
+        let clean = index.verify(since).unwrap();
+        assert_eq!(
+            clean.unchanged,
+            [changed.clone(), kept.clone(), removed.clone()]
+        );
+        assert!(!clean.has_drift());
+
+        write(&changed, "after").unwrap();
+        File::options()
+            .write(true)
+            .open(&changed)
+            .unwrap()
+            .set_modified(since + Duration::from_secs(10))
+            .unwrap();
+        remove_file(&removed).unwrap();
+        let added = dir.write("sub/added.md", "# New");
+        dir.write("no-extension", "not indexed");
+        dir.write("invalid.txt", b"not \xff UTF-8");
+        assert_eq!(
+            index.verify(since).unwrap(),
+            Verification {
+                unchanged: vec![kept],
+                modified: vec![changed],
+                missing: vec![removed],
+                unindexed: vec![added],
+            }
+        );
+        assert!(index.verify(since).unwrap().has_drift());
+
+        let listed = Index::from_paths([dir.path().join("kept.txt")]);
+        assert_eq!(
+            listed.verify(since).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert!(listed.verify_root(dir.path(), since).unwrap().has_drift());
+    }
+}
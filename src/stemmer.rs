@@ -0,0 +1,149 @@
+//! Reduction of words to their stem, so that `running` and `runs` both match `run`.
+
+use std::{borrow::Cow, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+/// Reduces words to a common stem
+pub trait Stemmer {
+    /// Stem of `word`, which is returned as is when it has no suffix to strip
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str>;
+}
+
+/// The Porter2 stemming algorithm for English, as specified by Snowball. Words containing
+/// anything but ASCII letters are left untouched, and stems are lowercase.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PorterStemmer;
+
+impl Stemmer for PorterStemmer {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        static STEMMER: OnceLock<rust_stemmers::Stemmer> = OnceLock::new();
+        if !word.bytes().all(|b| b.is_ascii_alphabetic()) {
+            return Cow::Borrowed(word);
+        }
+        snowball(&STEMMER, rust_stemmers::Algorithm::English, word)
+    }
+}
+
+/// The Snowball stemming algorithm for French. Stems are lowercase, accents included, so that
+/// `Chevaux` and `cheval` share the stem `cheval`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrenchStemmer;
+
+impl Stemmer for FrenchStemmer {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        static STEMMER: OnceLock<rust_stemmers::Stemmer> = OnceLock::new();
+        snowball(&STEMMER, rust_stemmers::Algorithm::French, word)
+    }
+}
+
+/// Lowercase stem of `word` by the Snowball `algorithm`, whose stemmer is created once in
+/// `stemmer`. Words without a suffix to strip are returned as is, whatever their case.
+fn snowball<'a>(
+    stemmer: &OnceLock<rust_stemmers::Stemmer>,
+    algorithm: rust_stemmers::Algorithm,
+    word: &'a str,
+) -> Cow<'a, str> {
+    let lowercase = word.to_lowercase();
+    let stem = stemmer
+        .get_or_init(|| rust_stemmers::Stemmer::create(algorithm))
+        .stem(&lowercase)
+        .into_owned();
+    if stem == lowercase {
+        Cow::Borrowed(word)
+    } else {
+        Cow::Owned(stem)
+    }
+}
+
+/// The stemmers an [`Analyzer`](crate::Analyzer) can be given, saved along with indexes by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StemmerKind {
+    /// See [`PorterStemmer`]
+    Porter,
+    /// See [`FrenchStemmer`]
+    French,
+}
+
+impl Stemmer for StemmerKind {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Porter => PorterStemmer.stem(word),
+            Self::French => FrenchStemmer.stem(word),
+        }
+    }
+}
+
+impl From<PorterStemmer> for StemmerKind {
+    fn from(_: PorterStemmer) -> Self {
+        Self::Porter
+    }
+}
+
+impl From<FrenchStemmer> for StemmerKind {
+    fn from(_: FrenchStemmer) -> Self {
+        Self::French
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn porter_stems() {
+        for (word, stem) in [
+            ("caresses", "caress"),
+            ("ponies", "poni"),
+            ("cats", "cat"),
+            ("feed", "feed"),
+            ("agreed", "agre"),
+            ("plastered", "plaster"),
+            ("motoring", "motor"),
+            ("sing", "sing"),
+            ("conflated", "conflat"),
+            ("hopping", "hop"),
+            ("falling", "fall"),
+            ("filing", "file"),
+            ("happy", "happi"),
+            ("relational", "relat"),
+            ("conditional", "condit"),
+            ("generalizations", "general"),
+            ("generously", "generous"),
+            ("skies", "sky"),
+            ("dying", "die"),
+            ("hopefulness", "hope"),
+            ("electrical", "electr"),
+            ("adjustment", "adjust"),
+            ("adoption", "adopt"),
+            ("controlling", "control"),
+            ("running", "run"),
+            ("runs", "run"),
+            ("Running", "run"),
+        ] {
+            assert_eq!(PorterStemmer.stem(word), stem, "{word}");
+        }
+    }
+
+    #[test]
+    fn french_stems() {
+        for (word, stem) in [
+            ("chevaux", "cheval"),
+            ("Chevaux", "cheval"),
+            ("continuellement", "continuel"),
+            ("mangeaient", "mang"),
+            ("nationales", "national"),
+            ("été", "été"),
+        ] {
+            assert_eq!(FrenchStemmer.stem(word), stem, "{word}");
+        }
+    }
+
+    #[test]
+    fn leaves_other_words_alone() {
+        for word in ["RUST", "is", "v2", "été", "snake_case"] {
+            assert!(matches!(PorterStemmer.stem(word), Cow::Borrowed(w) if w == word));
+        }
+    }
+}
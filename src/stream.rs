@@ -0,0 +1,174 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    path::Path,
+    vec,
+};
+
+use crate::{
+    compare_results, parse_query, query_matcher, weigh_boosted_terms, Index, SearchOptions,
+};
+
+/// Search results produced one at a time, best first.
+///
+/// Every matching document is scored when the stream is created, but results are only ranked as
+/// they are pulled: building the stream costs `O(n)` and each result `O(log n)`, instead of
+/// sorting everything up front. A result is final when it is yielded: the stream yields exactly
+/// the results of [`Index::search_with_options`], in the same order (decreasing score, ties
+/// broken by increasing path, unless [`SearchOptions::rank`] says otherwise). Taking the first
+/// `k` items therefore gives the batch top-`k`, and a consumer such as an async server can send
+/// each result as soon as it is yielded.
+///
+/// Ranking by coverage, capping results per directory and cutting them off automatically need
+/// every result to be ranked: with these options, results are all ranked when the stream is
+/// created, like [`Index::search_with_options`] does, and only then yielded one at a time.
+#[derive(Debug)]
+pub struct SearchStream<'a> {
+    results: Results<'a>,
+}
+
+/// Results of a [`SearchStream`], ranked as they are pulled or up front
+#[derive(Debug)]
+enum Results<'a> {
+    Lazy(BinaryHeap<Ranked<'a>>),
+    Ranked(vec::IntoIter<(&'a Path, f64)>),
+}
+
+impl<'a> Iterator for SearchStream<'a> {
+    type Item = (&'a Path, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.results {
+            Results::Lazy(heap) => heap.pop().map(|r| r.0),
+            Results::Ranked(results) => results.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match &self.results {
+            Results::Lazy(heap) => heap.len(),
+            Results::Ranked(results) => results.len(),
+        };
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for SearchStream<'_> {}
+
+/// Heap entry, greatest for the best ranked result
+#[derive(Debug)]
+struct Ranked<'a>((&'a Path, f64));
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_results(&self.0, &other.0).reverse()
+    }
+}
+
+impl Index {
+    /// Like [`Index::search_with_options`], but ranks results lazily, see [`SearchStream`]
+    pub fn search_stream<'a>(
+        &'a self,
+        terms: &'_ str,
+        options: &SearchOptions,
+    ) -> SearchStream<'a> {
+        let (terms, filter) = parse_query(terms, &self.analyzer, options, &self.vocabulary())
+            .unwrap_or_else(|e| {
+                log::warn!("{e}");
+                (Vec::new(), None)
+            });
+        let terms = weigh_boosted_terms(terms, options, |t| self.idf(t, options.idf));
+        let results = if options.ranks_by_score() {
+            let matches = query_matcher(&terms, filter.as_ref(), options);
+            let scored = self.score_where(&terms, options, |_, d| matches(d));
+            Results::Lazy(scored.map(Ranked).collect())
+        } else {
+            Results::Ranked(self.rank(&terms, filter.as_ref(), options).into_iter())
+        };
+        SearchStream { results }
+    }
+}
+
+/// The `n` best of `results`, in ranking order. Only `n` results are held at a time, the worst
+/// of them being dropped whenever a better one comes.
+pub(crate) fn top_results<'a>(
+    results: impl Iterator<Item = (&'a Path, f64)>,
+    n: usize,
+) -> Vec<(&'a Path, f64)> {
+    let mut heap = BinaryHeap::new();
+    for result in results {
+        heap.push(Reverse(Ranked(result)));
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(Ranked(result))| result)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil::TempDir, MatchMode, RankMode};
+
+    #[test]
+    fn stream_matches_batch() {
+        let dir = TempDir::new("stream");
+        for i in 0..20 {
+            let content = format!("{} {}", "rust ".repeat(i % 7), "filler ".repeat(i % 5));
+            dir.write(format!("{i}.txt"), content);
+        }
+        dir.write("other.txt", "nothing");
+        let index = Index::new(dir.path());
+        let options = SearchOptions::default();
+
+        let batch = index.search_with_options("rust", &options);
+        let stream = index.search_stream("rust", &options);
+        assert_eq!(stream.len(), batch.len());
+        assert_eq!(stream.collect::<Vec<_>>(), batch);
+
+        let top: Vec<_> = index.search_stream("rust", &options).take(5).collect();
+        assert_eq!(top, batch[..5]);
+
+        let all = SearchOptions {
+            match_mode: MatchMode::All,
+            ..Default::default()
+        };
+        let coverage = SearchOptions {
+            rank: RankMode::Coverage,
+            ..Default::default()
+        };
+        let cutoff = SearchOptions {
+            auto_cutoff: true,
+            ..Default::default()
+        };
+        for options in [&options, &all, &coverage, &cutoff] {
+            for query in ["rust -filler", "rust filler", "rust AND filler"] {
+                let batch = index.search_with_options(query, options);
+                assert!(!batch.is_empty(), "{query}");
+                let stream = index.search_stream(query, options);
+                assert_eq!(stream.len(), batch.len());
+                assert_eq!(stream.collect::<Vec<_>>(), batch, "{query} {options:?}");
+            }
+        }
+        assert!(index
+            .search_stream("rust -filler", &options)
+            .all(|(p, _)| !index.documents[p].contains("filler")));
+    }
+}
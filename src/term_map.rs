@@ -0,0 +1,160 @@
+//! Reading of the term maps of saved indexes.
+//!
+//! Terms used to be compared by ASCII case only, so an index saved before Unicode case folding
+//! may hold several terms now deemed equal, such as `Straße` and `STRASSE`. Their values are
+//! merged when read, instead of the last one replacing the others.
+
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    fmt,
+    marker::PhantomData,
+};
+
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::TermMap;
+
+/// Value attached to a term, which can absorb the value of an equal term
+pub(crate) trait MergeTerm {
+    fn merge(&mut self, other: Self);
+}
+
+/// Occurrence counts add up
+impl MergeTerm for usize {
+    fn merge(&mut self, other: Self) {
+        *self += other;
+    }
+}
+
+/// Positions stay sorted
+impl MergeTerm for Vec<usize> {
+    fn merge(&mut self, other: Self) {
+        self.extend(other);
+        self.sort_unstable();
+        self.dedup();
+    }
+}
+
+/// Forms stay distinct, in the order they were first seen in
+impl MergeTerm for Vec<String> {
+    fn merge(&mut self, other: Self) {
+        for form in other {
+            if !self.contains(&form) {
+                self.push(form);
+            }
+        }
+    }
+}
+
+/// Values of the same keys merge
+impl<K: Ord, V: MergeTerm> MergeTerm for BTreeMap<K, V> {
+    fn merge(&mut self, other: Self) {
+        for (key, value) in other {
+            insert_merged(self, key, value);
+        }
+    }
+}
+
+/// Adds `value` to `map`, merged with the value of an equal key if there is one
+pub(crate) fn insert_merged<K: Ord, V: MergeTerm>(map: &mut BTreeMap<K, V>, key: K, value: V) {
+    match map.entry(key) {
+        Entry::Occupied(mut e) => e.get_mut().merge(value),
+        Entry::Vacant(e) => {
+            e.insert(value);
+        }
+    }
+}
+
+/// [`TermMap`] read with [`insert_merged`]
+struct Merged<T>(TermMap<T>);
+
+impl<'de, T: Deserialize<'de> + MergeTerm> Deserialize<'de> for Merged<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MergedVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de> + MergeTerm> Visitor<'de> for MergedVisitor<T> {
+            type Value = Merged<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of terms")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+                let mut map = TermMap::new();
+                while let Some((term, value)) = access.next_entry()? {
+                    insert_merged(&mut map, term, value);
+                }
+                Ok(Merged(map))
+            }
+        }
+
+        deserializer.deserialize_map(MergedVisitor(PhantomData))
+    }
+}
+
+/// Reads a [`TermMap`], merging the values of equal terms, for `#[serde(deserialize_with)]`
+pub(crate) fn deserialize<'de, D, T>(deserializer: D) -> Result<TermMap<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + MergeTerm,
+{
+    Merged::deserialize(deserializer).map(|m| m.0)
+}
+
+/// Like [`deserialize`], for an optional map
+pub(crate) fn deserialize_optional<'de, D, T>(
+    deserializer: D,
+) -> Result<Option<TermMap<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + MergeTerm,
+{
+    Option::<Merged<T>>::deserialize(deserializer).map(|m| m.map(|m| m.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{CaseInsensitiveStr, Index};
+
+    #[test]
+    fn merges_terms_of_older_indexes() {
+        // Saved when terms were only compared by ASCII case
+        let saved = r#"{
+            "documents": {
+                "a.txt": {
+                    "term_frequency": {"Straße": 1, "STRASSE": 1, "Москва": 2, "МОСКВА": 1},
+                    "count": 5,
+                    "positions": {"Straße": [0], "STRASSE": [1], "Москва": [2, 4], "МОСКВА": [3]},
+                    "forms": {"Straße": ["Straße"], "STRASSE": ["STRASSE"]}
+                },
+                "b.txt": {"term_frequency": {"other": 1}, "count": 1}
+            },
+            "inverted": {
+                "other": [["b.txt", 1]],
+                "Straße": [["a.txt", 1]],
+                "STRASSE": [["a.txt", 1]],
+                "Москва": [["a.txt", 2]],
+                "МОСКВА": [["a.txt", 1]]
+            }
+        }"#;
+        let index = Index::load(saved.as_bytes()).unwrap();
+        let document = index.document(Path::new("a.txt")).unwrap();
+        let mut terms: Vec<_> = document.terms().collect();
+        terms.sort();
+        assert_eq!(terms, [("Straße", 2), ("Москва", 3)]);
+        let positions = document.positions.as_ref().unwrap();
+        assert_eq!(positions[CaseInsensitiveStr::new("strasse")], [0, 1]);
+        assert_eq!(positions[CaseInsensitiveStr::new("москва")], [2, 3, 4]);
+        assert_eq!(document.forms("strasse"), ["Straße", "STRASSE"]);
+        assert_eq!(
+            index.postings()[CaseInsensitiveStr::new("москва")],
+            [(Path::new("a.txt").to_owned(), 3)]
+        );
+        assert_eq!(index.documents_without("STRASSE"), [Path::new("b.txt")]);
+    }
+}
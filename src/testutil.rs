@@ -0,0 +1,120 @@
+use std::{
+    fs::{create_dir_all, remove_dir_all, write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Scratch directory removed when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "indexer-{name}-{pid}-{n}",
+            pid = std::process::id(),
+            n = COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = remove_dir_all(&path);
+        create_dir_all(&path).expect("cannot create temporary directory");
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn write(&self, name: impl AsRef<Path>, content: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.0.join(name);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).expect("cannot create parent directory");
+        }
+        write(&path, content).expect("cannot write file");
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.0);
+    }
+}
+
+/// Counts the allocations made by each thread
+mod counting_alloc {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct CountingAlloc;
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|c| c.set(c.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    /// Number of allocations made so far by the current thread
+    pub fn allocations() -> usize {
+        ALLOCATIONS.with(Cell::get)
+    }
+}
+
+pub use counting_alloc::allocations;
+
+/// Records the log records of each thread
+mod capturing_log {
+    use std::{cell::RefCell, sync::Once};
+
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+
+    thread_local! {
+        static RECORDS: RefCell<Option<Vec<(Level, String)>>> = const { RefCell::new(None) };
+    }
+
+    struct CapturingLogger;
+
+    impl Log for CapturingLogger {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            let _ = RECORDS.try_with(|r| {
+                if let Some(records) = r.borrow_mut().as_mut() {
+                    records.push((record.level(), record.args().to_string()));
+                }
+            });
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Runs `f`, returning the level and message of the records it logged on the current thread
+    pub fn capture_logs(f: impl FnOnce()) -> Vec<(Level, String)> {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&CapturingLogger).expect("a logger is already set");
+            log::set_max_level(LevelFilter::Trace);
+        });
+        RECORDS.with(|r| *r.borrow_mut() = Some(Vec::new()));
+        f();
+        RECORDS.with(|r| r.borrow_mut().take().unwrap_or_default())
+    }
+}
+
+pub use capturing_log::capture_logs;
@@ -1,42 +1,308 @@
 use std::{
-    collections::HashMap,
+    borrow::Cow,
     io::{self, Read},
 };
 
-use crate::CaseInsensitiveString;
+use crate::{analyzer::NgramWindow, Analyzer, CaseInsensitiveStr, CaseInsensitiveString, TermMap};
 
+mod code;
+mod csv;
+mod html;
+mod json;
 mod lexer;
+mod markdown;
+#[cfg(feature = "pdf")]
+mod pdf;
 mod text;
 mod xml;
 
+pub use self::code::CodeTokenizer;
+pub use self::csv::{Column, CsvTokenizer, Delimiter};
+pub use self::html::HtmlTokenizer;
+pub use self::json::JsonTokenizer;
+pub use self::markdown::MarkdownTokenizer;
+#[cfg(feature = "pdf")]
+pub use self::pdf::PdfTokenizer;
 pub use self::text::TextTokenizer;
 pub use self::xml::XmlTokenizer;
-pub use lexer::Lexer;
+pub use lexer::{Lexer, LexerOptions};
+
+/// Receives the terms of a document, in order
+pub trait TermSink {
+    fn push(&mut self, term: Cow<'_, str>);
+
+    /// Like [`TermSink::push`], along with the word of the document the term was derived from
+    fn push_form(&mut self, term: Cow<'_, str>, form: &str) {
+        let _ = form;
+        self.push(term);
+    }
+
+    /// Like [`TermSink::push_form`], for a term standing at the same position as the last one
+    /// pushed, such as a synonym or an n-gram
+    fn push_alias(&mut self, term: Cow<'_, str>, form: &str) {
+        self.push_form(term, form);
+    }
+}
+
+impl<S: TermSink + ?Sized> TermSink for &mut S {
+    fn push(&mut self, term: Cow<'_, str>) {
+        (**self).push(term);
+    }
+
+    fn push_form(&mut self, term: Cow<'_, str>, form: &str) {
+        (**self).push_form(term, form);
+    }
+
+    fn push_alias(&mut self, term: Cow<'_, str>, form: &str) {
+        (**self).push_alias(term, form);
+    }
+}
+
+impl TermSink for Vec<CaseInsensitiveString<'static>> {
+    fn push(&mut self, term: Cow<'_, str>) {
+        Vec::push(self, term.into_owned().into());
+    }
+}
+
+impl TermSink for TermMap<usize> {
+    fn push(&mut self, term: Cow<'_, str>) {
+        if let Some(c) = self.get_mut(CaseInsensitiveStr::new(&term)) {
+            *c += 1;
+        } else {
+            self.insert(term.into_owned().into(), 1);
+        }
+    }
+}
+
+/// Counts terms and, optionally, records the positions they appear at and the words they were
+/// derived from
+#[derive(Debug, Default)]
+pub struct TermCollector {
+    pub(crate) term_frequency: TermMap<usize>,
+    pub(crate) positions: Option<TermMap<Vec<usize>>>,
+    pub(crate) forms: Option<TermMap<Vec<String>>>,
+    next_position: usize,
+}
+
+impl TermCollector {
+    pub fn with_positions(mut self) -> Self {
+        self.positions = Some(TermMap::new());
+        self
+    }
+
+    /// Records the distinct words each term was derived from
+    pub fn with_forms(mut self) -> Self {
+        self.forms = Some(TermMap::new());
+        self
+    }
+}
+
+impl TermCollector {
+    fn record(&mut self, term: Cow<'_, str>, position: usize) {
+        if let Some(ref mut positions) = self.positions {
+            match positions.get_mut(CaseInsensitiveStr::new(&term)) {
+                // A term repeated at the same position, such as a synonym of itself
+                Some(p) if p.last() == Some(&position) => {}
+                Some(p) => p.push(position),
+                None => {
+                    positions.insert(term.to_string().into(), vec![position]);
+                }
+            }
+        }
+        self.term_frequency.push(term);
+    }
+
+    fn record_form(&mut self, term: &str, form: &str) {
+        if let Some(ref mut forms) = self.forms {
+            match forms.get_mut(CaseInsensitiveStr::new(term)) {
+                Some(f) if f.iter().any(|f| f == form) => {}
+                Some(f) => f.push(form.to_owned()),
+                None => {
+                    forms.insert(term.to_string().into(), vec![form.to_owned()]);
+                }
+            }
+        }
+    }
+}
+
+impl TermSink for TermCollector {
+    fn push(&mut self, term: Cow<'_, str>) {
+        self.next_position += 1;
+        self.record(term, self.next_position - 1);
+    }
+
+    fn push_form(&mut self, term: Cow<'_, str>, form: &str) {
+        self.record_form(&term, form);
+        self.push(term);
+    }
+
+    fn push_alias(&mut self, term: Cow<'_, str>, form: &str) {
+        self.record_form(&term, form);
+        self.record(term, self.next_position.saturating_sub(1));
+    }
+}
+
+/// Pushes a term the analyzer emitted to `terms`, see [`Analyzer::analyze_in_window`]
+pub(crate) fn push_positioned<S: TermSink + ?Sized>(
+    terms: &mut S,
+    term: Cow<'_, str>,
+    form: &str,
+    new_position: bool,
+) {
+    if new_position {
+        terms.push_form(term, form);
+    } else {
+        terms.push_alias(term, form);
+    }
+}
 
 pub trait Tokenizer {
     /// Returns the number of tokens encountered
-    fn tokenize<R: Read>(
+    fn tokenize<R: Read, S: TermSink>(&mut self, reader: R, terms: &mut S) -> io::Result<usize>;
+
+    /// Analyzer splitting the text of documents into terms
+    fn analyzer(&self) -> &Analyzer {
+        Analyzer::standard()
+    }
+
+    /// Title of the last tokenized document, if the tokenizer extracts one
+    fn title(&self) -> Option<&str> {
+        None
+    }
+
+    /// Feeds the terms of `s` to `terms` and returns their number
+    fn tokenize_string<S: TermSink>(&mut self, s: &str, terms: &mut S) -> usize {
+        let mut count = 0;
+        let mut window = NgramWindow::default();
+        self.analyzer()
+            .analyze_in_window(s, &mut window, |token, form, new_position| {
+                push_positioned(terms, token, form, new_position);
+                count += 1;
+            });
+        count
+    }
+
+    /// Terms of `s`, in order, as [`Tokenizer::tokenize_string`] feeds them to a [`TermSink`]
+    fn tokens(&mut self, s: &str) -> Vec<CaseInsensitiveString<'static>> {
+        let mut tokens = Vec::new();
+        self.tokenize_string(s, &mut tokens);
+        tokens
+    }
+}
+
+/// Object-safe form of [`Tokenizer`], implemented by every tokenizer, so that tokenizers can be
+/// picked at runtime, see [`TokenizerRegistry`](crate::TokenizerRegistry)
+pub trait DynTokenizer {
+    fn tokenize_dyn(
         &mut self,
-        reader: R,
-        term_frequency: &mut HashMap<CaseInsensitiveString<'static>, usize>,
+        reader: &mut dyn Read,
+        terms: &mut dyn TermSink,
     ) -> io::Result<usize>;
 
-    /// Returns the number of tokens encountered as well as the string btreemap
-    fn tokenize_string(
+    fn analyzer_dyn(&self) -> &Analyzer;
+
+    fn title_dyn(&self) -> Option<&str>;
+}
+
+impl<T: Tokenizer> DynTokenizer for T {
+    fn tokenize_dyn(
         &mut self,
-        s: &str,
-        term_frequency: &mut HashMap<CaseInsensitiveString<'static>, usize>,
-    ) -> usize {
-        let mut count = 0;
-        for token in lexer::Lexer::new(s) {
-            let token: CaseInsensitiveString<'static> = token.to_owned().into();
-            if let Some(c) = term_frequency.get_mut(&token) {
-                *c += 1;
-            } else {
-                term_frequency.insert(token, 1);
-            }
-            count += 1;
-        }
-        count
+        reader: &mut dyn Read,
+        mut terms: &mut dyn TermSink,
+    ) -> io::Result<usize> {
+        self.tokenize(reader, &mut terms)
+    }
+
+    fn analyzer_dyn(&self) -> &Analyzer {
+        self.analyzer()
+    }
+
+    fn title_dyn(&self) -> Option<&str> {
+        self.title()
+    }
+}
+
+impl Tokenizer for Box<dyn DynTokenizer> {
+    fn tokenize<R: Read, S: TermSink>(
+        &mut self,
+        mut reader: R,
+        terms: &mut S,
+    ) -> io::Result<usize> {
+        (**self).tokenize_dyn(&mut reader, terms)
+    }
+
+    fn analyzer(&self) -> &Analyzer {
+        (**self).analyzer_dyn()
+    }
+
+    fn title(&self) -> Option<&str> {
+        (**self).title_dyn()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::testutil::allocations;
+
+    #[test]
+    fn allocates_once_per_distinct_term() {
+        let text = "the quick brown fox jumps over the lazy dog\n".repeat(10_000);
+        let text = text.trim_end();
+        let mut tf = TermMap::new();
+        let before = allocations();
+        let count = TextTokenizer::default().tokenize_string(text, &mut tf);
+        let allocated = allocations() - before;
+        assert_eq!(count, 90_000);
+        assert_eq!(tf.len(), 8);
+        assert!(allocated < 32, "{allocated} allocations");
+    }
+
+    #[test]
+    fn tokens_match_counts() {
+        let mut tokenizer = TextTokenizer::default();
+        let tokens = tokenizer.tokens("a a b");
+        assert_eq!(tokens, ["a", "a", "b"].map(CaseInsensitiveString::from));
+        assert_eq!(
+            tokens.len(),
+            tokenizer.tokenize_string("a a b", &mut TermMap::new())
+        );
+
+        let analyzer = Analyzer::default().with_synonyms("car", ["automobile"]);
+        let mut tokenizer = TextTokenizer::default().with_analyzer(Arc::new(analyzer));
+        assert_eq!(
+            tokenizer.tokens("red car"),
+            ["red", "car", "automobile"].map(CaseInsensitiveString::from)
+        );
+        let mut code = CodeTokenizer::default();
+        assert_eq!(code.tokens("getName").len(), 3);
+    }
+
+    #[test]
+    fn collects_positions() {
+        let mut terms = TermCollector::default().with_positions();
+        TextTokenizer::default().tokenize_string("a b A c", &mut terms);
+        let positions = terms.positions.unwrap();
+        assert_eq!(positions[CaseInsensitiveStr::new("a")], [0, 2]);
+        assert_eq!(positions[CaseInsensitiveStr::new("c")], [3]);
+        assert_eq!(terms.term_frequency[CaseInsensitiveStr::new("a")], 2);
+    }
+
+    #[test]
+    fn collects_forms() {
+        let analyzer = Analyzer::default().with_synonyms("car", ["automobile"]);
+        let mut terms = TermCollector::default().with_forms();
+        TextTokenizer::default()
+            .with_analyzer(analyzer.into())
+            .tokenize_string("Car car CAR cars", &mut terms);
+        let forms = terms.forms.unwrap();
+        assert_eq!(forms[CaseInsensitiveStr::new("car")], ["Car", "car", "CAR"]);
+        assert_eq!(
+            forms[CaseInsensitiveStr::new("automobile")],
+            ["Car", "car", "CAR"]
+        );
+        assert_eq!(forms[CaseInsensitiveStr::new("cars")], ["cars"]);
     }
 }
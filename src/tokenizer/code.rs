@@ -0,0 +1,116 @@
+use std::{
+    io::{self, Read},
+    sync::Arc,
+};
+
+use crate::{
+    analyzer::NgramWindow,
+    tokenizer::{push_positioned, Lexer, LexerOptions, TermSink, Tokenizer},
+    Analyzer,
+};
+
+/// Tokenizes source code: identifiers are indexed as a whole, followed by the words they are
+/// made of, so that `getUserName`, `user_name` and `MAX_NAME_LEN` are all found by `name`
+#[derive(Debug, Default)]
+pub struct CodeTokenizer {
+    analyzer: Arc<Analyzer>,
+}
+
+impl CodeTokenizer {
+    pub fn with_analyzer(mut self, analyzer: Arc<Analyzer>) -> Self {
+        self.analyzer = analyzer;
+        self
+    }
+}
+
+impl Tokenizer for CodeTokenizer {
+    fn tokenize<R: Read, S: TermSink>(
+        &mut self,
+        mut reader: R,
+        terms: &mut S,
+    ) -> io::Result<usize> {
+        let mut s = String::new();
+        reader.read_to_string(&mut s)?;
+        Ok(self.tokenize_string(&s, terms))
+    }
+
+    fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    fn tokenize_string<S: TermSink>(&mut self, s: &str, terms: &mut S) -> usize {
+        let analyzer = &*self.analyzer;
+        let mut count = 0;
+        let mut window = NgramWindow::default();
+        analyzer.analyze_in_window(s, &mut window, |token, identifier, new_position| {
+            push_positioned(terms, token, identifier, new_position);
+            count += 1;
+            let parts = identifier_parts(identifier);
+            if parts.len() > 1 {
+                for part in parts {
+                    analyzer.analyze(part, |token| {
+                        terms.push_alias(token, identifier);
+                        count += 1;
+                    });
+                }
+            }
+        });
+        count
+    }
+}
+
+/// Words of a snake_case, camelCase, PascalCase or SCREAMING_SNAKE_CASE identifier, see
+/// [`LexerOptions::split_identifiers`]
+fn identifier_parts(identifier: &str) -> Vec<&str> {
+    let options = LexerOptions {
+        split_identifiers: true,
+    };
+    Lexer::new_with_options(identifier, options).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil::TempDir, Index, SourceKind, TermMap};
+
+    #[test]
+    fn indexes_identifiers_and_their_parts() {
+        let mut tf = TermMap::new();
+        let count = CodeTokenizer::default()
+            .tokenize(&b"let user_name = getUserName();"[..], &mut tf)
+            .unwrap();
+        let mut terms: Vec<_> = tf.iter().map(|(t, c)| (&**t, *c)).collect();
+        terms.sort();
+        assert_eq!(
+            terms,
+            [
+                ("(", 1),
+                (")", 1),
+                (";", 1),
+                ("=", 1),
+                ("get", 1),
+                ("getUserName", 1),
+                ("let", 1),
+                ("name", 2),
+                ("user", 2),
+                ("user_name", 1),
+            ]
+        );
+        assert_eq!(count, 12);
+    }
+
+    #[test]
+    fn source_files_are_found_by_identifier_parts() {
+        let dir = TempDir::new("code");
+        let java = dir.write("User.java", "getUserName");
+        let notes = dir.write("notes.txt", "getUserName");
+        dir.write("other.py", "def main(): pass");
+        dir.write("other.go", "func main() {}");
+        let index = Index::new(dir.path());
+        assert_eq!(index.document(&java).unwrap().kind(), SourceKind::Code);
+        let results: Vec<_> = index.search("name").into_iter().map(|(p, _)| p).collect();
+        assert_eq!(results, [java.as_path()]);
+        assert_eq!(index.search("getUserName").len(), 2);
+        assert!(index.document(&notes).is_some());
+    }
+}
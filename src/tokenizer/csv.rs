@@ -0,0 +1,287 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    io::{self, BufRead, BufReader, Cursor, Read},
+    str::FromStr,
+    sync::Arc,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    tokenizer::{TermSink, Tokenizer},
+    Analyzer,
+};
+
+/// Separator between the cells of a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delimiter {
+    Byte(u8),
+    /// Picks, among `,`, `;`, `|` and tab, the separator appearing the most in the first line
+    /// (quoted parts excluded). Falls back to `,`.
+    Auto,
+}
+
+impl Default for Delimiter {
+    fn default() -> Self {
+        Self::Byte(b',')
+    }
+}
+
+impl FromStr for Delimiter {
+    type Err = String;
+
+    /// Parses `auto`, `tab` or a single ASCII character
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "tab" | "\\t" => Ok(Self::Byte(b'\t')),
+            _ if s.len() == 1 && s.is_ascii() => Ok(Self::Byte(s.as_bytes()[0])),
+            _ => Err(format!("invalid delimiter {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for Delimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => f.write_str("auto"),
+            Self::Byte(b'\t') => f.write_str("tab"),
+            Self::Byte(b) => write!(f, "{}", *b as char),
+        }
+    }
+}
+
+impl Serialize for Delimiter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Delimiter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Column of a delimited file, by position starting at `0` or by header name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for Column {
+    type Err = std::convert::Infallible;
+
+    /// Parses a position if `s` is a number, and a header name otherwise
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse() {
+            Ok(index) => Self::Index(index),
+            Err(_) => Self::Name(s.to_owned()),
+        })
+    }
+}
+
+const CANDIDATES: [u8; 4] = [b',', b';', b'|', b'\t'];
+
+fn detect_delimiter(line: &[u8]) -> u8 {
+    let mut counts = [0usize; CANDIDATES.len()];
+    let mut quoted = false;
+    for b in line {
+        if *b == b'"' {
+            quoted = !quoted;
+        } else if !quoted {
+            if let Some(i) = CANDIDATES.iter().position(|c| c == b) {
+                counts[i] += 1;
+            }
+        }
+    }
+    let (best, count) = counts
+        .iter()
+        .enumerate()
+        .max_by_key(|(i, c)| (**c, std::cmp::Reverse(*i)))
+        .expect("candidates are not empty");
+    if *count == 0 {
+        b','
+    } else {
+        CANDIDATES[best]
+    }
+}
+
+/// Tokenizes the cells of delimited files (CSV, TSV, ...). Quoted cells may contain the
+/// delimiter, doubled quotes and line breaks.
+#[derive(Debug, Default)]
+pub struct CsvTokenizer {
+    analyzer: Arc<Analyzer>,
+    delimiter: Delimiter,
+    has_header: bool,
+    columns: Option<Vec<Column>>,
+}
+
+impl CsvTokenizer {
+    pub fn with_analyzer(mut self, analyzer: Arc<Analyzer>) -> Self {
+        self.analyzer = analyzer;
+        self
+    }
+
+    pub fn with_delimiter(mut self, delimiter: Delimiter) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Whether the first row holds the names of the columns, in which case it is not indexed
+    pub fn with_header(mut self, has_header: bool) -> Self {
+        self.has_header = has_header;
+        self
+    }
+
+    /// Only indexes the cells of `columns` rather than every column. Columns selected by name
+    /// need a header, see [`CsvTokenizer::with_header`].
+    pub fn with_columns(mut self, columns: Option<Vec<Column>>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Positions of the selected columns, `None` selecting them all
+    fn selected_columns(&self, header: Option<&csv::StringRecord>) -> Option<HashSet<usize>> {
+        let columns = self.columns.as_ref()?;
+        let selected = columns
+            .iter()
+            .filter_map(|column| match column {
+                Column::Index(index) => Some(*index),
+                Column::Name(name) => {
+                    let index = header.and_then(|h| h.iter().position(|c| c.trim() == name));
+                    if index.is_none() {
+                        log::warn!("no column named {name:?}");
+                    }
+                    index
+                }
+            })
+            .collect();
+        Some(selected)
+    }
+}
+
+fn csv_error(err: csv::Error) -> io::Error {
+    io::Error::other(err.to_string())
+}
+
+impl Tokenizer for CsvTokenizer {
+    fn tokenize<R: Read, S: TermSink>(&mut self, reader: R, terms: &mut S) -> io::Result<usize> {
+        let mut reader = BufReader::new(reader);
+        let mut first_line = Vec::new();
+        reader.read_until(b'\n', &mut first_line)?;
+        let delimiter = match self.delimiter {
+            Delimiter::Byte(b) => b,
+            Delimiter::Auto => detect_delimiter(&first_line),
+        };
+
+        let mut records = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(Cursor::new(first_line).chain(reader));
+        let mut records = records.records();
+        let header = match self.has_header {
+            true => records.next().transpose().map_err(csv_error)?,
+            false => None,
+        };
+        let selected = self.selected_columns(header.as_ref());
+        let mut count = 0;
+        for record in records {
+            let record = record.map_err(csv_error)?;
+            for (i, cell) in record.iter().enumerate() {
+                if selected.as_ref().is_none_or(|s| s.contains(&i)) {
+                    count += self.tokenize_string(cell, terms);
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CaseInsensitiveStr, TermMap};
+
+    fn cells(delimiter: Delimiter, content: &str) -> TermMap<usize> {
+        let mut tf = TermMap::new();
+        CsvTokenizer::default()
+            .with_delimiter(delimiter)
+            .tokenize(content.as_bytes(), &mut tf)
+            .unwrap();
+        tf
+    }
+
+    #[test]
+    fn pipe_delimited_with_quoted_pipe() {
+        let content = "name|motto\nalice|\"fast | safe\"\nbob|plain\n";
+        for delimiter in [Delimiter::Byte(b'|'), Delimiter::Auto] {
+            let tf = cells(delimiter, content);
+            assert_eq!(tf.get(CaseInsensitiveStr::new("fast")), Some(&1));
+            assert_eq!(tf.get(CaseInsensitiveStr::new("safe")), Some(&1));
+            assert_eq!(tf.get(CaseInsensitiveStr::new("|")), Some(&1));
+            assert_eq!(tf.len(), 8);
+        }
+    }
+
+    #[test]
+    fn quoted_multiline_cells() {
+        let content = "id,notes\n1,\"first line\nsecond, \"\"quoted\"\" line\"\n2,plain";
+        let tf = cells(Delimiter::Byte(b','), content);
+        assert_eq!(tf.get(CaseInsensitiveStr::new("line")), Some(&2));
+        assert_eq!(tf.get(CaseInsensitiveStr::new("quoted")), Some(&1));
+        assert_eq!(tf.get(CaseInsensitiveStr::new("\"")), Some(&2));
+        assert_eq!(tf.get(CaseInsensitiveStr::new("id")), Some(&1));
+    }
+
+    #[test]
+    fn selects_columns() {
+        let content = "id,name,motto\n17,alice,\"fast, safe\"\n42,bob,plain";
+        for columns in [
+            vec![Column::Name("name".into()), Column::Name("motto".into())],
+            vec![Column::Index(1), Column::Index(2)],
+        ] {
+            let mut tf = TermMap::new();
+            let count = CsvTokenizer::default()
+                .with_header(true)
+                .with_columns(Some(columns))
+                .tokenize(content.as_bytes(), &mut tf)
+                .unwrap();
+            assert_eq!(count, 6);
+            for absent in ["id", "name", "17", "42"] {
+                assert!(
+                    !tf.contains_key(CaseInsensitiveStr::new(absent)),
+                    "{absent}"
+                );
+            }
+            assert_eq!(tf.get(CaseInsensitiveStr::new("alice")), Some(&1));
+        }
+
+        let mut tf = TermMap::new();
+        CsvTokenizer::default()
+            .with_columns(Some(vec![Column::Name("name".into())]))
+            .tokenize(content.as_bytes(), &mut tf)
+            .unwrap();
+        assert!(tf.is_empty());
+        assert_eq!("2".parse(), Ok(Column::Index(2)));
+        assert_eq!("motto".parse(), Ok(Column::Name("motto".into())));
+    }
+
+    #[test]
+    fn detects_delimiter() {
+        assert_eq!(detect_delimiter(b"a;b;c\n"), b';');
+        assert_eq!(detect_delimiter(b"a\tb\tc,d\n"), b'\t');
+        assert_eq!(detect_delimiter(b"\"a,b,c\";d\n"), b';');
+        assert_eq!(detect_delimiter(b"single\n"), b',');
+    }
+}
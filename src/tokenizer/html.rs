@@ -0,0 +1,160 @@
+use std::{
+    io::{self, Read},
+    sync::Arc,
+};
+
+use html5gum::{DefaultEmitter, Token};
+
+use crate::{
+    tokenizer::{TermSink, Tokenizer},
+    Analyzer,
+};
+
+/// Elements whose content is not visible text
+const HIDDEN: [&[u8]; 4] = [b"script", b"style", b"template", b"noscript"];
+
+/// Elements rendered within the surrounding text, which do not separate words
+const INLINE: [&[u8]; 22] = [
+    b"a", b"abbr", b"b", b"bdi", b"bdo", b"cite", b"code", b"data", b"dfn", b"em", b"i", b"kbd",
+    b"mark", b"q", b"s", b"samp", b"small", b"span", b"strong", b"sub", b"sup", b"u",
+];
+
+/// Tokenizes the visible text of HTML documents. Parsing is lenient, as in browsers: unclosed or
+/// void tags such as `<p>` and `<br>` are accepted, entities are decoded, and the content of
+/// `<script>`, `<style>`, `<template>` and `<noscript>` elements is skipped.
+#[derive(Debug, Default)]
+pub struct HtmlTokenizer {
+    analyzer: Arc<Analyzer>,
+    capture_title: bool,
+    title: Option<String>,
+}
+
+impl HtmlTokenizer {
+    pub fn with_analyzer(mut self, analyzer: Arc<Analyzer>) -> Self {
+        self.analyzer = analyzer;
+        self
+    }
+
+    /// When enabled, the content of the `<title>` element is kept as the title of the document
+    pub fn with_title_capture(mut self, capture_title: bool) -> Self {
+        self.capture_title = capture_title;
+        self
+    }
+}
+
+impl Tokenizer for HtmlTokenizer {
+    fn tokenize<R: Read, S: TermSink>(
+        &mut self,
+        mut reader: R,
+        terms: &mut S,
+    ) -> io::Result<usize> {
+        let mut html = String::new();
+        reader.read_to_string(&mut html)?;
+        let mut emitter = DefaultEmitter::default();
+        // Reads the content of `<script>` and `<style>` as raw text rather than markup
+        emitter.naively_switch_states(true);
+
+        let mut text = String::new();
+        let mut title: Option<String> = None;
+        let mut hidden: Option<Vec<u8>> = None;
+        let mut in_title = false;
+        for token in html5gum::Tokenizer::new_with_emitter(&html, emitter) {
+            let Ok(token) = token;
+            match token {
+                Token::StartTag(tag) if hidden.is_none() => {
+                    if HIDDEN.contains(&tag.name.as_slice()) && !tag.self_closing {
+                        hidden = Some(tag.name.to_vec());
+                    } else if tag.name.as_slice() == b"title" {
+                        in_title = true;
+                    } else if !INLINE.contains(&tag.name.as_slice()) {
+                        text.push(' ');
+                    }
+                }
+                Token::EndTag(tag) => {
+                    if hidden.as_deref() == Some(tag.name.as_slice()) {
+                        hidden = None;
+                    } else if tag.name.as_slice() == b"title" {
+                        in_title = false;
+                    }
+                    if !INLINE.contains(&tag.name.as_slice()) {
+                        text.push(' ');
+                    }
+                }
+                Token::String(s) if hidden.is_none() => {
+                    let s = String::from_utf8_lossy(&s);
+                    if in_title {
+                        title.get_or_insert_with(String::new).push_str(&s);
+                    }
+                    text.push_str(&s);
+                }
+                _ => {}
+            }
+        }
+        if self.capture_title {
+            self.title = title
+                .map(|t| t.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|t| !t.is_empty());
+        }
+        // The lexer turns trailing whitespace into a token
+        text.truncate(text.trim_end().len());
+        Ok(self.tokenize_string(&text, terms))
+    }
+
+    fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CaseInsensitiveStr, TermMap};
+
+    const DOCUMENT: &str = r#"<!DOCTYPE html>
+<html><head><title>Fish &amp; Chips</title>
+<style>p { color: red }</style>
+<script>if (a < b && secret) { alert("hidden"); }</script>
+</head>
+<body>
+<p>First <b>para</b>graph<br>
+<p class=note>caf&eacute; &lt;tag&gt; <!-- comment -->
+<ul><li>one<li>two</ul>
+"#;
+
+    #[test]
+    fn indexes_visible_text() {
+        let mut tf = TermMap::new();
+        let mut tokenizer = HtmlTokenizer::default().with_title_capture(true);
+        tokenizer.tokenize(DOCUMENT.as_bytes(), &mut tf).unwrap();
+        for present in [
+            "Fish",
+            "Chips",
+            "&",
+            "First",
+            "paragraph",
+            "café",
+            "tag",
+            "one",
+            "two",
+        ] {
+            assert!(
+                tf.contains_key(CaseInsensitiveStr::new(present)),
+                "{present}"
+            );
+        }
+        for absent in [
+            "secret", "alert", "hidden", "color", "red", "comment", "note", "para", "amp", "eacute",
+        ] {
+            assert!(
+                !tf.contains_key(CaseInsensitiveStr::new(absent)),
+                "{absent}"
+            );
+        }
+        assert_eq!(tf[CaseInsensitiveStr::new("<")], 1);
+        assert_eq!(tokenizer.title(), Some("Fish & Chips"));
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    io::{self, Read},
+    sync::Arc,
+};
+
+use serde_json::Value;
+
+use crate::{
+    tokenizer::{TermSink, Tokenizer},
+    Analyzer,
+};
+
+/// Tokenizes the string values of JSON documents, walking nested arrays and objects. Numbers,
+/// booleans and nulls are skipped, and so are object keys unless enabled with
+/// [`JsonTokenizer::with_keys`]. Object members are visited in key order.
+#[derive(Debug, Default)]
+pub struct JsonTokenizer {
+    analyzer: Arc<Analyzer>,
+    index_keys: bool,
+}
+
+impl JsonTokenizer {
+    pub fn with_analyzer(mut self, analyzer: Arc<Analyzer>) -> Self {
+        self.analyzer = analyzer;
+        self
+    }
+
+    /// Whether to index the keys of objects, disabled by default
+    pub fn with_keys(mut self, index_keys: bool) -> Self {
+        self.index_keys = index_keys;
+        self
+    }
+
+    fn tokenize_value<S: TermSink>(&mut self, value: &Value, terms: &mut S) -> usize {
+        match value {
+            Value::String(s) => self.tokenize_string(s, terms),
+            Value::Array(values) => values.iter().map(|v| self.tokenize_value(v, terms)).sum(),
+            Value::Object(members) => members
+                .iter()
+                .map(|(key, v)| {
+                    let key_count = if self.index_keys {
+                        self.tokenize_string(key, terms)
+                    } else {
+                        0
+                    };
+                    key_count + self.tokenize_value(v, terms)
+                })
+                .sum(),
+            Value::Null | Value::Bool(_) | Value::Number(_) => 0,
+        }
+    }
+}
+
+impl Tokenizer for JsonTokenizer {
+    fn tokenize<R: Read, S: TermSink>(&mut self, reader: R, terms: &mut S) -> io::Result<usize> {
+        let value: Value = serde_json::from_reader(reader)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+        Ok(self.tokenize_value(&value, terms))
+    }
+
+    fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CaseInsensitiveStr, TermMap};
+
+    const DOCUMENT: &str = r#"{
+        "name": "indexer",
+        "version": 3,
+        "enabled": true,
+        "owner": null,
+        "tags": ["search", ["nested search"]],
+        "settings": {"tokenizer": "json tokenizer", "threshold": 0.5}
+    }"#;
+
+    #[test]
+    fn indexes_string_values() {
+        let mut tf = TermMap::new();
+        let count = JsonTokenizer::default()
+            .tokenize(DOCUMENT.as_bytes(), &mut tf)
+            .unwrap();
+        assert_eq!(count, 6);
+        assert_eq!(tf[CaseInsensitiveStr::new("search")], 2);
+        assert_eq!(tf[CaseInsensitiveStr::new("tokenizer")], 1);
+        for absent in ["name", "tags", "settings", "3", "true", "null", "0.5"] {
+            assert!(
+                !tf.contains_key(CaseInsensitiveStr::new(absent)),
+                "{absent}"
+            );
+        }
+    }
+
+    #[test]
+    fn keys_can_be_indexed() {
+        let mut tf = TermMap::new();
+        JsonTokenizer::default()
+            .with_keys(true)
+            .tokenize(DOCUMENT.as_bytes(), &mut tf)
+            .unwrap();
+        assert_eq!(tf[CaseInsensitiveStr::new("tokenizer")], 2);
+        assert_eq!(tf[CaseInsensitiveStr::new("threshold")], 1);
+        assert!(!tf.contains_key(CaseInsensitiveStr::new("0.5")));
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        let err = JsonTokenizer::default()
+            .tokenize(&b"{\"a\": "[..], &mut TermMap::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,10 +1,30 @@
+/// How [`Lexer`] splits text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LexerOptions {
+    /// Splits words at underscores and case changes, so that `snake_case` and `camelCase` each
+    /// yield two tokens. A run of capitals followed by a lowercase letter ends before its last
+    /// capital: `XMLParser` yields `XML` and `Parser`. Digits stay with the letters they follow.
+    pub split_identifiers: bool,
+}
+
 pub struct Lexer<'a> {
     content: &'a str,
+    options: LexerOptions,
+    /// Rest of the word being split into identifier parts
+    word: &'a str,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(content: &'a str) -> Self {
-        Self { content }
+        Self::new_with_options(content, LexerOptions::default())
+    }
+
+    pub fn new_with_options(content: &'a str, options: LexerOptions) -> Self {
+        Self {
+            content,
+            options,
+            word: "",
+        }
     }
 
     fn chomp_while<F>(&mut self, mut f: F) -> &'a str
@@ -14,44 +34,76 @@ impl<'a> Lexer<'a> {
         let index = self
             .content
             .char_indices()
-            .take_while(|(_, c)| f(c))
-            .last()
-            .map(|(i, c)| i + c.len_utf8())
-            .expect("At least the first char is valid");
+            .find(|(_, c)| !f(c))
+            .map_or(self.content.len(), |(i, _)| i);
         let token = &self.content[..index];
         self.content = &self.content[index..];
         token
     }
 
-    fn skip_whitespaces(&mut self) {
-        if let Some(index) = self
-            .content
-            .find(|c: char| !c.is_ascii_whitespace() && c != '\n')
-        {
-            self.content = &self.content[index..];
+    /// Digits, followed by a single decimal point if digits follow it
+    fn chomp_number(&mut self) -> &'a str {
+        let content = self.content;
+        let integer = self.chomp_while(char::is_ascii_digit).len();
+        let rest = self.content.as_bytes();
+        if rest.first() == Some(&b'.') && rest.get(1).is_some_and(u8::is_ascii_digit) {
+            self.content = &self.content[1..];
+            let fraction = self.chomp_while(char::is_ascii_digit).len();
+            &content[..integer + 1 + fraction]
+        } else {
+            &content[..integer]
         }
     }
 
+    /// Skips whitespace, Unicode included, up to the next token or the end of the content
+    fn skip_whitespaces(&mut self) {
+        self.content = self.content.trim_start();
+    }
+
     pub fn get_next_token(&mut self) -> Option<&'a str> {
+        if let Some(part) = self.next_identifier_part() {
+            return Some(part);
+        }
         self.skip_whitespaces();
         let first = self.content.chars().next()?;
 
         if first.is_ascii_digit() {
-            Some(self.chomp_while(|c| c.is_ascii_digit() || c == &'.'))
+            Some(self.chomp_number())
         } else if first.is_ascii_alphabetic() {
-            Some(self.chomp_while(|c| c.is_alphanumeric() || c == &'_'))
+            let word = self.chomp_while(|c| c.is_alphanumeric() || c == &'_');
+            if !self.options.split_identifiers {
+                return Some(word);
+            }
+            self.word = word;
+            self.next_identifier_part()
         } else {
             let mut first = true;
-            Some(self.chomp_while(|_| {
-                if first {
-                    first = false;
-                    true
-                } else {
-                    false
-                }
-            }))
+            Some(self.chomp_while(|_| std::mem::take(&mut first)))
         }
     }
+
+    /// Next part of the word being split, see [`LexerOptions::split_identifiers`]
+    fn next_identifier_part(&mut self) -> Option<&'a str> {
+        let word = self.word.trim_start_matches('_');
+        let mut chars = word.char_indices().peekable();
+        let (_, mut previous) = chars.next()?;
+        let mut end = word.len();
+        while let Some((i, c)) = chars.next() {
+            let next_is_lowercase = chars.peek().is_some_and(|(_, n)| n.is_lowercase());
+            let boundary = c == '_'
+                || (c.is_uppercase()
+                    && (previous.is_lowercase()
+                        || previous.is_ascii_digit()
+                        || (previous.is_uppercase() && next_is_lowercase)));
+            if boundary {
+                end = i;
+                break;
+            }
+            previous = c;
+        }
+        self.word = &word[end..];
+        Some(&word[..end])
+    }
 }
 
 impl<'a> Iterator for Lexer<'a> {
@@ -61,3 +113,70 @@ impl<'a> Iterator for Lexer<'a> {
         self.get_next_token()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chomp_while_nothing() {
+        let mut lexer = Lexer::new("abc");
+        assert_eq!(lexer.chomp_while(|_| false), "");
+        assert_eq!(lexer.content, "abc");
+        assert_eq!(lexer.chomp_while(|c| c == &'a'), "a");
+        assert_eq!(lexer.chomp_while(|_| true), "bc");
+        assert_eq!(lexer.chomp_while(|_| false), "");
+        assert_eq!(Lexer::new("é2ü").chomp_while(|c| !c.is_ascii_digit()), "é");
+    }
+
+    #[test]
+    fn whitespace_is_not_a_token() {
+        assert_eq!(Lexer::new("   \n\t  ").next(), None);
+        assert_eq!(Lexer::new("").next(), None);
+        assert_eq!(
+            Lexer::new("  one\ttwo\u{a0}three\n").collect::<Vec<_>>(),
+            ["one", "two", "three"]
+        );
+    }
+
+    #[test]
+    fn numbers_have_a_single_decimal_point() {
+        let tokens = |content| Lexer::new(content).collect::<Vec<_>>();
+        assert_eq!(tokens("pi is 3.14"), ["pi", "is", "3.14"]);
+        assert_eq!(tokens("3.14.15"), ["3.14", ".", "15"]);
+        assert_eq!(tokens("version 1.2.3"), ["version", "1.2", ".", "3"]);
+        assert_eq!(tokens("in 2024. Then"), ["in", "2024", ".", "Then"]);
+        assert_eq!(tokens("1. 2.."), ["1", ".", "2", ".", "."]);
+        assert_eq!(tokens("1,000,000"), ["1", ",", "000", ",", "000"]);
+        assert_eq!(tokens("v2.0"), ["v2", ".", "0"]);
+    }
+
+    fn split(content: &str) -> Vec<&str> {
+        let options = LexerOptions {
+            split_identifiers: true,
+        };
+        Lexer::new_with_options(content, options).collect()
+    }
+
+    #[test]
+    fn splits_identifiers() {
+        assert_eq!(
+            Lexer::new("fooBar foo_bar").collect::<Vec<_>>(),
+            ["fooBar", "foo_bar"]
+        );
+        assert_eq!(split("getUserName"), ["get", "User", "Name"]);
+        assert_eq!(split("snake_case_name"), ["snake", "case", "name"]);
+        assert_eq!(split("MAX_NAME_LEN"), ["MAX", "NAME", "LEN"]);
+        assert_eq!(
+            split("XMLParser parseXML"),
+            ["XML", "Parser", "parse", "XML"]
+        );
+        assert_eq!(split("utf8Decoder"), ["utf8", "Decoder"]);
+        assert_eq!(split("trailing__ a__b"), ["trailing", "a", "b"]);
+        assert_eq!(split("_private"), ["_", "private"]);
+        assert_eq!(
+            split("x = fooBar(1);"),
+            ["x", "=", "foo", "Bar", "(", "1", ")", ";"]
+        );
+    }
+}
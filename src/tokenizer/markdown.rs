@@ -0,0 +1,269 @@
+use std::{
+    io::{self, Read},
+    sync::Arc,
+};
+
+use crate::{
+    tokenizer::{TermSink, Tokenizer},
+    Analyzer,
+};
+
+/// Tokenizes the prose of Markdown documents: heading and list markers, emphasis, link URLs,
+/// HTML tags and link reference definitions are dropped, keeping the text of links and images
+#[derive(Debug)]
+pub struct MarkdownTokenizer {
+    analyzer: Arc<Analyzer>,
+    capture_title: bool,
+    index_code: bool,
+    title: Option<String>,
+}
+
+impl Default for MarkdownTokenizer {
+    fn default() -> Self {
+        Self {
+            analyzer: Arc::default(),
+            capture_title: false,
+            index_code: true,
+            title: None,
+        }
+    }
+}
+
+impl MarkdownTokenizer {
+    pub fn with_analyzer(mut self, analyzer: Arc<Analyzer>) -> Self {
+        self.analyzer = analyzer;
+        self
+    }
+
+    /// When enabled, the first non-empty line of text, usually the first heading, is kept as the
+    /// title of the document
+    pub fn with_title_capture(mut self, capture_title: bool) -> Self {
+        self.capture_title = capture_title;
+        self
+    }
+
+    /// Whether to index the content of code spans and fenced code blocks, enabled by default
+    pub fn with_code(mut self, index_code: bool) -> Self {
+        self.index_code = index_code;
+        self
+    }
+}
+
+impl Tokenizer for MarkdownTokenizer {
+    fn tokenize<R: Read, S: TermSink>(
+        &mut self,
+        mut reader: R,
+        terms: &mut S,
+    ) -> io::Result<usize> {
+        let mut s = String::new();
+        reader.read_to_string(&mut s)?;
+        let text = strip(&s, self.index_code);
+        if self.capture_title {
+            self.title = text
+                .lines()
+                .find(|l| !l.trim().is_empty())
+                .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+        Ok(self.tokenize_string(&text, terms))
+    }
+
+    fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+}
+
+/// Text of `markdown` without its formatting syntax, one line per source line
+fn strip(markdown: &str, index_code: bool) -> String {
+    let mut text = String::with_capacity(markdown.len());
+    let mut fence: Option<&str> = None;
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if let Some(f) = fence {
+            if trimmed.starts_with(f) {
+                fence = None;
+            } else if index_code {
+                text.push_str(line);
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+        } else if !is_reference_definition(trimmed) && !is_rule(trimmed) {
+            strip_inline(strip_block_markers(trimmed), index_code, &mut text);
+        }
+        text.push('\n');
+    }
+    // The lexer turns trailing whitespace into a token
+    text.truncate(text.trim_end().len());
+    text
+}
+
+/// `[label]: url`
+fn is_reference_definition(line: &str) -> bool {
+    line.starts_with('[') && line.contains("]:")
+}
+
+/// Horizontal rules and setext heading underlines
+fn is_rule(line: &str) -> bool {
+    let line = line.trim_end();
+    !line.is_empty()
+        && line
+            .chars()
+            .all(|c| matches!(c, '-' | '=' | '*' | '_' | ' '))
+}
+
+/// Removes blockquote, heading and list markers from the start of `line`
+fn strip_block_markers(mut line: &str) -> &str {
+    while let Some(rest) = line.strip_prefix('>') {
+        line = rest.trim_start();
+    }
+    let hashes = line.len() - line.trim_start_matches('#').len();
+    if (1..=6).contains(&hashes) && line[hashes..].starts_with([' ', '\t']) {
+        return line[hashes..].trim();
+    }
+    if let Some(rest) = line.strip_prefix(['-', '*', '+']) {
+        if rest.starts_with([' ', '\t']) {
+            return rest.trim_start();
+        }
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 {
+        if let Some(rest) = line[digits..].strip_prefix(['.', ')']) {
+            if rest.starts_with([' ', '\t']) {
+                return rest.trim_start();
+            }
+        }
+    }
+    line
+}
+
+/// Appends the text of `line` to `text`, without code spans unless `index_code`, link URLs, HTML
+/// tags and emphasis
+fn strip_inline(line: &str, index_code: bool, text: &mut String) {
+    let mut rest = line;
+    let mut previous = ' ';
+    while let Some(c) = rest.chars().next() {
+        let next = rest[c.len_utf8()..].chars().next().unwrap_or(' ');
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '`' => {
+                let ticks = 1 + rest.len() - rest.trim_start_matches('`').len();
+                let delimiter = &line[line.len() - rest.len() - 1..][..ticks];
+                rest = &rest[ticks - 1..];
+                if let Some(end) = rest.find(delimiter) {
+                    if index_code {
+                        text.push(' ');
+                        text.push_str(&rest[..end]);
+                    }
+                    rest = &rest[end + ticks..];
+                }
+                text.push(' ');
+            }
+            '!' if next == '[' => {}
+            '[' => text.push(' '),
+            ']' => {
+                let target = match next {
+                    '(' => rest.find(')'),
+                    '[' => rest.find(']'),
+                    _ => None,
+                };
+                if let Some(end) = target {
+                    rest = &rest[end + 1..];
+                }
+                text.push(' ');
+            }
+            '<' if next.is_ascii_alphabetic() || next == '/' => match rest.find('>') {
+                Some(end) => {
+                    rest = &rest[end + 1..];
+                    text.push(' ');
+                }
+                None => text.push(c),
+            },
+            '*' | '~' => text.push(' '),
+            '_' if !previous.is_alphanumeric() || !next.is_alphanumeric() => text.push(' '),
+            _ => text.push(c),
+        }
+        previous = c;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TermMap;
+
+    const DOCUMENT: &str = "\
+# Getting *started*
+
+Read the [user guide](https://example.com/guide) and ![the diagram](img/flow.png).
+> **Note:** call `build_index` first, see [setup][ref] or <https://example.org>.
+
+- first_item with __bold__ text
+1. numbered ~~old~~ step
+
+```rust
+fn main() {}
+```
+---
+[ref]: https://example.com/setup";
+
+    fn terms(tokenizer: &mut MarkdownTokenizer) -> Vec<String> {
+        let mut tf = TermMap::new();
+        tokenizer.tokenize(DOCUMENT.as_bytes(), &mut tf).unwrap();
+        let mut terms: Vec<_> = tf.into_keys().map(|t| t.to_string()).collect();
+        terms.sort();
+        terms
+    }
+
+    #[test]
+    fn strips_formatting() {
+        let mut tokenizer = MarkdownTokenizer::default().with_title_capture(true);
+        let mut expected = [
+            "(",
+            ")",
+            ",",
+            ".",
+            ":",
+            "Getting",
+            "Note",
+            "Read",
+            "and",
+            "bold",
+            "build_index",
+            "call",
+            "diagram",
+            "first",
+            "first_item",
+            "fn",
+            "guide",
+            "main",
+            "numbered",
+            "old",
+            "or",
+            "see",
+            "setup",
+            "started",
+            "step",
+            "text",
+            "the",
+            "user",
+            "with",
+            "{",
+            "}",
+        ];
+        expected.sort();
+        assert_eq!(terms(&mut tokenizer), expected);
+        assert_eq!(tokenizer.title(), Some("Getting started"));
+    }
+
+    #[test]
+    fn code_can_be_excluded() {
+        let terms = terms(&mut MarkdownTokenizer::default().with_code(false));
+        for term in ["build_index", "fn", "main", "rust"] {
+            assert!(!terms.iter().any(|t| t == term), "{term}");
+        }
+        assert!(terms.iter().any(|t| t == "call"));
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    io::{self, Read},
+    sync::Arc,
+};
+
+use crate::{
+    tokenizer::{TermSink, Tokenizer},
+    Analyzer,
+};
+
+/// Tokenizes the text of each page of a PDF file. Files whose text cannot be extracted, such as
+/// encrypted or malformed ones, are logged and indexed as empty documents; pages made of images
+/// only have no text to begin with.
+#[derive(Debug, Default)]
+pub struct PdfTokenizer {
+    analyzer: Arc<Analyzer>,
+}
+
+impl PdfTokenizer {
+    pub fn with_analyzer(mut self, analyzer: Arc<Analyzer>) -> Self {
+        self.analyzer = analyzer;
+        self
+    }
+}
+
+impl Tokenizer for PdfTokenizer {
+    fn tokenize<R: Read, S: TermSink>(
+        &mut self,
+        mut reader: R,
+        terms: &mut S,
+    ) -> io::Result<usize> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let document = match lopdf::Document::load_mem(&bytes) {
+            Ok(document) => document,
+            Err(e) => {
+                log::warn!("Cannot read PDF file: {e}");
+                return Ok(0);
+            }
+        };
+        if document.is_encrypted() {
+            log::warn!("Cannot extract the text of an encrypted PDF file");
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        for page in document.get_pages().into_keys() {
+            match document.extract_text(&[page]) {
+                Ok(text) => count += self.tokenize_string(&text, terms),
+                Err(e) => log::warn!("Cannot extract the text of PDF page {page}: {e}"),
+            }
+        }
+        if count == 0 {
+            log::warn!("PDF file without any text, its pages may be images");
+        }
+        Ok(count)
+    }
+
+    fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lopdf::{
+        content::{Content, Operation},
+        dictionary, Object, Stream,
+    };
+
+    use super::*;
+    use crate::{testutil::TempDir, Index, SourceKind, TermMap};
+
+    /// Single page PDF file showing `text`
+    fn pdf(text: &str) -> Vec<u8> {
+        let mut doc = lopdf::Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let resources_id = doc.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        });
+        let content = Content {
+            operations: vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                Operation::new("Td", vec![100.into(), 600.into()]),
+                Operation::new("Tj", vec![Object::string_literal(text)]),
+                Operation::new("ET", vec![]),
+            ],
+        };
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn extracts_page_text() {
+        let mut tf = TermMap::new();
+        let count = PdfTokenizer::default()
+            .tokenize(&pdf("quarterly revenue report")[..], &mut tf)
+            .unwrap();
+        let terms: Vec<_> = tf.iter().map(|(t, c)| (&**t, *c)).collect();
+        assert_eq!(terms, [("quarterly", 1), ("report", 1), ("revenue", 1)]);
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn unreadable_files_are_empty_documents() {
+        let dir = TempDir::new("pdf");
+        let report = dir.write("report.pdf", pdf("quarterly revenue"));
+        let broken = dir.write("broken.pdf", "not a pdf at all");
+        dir.write("notes.txt", "quarterly notes");
+        let index = Index::new(dir.path());
+        assert_eq!(index.document(&report).unwrap().kind(), SourceKind::Pdf);
+        let results: Vec<_> = index
+            .search("revenue")
+            .into_iter()
+            .map(|(p, _)| p)
+            .collect();
+        assert_eq!(results, [report.as_path()]);
+        assert_eq!(index.document(&broken).unwrap().count, 0);
+    }
+}
@@ -1,21 +1,307 @@
 use std::{
-    collections::HashMap,
+    collections::HashSet,
     io::{self, Read},
+    sync::Arc,
 };
 
-use crate::{tokenizer::Tokenizer, CaseInsensitiveString};
+use crate::{
+    analyzer::NgramWindow,
+    tokenizer::{push_positioned, TermSink, Tokenizer},
+    Analyzer, CaseInsensitiveString, StemmerKind,
+};
+
+/// Number of bytes read at once
+const CHUNK_SIZE: usize = 64 * 1024;
 
+/// Tokenizes plain text. Documents are read in chunks, so memory use does not grow with their
+/// size, only with the length of their longest token, such as a word or a number.
 #[derive(Debug, Default)]
-pub struct TextTokenizer;
+pub struct TextTokenizer {
+    analyzer: Arc<Analyzer>,
+    capture_title: bool,
+    title: Option<String>,
+}
+
+impl TextTokenizer {
+    pub fn with_analyzer(mut self, analyzer: Arc<Analyzer>) -> Self {
+        self.analyzer = analyzer;
+        self
+    }
+
+    /// Drops `stop_words` from documents, see [`Analyzer::with_stop_words`]
+    pub fn with_stop_words(mut self, stop_words: HashSet<CaseInsensitiveString<'static>>) -> Self {
+        let analyzer = Arc::unwrap_or_clone(self.analyzer);
+        self.analyzer = Arc::new(analyzer.with_stop_words(stop_words));
+        self
+    }
+
+    /// Reduces terms to their stem, see [`Analyzer::with_stemmer`]
+    pub fn with_stemmer(mut self, stemmer: impl Into<StemmerKind>) -> Self {
+        let analyzer = Arc::unwrap_or_clone(self.analyzer);
+        self.analyzer = Arc::new(analyzer.with_stemmer(stemmer));
+        self
+    }
+
+    /// When enabled, the first non-empty line of the document is kept as its title
+    pub fn with_title_capture(mut self, capture_title: bool) -> Self {
+        self.capture_title = capture_title;
+        self
+    }
+}
 
 impl Tokenizer for TextTokenizer {
-    fn tokenize<R: Read>(
+    fn tokenize<R: Read, S: TermSink>(
         &mut self,
         mut reader: R,
-        term_frequency: &mut HashMap<CaseInsensitiveString<'static>, usize>,
+        terms: &mut S,
     ) -> io::Result<usize> {
-        let mut s = String::new();
-        reader.read_to_string(&mut s)?;
-        Ok(self.tokenize_string(&s, term_frequency))
+        self.title = None;
+        // Start of the first non-empty line, while looking for the title
+        let mut line = self.capture_title.then(String::new);
+        let mut buffer = vec![0; CHUNK_SIZE];
+        // Bytes of a character split across reads, at the start of `buffer`
+        let mut partial = 0;
+        // Text read but not tokenized yet, as it may continue in the next read
+        let mut pending = String::new();
+        let mut window = NgramWindow::default();
+        let mut count = 0;
+        loop {
+            let read = match reader.read(&mut buffer[partial..]) {
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let end = read == 0;
+            let read = &buffer[..partial + read];
+
+            // Only the bytes just read are validated, the pending text already was
+            let valid = match std::str::from_utf8(read) {
+                Ok(text) => text,
+                // A character split across reads
+                Err(e) if e.error_len().is_none() && !end => {
+                    std::str::from_utf8(&read[..e.valid_up_to()]).expect("valid prefix")
+                }
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "stream did not contain valid UTF-8",
+                    ))
+                }
+            };
+            let new = pending.len();
+            pending.push_str(valid);
+            let rest = valid.len()..read.len();
+            partial = rest.len();
+            buffer.copy_within(rest, 0);
+
+            let text = if end {
+                &pending[..]
+            } else {
+                &pending[..safe_end(&pending, new)]
+            };
+            if let Some(ref mut l) = line {
+                l.push_str(text);
+                if let Some(title) = first_non_empty_line(l, end) {
+                    self.title = Some(title.to_owned());
+                    line = None;
+                } else if let Some(newline) = l.rfind('\n') {
+                    l.drain(..=newline);
+                }
+            }
+            self.analyzer
+                .analyze_in_window(text, &mut window, |token, form, new_position| {
+                    push_positioned(terms, token, form, new_position);
+                    count += 1;
+                });
+            let consumed = text.len();
+            pending.drain(..consumed);
+            if end {
+                return Ok(count);
+            }
+        }
+    }
+
+    fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+}
+
+/// Length of the longest prefix of `text` the lexer splits the same way whatever follows it, `0`
+/// if there is none. Tokens end at any character other than letters, digits, underscores and
+/// the decimal point of numbers, so the prefix ends before the last of them. Only the characters
+/// from `from` are looked at: the text before it is what was left of the previous call, with no
+/// such character past its first.
+fn safe_end(text: &str, from: usize) -> usize {
+    let mut previous = text[..from].chars().next_back();
+    let mut end = 0;
+    for (i, c) in text[from..].char_indices() {
+        let boundary = !c.is_alphanumeric()
+            && c != '_'
+            && (c != '.' || !previous.is_some_and(|p| p.is_ascii_digit()));
+        if boundary && from + i > 0 {
+            end = from + i;
+        }
+        previous = Some(c);
+    }
+    end
+}
+
+/// First non-empty line of `text`, trimmed, unless it may continue past the end of `text`
+fn first_non_empty_line(text: &str, end: bool) -> Option<&str> {
+    let complete = if end {
+        text
+    } else {
+        &text[..text.rfind('\n')? + 1]
+    };
+    complete.lines().map(str::trim).find(|l| !l.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TermMap;
+
+    #[test]
+    fn captures_first_non_empty_line() {
+        let mut tf = TermMap::new();
+        let mut tokenizer = TextTokenizer::default().with_title_capture(true);
+        tokenizer
+            .tokenize("\n  \n  Meeting notes \nbody".as_bytes(), &mut tf)
+            .unwrap();
+        assert_eq!(tokenizer.title(), Some("Meeting notes"));
+
+        let mut tokenizer = TextTokenizer::default();
+        tokenizer
+            .tokenize("title\nbody".as_bytes(), &mut tf)
+            .unwrap();
+        assert_eq!(tokenizer.title(), None);
+    }
+
+    /// Yields `content` `repeat` times, in reads of varying sizes
+    struct Repeat {
+        content: &'static [u8],
+        repeat: usize,
+        offset: usize,
+        reads: usize,
+    }
+
+    impl Read for Repeat {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let total = self.content.len() * self.repeat;
+            self.reads += 1;
+            let n = (self.reads * 7919 % 5003 + 1)
+                .min(buf.len())
+                .min(total - self.offset);
+            for (i, b) in buf[..n].iter_mut().enumerate() {
+                *b = self.content[(self.offset + i) % self.content.len()];
+            }
+            self.offset += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn streaming_matches_whole_text() {
+        let content =
+            "\n Title line\nword 3.14 caf\u{e9} snake_case,x\u{a0}y\u{1f980} 42. \t".as_bytes();
+        let repeat = 20_000;
+        let mut expected = TermMap::new();
+        let text = std::str::from_utf8(content).unwrap().repeat(repeat);
+        let expected_count = TextTokenizer::default().tokenize_string(&text, &mut expected);
+        assert!(text.len() > 10 * CHUNK_SIZE);
+
+        let mut tf = TermMap::new();
+        let mut tokenizer = TextTokenizer::default().with_title_capture(true);
+        let reader = Repeat {
+            content,
+            repeat,
+            offset: 0,
+            reads: 0,
+        };
+        let count = tokenizer.tokenize(reader, &mut tf).unwrap();
+        assert_eq!(count, expected_count);
+        assert_eq!(tf, expected);
+        assert_eq!(tokenizer.title(), Some("Title line"));
+
+        let mut tf = TermMap::new();
+        let err = TextTokenizer::default()
+            .tokenize(&b"ok \xff"[..], &mut tf)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn ngrams_span_reads() {
+        let content = "deep learning, machine learning\n".as_bytes();
+        let repeat = 1_000;
+        let analyzer = Arc::new(Analyzer::default().with_ngram_range(1, 3));
+        let mut tokenizer = TextTokenizer::default().with_analyzer(analyzer);
+        let text = std::str::from_utf8(content).unwrap().repeat(repeat);
+        let mut expected = TermMap::new();
+        let expected_count = tokenizer.tokenize_string(&text, &mut expected);
+        assert_eq!(expected_count, 13 * repeat - 3);
+
+        let mut tf = TermMap::new();
+        let reader = Repeat {
+            content,
+            repeat,
+            offset: 0,
+            reads: 0,
+        };
+        assert_eq!(tokenizer.tokenize(reader, &mut tf).unwrap(), expected_count);
+        assert_eq!(tf, expected);
+    }
+
+    #[test]
+    fn safe_end_keeps_last_token() {
+        assert_eq!(safe_end("ab cd", 0), 2);
+        assert_eq!(safe_end("ab  cd  ", 0), 7);
+        assert_eq!(safe_end("ab  cd", 3), 3);
+        assert_eq!(safe_end("abcd", 0), 0);
+        assert_eq!(safe_end(" ab", 0), 0);
+        assert_eq!(safe_end("a,b,c", 2), 3);
+        assert_eq!(safe_end("x.y 3.14", 0), 3);
+        assert_eq!(safe_end("3.14", 0), 0);
+        assert_eq!(safe_end("snake_case", 0), 0);
+    }
+
+    #[test]
+    fn streaming_without_whitespace() {
+        let content = "{\"k\":[1.5,\"v_1\"],\"t\":\"caf\u{e9}\"},".as_bytes();
+        let repeat = 5_000;
+        let text = std::str::from_utf8(content).unwrap().repeat(repeat);
+        let mut expected = TermMap::new();
+        let expected_count = TextTokenizer::default().tokenize_string(&text, &mut expected);
+        assert!(text.len() > 2 * CHUNK_SIZE);
+
+        let mut tf = TermMap::new();
+        let reader = Repeat {
+            content,
+            repeat,
+            offset: 0,
+            reads: 0,
+        };
+        let count = TextTokenizer::default().tokenize(reader, &mut tf).unwrap();
+        assert_eq!(count, expected_count);
+        assert_eq!(tf, expected);
+    }
+
+    #[test]
+    fn stop_words_are_not_terms() {
+        let mut tf = TermMap::new();
+        let mut tokenizer =
+            TextTokenizer::default().with_stop_words(Analyzer::english_stop_words());
+        let count = tokenizer
+            .tokenize("The cat and THE dog of the house".as_bytes(), &mut tf)
+            .unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(tf.values().sum::<usize>(), count);
+        for key in tf.keys() {
+            assert!(!Analyzer::english_stop_words().contains(key), "{key:?}");
+        }
     }
 }
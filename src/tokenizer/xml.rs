@@ -1,31 +1,148 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io::{self, Read},
+    sync::Arc,
 };
 
 use xml::reader::{EventReader, XmlEvent};
 
-use crate::{tokenizer::Tokenizer, CaseInsensitiveString};
+use crate::{
+    tokenizer::{TermSink, Tokenizer},
+    Analyzer, CaseInsensitiveString, StemmerKind,
+};
 
 #[derive(Debug, Default)]
-pub struct XmlTokenizer;
+pub struct XmlTokenizer {
+    analyzer: Arc<Analyzer>,
+    /// Number of times the terms of each element count, by lowercase element name
+    weights: HashMap<String, usize>,
+}
+
+impl XmlTokenizer {
+    pub fn with_analyzer(mut self, analyzer: Arc<Analyzer>) -> Self {
+        self.analyzer = analyzer;
+        self
+    }
+
+    /// Drops `stop_words` from documents, see [`Analyzer::with_stop_words`]
+    pub fn with_stop_words(mut self, stop_words: HashSet<CaseInsensitiveString<'static>>) -> Self {
+        let analyzer = Arc::unwrap_or_clone(self.analyzer);
+        self.analyzer = Arc::new(analyzer.with_stop_words(stop_words));
+        self
+    }
+
+    /// Reduces terms to their stem, see [`Analyzer::with_stemmer`]
+    pub fn with_stemmer(mut self, stemmer: impl Into<StemmerKind>) -> Self {
+        let analyzer = Arc::unwrap_or_clone(self.analyzer);
+        self.analyzer = Arc::new(analyzer.with_stemmer(stemmer));
+        self
+    }
+
+    /// Counts the terms found in the given elements, and those they contain, that many times
+    /// instead of once, e.g. `[("title", 3), ("h1", 2)]` for the terms of the title of XHTML
+    /// documents to weigh more than those of their body. Element names ignore case and
+    /// namespaces. Weighted terms also count that many times in the length of the document.
+    pub fn with_element_weights<S: Into<String>>(
+        mut self,
+        weights: impl IntoIterator<Item = (S, usize)>,
+    ) -> Self {
+        self.weights = weights
+            .into_iter()
+            .map(|(element, weight)| (element.into().to_lowercase(), weight))
+            .collect();
+        self
+    }
+}
 
 impl Tokenizer for XmlTokenizer {
-    fn tokenize<R: Read>(
-        &mut self,
-        reader: R,
-        term_frequency: &mut HashMap<CaseInsensitiveString<'static>, usize>,
-    ) -> io::Result<usize> {
+    fn tokenize<R: Read, S: TermSink>(&mut self, reader: R, terms: &mut S) -> io::Result<usize> {
         let mut count = 0;
+        // Weight of each open element, inherited from its parent unless set
+        let mut weights: Vec<usize> = Vec::new();
         for event in EventReader::new(reader).into_iter() {
-            let event =
-                event.map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
-            if let XmlEvent::Characters(s) = event {
-                let c = self.tokenize_string(&s, term_frequency);
-                count += c;
+            let event = event.map_err(|err| io::Error::other(err.to_string()))?;
+            match event {
+                XmlEvent::StartElement { name, .. } if !self.weights.is_empty() => {
+                    let weight = self
+                        .weights
+                        .get(&name.local_name.to_lowercase())
+                        .or(weights.last())
+                        .copied()
+                        .unwrap_or(1);
+                    weights.push(weight);
+                }
+                XmlEvent::EndElement { .. } => {
+                    weights.pop();
+                }
+                XmlEvent::Characters(s) => {
+                    for _ in 0..weights.last().copied().unwrap_or(1) {
+                        count += self.tokenize_string(&s, terms);
+                    }
+                }
+                _ => {}
             }
         }
 
         Ok(count)
     }
+
+    fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil::TempDir, Index, TermMap, TokenizerRegistry};
+
+    #[test]
+    fn stop_words_are_not_terms() {
+        let stop_words = HashSet::from(["the".into(), "of".into()]);
+        let mut tf = TermMap::new();
+        let count = XmlTokenizer::default()
+            .with_stop_words(stop_words)
+            .tokenize(
+                "<doc><p>The end</p><p>of</p><p>the story</p></doc>".as_bytes(),
+                &mut tf,
+            )
+            .unwrap();
+        assert_eq!(count, 2);
+        let mut keys: Vec<_> = tf.keys().map(|k| k.to_string()).collect();
+        keys.sort();
+        assert_eq!(keys, ["end", "story"]);
+    }
+
+    #[test]
+    fn weighted_elements_rank_higher() {
+        let dir = TempDir::new("xml-weights");
+        let page = |title, body| {
+            format!("<html><head><title>{title}</title></head><body><p>{body}</p></body></html>")
+        };
+        let titled = dir.write("titled.xhtml", page("rust", "notes about things"));
+        let body = dir.write("body.xhtml", page("notes", "rust about things"));
+        for i in 0..3 {
+            dir.write(format!("other{i}.xhtml"), page("other", "unrelated"));
+        }
+        let scores = |index: &Index| {
+            let results = index.search("rust");
+            let score = |path| results.iter().find(|(p, _)| *p == path).unwrap().1;
+            (score(&titled), score(&body))
+        };
+
+        let (titled_score, body_score) = scores(&Index::new(dir.path()));
+        assert_eq!(titled_score, body_score);
+
+        let registry = TokenizerRegistry::default_registry().with_extension("xhtml", |a, _| {
+            Box::new(
+                XmlTokenizer::default()
+                    .with_analyzer(Arc::clone(a))
+                    .with_element_weights([("title", 3)]),
+            )
+        });
+        let index = Index::with_tokenizer_registry(dir.path(), &registry);
+        let (titled_score, body_score) = scores(&index);
+        assert!(titled_score > body_score, "{titled_score} <= {body_score}");
+        assert_eq!(index.document(&titled).unwrap().count, 6);
+    }
 }
@@ -0,0 +1,173 @@
+use std::{collections::HashSet, fs::metadata, io, path::Path, sync::Arc};
+
+use crate::{dirconfig::DirectorySettings, traverse_tree, Index, TokenizerRegistry};
+
+/// What [`Index::update`] did with the files it found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpdateStats {
+    /// New files indexed
+    pub added: usize,
+    /// Documents whose file no longer exists
+    pub removed: usize,
+    /// Files modified since they were indexed, indexed again
+    pub updated: usize,
+    /// Files unchanged since they were indexed
+    pub skipped: usize,
+}
+
+impl Index {
+    /// Brings the documents beneath `root` up to date, `root` being spelled like when the index
+    /// was built: new and modified files are indexed, with the options the index was built with,
+    /// and the documents of deleted files are removed. Files unchanged since they were indexed
+    /// are not read. Documents outside of `root` are left alone.
+    ///
+    /// Files are tokenized as by [`Index::new`]: the documents indexed with the custom
+    /// tokenizers of a [`TokenizerRegistry`] are kept as they were, see
+    /// [`Index::update_with_registry`] to index them again.
+    pub fn update(&mut self, root: impl AsRef<Path>) -> io::Result<UpdateStats> {
+        self.update_with_registry(root, TokenizerRegistry::standard())
+    }
+
+    /// Like [`Index::update`], tokenizing files with `registry` like
+    /// [`Index::new_with_registry`]. Tokenizers are not saved along with the index, so it must
+    /// be the registry the index was built with.
+    pub fn update_with_registry(
+        &mut self,
+        root: impl AsRef<Path>,
+        registry: &TokenizerRegistry,
+    ) -> io::Result<UpdateStats> {
+        let root = root.as_ref();
+        // A missing root would otherwise remove every document
+        metadata(root)?;
+
+        let analyzer = Arc::new(self.analyzer.clone());
+        let options = self.options.clone();
+        let mut stats = UpdateStats::default();
+        let mut seen = HashSet::new();
+        // The settings files above `root` apply too, up to the directory the index was built from
+        let settings = match self.root().filter(|top| root.starts_with(top)) {
+            Some(top) => DirectorySettings::for_file_within(root, Some(top), &self.options),
+            None => DirectorySettings::from(&self.options),
+        };
+        traverse_tree(
+            root,
+            settings,
+            |dir, parent| parent.enter(dir),
+            |p, s| {
+                match self.options.within_size_limit(&p) {
+                    Ok(true) => {}
+                    Ok(false) => return,
+                    Err(e) => {
+                        log::warn!("cannot read {path}: {e}", path = p.display());
+                        return;
+                    }
+                }
+                seen.insert(p.clone());
+                let modified = metadata(&p).and_then(|m| m.modified()).ok();
+                match self.documents.get(&p).map(|d| d.modified) {
+                    Some(Some(indexed)) if modified.is_some_and(|m| m <= indexed) => {
+                        stats.skipped += 1;
+                        return;
+                    }
+                    Some(_) => stats.updated += 1,
+                    None => stats.added += 1,
+                }
+                self.add_file(p, &analyzer, s, registry);
+            },
+            |p, e| log::warn!("cannot read {path}: {e}", path = p.display()),
+            &options,
+        );
+
+        let removed: Vec<_> = self
+            .documents
+            .keys()
+            .filter(|p| p.starts_with(root) && !seen.contains(*p))
+            .cloned()
+            .collect();
+        stats.removed = removed.len();
+        for path in removed {
+            log::info!("removing {path}", path = path.display());
+            self.remove_document(&path);
+        }
+        Ok(stats)
+    }
+
+    /// Like [`Index::update`], walking the directory the index was built from. Fails for
+    /// indexes that do not know it, see [`Index::root`].
+    pub fn refresh(&mut self) -> io::Result<UpdateStats> {
+        let root = self.known_root()?.to_path_buf();
+        self.update(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{remove_file, write, File},
+        time::{Duration, SystemTime},
+    };
+
+    use super::*;
+    use crate::{testutil::TempDir, IndexOptions};
+
+    #[test]
+    fn only_changes_are_processed() {
+        let dir = TempDir::new("update");
+        let changed = dir.write("changed.txt", "before");
+        let removed = dir.write("sub/removed.txt", "removed");
+        for i in 0..3 {
+            dir.write(format!("kept{i}.txt"), "kept");
+        }
+        let options = IndexOptions {
+            track_positions: true,
+            ..Default::default()
+        };
+        let mut index = Index::new_with_options(dir.path(), options.clone());
+        assert_eq!(
+            index.update(dir.path()).unwrap(),
+            UpdateStats {
+                skipped: 5,
+                ..Default::default()
+            }
+        );
+
+        write(&changed, "after").unwrap();
+        File::options()
+            .write(true)
+            .open(&changed)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        remove_file(&removed).unwrap();
+        dir.write("sub/added.txt", "added");
+
+        // The index walks the directory it was built from again
+        assert_eq!(
+            index.refresh().unwrap(),
+            UpdateStats {
+                added: 1,
+                removed: 1,
+                updated: 1,
+                skipped: 3,
+            }
+        );
+        assert_eq!(index, Index::new_with_options(dir.path(), options));
+        assert!(index.update(dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn settings_above_root_apply() {
+        let dir = TempDir::new("update-settings");
+        dir.write(
+            "data/.indexer.toml",
+            "tokenizer = \"csv\"\ncsv_delimiter = \";\"\n",
+        );
+        dir.write("data/sub/values.log", "alpha;beta");
+        let mut index = Index::new(dir.path());
+        let added = dir.write("data/sub/added.log", "alpha;beta;alpha");
+
+        index.update(dir.path().join("data/sub")).unwrap();
+        assert_eq!(index.document(&added).unwrap().count, 3);
+        assert_eq!(index, Index::new(dir.path()));
+    }
+}
@@ -0,0 +1,106 @@
+use std::{borrow::Cow, collections::HashMap, ops::Bound};
+
+use crate::{CaseInsensitiveStr, Index};
+
+impl Index {
+    /// Number of indexed documents
+    pub fn document_count(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Number of distinct terms across all documents
+    pub fn vocabulary_size(&self) -> usize {
+        self.document_frequencies().len()
+    }
+
+    /// Returns the `n` terms found in the most documents, with the number of such documents.
+    /// Ties are broken alphabetically. Terms found in nearly every document are good stop word
+    /// candidates.
+    pub fn top_terms(&self, n: usize) -> Vec<(Cow<'_, str>, usize)> {
+        let mut counts: Vec<_> = self.document_frequencies().into_values().collect();
+        counts.sort_by(|(t1, c1), (t2, c2)| {
+            c2.cmp(c1)
+                .then_with(|| CaseInsensitiveStr::new(t1).cmp(CaseInsensitiveStr::new(t2)))
+        });
+        counts.truncate(n);
+        counts
+            .into_iter()
+            .map(|(t, c)| (self.analyzer.word(t), c))
+            .collect()
+    }
+
+    /// Indexed terms starting with the word `prefix`, ignoring case unless the index is case
+    /// sensitive, sorted
+    pub fn terms_with_prefix(&self, prefix: &str) -> Vec<Cow<'_, str>> {
+        self.keys_with_prefix(&self.analyzer.key(prefix))
+            .into_iter()
+            .map(|t| self.analyzer.word(t))
+            .collect()
+    }
+
+    /// Indexed terms starting with the term `prefix`, ignoring case, sorted. Terms are sorted
+    /// ignoring case as well, so those starting with `prefix` follow each other from `prefix`
+    /// on.
+    pub(crate) fn keys_with_prefix(&self, prefix: &str) -> Vec<&str> {
+        self.postings()
+            .range::<CaseInsensitiveStr, _>((
+                Bound::Included(CaseInsensitiveStr::new(prefix)),
+                Bound::Unbounded,
+            ))
+            .map(|(t, _)| &**t)
+            .take_while(|t| CaseInsensitiveStr::new(t).starts_with(prefix))
+            .collect()
+    }
+
+    /// [`Index::keys_with_prefix`], to expand the prefixes of queries
+    pub(crate) fn vocabulary(&self) -> impl Fn(&str) -> Vec<String> + '_ {
+        |prefix| {
+            self.keys_with_prefix(prefix)
+                .into_iter()
+                .map(str::to_owned)
+                .collect()
+        }
+    }
+
+    /// Number of documents containing each term, along with one of its spellings: the
+    /// greatest, so lowercase ones are preferred whatever the order documents are visited in
+    pub(crate) fn document_frequencies(&self) -> HashMap<&CaseInsensitiveStr, (&str, usize)> {
+        let mut counts: HashMap<&CaseInsensitiveStr, (&str, usize)> = HashMap::new();
+        for document in self.documents.values() {
+            for term in document.term_frequency.keys() {
+                let (spelling, count) = counts
+                    .entry(CaseInsensitiveStr::new(term))
+                    .or_insert((term, 0));
+                *spelling = (*spelling).max(&**term);
+                *count += 1;
+            }
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn most_common_terms_first() {
+        let dir = TempDir::new("vocabulary");
+        dir.write("a.txt", "the rust book");
+        dir.write("b.txt", "The cargo book");
+        dir.write("c.txt", "the borrow checker the end");
+        let index = Index::new(dir.path());
+
+        assert_eq!(index.document_count(), 3);
+        assert_eq!(index.vocabulary_size(), 7);
+        assert_eq!(
+            index.top_terms(4),
+            [("the", 3), ("book", 2), ("borrow", 1), ("cargo", 1)].map(|(t, c)| (Cow::from(t), c))
+        );
+        assert_eq!(index.top_terms(100).len(), 7);
+        assert!(Index::new(dir.path().join("missing"))
+            .top_terms(3)
+            .is_empty());
+    }
+}
@@ -0,0 +1,42 @@
+use std::fs;
+
+mod common;
+
+use common::{indexer, indexer_with_input, temp_dir};
+
+#[test]
+fn scripted_session() {
+    let dir = temp_dir("interactive");
+    fs::write(dir.join("a.txt"), "rust rust rust").unwrap();
+    fs::write(dir.join("b.txt"), "rust rust").unwrap();
+    fs::write(dir.join("c.txt"), "xml parser").unwrap();
+    fs::write(dir.join("d.txt"), "csv parser").unwrap();
+    fs::write(dir.join("e.txt"), "json parser").unwrap();
+    indexer(&dir, &["build", "."]);
+
+    let script = "rust\n\n   \n:count 1\n:again\n:count x\n:bogus\nmissing\n:quit\nxml\n";
+    let output = indexer_with_input(&dir, &["interactive", "--count", "2"], script);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = stdout
+        .lines()
+        .map(|l| l.split(':').next().unwrap())
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "./a.txt",
+            "./b.txt",
+            "./a.txt",
+            "No match for query \"missing\""
+        ]
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Invalid count \"x\""), "{stderr}");
+    assert!(stderr.contains("Unknown command :bogus"), "{stderr}");
+
+    // The end of the input ends the session like `:quit`
+    let output = indexer_with_input(&dir, &["interactive"], ":again\nxml");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("./c.txt: "), "{stdout}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No query to run again"));
+}
@@ -0,0 +1,46 @@
+use std::{fs, path::Path};
+
+use indexer::Index;
+
+mod common;
+
+use common::{indexer, temp_dir};
+
+/// Which of the files `names` are indexed in `index_file`
+fn indexed<'a>(index_file: &Path, names: &[&'a str]) -> Vec<&'a str> {
+    let index = Index::load(fs::File::open(index_file).unwrap()).unwrap();
+    names
+        .iter()
+        .copied()
+        .filter(|name| index.document(&Path::new(".").join(name)).is_some())
+        .collect()
+}
+
+#[test]
+fn prune_removes_deleted_files() {
+    let dir = temp_dir("prune");
+    let docs = dir.join("docs");
+    fs::create_dir_all(&docs).unwrap();
+    let names = ["a.txt", "b.txt", "c.txt"];
+    for name in names {
+        fs::write(docs.join(name), format!("{name} content")).unwrap();
+    }
+    let index_file = dir.join("index.json");
+    let index_arg = index_file.to_str().unwrap();
+
+    indexer(&docs, &["-i", index_arg, "build", "."]);
+    assert_eq!(indexed(&index_file, &names), names);
+    fs::remove_file(docs.join("b.txt")).unwrap();
+    let output = indexer(&docs, &["-i", index_arg, "prune"]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Pruned 1 documents\n"
+    );
+    assert_eq!(indexed(&index_file, &names), ["a.txt", "c.txt"]);
+
+    let output = indexer(&docs, &["-i", index_arg, "prune"]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Pruned 0 documents\n"
+    );
+}
@@ -0,0 +1,45 @@
+use std::fs;
+
+mod common;
+
+use common::{indexer, temp_dir};
+
+#[test]
+fn search_formats() {
+    let dir = temp_dir("format");
+    fs::write(dir.join("rust.txt"), "rust borrow checker").unwrap();
+    fs::write(dir.join("notes, rust.txt"), "rust rust notes").unwrap();
+    fs::write(dir.join("xml.txt"), "xml parser").unwrap();
+    fs::write(dir.join("csv.txt"), "csv reader").unwrap();
+    indexer(&dir, &["build", "."]);
+
+    let output = indexer(&dir, &["search", "--format", "json", "rust"]);
+    let results: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let results = results.as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["path"], "./notes, rust.txt");
+    assert_eq!(results[1]["path"], "./rust.txt");
+    assert!(results[0]["score"].as_f64().unwrap() > results[1]["score"].as_f64().unwrap());
+
+    let output = indexer(
+        &dir,
+        &["search", "--format", "json", "--offset", "1", "rust"],
+    );
+    let page: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(page.as_array().unwrap(), &results[1..]);
+    let output = indexer(&dir, &["search", "--offset", "2", "rust"]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "No more than 2 results for query \"rust\"\n"
+    );
+
+    let output = indexer(&dir, &["search", "--format", "json", "missing"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "[]\n");
+
+    let output = indexer(&dir, &["search", "--format", "csv", "rust"]);
+    let mut reader = csv::Reader::from_reader(&output.stdout[..]);
+    assert_eq!(reader.headers().unwrap(), vec!["path", "score"]);
+    let paths: Vec<_> = reader.records().map(|r| r.unwrap()[0].to_owned()).collect();
+    assert_eq!(paths, ["./notes, rust.txt", "./rust.txt"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"./notes, rust.txt\""));
+}
@@ -0,0 +1,61 @@
+use std::fs;
+
+mod common;
+
+use common::{indexer, run, temp_dir};
+
+#[test]
+fn searches_a_directory_of_shards() {
+    let dir = temp_dir("shards");
+    fs::create_dir_all(dir.join("docs/a")).unwrap();
+    fs::create_dir_all(dir.join("docs/b")).unwrap();
+    fs::create_dir(dir.join("shards")).unwrap();
+    fs::write(dir.join("docs/a/rust.txt"), "rust tokenizer index").unwrap();
+    fs::write(dir.join("docs/a/xml.txt"), "xml parser events").unwrap();
+    fs::write(dir.join("docs/b/rust.txt"), "rust rust borrow checker").unwrap();
+    fs::write(dir.join("docs/b/other.txt"), "nothing relevant here").unwrap();
+    indexer(&dir, &["-i", "shards/a.json", "build", "docs/a"]);
+    indexer(&dir, &["-i", "shards/b.json.gz", "build", "docs/b"]);
+    indexer(&dir, &["-i", "all.json", "build", "docs"]);
+
+    let search = |index: &str, query: &str| {
+        let output = indexer(&dir, &["-i", index, "search", "--format", "json", query]);
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+    let results = search("shards", "rust");
+    let paths: Vec<_> = results
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["path"].as_str().unwrap())
+        .collect();
+    assert_eq!(paths, ["docs/b/rust.txt", "docs/a/rust.txt"]);
+    for query in ["rust", "parser", "rust -borrow", "rust OR xml"] {
+        assert_eq!(
+            search("shards", query),
+            search("all.json", query),
+            "{query}"
+        );
+    }
+
+    // A shard cut short by an interrupted save only loads leniently
+    let saved = fs::read(dir.join("shards/a.json")).unwrap();
+    fs::write(dir.join("shards/a.json"), &saved[..saved.len() - 2]).unwrap();
+    assert!(!run(&dir, &["-i", "shards", "search", "rust"])
+        .status
+        .success());
+    let output = indexer(&dir, &["-i", "shards", "--lenient", "search", "rust"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("docs/b/rust.txt"));
+
+    fs::create_dir(dir.join("empty")).unwrap();
+    assert!(!run(&dir, &["-i", "empty", "search", "rust"])
+        .status
+        .success());
+
+    // Whether shards are outdated is not checked
+    let output = run(
+        &dir,
+        &["-i", "shards", "search", "--on-stale", "error", "rust"],
+    );
+    assert!(!output.status.success());
+}
@@ -0,0 +1,30 @@
+use std::fs;
+
+mod common;
+
+use common::{run, temp_dir};
+
+#[test]
+fn verify_fails_on_drift_when_strict() {
+    let dir = temp_dir("verify");
+    fs::write(dir.join("a.txt"), "a content").unwrap();
+    fs::write(dir.join("b.txt"), "b content").unwrap();
+    assert!(run(&dir, &["build", "."]).status.success());
+
+    let output = run(&dir, &["verify", "--strict"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "2 unchanged, 0 modified, 0 missing, 0 unindexed\n"
+    );
+
+    fs::remove_file(dir.join("b.txt")).unwrap();
+    fs::write(dir.join("c.txt"), "c content").unwrap();
+    let output = run(&dir, &["verify"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "missing: ./b.txt\nunindexed: ./c.txt\n1 unchanged, 0 modified, 1 missing, 1 unindexed\n"
+    );
+    assert!(!run(&dir, &["verify", "--strict"]).status.success());
+}